@@ -0,0 +1,682 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow};
+use chrono::Local;
+use serde_json::{Value, json};
+use undersea_lib::{
+    AutoDownload, Config, ControlError, Episode, LibraryError, ShowSettings, Shows, SortOrder,
+    bookmark::{self, Bookmark},
+    control::{Client, EpisodeSummary, NowPlaying},
+    credentials::{Auth, CREDENTIALS_FILE_NAME, CredentialStore, Credentials},
+    history::History,
+    opml,
+    playlist::Query,
+    sync::{self, SyncReport},
+};
+
+use crate::{
+    Bookmarks, Cli, Command, CredentialArgs, CredentialsCommand, Ctl, HistoryFormat, List,
+    Playlist, SettingsChanges, Sort, output,
+};
+
+/// Everything a command needs to run.
+struct State {
+    config: Config,
+    shows: Shows,
+    library_path: PathBuf,
+    json: bool,
+}
+
+impl State {
+    fn save(&mut self) -> Result<()> {
+        self.shows
+            .save(&self.library_path)
+            .with_context(|| format!("failed to save library {}", self.library_path.display()))?;
+        self.shows
+            .credentials_mut()
+            .save()
+            .context("failed to save credentials")
+    }
+}
+
+pub async fn run(cli: Cli) -> Result<()> {
+    let config = Config::load(cli.config.as_deref())?;
+    for warning in config.warnings() {
+        eprintln!("warning: {warning}");
+    }
+    // the tui has the library open, so it is left alone
+    if let Command::Ctl(command) = cli.command {
+        return ctl(&config, command, cli.json).await;
+    }
+
+    let library_path = cli.library.unwrap_or_else(|| config.library_path());
+    let shows = load_shows(&config, &library_path)?;
+
+    let mut state = State {
+        config,
+        shows,
+        library_path,
+        json: cli.json,
+    };
+    let json = state.json;
+
+    match cli.command {
+        Command::Add { urls, credentials } => {
+            let credentials = read_credentials(credentials)?;
+            let added = add_shows(&mut state, urls, credentials.as_ref()).await?;
+            output::added(&state.shows, &added, json)?;
+        }
+        Command::Remove { show } => {
+            let removed = state.shows.remove(&show)?;
+            state.save()?;
+            output::removed(&state.shows, &removed, json)?;
+        }
+        Command::List(command) => list(&state, command)?,
+        Command::Refresh => refresh(&mut state).await?,
+        Command::MarkPlayed { episodes, undo } => {
+            // every id is checked first, so a typo does not leave some marked
+            if let Some(missing) = episodes
+                .iter()
+                .find(|episode| state.shows.episode_by_id(episode).is_none())
+            {
+                return Err(LibraryError::EpisodeNotFound(missing.clone()).into());
+            }
+            for episode in &episodes {
+                state.shows.set_finished(episode, !undo)?;
+            }
+            state.save()?;
+        }
+        Command::Progress { episode, time } => {
+            let time = parse_time(&time)?;
+            state.shows.set_resume_time(&episode, time)?;
+            state.save()?;
+        }
+        Command::Settings { show, changes } => settings(&mut state, &show, changes)?,
+        Command::Tag { show, tags, remove } => tag(&mut state, &show, &tags, remove)?,
+        Command::ImportOpml { file } => import_opml(&mut state, &file).await?,
+        Command::ExportOpml {
+            file,
+            include_secrets,
+        } => {
+            let opml = opml::write(&state.shows, include_secrets);
+            match file {
+                Some(file) => fs::write(&file, opml)
+                    .with_context(|| format!("failed to write {}", file.display()))?,
+                None => print!("{opml}"),
+            }
+        }
+        Command::Download { episodes, dir } => {
+            let dir = dir.unwrap_or_else(|| state.config.download_dir.clone());
+            let mut downloaded = Vec::new();
+            for episode in episodes {
+                let result = state.shows.download(&episode, &dir).await;
+                state.save()?;
+                let path = result.with_context(|| format!("failed to download {episode}"))?;
+                downloaded.push((episode, path));
+            }
+            output::downloaded(&downloaded, json)?;
+        }
+        Command::Playlist(command) => playlist(&mut state, command)?,
+        Command::Bookmark(command) => bookmarks(&mut state, command)?,
+        Command::Credentials(command) => credentials(&mut state, command)?,
+        Command::Sync => sync(&mut state).await?,
+        Command::Stats => {
+            let history = load_history(&state)?;
+            output::stats(&state.shows, &history.stats(&Local::now()), json)?;
+        }
+        Command::ExportHistory { file, format } => {
+            let history = load_history(&state)?;
+            let mut out = Vec::new();
+            match format {
+                HistoryFormat::Csv => history.write_csv(&mut out)?,
+                HistoryFormat::Json => history.write_json(&mut out)?,
+            }
+            match file {
+                Some(file) => fs::write(&file, out)
+                    .with_context(|| format!("failed to write {}", file.display()))?,
+                None => io::stdout().write_all(&out)?,
+            }
+        }
+        Command::Ctl(_) => unreachable!("ctl is run before the library is loaded"),
+    }
+
+    Ok(())
+}
+
+fn playlist(state: &mut State, command: Playlist) -> Result<()> {
+    match command {
+        Playlist::Add { name, query } => {
+            let query = Query::parse(&query)?;
+            state.shows.add_playlist(&name, query);
+            state.save()?;
+        }
+        Playlist::Remove { name } => {
+            state.shows.remove_playlist(&name)?;
+            state.save()?;
+        }
+        Playlist::List => output::playlists(state.shows.playlists(), state.json)?,
+        Playlist::Episodes { name, query } => {
+            let query = if query {
+                Query::parse(&name)?
+            } else {
+                state
+                    .shows
+                    .find_playlist(&name)
+                    .ok_or(LibraryError::PlaylistNotFound(name))?
+                    .query
+                    .clone()
+            };
+            output::episode_list(&state.shows.query(&query), state.json)?;
+        }
+    }
+    Ok(())
+}
+
+fn bookmarks(state: &mut State, command: Bookmarks) -> Result<()> {
+    match command {
+        Bookmarks::Add {
+            episode,
+            time,
+            end,
+            note,
+        } => {
+            let start = parse_time(&time)?;
+            let end = end.as_deref().map(parse_time).transpose()?;
+            state
+                .shows
+                .add_bookmark(&episode, Bookmark::new(start, end, &note))?;
+            state.save()?;
+        }
+        Bookmarks::Remove { episode, number } => {
+            let (_, bookmark) = find_bookmark(&state.shows, &episode, number)?;
+            let created_at = bookmark.created_at;
+            state.shows.remove_bookmark(&episode, &created_at)?;
+            state.save()?;
+        }
+        Bookmarks::List { episode } => {
+            let episodes = match episode {
+                Some(id) => vec![
+                    state
+                        .shows
+                        .episode_by_id(&id)
+                        .ok_or(LibraryError::EpisodeNotFound(id))?,
+                ],
+                None => state
+                    .shows
+                    .shows()
+                    .into_iter()
+                    .flat_map(|show| {
+                        show.episodes()
+                            .into_iter()
+                            .map(move |episode| (show, episode))
+                    })
+                    .filter(|(_, episode)| !episode.bookmarks().is_empty())
+                    .collect(),
+            };
+            output::bookmarks(&episodes, state.json)?;
+        }
+        Bookmarks::Export { file } => {
+            let markdown = state.shows.bookmarks_markdown();
+            match file {
+                Some(file) => fs::write(&file, markdown)
+                    .with_context(|| format!("failed to write {}", file.display()))?,
+                None => print!("{markdown}"),
+            }
+        }
+        Bookmarks::Clip {
+            episode,
+            number,
+            file,
+        } => {
+            let (episode, bookmark) = find_bookmark(&state.shows, &episode, number)?;
+            bookmark::export_clip(episode, bookmark, &file)?;
+        }
+    }
+    Ok(())
+}
+
+/// A bookmark by its number in an episode, counting from 1 as they are
+/// listed.
+fn find_bookmark<'a>(
+    shows: &'a Shows,
+    id: &str,
+    number: usize,
+) -> Result<(&'a Episode, &'a Bookmark)> {
+    let (_, episode) = shows
+        .episode_by_id(id)
+        .ok_or_else(|| LibraryError::EpisodeNotFound(id.to_string()))?;
+    let bookmark = number
+        .checked_sub(1)
+        .and_then(|index| episode.bookmarks().get(index))
+        .ok_or_else(|| anyhow!("episode \"{id}\" has no bookmark {number}"))?;
+    Ok((episode, bookmark))
+}
+
+/// Load the library, with everything it needs from the config to fetch feeds.
+fn load_shows(config: &Config, library_path: &Path) -> Result<Shows> {
+    let mut shows = Shows::load_or_default(library_path)
+        .with_context(|| format!("failed to load library {}", library_path.display()))?;
+    shows.set_client(config.http_client()?);
+    // kept next to the library, so a library given with --library has its own
+    let credentials_path = library_path.with_file_name(CREDENTIALS_FILE_NAME);
+    shows.set_credentials(
+        CredentialStore::load(&credentials_path, config.keyring).with_context(|| {
+            format!("failed to load credentials {}", credentials_path.display())
+        })?,
+    );
+    shows.set_sync_secret_urls(config.sync_secret_urls);
+    if let Some(descriptions) = config.description_cache() {
+        shows.set_description_cache(descriptions);
+    }
+    Ok(shows)
+}
+
+/// Send a request to the running tui.
+async fn ctl(config: &Config, command: Ctl, json: bool) -> Result<()> {
+    let mut client = Client::connect(&config.control_socket()).await?;
+    let (method, params) = match command {
+        Ctl::Status => {
+            let status: NowPlaying =
+                serde_json::from_value(client.call("status", Value::Null).await?)?;
+            return output::now_playing(&status, json);
+        }
+        Ctl::Queue => {
+            let queue: Vec<EpisodeSummary> =
+                serde_json::from_value(client.call("queue", Value::Null).await?)?;
+            return output::remote_queue(&queue, json);
+        }
+        Ctl::Add { url } => {
+            let added = client.call("add", json!({ "url": url })).await?;
+            return output::remote_added(&added, json);
+        }
+        Ctl::Watch => {
+            client.subscribe().await?;
+            loop {
+                match client.next_event().await {
+                    Ok(event) => output::event(&event, json)?,
+                    Err(ControlError::Closed) => return Ok(()),
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+        Ctl::Play { episode } => ("play", json!({ "id": episode })),
+        Ctl::Pause => ("pause", Value::Null),
+        Ctl::Toggle => ("toggle", Value::Null),
+        Ctl::Stop => ("stop", Value::Null),
+        Ctl::Next => ("next", Value::Null),
+        Ctl::Previous => ("previous", Value::Null),
+        Ctl::Seek { time } => ("seek", parse_seek(&time)?),
+        Ctl::Speed { speed } => ("speed", json!({ "speed": speed })),
+        Ctl::Enqueue { episode } => ("enqueue", json!({ "id": episode })),
+    };
+    client.call(method, params).await?;
+    Ok(())
+}
+
+fn load_history(state: &State) -> Result<History> {
+    let path = state.config.history_path();
+    History::load(&path).with_context(|| format!("failed to load history {}", path.display()))
+}
+
+/// Subscribe to every url that is not already subscribed to, saving after each
+/// one so that a failure part way through keeps the shows that were added.
+/// Each show gets `credentials`, if there are any.
+async fn add_shows(
+    state: &mut State,
+    urls: impl IntoIterator<Item = String>,
+    credentials: Option<&Credentials>,
+) -> Result<Vec<String>> {
+    let mut added = Vec::new();
+    for url in urls {
+        if state.shows.find_show(&url).is_some() {
+            continue;
+        }
+        if let Some(credentials) = credentials {
+            state.shows.credentials_mut().set(&url, credentials.clone());
+        }
+        let redacted = state.shows.redact(&url);
+        let result = state.shows.add(url.as_str()).await;
+        if result.is_err() {
+            state.shows.credentials_mut().remove(&url);
+        }
+        state.save()?;
+        result.with_context(|| format!("failed to add {redacted}"))?;
+        added.push(url);
+    }
+    Ok(added)
+}
+
+fn credentials(state: &mut State, command: CredentialsCommand) -> Result<()> {
+    match command {
+        CredentialsCommand::Set { show, credentials } => {
+            let url = find_show_url(&state.shows, &show)?;
+            let credentials = read_credentials(credentials)?.unwrap_or_default();
+            state.shows.credentials_mut().set(&url, credentials);
+            state.save()?;
+        }
+        CredentialsCommand::Remove { show } => {
+            let url = find_show_url(&state.shows, &show)?;
+            state.shows.credentials_mut().remove(&url);
+            state.save()?;
+        }
+        CredentialsCommand::List => output::credentials(&state.shows, state.json)?,
+    }
+    Ok(())
+}
+
+fn find_show_url(shows: &Shows, query: &str) -> Result<String> {
+    let show = shows
+        .find_show(query)
+        .ok_or_else(|| LibraryError::ShowNotFound(query.to_string()))?;
+    Ok(show.url().to_string())
+}
+
+/// Credentials from the command line, reading the password from stdin if
+/// only a username was given. Returns [`None`] if none were given.
+fn read_credentials(args: CredentialArgs) -> Result<Option<Credentials>> {
+    let auth = match (args.username, args.password, args.bearer) {
+        (Some(username), Some(password), _) => Some(Auth::Basic { username, password }),
+        (Some(username), None, _) => {
+            let mut password = String::new();
+            io::stdin().read_line(&mut password)?;
+            let password = password.trim_end_matches(['\n', '\r']).to_string();
+            Some(Auth::Basic { username, password })
+        }
+        (None, _, Some(token)) => Some(Auth::Bearer { token }),
+        (None, _, None) => None,
+    };
+    let headers = args
+        .headers
+        .iter()
+        .map(|header| {
+            header
+                .split_once(':')
+                .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                .filter(|(name, _)| !name.is_empty())
+                .ok_or_else(|| anyhow!("invalid header \"{header}\", use \"Name: value\""))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let credentials = Credentials {
+        auth,
+        headers,
+        private_url: args.private_url,
+    };
+    Ok((!credentials.is_empty()).then_some(credentials))
+}
+
+async fn refresh(state: &mut State) -> Result<()> {
+    let summary = state.shows.refresh(state.config.concurrency).await;
+    state.save()?;
+
+    let mut downloaded = Vec::new();
+    for episode in &summary.to_download {
+        match state
+            .shows
+            .download(episode, &state.config.download_dir)
+            .await
+        {
+            Ok(path) => downloaded.push((episode.clone(), path)),
+            Err(err) => eprintln!("warning: failed to download {episode}: {err}"),
+        }
+        state.save()?;
+    }
+
+    output::refreshed(&state.shows, &summary, &downloaded, state.json)?;
+    if let Some((url, err)) = summary.failed.into_iter().next() {
+        let url = state.shows.redact(&url);
+        return Err(anyhow::Error::new(err).context(format!("failed to refresh {url}")));
+    }
+    Ok(())
+}
+
+/// Sync with every sync backend in the config.
+async fn sync(state: &mut State) -> Result<()> {
+    let mut backends = sync::backends(&state.config, state.config.http_client()?)?;
+    if backends.is_empty() {
+        return Err(anyhow!(
+            "nothing to sync with, add a [server], [gpodder] or [sync_folder] table to the config"
+        ));
+    }
+
+    let mut report = SyncReport::default();
+    for backend in &mut backends {
+        let result = state.shows.sync(backend.as_mut()).await;
+        // whatever was done before a failure still needs to be kept
+        state.save()?;
+        report.merge(result.with_context(|| format!("failed to sync with {}", backend.name()))?);
+    }
+
+    output::synced(&report, state.json)
+}
+
+fn settings(state: &mut State, show: &str, changes: SettingsChanges) -> Result<()> {
+    let current = state
+        .shows
+        .find_show(show)
+        .ok_or_else(|| LibraryError::ShowNotFound(show.to_string()))?;
+    let name = current.name().to_string();
+    let settings = apply_changes(current.settings().clone(), changes)?;
+
+    state.shows.set_show_settings(show, settings.clone())?;
+    state.save()?;
+    output::settings(&name, &settings, state.json)
+}
+
+fn list(state: &State, command: List) -> Result<()> {
+    let json = state.json;
+    match command {
+        List::Shows { tag } => {
+            let shows = match tag {
+                Some(tag) => state.shows.shows_tagged(&tag),
+                None => state.shows.shows(),
+            };
+            output::shows(&state.shows, &shows, json)
+        }
+        List::Queue => output::queue(&state.shows, json),
+        List::Tags => output::tags(&state.shows, json),
+        List::Episodes {
+            show,
+            tag,
+            unplayed,
+        } => {
+            let filtered = match (show, tag) {
+                (Some(query), _) => {
+                    let show = state
+                        .shows
+                        .find_show(&query)
+                        .ok_or(LibraryError::ShowNotFound(query))?;
+                    vec![show]
+                }
+                (None, Some(tag)) => state.shows.shows_tagged(&tag),
+                (None, None) => state.shows.shows(),
+            };
+            output::episodes(&filtered, unplayed, json)
+        }
+    }
+}
+
+fn tag(state: &mut State, show: &str, tags: &[String], remove: bool) -> Result<()> {
+    if remove {
+        state.shows.remove_tags(show, tags)?;
+    } else {
+        state.shows.add_tags(show, tags)?;
+    }
+    state.save()?;
+    let show = state
+        .shows
+        .find_show(show)
+        .ok_or_else(|| LibraryError::ShowNotFound(show.to_string()))?;
+    output::show_tags(show, state.json)
+}
+
+async fn import_opml(state: &mut State, file: &Path) -> Result<()> {
+    let contents = if file == Path::new("-") {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents)?;
+        contents
+    } else {
+        fs::read_to_string(file).with_context(|| format!("failed to read {}", file.display()))?
+    };
+
+    let feeds = opml::parse(&contents)?;
+    let added = add_shows(state, feeds.iter().map(|feed| feed.url.clone()), None).await?;
+    // folders are kept as tags, for shows that were already subscribed to as well
+    for feed in &feeds {
+        if state.shows.find_show(&feed.url).is_some() {
+            state.shows.add_tags(&feed.url, &feed.tags)?;
+        }
+    }
+    state.save()?;
+    output::added(&state.shows, &added, state.json)
+}
+
+fn apply_changes(mut settings: ShowSettings, changes: SettingsChanges) -> Result<ShowSettings> {
+    if let Some(speed) = changes.speed {
+        settings.playback_speed = if speed == "default" {
+            None
+        } else {
+            let speed: f32 = speed
+                .parse()
+                .map_err(|_| anyhow!("invalid speed \"{speed}\""))?;
+            if !(0.25..=4.0).contains(&speed) {
+                return Err(anyhow!("speed must be between 0.25 and 4"));
+            }
+            Some(speed)
+        };
+    }
+    if let Some(skip_intro) = changes.skip_intro {
+        settings.skip_intro = parse_time(&skip_intro)?;
+    }
+    if let Some(skip_outro) = changes.skip_outro {
+        settings.skip_outro = parse_time(&skip_outro)?;
+    }
+    if let Some(sort) = changes.sort {
+        settings.sort_order = match sort {
+            Sort::Oldest => SortOrder::OldestFirst,
+            Sort::Newest => SortOrder::NewestFirst,
+        };
+    }
+    if let Some(auto_download) = changes.auto_download {
+        settings.auto_download = parse_auto_download(&auto_download)?;
+    }
+    if let Some(queue_new) = changes.queue_new {
+        settings.add_to_queue = queue_new;
+    }
+    if let Some(hide_played) = changes.hide_played {
+        settings.hide_played = hide_played;
+    }
+    if let Some(skip_silence) = changes.skip_silence {
+        settings.skip_silence = parse_override(&skip_silence)?;
+    }
+    if let Some(normalize) = changes.normalize {
+        settings.normalize = parse_override(&normalize)?;
+    }
+    if let Some(mono) = changes.mono {
+        settings.mono = parse_override(&mono)?;
+    }
+    Ok(settings)
+}
+
+/// Parse `true`, `false` or `default`, which leaves it to the config.
+fn parse_override(value: &str) -> Result<Option<bool>> {
+    match value {
+        "default" => Ok(None),
+        _ => value
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow!("invalid value \"{value}\", expected true, false or default")),
+    }
+}
+
+/// Parse `never`, `new` or `latest:<count>`.
+fn parse_auto_download(value: &str) -> Result<AutoDownload> {
+    match value {
+        "never" => Ok(AutoDownload::Never),
+        "new" => Ok(AutoDownload::New),
+        _ => value
+            .strip_prefix("latest:")
+            .and_then(|count| count.parse().ok())
+            .map(AutoDownload::Latest)
+            .ok_or_else(|| {
+                anyhow!("invalid auto download \"{value}\", expected never, new or latest:<count>")
+            }),
+    }
+}
+
+/// Parse a time given as seconds, `mm:ss` or `hh:mm:ss`.
+fn parse_time(time: &str) -> Result<Duration> {
+    let invalid = || anyhow!("invalid time \"{time}\", expected seconds, mm:ss or hh:mm:ss");
+
+    let parts = time.split(':').collect::<Vec<_>>();
+    if parts.len() > 3 {
+        return Err(invalid());
+    }
+
+    let mut seconds = 0.0;
+    for (index, part) in parts.iter().enumerate() {
+        let value: f64 = part.parse().map_err(|_| invalid())?;
+        let is_last = index == parts.len() - 1;
+        if value < 0.0 || (!is_last && value.fract() != 0.0) || (index > 0 && value >= 60.0) {
+            return Err(invalid());
+        }
+        seconds = seconds * 60.0 + value;
+    }
+
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
+
+/// The params of a `seek`, a time to jump to, or to jump by if it starts
+/// with + or -.
+fn parse_seek(time: &str) -> Result<Value> {
+    if let Some(by) = time.strip_prefix('+') {
+        Ok(json!({ "by": parse_time(by)?.as_secs_f64() }))
+    } else if let Some(by) = time.strip_prefix('-') {
+        Ok(json!({ "by": -parse_time(by)?.as_secs_f64() }))
+    } else {
+        Ok(json!({ "position": parse_time(time)?.as_secs_f64() }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_times() {
+        assert_eq!(parse_time("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_time("1:30").unwrap(), Duration::from_secs(90));
+        assert_eq!(
+            parse_time("1:01:30.5").unwrap(),
+            Duration::from_secs_f64(3690.5)
+        );
+        assert!(parse_time("1:60").is_err());
+        assert!(parse_time("1:2:3:4").is_err());
+        assert!(parse_time("soon").is_err());
+    }
+
+    #[test]
+    fn parse_seeks() {
+        assert_eq!(parse_seek("+30").unwrap(), json!({ "by": 30.0 }));
+        assert_eq!(parse_seek("-1:00").unwrap(), json!({ "by": -60.0 }));
+        assert_eq!(parse_seek("12:34").unwrap(), json!({ "position": 754.0 }));
+        assert!(parse_seek("+-5").is_err());
+    }
+
+    #[test]
+    fn parse_auto_downloads() {
+        assert_eq!(parse_auto_download("never").unwrap(), AutoDownload::Never);
+        assert_eq!(parse_auto_download("new").unwrap(), AutoDownload::New);
+        assert_eq!(
+            parse_auto_download("latest:3").unwrap(),
+            AutoDownload::Latest(3)
+        );
+        assert!(parse_auto_download("latest:").is_err());
+        assert!(parse_auto_download("always").is_err());
+    }
+}
@@ -0,0 +1,377 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use clap::{Parser, Subcommand};
+use undersea_lib::{
+    ConfigError, ControlError, DownloadError, FeedError, LibraryError, SyncError, control::RpcError,
+};
+
+mod commands;
+mod output;
+
+/// Exit codes, so that scripts can tell failures apart. Usage errors exit
+/// with 2, as is done by clap.
+mod exit {
+    pub const FAILURE: u8 = 1;
+    pub const NOT_FOUND: u8 = 3;
+    /// Fetching or reading a feed or media failed.
+    pub const FEED: u8 = 4;
+    /// The config file is invalid.
+    pub const CONFIG: u8 = 5;
+    /// The sync server could not be reached or rejected a request.
+    pub const SYNC: u8 = 6;
+    /// `ctl` was used without the tui running.
+    pub const NOT_RUNNING: u8 = 7;
+}
+
+/// Manage your podcasts from the command line
+#[derive(Debug, Parser)]
+#[command(name = "undersea", version)]
+pub struct Cli {
+    /// Config file to use instead of the default one
+    #[arg(long, global = true, env = "UNDERSEA_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Library file to use instead of the default one
+    #[arg(long, global = true, env = "UNDERSEA_LIBRARY")]
+    library: Option<PathBuf>,
+
+    /// Print output as json
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Subscribe to one or more shows by their feed url
+    Add {
+        #[arg(required = true)]
+        urls: Vec<String>,
+        #[command(flatten)]
+        credentials: CredentialArgs,
+    },
+    /// Unsubscribe from a show, by its url or name
+    Remove { show: String },
+    /// List shows or episodes
+    #[command(subcommand)]
+    List(List),
+    /// Check every show for new episodes
+    Refresh,
+    /// Mark episodes as played
+    MarkPlayed {
+        #[arg(required = true)]
+        episodes: Vec<String>,
+        /// Mark the episodes as not played instead
+        #[arg(long)]
+        undo: bool,
+    },
+    /// Set where an episode will resume from, as seconds, mm:ss or hh:mm:ss
+    Progress { episode: String, time: String },
+    /// Show or change the settings of a show
+    Settings {
+        /// The show, by its url or name
+        show: String,
+        #[command(flatten)]
+        changes: SettingsChanges,
+    },
+    /// Give a show tags, which are folders in the tui and in OPML files
+    Tag {
+        /// The show, by its url or name
+        show: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+        /// Take the tags away instead
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Subscribe to every show in an OPML file, keeping its folders as tags,
+    /// use - to read from stdin
+    ImportOpml { file: PathBuf },
+    /// Write all subscriptions as OPML, to stdout if no file is given
+    ExportOpml {
+        file: Option<PathBuf>,
+        /// Keep secrets in feed urls, such as `?auth=` tokens, instead of
+        /// hiding them
+        #[arg(long)]
+        include_secrets: bool,
+    },
+    /// Download episodes
+    Download {
+        #[arg(required = true)]
+        episodes: Vec<String>,
+        /// Directory to download into, instead of the one in the config
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+    /// Save, remove and list smart playlists
+    #[command(subcommand)]
+    Playlist(Playlist),
+    /// Bookmark moments in episodes, list them and export them
+    #[command(subcommand)]
+    Bookmark(Bookmarks),
+    /// Set, remove and list the credentials of private feeds, which are kept
+    /// apart from the library
+    #[command(subcommand)]
+    Credentials(CredentialsCommand),
+    /// Sync subscriptions, progress and the queue with the sync backends in the config
+    Sync,
+    /// Show how much you have listened
+    Stats,
+    /// Write every play session, to stdout if no file is given
+    ExportHistory {
+        file: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = HistoryFormat::Csv)]
+        format: HistoryFormat,
+    },
+    /// Control the player in a running tui
+    #[command(subcommand)]
+    Ctl(Ctl),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Ctl {
+    /// Show what is playing and where it is up to
+    Status,
+    /// List the episodes in the queue
+    Queue,
+    /// Play an episode, or resume if none is given
+    Play { episode: Option<String> },
+    /// Pause playback
+    Pause,
+    /// Pause, or resume if paused
+    Toggle,
+    /// Stop playing the episode
+    Stop,
+    /// Play the next episode in the queue
+    Next,
+    /// Go back to the start of the episode
+    Previous,
+    /// Jump to a time, as seconds, mm:ss or hh:mm:ss, or by a time starting
+    /// with + or -
+    Seek {
+        #[arg(allow_hyphen_values = true)]
+        time: String,
+    },
+    /// Set the playback speed
+    Speed { speed: f64 },
+    /// Add an episode to the end of the queue
+    Enqueue { episode: String },
+    /// Subscribe to a show by its feed url
+    Add { url: String },
+    /// Print every change as it happens, until the tui closes
+    Watch,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Playlist {
+    /// Save a playlist, replacing any with the same name, such as
+    /// `undersea playlist add "short news" "unplayed duration<30m tag:news"`
+    Add { name: String, query: String },
+    /// Remove a saved playlist
+    Remove { name: String },
+    /// List saved playlists
+    List,
+    /// List the episodes in a saved playlist
+    Episodes {
+        name: String,
+        /// Treat the name as a query instead, without saving it
+        #[arg(long)]
+        query: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Bookmarks {
+    /// Bookmark a moment in an episode, at a time given as seconds, mm:ss or
+    /// hh:mm:ss
+    Add {
+        episode: String,
+        time: String,
+        /// Where the bookmark ends, to make it a clip
+        #[arg(long)]
+        end: Option<String>,
+        /// What the moment is about
+        #[arg(long, default_value = "")]
+        note: String,
+    },
+    /// Remove a bookmark, by its number in `undersea bookmark list`
+    Remove { episode: String, number: usize },
+    /// List the bookmarks in an episode, or in every episode if none is given
+    List { episode: Option<String> },
+    /// Write every bookmark as Markdown, to stdout if no file is given
+    Export { file: Option<PathBuf> },
+    /// Write the audio of a clip from a downloaded episode, by its number in
+    /// `undersea bookmark list`, in the format the file extension asks for
+    Clip {
+        episode: String,
+        number: usize,
+        file: PathBuf,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CredentialsCommand {
+    /// Set the credentials of a show, replacing any it had
+    Set {
+        /// The show, by its url or name
+        show: String,
+        #[command(flatten)]
+        credentials: CredentialArgs,
+    },
+    /// Remove the credentials of a show
+    Remove {
+        /// The show, by its url or name
+        show: String,
+    },
+    /// List the shows that have credentials, without their secrets
+    List,
+}
+
+/// Credentials for a private feed.
+#[derive(Debug, clap::Args)]
+pub struct CredentialArgs {
+    /// Username for http basic auth, the password is read from stdin if
+    /// --password is not given
+    #[arg(long, conflicts_with = "bearer")]
+    username: Option<String>,
+    #[arg(long, requires = "username")]
+    password: Option<String>,
+    /// Token to send as `Authorization: Bearer`
+    #[arg(long)]
+    bearer: Option<String>,
+    /// Header to send with every request, as "Name: value", can be given
+    /// more than once
+    #[arg(long = "header", value_name = "HEADER")]
+    headers: Vec<String>,
+    /// The whole feed url is a secret, so it is never shown or synced
+    #[arg(long)]
+    private_url: bool,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum HistoryFormat {
+    Csv,
+    Json,
+}
+
+/// Changes to make to a shows settings, anything not given is left alone.
+#[derive(Debug, clap::Args)]
+pub struct SettingsChanges {
+    /// Playback speed, or "default" to use the speed from the config
+    #[arg(long)]
+    speed: Option<String>,
+    /// How much of the start of each episode to skip, as seconds, mm:ss or hh:mm:ss
+    #[arg(long)]
+    skip_intro: Option<String>,
+    /// How much of the end of each episode to skip, as seconds, mm:ss or hh:mm:ss
+    #[arg(long)]
+    skip_outro: Option<String>,
+    /// Which episodes are listed first
+    #[arg(long)]
+    sort: Option<Sort>,
+    /// When to download episodes: never, new, or latest:<count>
+    #[arg(long)]
+    auto_download: Option<String>,
+    /// If new episodes are added to the queue
+    #[arg(long)]
+    queue_new: Option<bool>,
+    /// If played episodes are hidden
+    #[arg(long)]
+    hide_played: Option<bool>,
+    /// If long silences are skipped, or "default" to use the config
+    #[arg(long)]
+    skip_silence: Option<String>,
+    /// If loudness is normalised, or "default" to use the config
+    #[arg(long)]
+    normalize: Option<String>,
+    /// If audio is mixed down to mono, or "default" to use the config
+    #[arg(long)]
+    mono: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Sort {
+    Oldest,
+    Newest,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum List {
+    /// List all shows
+    Shows {
+        /// Only list shows with this tag
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// List episodes, from every show unless one is given
+    Episodes {
+        /// Only list episodes from this show, by its url or name
+        #[arg(long)]
+        show: Option<String>,
+        /// Only list episodes from shows with this tag
+        #[arg(long, conflicts_with = "show")]
+        tag: Option<String>,
+        /// Only list episodes that have not been played
+        #[arg(long)]
+        unplayed: bool,
+    },
+    /// List the episodes in the queue
+    Queue,
+    /// List every tag, with how many shows have it
+    Tags,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match commands::run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err:#}");
+            ExitCode::from(exit_code(&err))
+        }
+    }
+}
+
+fn exit_code(err: &anyhow::Error) -> u8 {
+    for cause in err.chain() {
+        match cause.downcast_ref::<ControlError>() {
+            Some(ControlError::NotRunning(_)) => return exit::NOT_RUNNING,
+            Some(ControlError::Rpc(err)) if err.code == RpcError::NOT_FOUND => {
+                return exit::NOT_FOUND;
+            }
+            _ => {}
+        }
+        if let Some(
+            LibraryError::ShowNotFound(_)
+            | LibraryError::EpisodeNotFound(_)
+            | LibraryError::PlaylistNotFound(_)
+            | LibraryError::BookmarkNotFound(_),
+        ) = cause.downcast_ref::<LibraryError>()
+        {
+            return exit::NOT_FOUND;
+        }
+        if let Some(err) = cause.downcast_ref::<DownloadError>() {
+            match err {
+                DownloadError::EpisodeNotFound(_) => return exit::NOT_FOUND,
+                DownloadError::NetworkError(_) => return exit::FEED,
+                DownloadError::IoError(_) => {}
+            }
+        }
+        if let Some(ConfigError::InvalidValue { .. } | ConfigError::ParseError(_)) =
+            cause.downcast_ref::<ConfigError>()
+        {
+            return exit::CONFIG;
+        }
+        if cause.downcast_ref::<SyncError>().is_some() {
+            return exit::SYNC;
+        }
+        if cause.downcast_ref::<FeedError>().is_some() {
+            return exit::FEED;
+        }
+    }
+    exit::FAILURE
+}
@@ -0,0 +1,666 @@
+//! Printing results, either as plain text for people or json for scripts.
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use undersea_lib::{
+    AutoDownload, Episode, RefreshSummary, Show, ShowSettings, Shows, SortOrder,
+    control::{EpisodeSummary, Event, NowPlaying},
+    credentials::Auth,
+    history::Stats,
+    player::{Status, format_time},
+    playlist::SmartPlaylist,
+    sync::SyncReport,
+};
+
+#[derive(Serialize)]
+struct ShowOutput<'a> {
+    name: &'a str,
+    /// With any secrets hidden
+    url: String,
+    episodes: usize,
+    unplayed: usize,
+    last_upload: &'a DateTime<Utc>,
+    last_checked: &'a DateTime<Utc>,
+    tags: &'a [String],
+    /// If the feed has been missing for long enough that it is probably gone
+    dead: bool,
+}
+
+impl<'a> ShowOutput<'a> {
+    fn new(shows: &Shows, show: &'a Show) -> Self {
+        let episodes = show.episodes();
+        Self {
+            name: show.name(),
+            url: shows.redact(show.url()),
+            episodes: episodes.len(),
+            unplayed: episodes.iter().filter(|ep| !ep.finished()).count(),
+            last_upload: show.last_upload(),
+            last_checked: show.last_check(),
+            tags: show.tags(),
+            dead: show.is_dead(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct EpisodeOutput<'a> {
+    id: &'a str,
+    show: &'a str,
+    title: &'a str,
+    date: &'a DateTime<Utc>,
+    finished: bool,
+    /// Seconds into the episode where playback will resume
+    resume_time: f64,
+    /// Length of the episode in seconds, if it is known
+    duration: Option<f64>,
+    media_url: &'a str,
+    download_path: Option<&'a PathBuf>,
+}
+
+impl<'a> EpisodeOutput<'a> {
+    fn new(show: &'a Show, episode: &'a Episode) -> Self {
+        Self {
+            id: episode.id(),
+            show: show.name(),
+            title: episode.title(),
+            date: episode.date(),
+            finished: episode.finished(),
+            resume_time: episode.resume_time().as_secs_f64(),
+            duration: episode.duration().map(|duration| duration.as_secs_f64()),
+            media_url: episode.media_url(),
+            download_path: episode.download_path(),
+        }
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Urls have their secrets hidden.
+pub fn shows(library: &Shows, shows: &[&Show], json: bool) -> Result<()> {
+    let shows = shows
+        .iter()
+        .map(|show| ShowOutput::new(library, show))
+        .collect::<Vec<_>>();
+    if json {
+        return print_json(&shows);
+    }
+
+    for show in shows {
+        let dead = if show.dead { " (gone)" } else { "" };
+        println!(
+            "{}{dead}\t{}/{} unplayed\t{}\t{}",
+            show.name,
+            show.unplayed,
+            show.episodes,
+            show.url,
+            show.tags.join(", ")
+        );
+    }
+    Ok(())
+}
+
+pub fn tags(shows: &Shows, json: bool) -> Result<()> {
+    #[derive(Serialize)]
+    struct TagOutput<'a> {
+        tag: &'a str,
+        shows: usize,
+    }
+
+    let tags = shows
+        .tags()
+        .into_iter()
+        .map(|tag| TagOutput {
+            tag,
+            shows: shows.shows_tagged(tag).len(),
+        })
+        .collect::<Vec<_>>();
+    if json {
+        return print_json(&tags);
+    }
+
+    for tag in tags {
+        println!("{}\t{} shows", tag.tag, tag.shows);
+    }
+    Ok(())
+}
+
+pub fn show_tags(show: &Show, json: bool) -> Result<()> {
+    if json {
+        return print_json(&show.tags());
+    }
+
+    println!("{}\t{}", show.name(), show.tags().join(", "));
+    Ok(())
+}
+
+pub fn episodes(shows: &[&Show], unplayed_only: bool, json: bool) -> Result<()> {
+    let episodes = shows
+        .iter()
+        .flat_map(|show| {
+            show.episodes()
+                .into_iter()
+                .filter(|ep| !unplayed_only || !ep.finished())
+                .map(|ep| EpisodeOutput::new(show, ep))
+        })
+        .collect::<Vec<_>>();
+    if json {
+        return print_json(&episodes);
+    }
+
+    for episode in episodes {
+        let progress = if episode.finished {
+            "played".to_string()
+        } else {
+            format_time(Duration::from_secs_f64(episode.resume_time))
+        };
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            episode.id,
+            episode.date.format("%Y-%m-%d"),
+            progress,
+            episode.show,
+            episode.title
+        );
+    }
+    Ok(())
+}
+
+pub fn added(shows: &Shows, urls: &[String], json: bool) -> Result<()> {
+    let added = urls
+        .iter()
+        .filter_map(|url| shows.find_show(url))
+        .map(|show| ShowOutput::new(shows, show))
+        .collect::<Vec<_>>();
+    if json {
+        return print_json(&added);
+    }
+
+    for show in added {
+        println!("added {} ({} episodes)", show.name, show.episodes);
+    }
+    Ok(())
+}
+
+pub fn removed(shows: &Shows, show: &Show, json: bool) -> Result<()> {
+    if json {
+        return print_json(&ShowOutput::new(shows, show));
+    }
+
+    println!("removed {}", show.name());
+    Ok(())
+}
+
+/// Urls in the summary have their secrets hidden.
+pub fn refreshed(
+    shows: &Shows,
+    summary: &RefreshSummary,
+    downloads: &[(String, PathBuf)],
+    json: bool,
+) -> Result<()> {
+    #[derive(Serialize)]
+    struct Failed {
+        url: String,
+        error: String,
+    }
+
+    #[derive(Serialize)]
+    struct Moved {
+        from: String,
+        to: String,
+    }
+
+    #[derive(Serialize)]
+    struct Refreshed<'a> {
+        new_episodes: usize,
+        failed: Vec<Failed>,
+        moved: Vec<Moved>,
+        downloaded: Vec<Downloaded<'a>>,
+    }
+
+    if json {
+        return print_json(&Refreshed {
+            new_episodes: summary.new_episodes,
+            failed: summary
+                .failed
+                .iter()
+                .map(|(url, err)| Failed {
+                    url: shows.redact(url),
+                    error: err.to_string(),
+                })
+                .collect(),
+            moved: summary
+                .moved
+                .iter()
+                .map(|(from, to)| Moved {
+                    from: shows.redact(from),
+                    to: shows.redact(to),
+                })
+                .collect(),
+            downloaded: downloads
+                .iter()
+                .map(|(id, path)| Downloaded { id, path })
+                .collect(),
+        });
+    }
+
+    println!("{} new episodes", summary.new_episodes);
+    for (from, to) in &summary.moved {
+        println!("moved {} to {}", shows.redact(from), shows.redact(to));
+    }
+    for (_, path) in downloads {
+        println!("downloaded {}", path.display());
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Downloaded<'a> {
+    id: &'a str,
+    path: &'a PathBuf,
+}
+
+pub fn synced(report: &SyncReport, json: bool) -> Result<()> {
+    #[derive(Serialize)]
+    struct Failed<'a> {
+        url: &'a str,
+        error: String,
+    }
+
+    #[derive(Serialize)]
+    struct Synced<'a> {
+        pushed: usize,
+        pulled: usize,
+        failed: Vec<Failed<'a>>,
+    }
+
+    let failed = report
+        .failed
+        .iter()
+        .map(|(url, err)| Failed {
+            url,
+            error: err.to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    if json {
+        return print_json(&Synced {
+            pushed: report.pushed,
+            pulled: report.pulled,
+            failed,
+        });
+    }
+
+    println!(
+        "sent {} changes, got {} changes",
+        report.pushed, report.pulled
+    );
+    for Failed { url, error } in failed {
+        eprintln!("warning: failed to add {url}: {error}");
+    }
+    Ok(())
+}
+
+pub fn downloaded(downloads: &[(String, PathBuf)], json: bool) -> Result<()> {
+    if json {
+        let downloads = downloads
+            .iter()
+            .map(|(id, path)| Downloaded { id, path })
+            .collect::<Vec<_>>();
+        return print_json(&downloads);
+    }
+
+    for (_, path) in downloads {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+pub fn queue(shows: &Shows, json: bool) -> Result<()> {
+    episode_list(&shows.queue(), json)
+}
+
+/// Episodes in a given order, from any show.
+pub fn episode_list(episodes: &[(&Show, &Episode)], json: bool) -> Result<()> {
+    let episodes = episodes
+        .iter()
+        .map(|(show, episode)| EpisodeOutput::new(show, episode))
+        .collect::<Vec<_>>();
+    if json {
+        return print_json(&episodes);
+    }
+
+    for episode in episodes {
+        println!("{}\t{}\t{}", episode.id, episode.show, episode.title);
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct BookmarkOutput<'a> {
+    episode: &'a str,
+    show: &'a str,
+    title: &'a str,
+    /// Number of the bookmark in the episode, counting from 1
+    number: usize,
+    /// Seconds into the episode where the bookmark starts
+    start: f64,
+    /// Seconds into the episode where a clip ends
+    end: Option<f64>,
+    note: &'a str,
+    created_at: &'a DateTime<Utc>,
+}
+
+/// The bookmarks in some episodes.
+pub fn bookmarks(episodes: &[(&Show, &Episode)], json: bool) -> Result<()> {
+    let bookmarks = episodes
+        .iter()
+        .flat_map(|(show, episode)| {
+            episode
+                .bookmarks()
+                .iter()
+                .enumerate()
+                .map(|(index, bookmark)| BookmarkOutput {
+                    episode: episode.id(),
+                    show: show.name(),
+                    title: episode.title(),
+                    number: index + 1,
+                    start: bookmark.start.as_secs_f64(),
+                    end: bookmark.end.map(|end| end.as_secs_f64()),
+                    note: &bookmark.note,
+                    created_at: &bookmark.created_at,
+                })
+        })
+        .collect::<Vec<_>>();
+    if json {
+        return print_json(&bookmarks);
+    }
+
+    for bookmark in bookmarks {
+        let start = format_time(Duration::from_secs_f64(bookmark.start));
+        let time = match bookmark.end {
+            Some(end) => format!("{start}-{}", format_time(Duration::from_secs_f64(end))),
+            None => start,
+        };
+        println!(
+            "{}\t{}\t{time}\t{}",
+            bookmark.episode, bookmark.number, bookmark.note
+        );
+    }
+    Ok(())
+}
+
+/// Which shows have credentials and what kind, never the secrets themselves.
+pub fn credentials(shows: &Shows, json: bool) -> Result<()> {
+    #[derive(Serialize)]
+    struct CredentialsOutput<'a> {
+        show: &'a str,
+        url: String,
+        /// `basic` or `bearer`
+        auth: Option<&'static str>,
+        username: Option<&'a str>,
+        headers: Vec<&'a str>,
+        private_url: bool,
+    }
+
+    let store = shows.credentials();
+    let list = store
+        .urls()
+        .filter_map(|url| Some((url, store.get(url)?)))
+        .map(|(url, credentials)| {
+            let (auth, username) = match &credentials.auth {
+                Some(Auth::Basic { username, .. }) => (Some("basic"), Some(username.as_str())),
+                Some(Auth::Bearer { .. }) => (Some("bearer"), None),
+                None => (None, None),
+            };
+            CredentialsOutput {
+                show: shows.find_show(url).map_or("", Show::name),
+                url: shows.redact(url),
+                auth,
+                username,
+                headers: credentials
+                    .headers
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect(),
+                private_url: credentials.private_url,
+            }
+        })
+        .collect::<Vec<_>>();
+    if json {
+        return print_json(&list);
+    }
+
+    for credentials in list {
+        let auth = match (credentials.auth, credentials.username) {
+            (Some(auth), Some(username)) => format!("{auth} {username}"),
+            (Some(auth), None) => auth.to_string(),
+            (None, _) => "-".to_string(),
+        };
+        let private = if credentials.private_url {
+            "\tprivate url"
+        } else {
+            ""
+        };
+        println!(
+            "{}\t{}\t{auth}\t{}{private}",
+            credentials.show,
+            credentials.url,
+            credentials.headers.join(", ")
+        );
+    }
+    Ok(())
+}
+
+pub fn playlists(playlists: &[SmartPlaylist], json: bool) -> Result<()> {
+    if json {
+        return print_json(&playlists);
+    }
+
+    for playlist in playlists {
+        println!("{}\t{}", playlist.name, playlist.query);
+    }
+    Ok(())
+}
+
+pub fn settings(show: &str, settings: &ShowSettings, json: bool) -> Result<()> {
+    if json {
+        return print_json(settings);
+    }
+
+    let speed = settings
+        .playback_speed
+        .map_or_else(|| "default".to_string(), |speed| format!("{speed}x"));
+    let sort = match settings.sort_order {
+        SortOrder::OldestFirst => "oldest first",
+        SortOrder::NewestFirst => "newest first",
+    };
+    let auto_download = match settings.auto_download {
+        AutoDownload::Never => "never".to_string(),
+        AutoDownload::New => "new episodes".to_string(),
+        AutoDownload::Latest(count) => format!("latest {count} unplayed"),
+    };
+
+    println!("{show}");
+    println!("  speed:         {speed}");
+    println!("  skip intro:    {}", format_time(settings.skip_intro));
+    println!("  skip outro:    {}", format_time(settings.skip_outro));
+    println!("  sort:          {sort}");
+    println!("  auto download: {auto_download}");
+    println!("  queue new:     {}", settings.add_to_queue);
+    println!("  hide played:   {}", settings.hide_played);
+    let setting = |value: Option<bool>| {
+        value.map_or_else(|| "default".to_string(), |value| value.to_string())
+    };
+    println!("  skip silence:  {}", setting(settings.skip_silence));
+    println!("  normalize:     {}", setting(settings.normalize));
+    println!("  mono:          {}", setting(settings.mono));
+    Ok(())
+}
+
+pub fn stats(shows: &Shows, stats: &Stats, json: bool) -> Result<()> {
+    #[derive(Serialize)]
+    struct ShowTotals<'a> {
+        name: &'a str,
+        url: &'a str,
+        /// Seconds spent listening
+        listened: f64,
+        sessions: usize,
+        episodes_finished: usize,
+    }
+
+    #[derive(Serialize)]
+    struct Count {
+        start: NaiveDate,
+        finished: usize,
+    }
+
+    #[derive(Serialize)]
+    struct Totals<'a> {
+        /// Seconds spent listening
+        listened: f64,
+        /// Seconds saved by playing faster than normal speed and skipping
+        /// silence
+        time_saved: f64,
+        /// Seconds of `time_saved` from skipping silence
+        silence_saved: f64,
+        sessions: usize,
+        episodes_finished: usize,
+        current_streak: u32,
+        longest_streak: u32,
+        shows: Vec<ShowTotals<'a>>,
+        weeks: Vec<Count>,
+        months: Vec<Count>,
+    }
+
+    let show_totals = stats
+        .shows
+        .iter()
+        .map(|totals| ShowTotals {
+            name: shows.find_show(&totals.show).map_or("", |show| show.name()),
+            url: &totals.show,
+            listened: totals.listened.as_secs_f64(),
+            sessions: totals.sessions,
+            episodes_finished: totals.episodes_finished,
+        })
+        .collect::<Vec<_>>();
+    let counts = |counts: &[(NaiveDate, usize)]| {
+        counts
+            .iter()
+            .map(|&(start, finished)| Count { start, finished })
+            .collect::<Vec<_>>()
+    };
+
+    if json {
+        return print_json(&Totals {
+            listened: stats.listened.as_secs_f64(),
+            time_saved: stats.time_saved.as_secs_f64(),
+            silence_saved: stats.silence_saved.as_secs_f64(),
+            sessions: stats.sessions,
+            episodes_finished: stats.episodes_finished,
+            current_streak: stats.current_streak,
+            longest_streak: stats.longest_streak,
+            shows: show_totals,
+            weeks: counts(&stats.finished_by_week),
+            months: counts(&stats.finished_by_month),
+        });
+    }
+
+    println!("listened:          {}", format_time(stats.listened));
+    println!("time saved:        {}", format_time(stats.time_saved));
+    println!("  by silence:      {}", format_time(stats.silence_saved));
+    println!("episodes finished: {}", stats.episodes_finished);
+    println!(
+        "streak:            {} days, longest {} days",
+        stats.current_streak, stats.longest_streak
+    );
+    for totals in show_totals {
+        let name = if totals.name.is_empty() {
+            totals.url
+        } else {
+            totals.name
+        };
+        println!(
+            "{}\t{} finished\t{name}",
+            format_time(Duration::from_secs_f64(totals.listened)),
+            totals.episodes_finished
+        );
+    }
+    Ok(())
+}
+
+pub fn now_playing(now_playing: &NowPlaying, json: bool) -> Result<()> {
+    if json {
+        return print_json(now_playing);
+    }
+
+    println!("{}", now_playing_line(now_playing));
+    Ok(())
+}
+
+fn now_playing_line(now_playing: &NowPlaying) -> String {
+    let status = match now_playing.status {
+        Status::Playing => "playing",
+        Status::Paused => "paused",
+        Status::Stopped => "stopped",
+    };
+    let Some(episode) = &now_playing.episode else {
+        return status.to_string();
+    };
+    let length = episode
+        .duration
+        .map(|length| format!("/{}", format_time(Duration::from_secs_f64(length))))
+        .unwrap_or_default();
+    format!(
+        "{status}\t{}{length}\t{}x\t{}\t{}\t{}",
+        format_time(Duration::from_secs_f64(now_playing.position)),
+        now_playing.speed,
+        episode.id,
+        episode.show,
+        episode.title
+    )
+}
+
+/// The queue of the running tui.
+pub fn remote_queue(episodes: &[EpisodeSummary], json: bool) -> Result<()> {
+    if json {
+        return print_json(&episodes);
+    }
+
+    for episode in episodes {
+        println!("{}\t{}\t{}", episode.id, episode.show, episode.title);
+    }
+    Ok(())
+}
+
+/// A show added by the running tui, with its `url` and `name`.
+pub fn remote_added(added: &Value, json: bool) -> Result<()> {
+    if json {
+        return print_json(added);
+    }
+
+    println!("added {}", added["name"].as_str().unwrap_or_default());
+    Ok(())
+}
+
+/// An event from `ctl watch`, json is kept to one line per event so it can
+/// be read as it arrives.
+pub fn event(event: &Event, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string(event)?);
+        return Ok(());
+    }
+
+    match event {
+        Event::Status(now_playing) => println!("{}", now_playing_line(now_playing)),
+        Event::Queue { episodes } => println!("queue\t{} episodes", episodes.len()),
+        Event::Added { name, .. } => println!("added\t{name}"),
+    }
+    Ok(())
+}
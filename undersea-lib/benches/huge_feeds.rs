@@ -0,0 +1,157 @@
+//! Peak memory and time for reading huge feeds, all at once as undersea used
+//! to, and item by item with and without a description cache.
+//!
+//! Run with `cargo bench -p undersea-lib --bench huge_feeds`.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use axum::{Router, extract::Path, routing::get};
+use common::{huge_feed, temp_dir};
+use tokio::net::TcpListener;
+use undersea_lib::{Shows, description::DescriptionCache};
+
+/// Sizes of the feeds read, in items.
+const SIZES: [usize; 3] = [1_000, 5_000, 20_000];
+
+/// How many descriptions of each show are kept in memory with a cache.
+const KEEP: usize = 20;
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, keeping count of how much is allocated.
+struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+            grow(new_size);
+        }
+        new_ptr
+    }
+}
+
+fn grow(size: usize) {
+    let current = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(current, Ordering::Relaxed);
+}
+
+/// What a run allocated, counting from when it started.
+struct Measured {
+    /// The most that was allocated at once
+    peak: usize,
+    /// What was still allocated at the end, what the result holds on to
+    kept: usize,
+    time: Duration,
+}
+
+/// Run `f`, measuring what it allocates. Its result is held until the end,
+/// so what it keeps is counted.
+async fn measure<T, F: Future<Output = T>>(f: impl FnOnce() -> F) -> Measured {
+    let start = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(start, Ordering::Relaxed);
+    let started = Instant::now();
+    let result = f().await;
+    let time = started.elapsed();
+    let measured = Measured {
+        peak: PEAK.load(Ordering::Relaxed).saturating_sub(start),
+        kept: CURRENT.load(Ordering::Relaxed).saturating_sub(start),
+        time,
+    };
+    drop(result);
+    measured
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn megabytes(bytes: usize) -> f64 {
+    bytes as f64 / 1_000_000.0
+}
+
+#[tokio::main]
+async fn main() {
+    // feeds are made before anything is measured, and served without being
+    // copied, so the server adds next to nothing
+    let feeds: &'static [&'static str] = SIZES
+        .iter()
+        .map(|items| &*huge_feed(*items).leak())
+        .collect::<Vec<_>>()
+        .leak();
+    let app = Router::new().route(
+        "/{index}",
+        get(move |Path(index): Path<usize>| async move { feeds[index] }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let dir = temp_dir("bench-descriptions");
+    println!(
+        "{:>7} {:>9}  {:<32} {:>10} {:>10} {:>9}",
+        "items", "feed", "read", "peak", "kept", "time"
+    );
+    for (index, (items, feed)) in SIZES.iter().zip(feeds).enumerate() {
+        let url = format!("http://{addr}/{index}");
+
+        // the feed and the channel made of it, before any episodes are made
+        // from it, so the most the old way could have needed is more than this
+        let whole = measure(|| async {
+            let bytes = reqwest::get(&url).await.unwrap().bytes().await.unwrap();
+            let channel = rss::Channel::read_from(&bytes[..]).unwrap();
+            (bytes, channel)
+        })
+        .await;
+        let streamed = measure(|| async {
+            let mut shows = Shows::default();
+            shows.add(url.as_str()).await.unwrap();
+            shows
+        })
+        .await;
+        let cached = measure(|| async {
+            let mut shows = Shows::default();
+            shows.set_description_cache(DescriptionCache::new(dir.clone(), KEEP));
+            shows.add(url.as_str()).await.unwrap();
+            shows
+        })
+        .await;
+
+        for (name, measured) in [
+            ("whole feed, then rss::Channel".to_string(), whole),
+            ("item by item".to_string(), streamed),
+            (format!("item by item, {KEEP} descriptions"), cached),
+        ] {
+            println!(
+                "{items:>7} {:>7.1}MB  {name:<32} {:>8.1}MB {:>8.1}MB {:>7}ms",
+                megabytes(feed.len()),
+                megabytes(measured.peak),
+                megabytes(measured.kept),
+                measured.time.as_millis(),
+            );
+        }
+    }
+    let _ = std::fs::remove_dir_all(dir);
+}
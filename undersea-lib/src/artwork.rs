@@ -0,0 +1,145 @@
+//! Downloading and caching the artwork of shows and episodes, scaled to the
+//! size it is shown at.
+//!
+//! Images are kept in the cache directory under a name made from their url,
+//! the file as it was downloaded along with a png for each size it has been
+//! scaled to. Nothing is ever fetched again once it is cached, feeds give new
+//! artwork a new url.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use image::{ImageFormat, ImageReader, RgbaImage, imageops::FilterType};
+
+use crate::{ArtworkError, http::HttpClient};
+
+/// A directory of downloaded artwork, see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct ArtworkCache {
+    dir: PathBuf,
+    client: HttpClient,
+}
+
+impl ArtworkCache {
+    /// Use `dir` for the cache, such as [`crate::Config::artwork_dir`]. It is
+    /// created when something is first cached.
+    #[must_use]
+    pub fn new(dir: PathBuf, client: impl Into<HttpClient>) -> Self {
+        Self {
+            dir,
+            client: client.into(),
+        }
+    }
+
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path to the artwork as it was downloaded, fetching it if it is not
+    /// cached yet.
+    ///
+    /// # Errors
+    /// Fails if the artwork could not be fetched, is not an image that can be
+    /// read, or could not be written to the cache.
+    pub async fn original(&self, url: &str) -> Result<PathBuf, ArtworkError> {
+        let path = self.dir.join(cache_key(url));
+        if path.exists() {
+            return Ok(path);
+        }
+
+        let bytes = self
+            .client
+            .send(self.client.get(url))
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        // only cache what can be shown, not an error page served with a 200
+        image::load_from_memory(&bytes)?;
+        write_atomic(&path, &bytes)?;
+        Ok(path)
+    }
+
+    /// Path to the artwork scaled to fit in a `size` pixel square, as a png.
+    /// Artwork smaller than that is not scaled up.
+    ///
+    /// # Errors
+    /// Fails in the same ways as [`ArtworkCache::original`].
+    pub async fn scaled(&self, url: &str, size: u32) -> Result<PathBuf, ArtworkError> {
+        let path = self.dir.join(format!("{}-{size}.png", cache_key(url)));
+        if path.exists() {
+            return Ok(path);
+        }
+
+        // the original has no extension, so its format has to be guessed
+        let original = ImageReader::open(self.original(url).await?)?
+            .with_guessed_format()?
+            .decode()?;
+        let scaled = if original.width() > size || original.height() > size {
+            original.resize(size, size, FilterType::Lanczos3)
+        } else {
+            original
+        };
+        let mut png = io::Cursor::new(Vec::new());
+        scaled.write_to(&mut png, ImageFormat::Png)?;
+        write_atomic(&path, png.get_ref())?;
+        Ok(path)
+    }
+
+    /// The artwork scaled as in [`ArtworkCache::scaled`], decoded to pixels.
+    ///
+    /// # Errors
+    /// Fails in the same ways as [`ArtworkCache::original`].
+    pub async fn load(&self, url: &str, size: u32) -> Result<RgbaImage, ArtworkError> {
+        let path = self.scaled(url, size).await?;
+        Ok(image::open(path)?.into_rgba8())
+    }
+
+    /// Remove everything in the cache.
+    ///
+    /// # Errors
+    /// Fails if the directory exists but could not be removed.
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+/// A file name for a url, the 64 bit FNV-1a hash of it in hex. This has to
+/// stay the same between versions, so the hasher from std is not used.
+pub(crate) fn cache_key(url: &str) -> String {
+    let hash = url.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// Write a whole file, so that a crash never leaves half of one in the cache.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial_path = path.with_extension("part");
+    fs::write(&partial_path, contents)?;
+    fs::rename(partial_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_stable() {
+        assert_eq!(cache_key(""), "cbf29ce484222325");
+        assert_eq!(cache_key("a"), "af63dc4c8601ec8c");
+        assert_ne!(
+            cache_key("https://example.com/a.jpg"),
+            cache_key("https://example.com/b.jpg")
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Moments in episodes worth coming back to, like a recommendation or a
+//! quote, and clips of them.
+
+use std::{
+    fmt::Write,
+    path::Path,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{ClipError, Episode, LibraryError, Shows, player::format_time, sync::Change};
+
+/// A moment in an episode, or a clip of it if it has an end.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bookmark {
+    /// Where the moment starts in the episode
+    pub start: Duration,
+    /// Where the clip ends, [`None`] for a single moment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<Duration>,
+    pub note: String,
+    /// When the bookmark was made, which also tells bookmarks apart across
+    /// devices
+    pub created_at: DateTime<Utc>,
+}
+
+impl Bookmark {
+    /// A bookmark made now.
+    #[must_use]
+    pub fn new(start: Duration, end: Option<Duration>, note: &str) -> Self {
+        Self {
+            start,
+            end,
+            note: note.trim().to_string(),
+            created_at: Utc::now(),
+        }
+    }
+}
+
+impl Episode {
+    /// Add a bookmark, or replace the one made at the same time. Returns if
+    /// anything changed.
+    pub(crate) fn put_bookmark(&mut self, bookmark: Bookmark) -> bool {
+        if let Some(existing) = self
+            .bookmarks
+            .iter_mut()
+            .find(|existing| existing.created_at == bookmark.created_at)
+        {
+            if *existing == bookmark {
+                return false;
+            }
+            *existing = bookmark;
+        } else {
+            self.bookmarks.push(bookmark);
+        }
+        self.bookmarks
+            .sort_by_key(|bookmark| (bookmark.start, bookmark.created_at));
+        true
+    }
+
+    /// Take out the bookmark made at `created_at`, if there is one.
+    pub(crate) fn take_bookmark(&mut self, created_at: &DateTime<Utc>) -> Option<Bookmark> {
+        let index = self
+            .bookmarks
+            .iter()
+            .position(|bookmark| bookmark.created_at == *created_at)?;
+        Some(self.bookmarks.remove(index))
+    }
+}
+
+impl Shows {
+    /// Bookmark a moment in an episode.
+    ///
+    /// # Errors
+    /// Fails if there is no episode with that id, or the bookmark ends before
+    /// it starts.
+    pub fn add_bookmark(&mut self, id: &str, bookmark: Bookmark) -> Result<(), LibraryError> {
+        if bookmark.end.is_some_and(|end| end <= bookmark.start) {
+            return Err(LibraryError::InvalidBookmark);
+        }
+        self.episode_by_id_mut(id)?.put_bookmark(bookmark.clone());
+        self.last_change = Utc::now();
+
+        let (show, episode) = self.sync_ref(id)?;
+        self.record(Change::Bookmark {
+            show,
+            episode,
+            bookmark,
+        });
+        Ok(())
+    }
+
+    /// Remove the bookmark made at `created_at` from an episode.
+    ///
+    /// # Errors
+    /// Fails if there is no episode with that id, or it has no such bookmark.
+    pub fn remove_bookmark(
+        &mut self,
+        id: &str,
+        created_at: &DateTime<Utc>,
+    ) -> Result<Bookmark, LibraryError> {
+        let removed = self
+            .episode_by_id_mut(id)?
+            .take_bookmark(created_at)
+            .ok_or(LibraryError::BookmarkNotFound(*created_at))?;
+        self.last_change = Utc::now();
+
+        let (show, episode) = self.sync_ref(id)?;
+        self.record(Change::RemoveBookmark {
+            show,
+            episode,
+            created_at: *created_at,
+        });
+        Ok(removed)
+    }
+
+    /// Every bookmark as Markdown, under headings for their shows and
+    /// episodes.
+    #[must_use]
+    pub fn bookmarks_markdown(&self) -> String {
+        let mut markdown = "# Bookmarks\n".to_string();
+        for show in &self.shows {
+            let mut bookmarked = show
+                .episodes
+                .iter()
+                .filter(|episode| !episode.bookmarks.is_empty())
+                .peekable();
+            if bookmarked.peek().is_none() {
+                continue;
+            }
+            let _ = write!(markdown, "\n## {}\n", show.name());
+            for episode in bookmarked {
+                let _ = write!(markdown, "\n### {}\n\n", episode.title);
+                for bookmark in &episode.bookmarks {
+                    let time = match bookmark.end {
+                        Some(end) => {
+                            format!("{}–{}", format_time(bookmark.start), format_time(end))
+                        }
+                        None => format_time(bookmark.start),
+                    };
+                    // no trailing space for bookmarks without a note
+                    let line = format!("- **{time}** {}", bookmark.note);
+                    let _ = writeln!(markdown, "{}", line.trim_end());
+                }
+            }
+        }
+        markdown
+    }
+}
+
+/// Write the audio of a clip from a downloaded episode to `out`, in the
+/// format its extension asks for. This is done by mpv, which has to be
+/// installed and on the `PATH`.
+///
+/// # Errors
+/// Fails if the bookmark has no end, the episode has not been downloaded, or
+/// mpv could not be run or failed.
+pub fn export_clip(episode: &Episode, bookmark: &Bookmark, out: &Path) -> Result<(), ClipError> {
+    let end = bookmark.end.ok_or(ClipError::NoEnd)?;
+    let file = episode
+        .download_path()
+        .ok_or_else(|| ClipError::NotDownloaded(episode.id.clone()))?;
+
+    let output = Command::new("mpv")
+        .args(["--no-config", "--no-video", "--really-quiet"])
+        .arg(format!("--start={}", bookmark.start.as_secs_f64()))
+        .arg(format!("--end={}", end.as_secs_f64()))
+        .arg(format!("--o={}", out.display()))
+        .arg("--")
+        .arg(file)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()?;
+    if !output.status.success() {
+        return Err(ClipError::Mpv(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(())
+}
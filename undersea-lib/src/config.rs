@@ -0,0 +1,632 @@
+//! User settings, and where undersea keeps its files.
+//!
+//! Settings are read from `config.toml` in the config directory, which
+//! follows the [XDG base directory spec](https://specifications.freedesktop.org/basedir-spec/latest/).
+//! A different config file can be used by setting `UNDERSEA_CONFIG`.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use reqwest::Proxy;
+
+use crate::{
+    ConfigError,
+    control::SOCKET_FILE_NAME,
+    credentials::CREDENTIALS_FILE_NAME,
+    description::DescriptionCache,
+    history::HISTORY_FILE_NAME,
+    http::{
+        DEFAULT_CONNECT_TIMEOUT, DEFAULT_READ_TIMEOUT, DEFAULT_USER_AGENT, HttpClient, HttpOptions,
+        Retry,
+    },
+    library::LIBRARY_FILE_NAME,
+    player::{SmartResume, dsp::AudioSettings},
+    sync::{folder::FolderConfig, gpodder::GpodderConfig, server::ServerConfig},
+};
+
+/// Environment variable that points to a config file to use instead of the
+/// default one.
+pub const CONFIG_ENV: &str = "UNDERSEA_CONFIG";
+
+/// Name of the config file inside the config directory.
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// The directories undersea keeps its files in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dirs {
+    /// Settings and themes, `$XDG_CONFIG_HOME/undersea`
+    pub config: PathBuf,
+    /// The library and downloads, `$XDG_DATA_HOME/undersea`
+    pub data: PathBuf,
+    /// Anything that can be fetched again, `$XDG_CACHE_HOME/undersea`
+    pub cache: PathBuf,
+    /// Sockets, `$XDG_RUNTIME_DIR/undersea`, or the cache directory if there
+    /// is no runtime directory
+    pub runtime: PathBuf,
+}
+
+impl Dirs {
+    /// Work out the directories from the `XDG_*` environment variables,
+    /// falling back to the defaults in `$HOME`.
+    ///
+    /// # Errors
+    /// Returns [`None`] if a directory is not set and `HOME` is not set either.
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        let cache = xdg_dir("XDG_CACHE_HOME", ".cache")?.join("undersea");
+        // the runtime directory has no fallback in $HOME
+        let runtime = env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .map_or_else(|| cache.clone(), |dir| dir.join("undersea"));
+        Some(Self {
+            config: xdg_dir("XDG_CONFIG_HOME", ".config")?.join("undersea"),
+            data: xdg_dir("XDG_DATA_HOME", ".local/share")?.join("undersea"),
+            cache,
+            runtime,
+        })
+    }
+}
+
+/// Read an XDG directory variable, relative paths are not allowed by the spec
+/// and are ignored.
+fn xdg_dir(var: &str, home_fallback: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(home_fallback)))
+}
+
+/// Settings shared by every undersea frontend.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub dirs: Dirs,
+    /// Where episodes are downloaded to
+    pub download_dir: PathBuf,
+    /// How long to wait before checking a show for new episodes again
+    pub refresh_interval: Duration,
+    /// How many feeds or downloads to fetch at once
+    pub concurrency: usize,
+    /// How far to jump forward when skipping
+    pub skip_forward: Duration,
+    /// How far to jump back when skipping
+    pub skip_back: Duration,
+    /// Playback speed for shows that do not set their own
+    pub playback_speed: f32,
+    /// How long a sleep timer fades out for before pausing
+    pub sleep_fade: Duration,
+    /// How far to go back when a sleep timer pauses
+    pub sleep_rewind: Duration,
+    /// How audio is processed while playing, the `[audio]` table, shows can
+    /// turn each stage on or off for themselves
+    pub audio: AudioSettings,
+    /// How far to go back when playing after a pause, the `[smart_resume]`
+    /// table, [`None`] if it is turned off
+    pub smart_resume: Option<SmartResume>,
+    /// `User-Agent` header sent with every request
+    pub user_agent: String,
+    /// Proxy url to send every request through, http or socks
+    pub proxy: Option<String>,
+    /// How long to wait for a connection to a host
+    pub connect_timeout: Duration,
+    /// How long to wait for each read from a host
+    pub read_timeout: Duration,
+    /// How many times to try a request again when it fails in a way that may
+    /// pass, see [`Retry`]
+    pub retries: u32,
+    /// Keep credentials for private feeds in the system keyring rather than
+    /// the credentials file
+    pub keyring: bool,
+    /// Sync feed urls with their secrets, so other devices can subscribe to
+    /// private feeds by themselves
+    pub sync_secret_urls: bool,
+    /// How many episodes of each show keep their description in memory, the
+    /// rest are read from the cache when needed, [`None`] keeps them all
+    pub descriptions_in_memory: Option<usize>,
+    /// Name of a built-in theme, or the path to a theme file
+    pub theme: Option<String>,
+    /// How the tui draws artwork: `auto`, `kitty`, `sixel`, `iterm2`,
+    /// `halfblocks` or `off`
+    pub artwork: Option<String>,
+    /// Server to sync subscriptions and progress with, the `[gpodder]` table
+    pub gpodder: Option<GpodderConfig>,
+    /// Folder to sync through, the `[sync_folder]` table
+    pub sync_folder: Option<FolderConfig>,
+    /// `undersea-server` to sync with, the `[server]` table
+    pub server: Option<ServerConfig>,
+    warnings: Vec<String>,
+}
+
+impl Config {
+    /// Default settings, keeping files in `dirs`.
+    #[must_use]
+    pub fn new(dirs: Dirs) -> Self {
+        Self {
+            download_dir: dirs.data.join("downloads"),
+            dirs,
+            refresh_interval: Duration::from_hours(1),
+            concurrency: 4,
+            skip_forward: Duration::from_secs(30),
+            skip_back: Duration::from_secs(10),
+            playback_speed: 1.0,
+            sleep_fade: Duration::from_secs(30),
+            sleep_rewind: Duration::ZERO,
+            audio: AudioSettings::default(),
+            smart_resume: Some(SmartResume::default()),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            proxy: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            retries: Retry::default().retries,
+            keyring: false,
+            sync_secret_urls: false,
+            descriptions_in_memory: None,
+            theme: None,
+            artwork: None,
+            gpodder: None,
+            sync_folder: None,
+            server: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Load the config file, from `path` if one is given, otherwise from
+    /// `UNDERSEA_CONFIG`, otherwise from the config directory. It is fine for
+    /// the default config file to not exist, the default settings are used.
+    ///
+    /// # Errors
+    /// Fails if the directories could not be worked out, the file could not be
+    /// read, or a setting has an invalid value.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let dirs = Dirs::from_env().ok_or(ConfigError::NoHome)?;
+
+        let explicit_path = path
+            .map(Path::to_path_buf)
+            .or_else(|| env::var_os(CONFIG_ENV).map(PathBuf::from));
+
+        let path = if let Some(path) = explicit_path {
+            path
+        } else {
+            let path = dirs.config.join(CONFIG_FILE_NAME);
+            if !path.exists() {
+                return Ok(Self::new(dirs));
+            }
+            path
+        };
+
+        let contents = fs::read_to_string(&path).map_err(|source| ConfigError::IoError {
+            path: path.clone(),
+            source,
+        })?;
+        Self::from_toml(&contents, dirs)
+    }
+
+    /// Read settings from the contents of a config file. Unknown keys are
+    /// ignored, and can be seen with [`Config::warnings`].
+    ///
+    /// # Errors
+    /// Fails if the contents are not valid toml, or a setting has an invalid value.
+    pub fn from_toml(contents: &str, dirs: Dirs) -> Result<Self, ConfigError> {
+        let table: toml::Table = toml::from_str(contents)?;
+        let mut config = Self::new(dirs);
+
+        for (key, value) in table {
+            match key.as_str() {
+                "download_dir" => config.download_dir = expand_home(&parse::<String>(&key, value)?),
+                "refresh_interval" => {
+                    config.refresh_interval =
+                        Duration::from_mins(positive(&key, parse(&key, value)?)?);
+                }
+                "concurrency" => {
+                    config.concurrency = usize::try_from(positive(&key, parse(&key, value)?)?)
+                        .map_err(|_| invalid(&key, "is too large"))?;
+                }
+                "skip_forward" => {
+                    config.skip_forward = Duration::from_secs(positive(&key, parse(&key, value)?)?);
+                }
+                "skip_back" => {
+                    config.skip_back = Duration::from_secs(positive(&key, parse(&key, value)?)?);
+                }
+                "playback_speed" => {
+                    let speed: f64 = parse(&key, value)?;
+                    if !(0.25..=4.0).contains(&speed) {
+                        return Err(invalid(&key, "must be between 0.25 and 4"));
+                    }
+                    #[allow(clippy::cast_possible_truncation)]
+                    let speed = speed as f32;
+                    config.playback_speed = speed;
+                }
+                "sleep_fade" => config.sleep_fade = Duration::from_secs(parse(&key, value)?),
+                "sleep_rewind" => config.sleep_rewind = Duration::from_secs(parse(&key, value)?),
+                "audio" => config.audio = parse_audio(&key, value)?,
+                "smart_resume" => config.smart_resume = parse_smart_resume(&key, value)?,
+                "user_agent" => {
+                    let user_agent: String = parse(&key, value)?;
+                    if user_agent.trim().is_empty() {
+                        return Err(invalid(&key, "must not be empty"));
+                    }
+                    config.user_agent = user_agent;
+                }
+                "proxy" => {
+                    let proxy: String = parse(&key, value)?;
+                    Proxy::all(&proxy).map_err(|err| invalid(&key, &err.to_string()))?;
+                    config.proxy = Some(proxy);
+                }
+                "connect_timeout" => {
+                    config.connect_timeout =
+                        Duration::from_secs(positive(&key, parse(&key, value)?)?);
+                }
+                "read_timeout" => {
+                    config.read_timeout = Duration::from_secs(positive(&key, parse(&key, value)?)?);
+                }
+                "retries" => {
+                    let retries: i64 = parse(&key, value)?;
+                    config.retries = u32::try_from(retries)
+                        .ok()
+                        .filter(|retries| *retries <= 10)
+                        .ok_or_else(|| invalid(&key, "must be between 0 and 10"))?;
+                }
+                "keyring" => config.keyring = parse(&key, value)?,
+                "sync_secret_urls" => config.sync_secret_urls = parse(&key, value)?,
+                "descriptions_in_memory" => {
+                    config.descriptions_in_memory = Some(
+                        usize::try_from(positive(&key, parse(&key, value)?)?)
+                            .map_err(|_| invalid(&key, "is too large"))?,
+                    );
+                }
+                "theme" => config.theme = Some(parse(&key, value)?),
+                "artwork" => config.artwork = Some(parse(&key, value)?),
+                "gpodder" => config.gpodder = Some(parse(&key, value)?),
+                "sync_folder" => {
+                    let mut folder: FolderConfig = parse(&key, value)?;
+                    let device = &folder.device;
+                    if device.is_empty() || device.contains(['/', '\\', '.']) {
+                        return Err(invalid(&key, "device must be a name without / or ."));
+                    }
+                    folder.path = expand_home(&folder.path.to_string_lossy());
+                    config.sync_folder = Some(folder);
+                }
+                "server" => config.server = Some(parse(&key, value)?),
+                _ => config
+                    .warnings
+                    .push(format!("unknown config key \"{key}\", it will be ignored")),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Problems with the config file that were not bad enough to fail loading.
+    #[must_use]
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Where the library file is kept.
+    #[must_use]
+    pub fn library_path(&self) -> PathBuf {
+        self.dirs.data.join(LIBRARY_FILE_NAME)
+    }
+
+    /// Where credentials for private feeds are kept, see
+    /// [`crate::credentials::CredentialStore`].
+    #[must_use]
+    pub fn credentials_path(&self) -> PathBuf {
+        self.dirs.data.join(CREDENTIALS_FILE_NAME)
+    }
+
+    /// Where downloaded artwork is cached, see [`crate::artwork::ArtworkCache`].
+    #[must_use]
+    pub fn artwork_dir(&self) -> PathBuf {
+        self.dirs.cache.join("artwork")
+    }
+
+    /// Where downloaded transcripts are cached, see
+    /// [`crate::transcript::TranscriptCache`].
+    #[must_use]
+    pub fn transcript_dir(&self) -> PathBuf {
+        self.dirs.cache.join("transcripts")
+    }
+
+    /// Where episode descriptions are cached, see
+    /// [`crate::description::DescriptionCache`].
+    #[must_use]
+    pub fn description_dir(&self) -> PathBuf {
+        self.dirs.cache.join("descriptions")
+    }
+
+    /// The description cache to use, if only some descriptions are kept in
+    /// memory.
+    #[must_use]
+    pub fn description_cache(&self) -> Option<DescriptionCache> {
+        self.descriptions_in_memory
+            .map(|keep| DescriptionCache::new(self.description_dir(), keep))
+    }
+
+    /// Where the running tui listens for commands, see [`crate::control`].
+    #[must_use]
+    pub fn control_socket(&self) -> PathBuf {
+        self.dirs.runtime.join(SOCKET_FILE_NAME)
+    }
+
+    /// Where the listening history is kept.
+    #[must_use]
+    pub fn history_path(&self) -> PathBuf {
+        self.dirs.data.join(HISTORY_FILE_NAME)
+    }
+
+    /// Settings for the http client, see [`Config::http_client`].
+    #[must_use]
+    pub fn http_options(&self) -> HttpOptions {
+        HttpOptions {
+            user_agent: self.user_agent.clone(),
+            proxy: self.proxy.clone(),
+            connect_timeout: self.connect_timeout,
+            read_timeout: self.read_timeout,
+            retry: Retry {
+                retries: self.retries,
+                ..Retry::default()
+            },
+        }
+    }
+
+    /// Build the http client used for all requests.
+    ///
+    /// # Errors
+    /// Fails if the proxy is invalid or the client could not be created.
+    pub fn http_client(&self) -> Result<HttpClient, ConfigError> {
+        if let Some(proxy) = &self.proxy {
+            Proxy::all(proxy).map_err(|err| invalid("proxy", &err.to_string()))?;
+        }
+        HttpClient::new(&self.http_options()).map_err(ConfigError::HttpClient)
+    }
+}
+
+fn parse<'de, T: serde::Deserialize<'de>>(key: &str, value: toml::Value) -> Result<T, ConfigError> {
+    value
+        .try_into()
+        .map_err(|err: toml::de::Error| invalid(key, err.message()))
+}
+
+/// Read the `[audio]` table, where lengths are in seconds.
+fn parse_audio(key: &str, value: toml::Value) -> Result<AudioSettings, ConfigError> {
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Audio {
+        skip_silence: Option<bool>,
+        silence_threshold: Option<f32>,
+        silence_min_length: Option<f64>,
+        normalize: Option<bool>,
+        target_lufs: Option<f32>,
+        mono: Option<bool>,
+    }
+
+    let table: Audio = parse(key, value)?;
+    let mut audio = AudioSettings::default();
+    audio.skip_silence = table.skip_silence.unwrap_or(audio.skip_silence);
+    audio.normalize = table.normalize.unwrap_or(audio.normalize);
+    audio.mono = table.mono.unwrap_or(audio.mono);
+    if let Some(threshold) = table.silence_threshold {
+        if !(-100.0..0.0).contains(&threshold) {
+            return Err(invalid(
+                key,
+                "silence_threshold must be between -100 and 0 dB",
+            ));
+        }
+        audio.silence_threshold = threshold;
+    }
+    if let Some(length) = table.silence_min_length {
+        audio.silence_min_length = Duration::try_from_secs_f64(length)
+            .ok()
+            .filter(|length| *length > Duration::ZERO && *length <= Duration::from_mins(1))
+            .ok_or_else(|| invalid(key, "silence_min_length must be between 0 and 60 seconds"))?;
+    }
+    if let Some(target) = table.target_lufs {
+        if !(-70.0..=-5.0).contains(&target) {
+            return Err(invalid(key, "target_lufs must be between -70 and -5"));
+        }
+        audio.target_lufs = target;
+    }
+    Ok(audio)
+}
+
+/// Read the `[smart_resume]` table, where lengths are in seconds.
+fn parse_smart_resume(key: &str, value: toml::Value) -> Result<Option<SmartResume>, ConfigError> {
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Table {
+        enabled: Option<bool>,
+        after_minute: Option<f64>,
+        after_day: Option<f64>,
+    }
+
+    let table: Table = parse(key, value)?;
+    let seconds = |value: Option<f64>, default: Duration| match value {
+        Some(value) => Duration::try_from_secs_f64(value)
+            .ok()
+            .filter(|value| *value <= Duration::from_mins(10))
+            .ok_or_else(|| invalid(key, "rewinds must be between 0 and 600 seconds")),
+        None => Ok(default),
+    };
+    let default = SmartResume::default();
+    let smart_resume = SmartResume {
+        after_minute: seconds(table.after_minute, default.after_minute)?,
+        after_day: seconds(table.after_day, default.after_day)?,
+    };
+    if smart_resume.after_minute > smart_resume.after_day {
+        return Err(invalid(key, "after_minute must not be more than after_day"));
+    }
+    Ok(table.enabled.unwrap_or(true).then_some(smart_resume))
+}
+
+fn positive(key: &str, value: i64) -> Result<u64, ConfigError> {
+    u64::try_from(value)
+        .ok()
+        .filter(|value| *value > 0)
+        .ok_or_else(|| invalid(key, "must be greater than 0"))
+}
+
+fn invalid(key: &str, reason: &str) -> ConfigError {
+    ConfigError::InvalidValue {
+        key: key.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// Expand a leading `~` to the users home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dirs() -> Dirs {
+        Dirs {
+            config: PathBuf::from("/config"),
+            data: PathBuf::from("/data"),
+            cache: PathBuf::from("/cache"),
+            runtime: PathBuf::from("/run"),
+        }
+    }
+
+    #[test]
+    fn defaults_when_empty() {
+        let config = Config::from_toml("", dirs()).expect("to parse config");
+
+        assert_eq!(config.download_dir, PathBuf::from("/data/downloads"));
+        assert_eq!(config.library_path(), PathBuf::from("/data/library.json"));
+        assert!(config.warnings().is_empty());
+    }
+
+    #[test]
+    fn reads_settings() {
+        let config = Config::from_toml(
+            r#"
+            download_dir = "/podcasts"
+            refresh_interval = 30
+            concurrency = 8
+            skip_forward = 45
+            skip_back = 5
+            playback_speed = 1.5
+            sleep_fade = 0
+            sleep_rewind = 15
+            user_agent = "my podcast app"
+            proxy = "socks5h://localhost:1080"
+            connect_timeout = 5
+            read_timeout = 60
+            retries = 0
+            keyring = true
+            descriptions_in_memory = 20
+
+            [audio]
+            skip_silence = true
+            silence_min_length = 0.5
+            target_lufs = -19
+
+            [smart_resume]
+            after_minute = 5
+            after_day = 60
+
+            [gpodder]
+            server = "https://cloud.example.com"
+            username = "me"
+            password = "hunter2"
+            api = "nextcloud"
+
+            [sync_folder]
+            path = "/sync/undersea"
+            device = "laptop"
+            "#,
+            dirs(),
+        )
+        .expect("to parse config");
+
+        assert_eq!(config.download_dir, PathBuf::from("/podcasts"));
+        assert_eq!(config.refresh_interval, Duration::from_mins(30));
+        assert_eq!(config.concurrency, 8);
+        assert_eq!(config.skip_forward, Duration::from_secs(45));
+        assert_eq!(config.skip_back, Duration::from_secs(5));
+        assert!((config.playback_speed - 1.5).abs() < f32::EPSILON);
+        assert_eq!(config.sleep_fade, Duration::ZERO);
+        assert_eq!(config.sleep_rewind, Duration::from_secs(15));
+        assert_eq!(config.user_agent, "my podcast app");
+        assert_eq!(config.proxy.as_deref(), Some("socks5h://localhost:1080"));
+        assert_eq!(config.connect_timeout, Duration::from_secs(5));
+        assert_eq!(config.read_timeout, Duration::from_mins(1));
+        assert_eq!(config.http_options().retry.retries, 0);
+        config.http_client().expect("to build the http client");
+        assert!(config.keyring);
+        assert_eq!(
+            config.description_cache().map(|cache| cache.keep()),
+            Some(20)
+        );
+        assert!(!config.sync_secret_urls);
+        assert!(config.audio.skip_silence);
+        assert_eq!(config.audio.silence_min_length, Duration::from_millis(500));
+        assert!((config.audio.target_lufs + 19.0).abs() < f32::EPSILON);
+        assert!(!config.audio.normalize);
+        assert_eq!(
+            config.smart_resume,
+            Some(SmartResume {
+                after_minute: Duration::from_secs(5),
+                after_day: Duration::from_mins(1),
+            })
+        );
+
+        let gpodder = config.gpodder.expect("gpodder to be set");
+        assert_eq!(gpodder.device, "undersea");
+        assert_eq!(gpodder.api, crate::sync::gpodder::Api::Nextcloud);
+        let folder = config.sync_folder.expect("sync folder to be set");
+        assert_eq!(folder.path, PathBuf::from("/sync/undersea"));
+
+        let config =
+            Config::from_toml("[smart_resume]\nenabled = false", dirs()).expect("to parse config");
+        assert_eq!(config.smart_resume, None);
+    }
+
+    #[test]
+    fn unknown_keys_warn() {
+        let config = Config::from_toml("colour = \"blue\"", dirs()).expect("to parse config");
+        assert_eq!(config.warnings().len(), 1);
+    }
+
+    #[test]
+    fn invalid_values_fail() {
+        for contents in [
+            "concurrency = 0",
+            "concurrency = \"four\"",
+            "playback_speed = 10.0",
+            "skip_back = -5",
+            "sleep_rewind = -5",
+            "user_agent = \"\"",
+            "proxy = \"not a url\"",
+            "connect_timeout = 0",
+            "read_timeout = -1",
+            "retries = -1",
+            "retries = 100",
+            "keyring = \"yes\"",
+            "descriptions_in_memory = 0",
+            "[audio]\ntarget_lufs = 3",
+            "[audio]\nsilence_min_length = 0",
+            "[audio]\nskip_silnce = true",
+            "[smart_resume]\nafter_minute = -1",
+            "[smart_resume]\nafter_minute = 40\nafter_day = 20",
+            "[gpodder]\nserver = \"https://gpodder.net\"",
+            "[sync_folder]\npath = \"/sync\"\ndevice = \"../laptop\"",
+        ] {
+            let err = Config::from_toml(contents, dirs()).expect_err(contents);
+            assert!(
+                matches!(err, ConfigError::InvalidValue { .. }),
+                "{contents}"
+            );
+        }
+    }
+}
@@ -0,0 +1,539 @@
+//! Controlling a running player from other programs, such as a status line,
+//! window manager keybindings or `undersea ctl`.
+//!
+//! The player listens on a Unix socket, [`crate::Config::control_socket`],
+//! for [JSON-RPC 2.0](https://www.jsonrpc.org/specification) requests, one
+//! per line. The methods are:
+//!
+//! - `status`: what is playing, as a [`NowPlaying`]
+//! - `queue`: the queued episodes, as [`EpisodeSummary`]s
+//! - `play`: play the episode with the `id` param, or resume if there is none
+//! - `pause`, `toggle`, `stop`, `next` and `previous`
+//! - `seek`: jump to `position`, or by `by`, both in seconds
+//! - `speed`: set the playback speed to `speed`
+//! - `enqueue`: add the episode with the `id` param to the queue
+//! - `add`: subscribe to the show with the feed `url`
+//! - `subscribe`: get an `event` notification with an [`Event`] for every
+//!   change from then on
+//!
+//! The [`Server`] passes each request on as a [`Call`] for whatever owns the
+//! player to answer, and the [`Client`] makes requests.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{self, DirBuilder},
+    io,
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        UnixListener, UnixStream,
+        unix::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::{
+        broadcast,
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    task::JoinHandle,
+};
+
+use crate::{
+    ControlError, Episode, Show, Shows,
+    player::{Control, Player, SEEK_TOLERANCE, Status},
+};
+
+/// Name of the socket inside the runtime directory.
+pub const SOCKET_FILE_NAME: &str = "control.sock";
+
+/// How many events a slow subscriber can fall behind by before it misses
+/// some.
+const EVENT_BACKLOG: usize = 64;
+
+/// A request for whatever owns the player to carry out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Status,
+    Queue,
+    /// Play an episode by its id, or resume if there is none
+    Play(Option<String>),
+    Control(Control),
+    Enqueue(String),
+    Add(String),
+}
+
+impl Command {
+    fn parse(method: &str, params: &Value) -> Result<Self, RpcError> {
+        Ok(match method {
+            "status" => Self::Status,
+            "queue" => Self::Queue,
+            "play" => match param::<String>(params, "id")? {
+                Some(id) => Self::Play(Some(id)),
+                None => Self::Control(Control::Play),
+            },
+            "pause" => Self::Control(Control::Pause),
+            "toggle" => Self::Control(Control::PlayPause),
+            "stop" => Self::Control(Control::Stop),
+            "next" => Self::Control(Control::Next),
+            "previous" => Self::Control(Control::Previous),
+            "seek" => {
+                let seconds = |value: f64| {
+                    Duration::try_from_secs_f64(value.abs()).map_err(|err| {
+                        RpcError::new(RpcError::INVALID_PARAMS, format!("invalid time: {err}"))
+                    })
+                };
+                if let Some(position) = param::<f64>(params, "position")? {
+                    Self::Control(Control::SetPosition(seconds(position)?))
+                } else if let Some(by) = param::<f64>(params, "by")? {
+                    Self::Control(Control::Seek {
+                        forward: by >= 0.0,
+                        by: seconds(by)?,
+                    })
+                } else {
+                    return Err(missing("position\" or \"by"));
+                }
+            }
+            "speed" => {
+                let speed = param::<f64>(params, "speed")?.ok_or_else(|| missing("speed"))?;
+                Self::Control(Control::SetRate(speed))
+            }
+            "enqueue" => Self::Enqueue(param(params, "id")?.ok_or_else(|| missing("id"))?),
+            "add" => Self::Add(param(params, "url")?.ok_or_else(|| missing("url"))?),
+            _ => {
+                return Err(RpcError::new(
+                    RpcError::METHOD_NOT_FOUND,
+                    format!("no method named \"{method}\""),
+                ));
+            }
+        })
+    }
+}
+
+/// A named param, [`None`] if it was not given.
+fn param<T: DeserializeOwned>(params: &Value, name: &str) -> Result<Option<T>, RpcError> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => serde_json::from_value(value.clone())
+            .map(Some)
+            .map_err(|err| {
+                RpcError::new(
+                    RpcError::INVALID_PARAMS,
+                    format!("invalid \"{name}\": {err}"),
+                )
+            }),
+    }
+}
+
+fn missing(name: &str) -> RpcError {
+    RpcError::new(RpcError::INVALID_PARAMS, format!("missing \"{name}\""))
+}
+
+/// The error object of a JSON-RPC response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+    /// The show or episode a request named does not exist
+    pub const NOT_FOUND: i64 = -32000;
+    /// The request was understood, but carrying it out failed
+    pub const FAILED: i64 = -32001;
+
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// An episode, as sent over the socket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EpisodeSummary {
+    pub id: String,
+    pub title: String,
+    /// Name of the show
+    pub show: String,
+    /// Length in seconds, if it is known
+    pub duration: Option<f64>,
+}
+
+impl EpisodeSummary {
+    #[must_use]
+    pub fn new(show: &Show, episode: &Episode) -> Self {
+        Self {
+            id: episode.id().to_string(),
+            title: episode.title().to_string(),
+            show: show.name().to_string(),
+            duration: episode.duration().map(|duration| duration.as_secs_f64()),
+        }
+    }
+}
+
+/// What the player is doing, the result of `status`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NowPlaying {
+    pub status: Status,
+    pub episode: Option<EpisodeSummary>,
+    /// Seconds into the episode
+    pub position: f64,
+    pub speed: f32,
+}
+
+impl NowPlaying {
+    #[must_use]
+    pub fn new(player: &Player, shows: &Shows) -> Self {
+        Self {
+            status: player.status(),
+            episode: player
+                .now_playing(shows)
+                .map(|(show, episode)| EpisodeSummary::new(show, episode)),
+            position: player.position().as_secs_f64(),
+            speed: player.speed(),
+        }
+    }
+
+    /// If anything changed since `old`, which was `elapsed` ago, other than
+    /// the position moving on as the episode plays.
+    #[must_use]
+    pub fn changed_from(&self, old: &Self, elapsed: Duration) -> bool {
+        let expected = if old.status == Status::Playing {
+            old.position + elapsed.as_secs_f64() * f64::from(old.speed)
+        } else {
+            old.position
+        };
+        let jumped = (self.position - expected).abs() > SEEK_TOLERANCE.as_secs_f64();
+        self.status != old.status
+            || self.episode != old.episode
+            || (self.speed - old.speed).abs() > f32::EPSILON
+            || jumped
+    }
+}
+
+/// A change sent to subscribers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Playback started, stopped, paused, jumped or changed speed
+    Status(NowPlaying),
+    /// Episodes were added to or taken out of the queue
+    Queue { episodes: Vec<EpisodeSummary> },
+    /// A show was subscribed to
+    Added { url: String, name: String },
+}
+
+/// Any JSON-RPC message, a request, response or notification.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Message {
+    jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    method: Option<String>,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    params: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+impl Message {
+    fn response(id: Option<Value>, result: Result<Value, RpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: "2.0".to_string(),
+            // errors from before the id could be read have a null id
+            id: Some(id.unwrap_or(Value::Null)),
+            result,
+            error,
+            ..Self::default()
+        }
+    }
+
+    fn to_line(&self) -> String {
+        let mut line = serde_json::to_string(self).unwrap_or_default();
+        line.push('\n');
+        line
+    }
+}
+
+/// A request from a [`Client`], answer it with [`Call::reply`].
+pub struct Call {
+    pub command: Command,
+    reply: oneshot::Sender<Result<Value, RpcError>>,
+}
+
+impl Call {
+    pub fn reply(self, result: Result<Value, RpcError>) {
+        let _ = self.reply.send(result);
+    }
+}
+
+/// Listens on the control socket, see the [module docs](self). The socket is
+/// removed when it is dropped.
+pub struct Server {
+    path: PathBuf,
+    events: broadcast::Sender<String>,
+    accept: JoinHandle<()>,
+}
+
+impl Server {
+    /// Start listening on `path`, requests arrive on the returned receiver.
+    /// A socket left behind by a player that crashed is replaced. This has
+    /// to be called from inside a tokio runtime.
+    ///
+    /// # Errors
+    /// Fails if another player is listening on `path`, or the socket could
+    /// not be created.
+    pub fn bind(path: &Path) -> Result<(Self, UnboundedReceiver<Call>), ControlError> {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(ControlError::InUse(path.to_path_buf()));
+        }
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        if let Some(parent) = path.parent() {
+            // only this user should be able to control their player
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(parent)?;
+        }
+        let listener = UnixListener::bind(path)?;
+
+        let (calls, receiver) = mpsc::unbounded_channel();
+        let (events, _) = broadcast::channel(EVENT_BACKLOG);
+        let accept = tokio::spawn({
+            let events = events.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(serve(stream, calls.clone(), events.subscribe()));
+                }
+            }
+        });
+
+        let server = Self {
+            path: path.to_path_buf(),
+            events,
+            accept,
+        };
+        Ok((server, receiver))
+    }
+
+    /// Send an event to every client that subscribed.
+    pub fn notify(&self, event: &Event) {
+        let message = Message {
+            jsonrpc: "2.0".to_string(),
+            method: Some("event".to_string()),
+            params: serde_json::to_value(event).unwrap_or_default(),
+            ..Message::default()
+        };
+        // there being no subscribers is not an error
+        let _ = self.events.send(message.to_line());
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.accept.abort();
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Answer requests from one client until it disconnects, or the server is
+/// dropped.
+async fn serve(
+    stream: UnixStream,
+    calls: UnboundedSender<Call>,
+    mut events: broadcast::Receiver<String>,
+) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    let mut subscribed = false;
+    loop {
+        let out = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => answer(&line, &calls, &mut subscribed).await,
+                _ => break,
+            },
+            event = events.recv() => match event {
+                Ok(event) => subscribed.then_some(event),
+                Err(broadcast::error::RecvError::Lagged(_)) => None,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        };
+        if let Some(out) = out
+            && write.write_all(out.as_bytes()).await.is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// The response to one line from a client, if it should get one.
+async fn answer(
+    line: &str,
+    calls: &UnboundedSender<Call>,
+    subscribed: &mut bool,
+) -> Option<String> {
+    let request = match serde_json::from_str::<Message>(line) {
+        Ok(request) => request,
+        Err(err) => {
+            let error = RpcError::new(RpcError::PARSE_ERROR, err.to_string());
+            return Some(Message::response(None, Err(error)).to_line());
+        }
+    };
+    let Some(method) = request.method else {
+        let error = RpcError::new(RpcError::INVALID_REQUEST, "missing \"method\"");
+        return Some(Message::response(request.id, Err(error)).to_line());
+    };
+
+    let result = if method == "subscribe" {
+        *subscribed = true;
+        Ok(Value::Bool(true))
+    } else {
+        match Command::parse(&method, &request.params) {
+            Ok(command) => {
+                let (reply, receiver) = oneshot::channel();
+                let _ = calls.send(Call { command, reply });
+                receiver.await.unwrap_or_else(|_| {
+                    Err(RpcError::new(
+                        RpcError::INTERNAL_ERROR,
+                        "the player did not answer",
+                    ))
+                })
+            }
+            Err(err) => Err(err),
+        }
+    };
+    // requests without an id are notifications, which get no response
+    request
+        .id
+        .is_some()
+        .then(|| Message::response(request.id, result).to_line())
+}
+
+/// Makes requests to a running player, see the [module docs](self).
+pub struct Client {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    write: OwnedWriteHalf,
+    next_id: u64,
+    /// Events that arrived while waiting for a response
+    events: VecDeque<Event>,
+}
+
+impl Client {
+    /// # Errors
+    /// Fails with [`ControlError::NotRunning`] if nothing is listening on
+    /// `path`.
+    pub async fn connect(path: &Path) -> Result<Self, ControlError> {
+        let stream = UnixStream::connect(path).await.map_err(|err| {
+            if matches!(
+                err.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+            ) {
+                ControlError::NotRunning(path.to_path_buf())
+            } else {
+                err.into()
+            }
+        })?;
+        let (read, write) = stream.into_split();
+        Ok(Self {
+            lines: BufReader::new(read).lines(),
+            write,
+            next_id: 1,
+            events: VecDeque::new(),
+        })
+    }
+
+    /// Call a method and wait for its result.
+    ///
+    /// # Errors
+    /// Fails if the player answered with an error, or the connection was
+    /// lost.
+    pub async fn call(&mut self, method: &str, params: Value) -> Result<Value, ControlError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = Message {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(id)),
+            method: Some(method.to_string()),
+            params,
+            ..Message::default()
+        };
+        self.write.write_all(request.to_line().as_bytes()).await?;
+
+        loop {
+            let message = self.read().await?;
+            if message.method.as_deref() == Some("event") {
+                if let Ok(event) = serde_json::from_value(message.params) {
+                    self.events.push_back(event);
+                }
+                continue;
+            }
+            if message.id != Some(json!(id)) {
+                continue;
+            }
+            return match message.error {
+                Some(error) => Err(ControlError::Rpc(error)),
+                None => Ok(message.result.unwrap_or_default()),
+            };
+        }
+    }
+
+    /// Ask for every change to be sent, they can be read with
+    /// [`Client::next_event`].
+    ///
+    /// # Errors
+    /// Fails if the connection was lost.
+    pub async fn subscribe(&mut self) -> Result<(), ControlError> {
+        self.call("subscribe", Value::Null).await?;
+        Ok(())
+    }
+
+    /// Wait for the next event, after [`Client::subscribe`].
+    ///
+    /// # Errors
+    /// Fails with [`ControlError::Closed`] once the player closes.
+    pub async fn next_event(&mut self) -> Result<Event, ControlError> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(event);
+        }
+        loop {
+            let message = self.read().await?;
+            if message.method.as_deref() == Some("event") {
+                return Ok(serde_json::from_value(message.params)?);
+            }
+        }
+    }
+
+    async fn read(&mut self) -> Result<Message, ControlError> {
+        let line = self.lines.next_line().await?.ok_or(ControlError::Closed)?;
+        Ok(serde_json::from_str(&line)?)
+    }
+}
@@ -0,0 +1,376 @@
+//! Keeping episode descriptions on disk, so that only those of the newest
+//! few episodes of each show are held in memory.
+//!
+//! Each show has a file in the cache directory under a name made from its
+//! url, with a line of json for every episode that has a description. It is
+//! written again each time the feed is read, and searched when a
+//! description that is not in memory is asked for, see
+//! [`crate::Shows::description`].
+
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fs,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{Episode, LibraryError, Shows, artwork::cache_key};
+
+/// Descriptions dropped from memory are only let go of once this many have
+/// been written to the cache, and it has been flushed.
+const FLUSH_EVERY: usize = 64;
+
+/// A directory of episode descriptions, see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct DescriptionCache {
+    dir: PathBuf,
+    keep: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Line<'a> {
+    #[serde(borrow)]
+    id: Cow<'a, str>,
+    #[serde(borrow)]
+    description: Cow<'a, str>,
+}
+
+impl DescriptionCache {
+    /// Use `dir` for the cache, such as [`crate::Config::description_dir`],
+    /// keeping the descriptions of the newest `keep` episodes of each show in
+    /// memory. It is created when something is first cached.
+    #[must_use]
+    pub fn new(dir: PathBuf, keep: usize) -> Self {
+        Self { dir, keep }
+    }
+
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// How many descriptions of each show are kept in memory.
+    #[must_use]
+    pub fn keep(&self) -> usize {
+        self.keep
+    }
+
+    /// The description of episode `id` of the show at `url`, [`None`] if
+    /// the cache does not have one.
+    ///
+    /// # Errors
+    /// Fails if the cache file could not be read.
+    pub fn load(&self, url: &str, id: &str) -> Result<Option<String>, LibraryError> {
+        let file = match fs::File::open(self.path(url)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let cached: Line = serde_json::from_str(&line)?;
+            if cached.id == id {
+                return Ok(Some(cached.description.into_owned()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Forget the descriptions of the show at `url`.
+    pub(crate) fn remove(&self, url: &str) {
+        let _ = fs::remove_file(self.path(url));
+    }
+
+    /// Start keeping the descriptions of a feed as it is read.
+    pub(crate) fn start(&self, url: &str) -> KeptDescriptions {
+        let partial_path = self.path(url).with_extension("part");
+        let writer = fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::File::create(&partial_path))
+            .map(BufWriter::new)
+            .ok();
+        KeptDescriptions {
+            dir: self.dir.clone(),
+            keep: self.keep,
+            newest: BinaryHeap::new(),
+            unflushed: Vec::new(),
+            dropped: false,
+            writer,
+            partial_path,
+            finished: false,
+        }
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir.join(cache_key(url))
+    }
+}
+
+/// The descriptions of a feed being read, all of them are written to the
+/// cache, and all but the newest are dropped from memory once they are
+/// safely in it.
+///
+/// If the cache can not be written to, the descriptions that were dropped
+/// are read back from what was written, every description is kept in memory
+/// from then on, and the cache keeps what it had from the last time. The
+/// partial file is removed unless it is put in place by
+/// [`KeptDescriptions::finish`].
+pub(crate) struct KeptDescriptions {
+    dir: PathBuf,
+    keep: usize,
+    /// Dates and indices of the episodes whose descriptions are in memory,
+    /// the oldest on top
+    newest: BinaryHeap<Reverse<(DateTime<Utc>, usize)>>,
+    /// Indices of the episodes whose descriptions are to be dropped, once
+    /// the writer has been flushed
+    unflushed: Vec<usize>,
+    /// If any descriptions have been dropped from memory
+    dropped: bool,
+    writer: Option<BufWriter<fs::File>>,
+    partial_path: PathBuf,
+    finished: bool,
+}
+
+impl KeptDescriptions {
+    /// Keep the description of the last of `episodes`, which was just read,
+    /// dropping the oldest description in memory if there are too many.
+    pub(crate) fn add(&mut self, episodes: &mut [Episode]) {
+        let Some(index) = episodes.len().checked_sub(1) else {
+            return;
+        };
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+        let episode = &episodes[index];
+        let Some(description) = &episode.description else {
+            return;
+        };
+        let line = Line {
+            id: Cow::Borrowed(&episode.id),
+            description: Cow::Borrowed(description),
+        };
+        let written = serde_json::to_writer(&mut *writer, &line)
+            .map_err(io::Error::from)
+            .and_then(|()| writer.write_all(b"\n"));
+        if written.is_err() {
+            self.fail(episodes);
+            return;
+        }
+
+        self.newest.push(Reverse((episode.date, index)));
+        if self.newest.len() > self.keep
+            && let Some(Reverse((_, oldest))) = self.newest.pop()
+        {
+            self.unflushed.push(oldest);
+        }
+        if self.unflushed.len() >= FLUSH_EVERY {
+            self.flush(episodes);
+        }
+    }
+
+    /// Put the cache file in place for the show at `url`, its url once it
+    /// has been read, and drop the last of the old descriptions from
+    /// `episodes`. The file for `previous_url` is removed if it moved.
+    pub(crate) fn finish(mut self, episodes: &mut [Episode], url: &str, previous_url: &str) {
+        let Some(mut writer) = self.writer.take() else {
+            return;
+        };
+        let path = self.dir.join(cache_key(url));
+        if writer.flush().is_err() || fs::rename(&self.partial_path, path).is_err() {
+            self.fail(episodes);
+            return;
+        }
+        self.finished = true;
+        for index in self.unflushed.drain(..) {
+            episodes[index].description = None;
+        }
+        if url != previous_url {
+            let _ = fs::remove_file(self.dir.join(cache_key(previous_url)));
+        }
+    }
+
+    /// Flush what has been written, then drop the descriptions that are now
+    /// in the cache from memory.
+    fn flush(&mut self, episodes: &mut [Episode]) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+        if writer.flush().is_err() {
+            self.fail(episodes);
+            return;
+        }
+        for index in self.unflushed.drain(..) {
+            episodes[index].description = None;
+            self.dropped = true;
+        }
+    }
+
+    /// Stop writing to the cache, and read back the descriptions that were
+    /// dropped from memory from what was written of it.
+    fn fail(&mut self, episodes: &mut [Episode]) {
+        self.writer = None;
+        self.unflushed.clear();
+        if !std::mem::take(&mut self.dropped) {
+            return;
+        }
+
+        let mut missing = episodes
+            .iter()
+            .enumerate()
+            .filter(|(_, episode)| episode.description.is_none())
+            .map(|(index, episode)| (episode.id.clone(), index))
+            .collect::<HashMap<_, _>>();
+        let Ok(file) = fs::File::open(&self.partial_path) else {
+            return;
+        };
+        for line in BufReader::new(file).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let Ok(cached) = serde_json::from_str::<Line>(&line) else {
+                continue;
+            };
+            if let Some(index) = missing.remove(cached.id.as_ref()) {
+                episodes[index].description = Some(cached.description.into_owned());
+            }
+        }
+    }
+}
+
+impl Drop for KeptDescriptions {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = fs::remove_file(&self.partial_path);
+        }
+    }
+}
+
+impl Shows {
+    /// Keep only the newest descriptions of each show in memory, and the
+    /// rest in `cache`. This takes effect as each feed is next read.
+    pub fn set_description_cache(&mut self, cache: DescriptionCache) {
+        self.descriptions = Some(cache);
+    }
+
+    /// The description (show notes) of an episode as html, loading it from
+    /// the [`DescriptionCache`] if it is not in memory. A cache that can not
+    /// be read is the same as one without the description, it is written
+    /// again when the feed is next read.
+    #[must_use]
+    pub fn description(&self, id: &str) -> Option<String> {
+        let (show, episode) = self.episode_by_id(id)?;
+        if let Some(description) = episode.descrpition() {
+            return Some(description.to_string());
+        }
+        let cache = self.descriptions.as_ref()?;
+        // a show moved by sync has its descriptions at the old url until
+        // the feed is read again
+        std::iter::once(&show.url)
+            .chain(show.previous_urls.iter().rev())
+            .find_map(|url| cache.load(url, id).ok().flatten())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const URL: &str = "https://example.com/feed.xml";
+
+    fn episode(number: i64) -> Episode {
+        Episode {
+            id: format!("episode-{number}"),
+            media_url: format!("https://example.com/{number}.mp3"),
+            title: format!("Episode {number}"),
+            description: Some(format!("notes {number}")),
+            date: DateTime::UNIX_EPOCH + chrono::Duration::days(number),
+            duration: None,
+            resume_time: Duration::ZERO,
+            finished: false,
+            download_path: None,
+            changed_at: None,
+            last_played_at: None,
+            artwork: None,
+            transcripts: Vec::new(),
+            chapters: Vec::new(),
+            bookmarks: Vec::new(),
+        }
+    }
+
+    /// Read `count` episodes into `kept`.
+    fn read(kept: &mut KeptDescriptions, count: i64) -> Vec<Episode> {
+        let mut episodes = Vec::new();
+        for number in 0..count {
+            episodes.push(episode(number));
+            kept.add(&mut episodes);
+        }
+        episodes
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("undersea-{name}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn only_the_newest_stay_in_memory() {
+        let dir = temp_dir("descriptions-newest");
+        let cache = DescriptionCache::new(dir.clone(), 2);
+        let mut kept = cache.start(URL);
+        let mut episodes = read(&mut kept, 100);
+        kept.finish(&mut episodes, URL, URL);
+
+        let in_memory = episodes
+            .iter()
+            .filter_map(|episode| episode.description.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(in_memory, ["notes 98", "notes 99"]);
+        assert_eq!(
+            cache.load(URL, "episode-0").unwrap().as_deref(),
+            Some("notes 0")
+        );
+        assert!(!cache.path(URL).with_extension("part").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn descriptions_are_read_back_when_the_cache_fails() {
+        let dir = temp_dir("descriptions-failing");
+        let cache = DescriptionCache::new(dir.clone(), 2);
+        let mut kept = cache.start(URL);
+        let mut episodes = read(&mut kept, 100);
+        assert_eq!(episodes[0].description, None);
+
+        // a file opened only for reading can not be written to
+        let partial = fs::File::open(&kept.partial_path).unwrap();
+        kept.writer = Some(BufWriter::new(partial));
+        episodes.push(episode(100));
+        kept.add(&mut episodes);
+        kept.finish(&mut episodes, URL, URL);
+
+        for (number, episode) in (0..).zip(&episodes) {
+            assert_eq!(episode.description, Some(format!("notes {number}")));
+        }
+        assert!(!cache.path(URL).exists());
+        assert!(!cache.path(URL).with_extension("part").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn partial_files_are_removed_if_reading_stops() {
+        let dir = temp_dir("descriptions-stopped");
+        let cache = DescriptionCache::new(dir.clone(), 2);
+        let mut kept = cache.start(URL);
+        read(&mut kept, 10);
+        assert!(cache.path(URL).with_extension("part").exists());
+
+        drop(kept);
+        assert!(!cache.path(URL).with_extension("part").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+use std::path::{Path, PathBuf};
+
+use reqwest::Url;
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{
+    DownloadError, Episode, Shows,
+    credentials::{Credentials, authorize},
+    http::HttpClient,
+};
+
+impl Shows {
+    /// Download an episodes media into `dir/<show name>/`, and remember where
+    /// it was saved. If the episode has already been downloaded, and the file
+    /// is still there, nothing is downloaded.
+    ///
+    /// # Errors
+    /// Fails if there is no episode with that id, the media could not be
+    /// fetched, or the file could not be written.
+    pub async fn download(&mut self, id: &str, dir: &Path) -> Result<PathBuf, DownloadError> {
+        let (show, episode) = self
+            .episode_by_id(id)
+            .ok_or_else(|| DownloadError::EpisodeNotFound(id.to_string()))?;
+
+        if let Some(path) = episode.download_path().filter(|path| path.exists()) {
+            return Ok(path.clone());
+        }
+
+        let path = dir
+            .join(sanitise_file_name(show.name()))
+            .join(file_name(episode));
+        // private feeds often need the same credentials for their media, but
+        // they are never sent to other hosts
+        let credentials = self
+            .credentials
+            .get(show.url())
+            .filter(|_| same_host(show.url(), episode.media_url()));
+        download_to(&self.client, credentials, episode.media_url(), &path).await?;
+
+        if let Ok(episode) = self.episode_by_id_mut(id) {
+            episode.download_path = Some(path.clone());
+        }
+        self.last_change = chrono::Utc::now();
+        Ok(path)
+    }
+}
+
+/// Stream a url into a file, so that large episodes are never held in memory.
+/// The file is only moved into place once the download has finished. Errors
+/// leave out the url, as media urls of private feeds have secrets in them.
+async fn download_to(
+    client: &HttpClient,
+    credentials: Option<&Credentials>,
+    url: &str,
+    path: &Path,
+) -> Result<(), DownloadError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    let mut response = client
+        .send(authorize(client.get(url), credentials))
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(reqwest::Error::without_url)?;
+    let partial_path = path.with_extension("part");
+    let mut file = fs::File::create(&partial_path).await?;
+
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(reqwest::Error::without_url)?
+    {
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    fs::rename(partial_path, path).await?;
+
+    Ok(())
+}
+
+/// If two urls are on the same host.
+fn same_host(a: &str, b: &str) -> bool {
+    match (Url::parse(a), Url::parse(b)) {
+        (Ok(a), Ok(b)) => a.host_str().is_some() && a.host_str() == b.host_str(),
+        _ => false,
+    }
+}
+
+/// `<date> <title>.<extension>`, the date keeps files in release order.
+fn file_name(episode: &Episode) -> String {
+    let extension = media_extension(episode.media_url()).unwrap_or("mp3");
+    let date = episode.date().format("%Y-%m-%d");
+    sanitise_file_name(&format!("{date} {}.{extension}", episode.title()))
+}
+
+/// The extension of the file a url points to, ignoring any query string.
+fn media_extension(url: &str) -> Option<&str> {
+    let path = url.split(['?', '#']).next()?;
+    let file = path.rsplit('/').next()?;
+    let (_, extension) = file.rsplit_once('.')?;
+
+    let valid = !extension.is_empty()
+        && extension.len() <= 4
+        && extension.chars().all(|c| c.is_ascii_alphanumeric());
+    valid.then_some(extension)
+}
+
+/// Replace characters that are not allowed in file names on common platforms.
+fn sanitise_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .trim()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extension_from_url() {
+        assert_eq!(
+            media_extension("https://example.com/ep/1.mp3?token=a.b"),
+            Some("mp3")
+        );
+        assert_eq!(media_extension("https://example.com/ep/1"), None);
+        assert_eq!(media_extension("https://example.com/a.b/episode"), None);
+    }
+
+    #[test]
+    fn sanitise() {
+        assert_eq!(sanitise_file_name("../a/b: c?"), "_a_b_ c_");
+    }
+}
@@ -1,9 +1,13 @@
 use chrono::{DateTime, Utc};
-use std::time;
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, time};
+
+use crate::{bookmark::Bookmark, transcript::TranscriptLink};
 
 /// An espisode, contains the title, url, media url, and some media metadata.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Episode {
+    pub(crate) id: String,
     pub(crate) media_url: String,
     pub(crate) title: String,
     pub(crate) description: Option<String>,
@@ -11,9 +15,44 @@ pub struct Episode {
     pub(crate) duration: Option<time::Duration>,
     pub(crate) resume_time: time::Duration,
     pub(crate) finished: bool,
+    #[serde(default)]
+    pub(crate) download_path: Option<PathBuf>,
+    /// When `resume_time` or `finished` last changed, on any device
+    #[serde(default)]
+    pub(crate) changed_at: Option<DateTime<Utc>>,
+    /// When playback of the episode last stopped, on any device
+    #[serde(default)]
+    pub(crate) last_played_at: Option<DateTime<Utc>>,
+    /// Url of the episodes own `itunes:image`, if it has one
+    #[serde(default)]
+    pub(crate) artwork: Option<String>,
+    /// Every `podcast:transcript` of the episode
+    #[serde(default)]
+    pub(crate) transcripts: Vec<TranscriptLink>,
+    /// The `psc:chapters` of the episode, in order
+    #[serde(default)]
+    pub(crate) chapters: Vec<Chapter>,
+    /// Moments bookmarked on any device, in order of where they start
+    #[serde(default)]
+    pub(crate) bookmarks: Vec<Bookmark>,
+}
+
+/// A named part of an episode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chapter {
+    /// Where the chapter starts in the episode
+    pub start: time::Duration,
+    pub title: String,
 }
 
 impl Episode {
+    /// Returns the id of the episode, this is the guid from the feed, or the
+    /// media url if the feed did not give it one.
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
     /// Returns the url of the episodes attached media.
     #[must_use]
     pub fn media_url(&self) -> &str {
@@ -28,8 +67,13 @@ impl Episode {
 
     /// Returns the description (show notes) of an episode as html
     ///
+    /// With a [`crate::description::DescriptionCache`] set, only the newest
+    /// episodes of each show have their description in memory, use
+    /// [`crate::Shows::description`] to get it from the cache for the rest.
+    ///
     /// # Erors
-    /// Returns None if there was no attached description
+    /// Returns None if there was no attached description, or it is only in
+    /// the cache
     #[must_use]
     pub fn descrpition(&self) -> Option<&str> {
         self.description.as_deref()
@@ -55,9 +99,95 @@ impl Episode {
         &self.resume_time
     }
 
+    /// Returns when playback of the episode last stopped, on any device, or
+    /// None if it has not been played since this was first kept.
+    #[must_use]
+    pub fn last_played_at(&self) -> Option<&DateTime<Utc>> {
+        self.last_played_at.as_ref()
+    }
+
     /// Returns weather or not the episode has been finished.
     #[must_use]
     pub fn finished(&self) -> bool {
         self.finished
     }
+
+    /// Returns the url of the episodes own artwork, most episodes use the
+    /// artwork of their show instead, see [`crate::Show::artwork_for`].
+    #[must_use]
+    pub fn artwork_url(&self) -> Option<&str> {
+        self.artwork.as_deref()
+    }
+
+    /// Returns the transcripts the feed links for the episode, usually the
+    /// same one in different formats or languages.
+    #[must_use]
+    pub fn transcripts(&self) -> &[TranscriptLink] {
+        &self.transcripts
+    }
+
+    /// Returns the transcript best suited to following along with, one that
+    /// can be parsed, preferring formats with the most precise times.
+    #[must_use]
+    pub fn transcript(&self) -> Option<&TranscriptLink> {
+        use crate::transcript::Format;
+
+        self.transcripts
+            .iter()
+            .filter_map(|link| Some((link, link.format()?)))
+            .min_by_key(|(_, format)| match format {
+                Format::Vtt => 0,
+                Format::Json => 1,
+                Format::Srt => 2,
+                Format::Html => 3,
+            })
+            .map(|(link, _)| link)
+    }
+
+    /// Returns the chapters of the episode in order, most episodes have none.
+    #[must_use]
+    pub fn chapters(&self) -> &[Chapter] {
+        &self.chapters
+    }
+
+    /// Returns where the chapter playing at `position` ends, which is where
+    /// the next one starts. This is None in the last chapter, or if the
+    /// episode has no chapters.
+    #[must_use]
+    pub fn chapter_end(&self, position: time::Duration) -> Option<time::Duration> {
+        self.chapters
+            .iter()
+            .map(|chapter| chapter.start)
+            .find(|start| *start > position)
+    }
+
+    /// Returns the bookmarks in the episode, in order of where they start.
+    #[must_use]
+    pub fn bookmarks(&self) -> &[Bookmark] {
+        &self.bookmarks
+    }
+
+    /// Returns where the episode was downloaded to, if it has been downloaded.
+    #[must_use]
+    pub fn download_path(&self) -> Option<&PathBuf> {
+        self.download_path.as_ref()
+    }
+
+    /// Mark the episode as finished, or not finished. Finishing an episode
+    /// resets where it will resume from.
+    pub(crate) fn set_finished(&mut self, finished: bool, at: DateTime<Utc>) {
+        self.finished = finished;
+        self.changed_at = Some(at);
+        if finished {
+            self.resume_time = time::Duration::ZERO;
+        }
+    }
+
+    /// Set where playback should resume from, this also marks the episode as
+    /// not finished.
+    pub(crate) fn set_resume_time(&mut self, resume_time: time::Duration, at: DateTime<Utc>) {
+        self.resume_time = resume_time;
+        self.changed_at = Some(at);
+        self.finished = false;
+    }
 }
@@ -1,7 +1,183 @@
+use std::path::PathBuf;
+
 use thiserror::Error;
 
+use crate::control::RpcError;
+
 #[derive(Error, Debug)]
 pub enum FeedError {
     #[error("network error: {0}")]
     NetworkError(#[from] reqwest::Error),
+    #[error("invalid feed: {0}")]
+    InvalidFeed(#[from] rss::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum LibraryError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("invalid library file: {0}")]
+    ParseError(#[from] serde_json::Error),
+    #[error("no show matching \"{0}\"")]
+    ShowNotFound(String),
+    #[error("no episode with id \"{0}\"")]
+    EpisodeNotFound(String),
+    #[error("no playlist named \"{0}\"")]
+    PlaylistNotFound(String),
+    #[error("no bookmark made at {0}")]
+    BookmarkNotFound(chrono::DateTime<chrono::Utc>),
+    #[error("a bookmark has to end after it starts")]
+    InvalidBookmark,
+}
+
+#[derive(Error, Debug)]
+pub enum OpmlError {
+    #[error("invalid opml: {0}")]
+    ParseError(#[from] quick_xml::Error),
+    #[error("invalid opml attribute: {0}")]
+    AttributeError(#[from] quick_xml::events::attributes::AttrError),
+}
+
+#[derive(Error, Debug)]
+pub enum DownloadError {
+    #[error("network error: {0}")]
+    NetworkError(#[from] reqwest::Error),
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("no episode with id \"{0}\"")]
+    EpisodeNotFound(String),
+}
+
+#[derive(Error, Debug)]
+pub enum ClipError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("only a bookmark with an end can be exported as a clip")]
+    NoEnd,
+    #[error("episode \"{0}\" has to be downloaded to export a clip")]
+    NotDownloaded(String),
+    #[error("mpv failed to export the clip: {0}")]
+    Mpv(String),
+}
+
+#[derive(Error, Debug)]
+pub enum ArtworkError {
+    #[error("network error: {0}")]
+    NetworkError(#[from] reqwest::Error),
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("invalid image: {0}")]
+    ImageError(#[from] image::ImageError),
+}
+
+#[derive(Error, Debug)]
+pub enum TranscriptError {
+    #[error("network error: {0}")]
+    NetworkError(#[from] reqwest::Error),
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("unsupported transcript type \"{0}\"")]
+    UnsupportedFormat(String),
+    #[error("invalid transcript: {0}")]
+    Invalid(String),
+    #[error("invalid json transcript: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum PlayerError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("player error: {0}")]
+    Backend(String),
+    #[error("no episode with id \"{0}\"")]
+    EpisodeNotFound(String),
+    #[error(transparent)]
+    Library(#[from] LibraryError),
+    #[error("failed to record play session: {0}")]
+    History(#[from] HistoryError),
+    #[cfg(feature = "mpris")]
+    #[error("d-bus error: {0}")]
+    Dbus(#[from] zbus::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum ControlError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("invalid message: {0}")]
+    InvalidMessage(#[from] serde_json::Error),
+    #[error("{0}")]
+    Rpc(RpcError),
+    #[error("another player is already listening on {0}")]
+    InUse(PathBuf),
+    #[error("nothing is listening on {0}, is the tui running?")]
+    NotRunning(PathBuf),
+    #[error("the player closed the connection")]
+    Closed,
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("could not find the home directory")]
+    NoHome,
+    #[error("failed to read config file {path}: {source}")]
+    IoError {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid config file: {0}")]
+    ParseError(#[from] toml::de::Error),
+    #[error("invalid value for \"{key}\": {reason}")]
+    InvalidValue { key: String, reason: String },
+    #[error("failed to create http client: {0}")]
+    HttpClient(reqwest::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum SyncError {
+    #[error("network error: {0}")]
+    NetworkError(#[from] reqwest::Error),
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("failed to read sync state: {0}")]
+    ParseError(#[from] serde_json::Error),
+    #[error("sync backend unavailable: {0}")]
+    Unavailable(String),
+}
+
+#[derive(Error, Debug)]
+pub enum CredentialsError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("invalid credentials file: {0}")]
+    ParseError(#[from] serde_json::Error),
+    #[error("keyring error: {0}")]
+    Keyring(String),
+}
+
+#[derive(Error, Debug)]
+pub enum HistoryError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("invalid history entry: {0}")]
+    ParseError(#[from] serde_json::Error),
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    #[error("unexpected \"{0}\" in query")]
+    Unexpected(String),
+    #[error("query ended too soon")]
+    UnexpectedEnd,
+    #[error("unknown term \"{0}\" in query")]
+    UnknownField(String),
+    #[error("unknown sort \"{0}\", use date, duration, remaining, show or title")]
+    UnknownSort(String),
+    #[error("invalid length \"{0}\", use something like 90s, 30m or 1h30m")]
+    InvalidLength(String),
+    #[error("invalid limit \"{0}\"")]
+    InvalidLimit(String),
+    #[error("missing closing quote in query")]
+    UnclosedQuote,
 }
@@ -0,0 +1,208 @@
+//! Reading a feed while it downloads, one item at a time.
+//!
+//! Only the item being read is held in memory, along with the elements of
+//! the channel that are not items. Each item is read by `rss` on its own,
+//! wrapped in the same `<rss>` and `<channel>` tags it had in the feed, so
+//! namespaces and encodings are understood exactly as in the whole feed.
+
+use std::{io, pin::Pin, sync::Arc};
+
+use futures_util::{Stream, TryStreamExt, stream};
+use quick_xml::{
+    Reader, Writer,
+    events::{BytesStart, Event},
+};
+use reqwest::Response;
+use rss::{Channel, Item};
+use tokio_util::{bytes::Bytes, io::StreamReader};
+
+use crate::FeedError;
+
+type Body = StreamReader<Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>, Bytes>;
+
+/// A feed being read, see the [module docs](self).
+pub(crate) struct FeedReader {
+    reader: Reader<Body>,
+    buf: Vec<u8>,
+    /// The `<?xml?>` declaration, which has the encoding
+    declaration: Vec<u8>,
+    /// The opening `<rss>` or `<rdf:RDF>` tag, with its namespaces
+    root: Option<BytesStart<'static>>,
+    /// The opening `<channel>` tag
+    channel: Option<BytesStart<'static>>,
+    /// Everything in the channel that is not an item
+    head: Writer<Vec<u8>>,
+    /// Everything in the root that is not the channel or an item, like the
+    /// image of RSS 1.0 feeds
+    tail: Writer<Vec<u8>>,
+    /// How many elements are open
+    depth: usize,
+    in_channel: bool,
+    done: bool,
+}
+
+impl FeedReader {
+    pub(crate) fn new(response: Response) -> Self {
+        let chunks = stream::try_unfold(response, |mut response| async move {
+            let chunk = response.chunk().await?;
+            Ok(chunk.map(|chunk| (chunk, response)))
+        })
+        .map_err(|err: reqwest::Error| io::Error::other(err.without_url()));
+        let body: Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>> = Box::pin(chunks);
+
+        Self {
+            reader: Reader::from_reader(StreamReader::new(body)),
+            buf: Vec::new(),
+            declaration: Vec::new(),
+            root: None,
+            channel: None,
+            head: Writer::new(Vec::new()),
+            tail: Writer::new(Vec::new()),
+            depth: 0,
+            in_channel: false,
+            done: false,
+        }
+    }
+
+    /// Read up to the next item of the feed, [`None`] once the feed has
+    /// ended.
+    ///
+    /// # Errors
+    /// Fails if the feed could not be downloaded, or is not a valid feed.
+    pub(crate) async fn next_item(&mut self) -> Result<Option<Item>, FeedError> {
+        while !self.done {
+            self.buf.clear();
+            let event = self
+                .reader
+                .read_event_into_async(&mut self.buf)
+                .await
+                .map_err(feed_error)?;
+            let target = if self.in_channel {
+                &mut self.head
+            } else {
+                &mut self.tail
+            };
+
+            match event {
+                Event::Decl(_) if self.depth == 0 => {
+                    let mut writer = Writer::new(Vec::new());
+                    write(&mut writer, event)?;
+                    self.declaration = writer.into_inner();
+                }
+                Event::Start(start) => {
+                    let name = start.name();
+                    let is_item = name.as_ref() == b"item"
+                        && (self.depth == 1 || (self.depth == 2 && self.in_channel));
+                    if is_item {
+                        let start = start.into_owned();
+                        return self.read_item(start).await.map(Some);
+                    }
+
+                    match self.depth {
+                        0 if matches!(name.as_ref(), b"rss" | b"rdf:RDF") => {
+                            self.root = Some(start.into_owned());
+                        }
+                        0 => return Err(rss::Error::InvalidStartTag.into()),
+                        1 if name.as_ref() == b"channel" => {
+                            self.channel = Some(start.into_owned());
+                            self.in_channel = true;
+                        }
+                        _ => write(target, Event::Start(start))?,
+                    }
+                    self.depth += 1;
+                }
+                Event::End(end) => {
+                    self.depth = self.depth.saturating_sub(1);
+                    match self.depth {
+                        0 => self.done = true,
+                        1 if self.in_channel => self.in_channel = false,
+                        _ => write(target, Event::End(end))?,
+                    }
+                }
+                Event::Eof => self.done = true,
+                _ if self.depth >= 2 => write(target, event)?,
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// The channel without its items, once all of them have been read.
+    ///
+    /// # Errors
+    /// Fails if the feed had no channel, or it is not valid.
+    pub(crate) fn channel(self) -> Result<Channel, FeedError> {
+        let (Some(root), Some(channel)) = (&self.root, &self.channel) else {
+            return Err(rss::Error::Eof.into());
+        };
+        let mut writer = Writer::new(self.declaration);
+        write(&mut writer, Event::Start(root.borrow()))?;
+        write(&mut writer, Event::Start(channel.borrow()))?;
+        writer.get_mut().extend(self.head.into_inner());
+        write(&mut writer, Event::End(channel.to_end()))?;
+        writer.get_mut().extend(self.tail.into_inner());
+        write(&mut writer, Event::End(root.to_end()))?;
+        Ok(Channel::read_from(&writer.into_inner()[..])?)
+    }
+
+    /// Read the rest of an item that has just started, and parse it on its
+    /// own.
+    async fn read_item(&mut self, start: BytesStart<'static>) -> Result<Item, FeedError> {
+        let Some(root) = &self.root else {
+            return Err(rss::Error::InvalidStartTag.into());
+        };
+        let channel = self
+            .channel
+            .clone()
+            .unwrap_or_else(|| BytesStart::new("channel"));
+
+        let mut writer = Writer::new(self.declaration.clone());
+        write(&mut writer, Event::Start(root.borrow()))?;
+        write(&mut writer, Event::Start(channel.borrow()))?;
+        write(&mut writer, Event::Start(start))?;
+        let mut depth = 1_usize;
+        while depth > 0 {
+            self.buf.clear();
+            let event = self
+                .reader
+                .read_event_into_async(&mut self.buf)
+                .await
+                .map_err(feed_error)?;
+            match event {
+                Event::Start(_) => depth += 1,
+                Event::End(_) => depth -= 1,
+                Event::Eof => return Err(rss::Error::Eof.into()),
+                _ => {}
+            }
+            write(&mut writer, event)?;
+        }
+        write(&mut writer, Event::End(channel.to_end()))?;
+        write(&mut writer, Event::End(root.to_end()))?;
+
+        let channel = Channel::read_from(&writer.into_inner()[..])?;
+        channel
+            .items
+            .into_iter()
+            .next()
+            .ok_or(FeedError::InvalidFeed(rss::Error::Eof))
+    }
+}
+
+/// Write an event, which can not fail when writing to memory.
+fn write(writer: &mut Writer<Vec<u8>>, event: Event) -> Result<(), FeedError> {
+    writer.write_event(event).map_err(rss::Error::from)?;
+    Ok(())
+}
+
+/// Network errors while reading come through the xml reader as io errors,
+/// this gets them back out.
+fn feed_error(err: quick_xml::Error) -> FeedError {
+    let quick_xml::Error::Io(io) = err else {
+        return FeedError::InvalidFeed(err.into());
+    };
+    match Arc::try_unwrap(io).map(io::Error::downcast::<reqwest::Error>) {
+        Ok(Ok(err)) => FeedError::NetworkError(err),
+        Ok(Err(io)) => FeedError::InvalidFeed(io.into()),
+        Err(io) => FeedError::InvalidFeed(quick_xml::Error::Io(io).into()),
+    }
+}
@@ -0,0 +1,302 @@
+//! A log of every time an episode was played, and statistics worked out from
+//! it.
+//!
+//! The history is kept apart from the library, as one json [`PlaySession`]
+//! per line, so that recording a session only ever appends to the file.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Datelike, Days, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::HistoryError;
+
+/// Name of the history file inside the data directory.
+pub const HISTORY_FILE_NAME: &str = "history.jsonl";
+
+/// One stretch of playing an episode without pausing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlaySession {
+    /// Url of the show the episode is from
+    pub show: String,
+    /// [`crate::Episode::id`] of the episode
+    pub episode: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    /// Position in the episode when playback started
+    pub start: Duration,
+    /// Position in the episode when playback stopped
+    pub end: Duration,
+    pub speed: f32,
+    /// How much less time it took because silence was skipped
+    #[serde(default)]
+    pub silence_saved: Duration,
+    /// If the episode was played to the end in this session
+    #[serde(default)]
+    pub finished: bool,
+}
+
+impl PlaySession {
+    /// How much of the episode was played.
+    #[must_use]
+    pub fn content(&self) -> Duration {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// How long it took to play, which is less than [`PlaySession::content`]
+    /// when sped up or skipping silence.
+    #[must_use]
+    pub fn listened(&self) -> Duration {
+        let played = if self.speed > 0.0 {
+            self.content().div_f32(self.speed)
+        } else {
+            self.content()
+        };
+        played.saturating_sub(self.silence_saved)
+    }
+
+    /// Time saved by playing faster than normal speed and skipping silence.
+    #[must_use]
+    pub fn time_saved(&self) -> Duration {
+        self.content().saturating_sub(self.listened())
+    }
+}
+
+/// Every [`PlaySession`], oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    sessions: Vec<PlaySession>,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// Read the history from `path`, sessions recorded later are appended to
+    /// it. A missing file is an empty history, and lines that can not be read,
+    /// such as one cut short by a crash, are skipped.
+    ///
+    /// # Errors
+    /// Fails if the file exists but could not be read.
+    pub fn load(path: &Path) -> Result<Self, HistoryError> {
+        let sessions = match fs::read_to_string(path) {
+            Ok(contents) => contents
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self {
+            sessions,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Add a session, writing it to the history file if there is one.
+    ///
+    /// # Errors
+    /// Fails if the session could not be written, it is still added.
+    pub fn record(&mut self, session: PlaySession) -> Result<(), HistoryError> {
+        let line = serde_json::to_string(&session)?;
+        self.sessions.push(session);
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{line}")?;
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn sessions(&self) -> &[PlaySession] {
+        &self.sessions
+    }
+
+    /// Work out statistics, with days starting at midnight in the time zone of
+    /// `now`.
+    #[must_use]
+    pub fn stats<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Stats {
+        let tz = now.timezone();
+        let day = |at: &DateTime<Utc>| at.with_timezone(&tz).date_naive();
+
+        let mut stats = Stats {
+            today: now.date_naive(),
+            ..Stats::default()
+        };
+        let mut shows: HashMap<&str, ShowStats> = HashMap::new();
+        let mut by_week = BTreeMap::new();
+        let mut by_month = BTreeMap::new();
+        for session in &self.sessions {
+            stats.listened += session.listened();
+            stats.time_saved += session.time_saved();
+            stats.silence_saved += session.silence_saved;
+            *stats
+                .listened_by_day
+                .entry(day(&session.started_at))
+                .or_default() += session.listened();
+
+            let show = shows.entry(&session.show).or_insert_with(|| ShowStats {
+                show: session.show.clone(),
+                ..ShowStats::default()
+            });
+            show.listened += session.listened();
+            show.sessions += 1;
+
+            if session.finished {
+                stats.episodes_finished += 1;
+                show.episodes_finished += 1;
+                let day = day(&session.ended_at);
+                *by_week.entry(week_start(day)).or_default() += 1;
+                *by_month.entry(day.with_day(1).unwrap_or(day)).or_default() += 1;
+            }
+        }
+        stats.sessions = self.sessions.len();
+
+        stats.shows = shows.into_values().collect();
+        stats.shows.sort_by(|a, b| {
+            b.listened
+                .cmp(&a.listened)
+                .then_with(|| a.show.cmp(&b.show))
+        });
+        stats.finished_by_week = by_week.into_iter().collect();
+        stats.finished_by_month = by_month.into_iter().collect();
+
+        let days = stats.listened_by_day.keys().copied().collect();
+        (stats.current_streak, stats.longest_streak) = streaks(&days, stats.today);
+        stats
+    }
+
+    /// Write every session as csv, with a header. Times are RFC 3339 and
+    /// positions are in seconds.
+    ///
+    /// # Errors
+    /// Fails if writing fails.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "show,episode,started_at,ended_at,start,end,speed,finished"
+        )?;
+        for session in &self.sessions {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{}",
+                csv_field(&session.show),
+                csv_field(&session.episode),
+                session.started_at.to_rfc3339(),
+                session.ended_at.to_rfc3339(),
+                session.start.as_secs_f64(),
+                session.end.as_secs_f64(),
+                session.speed,
+                session.finished,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write every session as a json array.
+    ///
+    /// # Errors
+    /// Fails if writing fails.
+    pub fn write_json(&self, writer: impl Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(writer, &self.sessions).map_err(io::Error::from)
+    }
+}
+
+/// Totals from a [`History`], see [`History::stats`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    /// Time spent listening, at whatever speed was used
+    pub listened: Duration,
+    /// Time saved by playing faster than normal speed and skipping silence
+    pub time_saved: Duration,
+    /// The part of [`Stats::time_saved`] from skipping silence
+    pub silence_saved: Duration,
+    pub sessions: usize,
+    pub episodes_finished: usize,
+    /// Totals for each show that has been listened to, most listened first
+    pub shows: Vec<ShowStats>,
+    /// Episodes finished in each week, by the monday it starts on, oldest first
+    pub finished_by_week: Vec<(NaiveDate, usize)>,
+    /// Episodes finished in each month, by its first day, oldest first
+    pub finished_by_month: Vec<(NaiveDate, usize)>,
+    /// Time spent listening on each day that had any listening
+    pub listened_by_day: BTreeMap<NaiveDate, Duration>,
+    /// Days in a row with some listening, up to today. Today not having any
+    /// listening yet does not break the streak.
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    today: NaiveDate,
+}
+
+impl Stats {
+    /// Time spent listening on each of the last `days` days, ending with
+    /// today.
+    #[must_use]
+    pub fn last_days(&self, days: u64) -> Vec<Duration> {
+        (0..days)
+            .rev()
+            .map(|ago| {
+                self.today
+                    .checked_sub_days(Days::new(ago))
+                    .and_then(|day| self.listened_by_day.get(&day))
+                    .copied()
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+}
+
+/// Totals for one show, see [`Stats::shows`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShowStats {
+    /// Url of the show
+    pub show: String,
+    pub listened: Duration,
+    pub sessions: usize,
+    pub episodes_finished: usize,
+}
+
+fn week_start(day: NaiveDate) -> NaiveDate {
+    day.checked_sub_days(Days::new(day.weekday().num_days_from_monday().into()))
+        .unwrap_or(day)
+}
+
+/// The current and longest runs of consecutive days.
+fn streaks(days: &BTreeSet<NaiveDate>, today: NaiveDate) -> (u32, u32) {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for &day in days {
+        run = match previous {
+            Some(previous) if previous.succ_opt() == Some(day) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(day);
+    }
+
+    let yesterday = today.pred_opt();
+    let current = match previous {
+        Some(last) if last == today || Some(last) == yesterday => run,
+        _ => 0,
+    };
+    (current, longest)
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -0,0 +1,286 @@
+//! The http client used for every request: feeds, media, artwork,
+//! transcripts and syncing.
+//!
+//! It sends a `User-Agent`, gives up on hosts that take too long to connect
+//! or to send anything, goes through a proxy if one is set, and accepts gzip
+//! and brotli bodies. Requests that fail for a reason that may pass are tried
+//! again after waiting a little longer each time, or as long as the server
+//! asked for with `Retry-After`.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::{
+    Client, ClientBuilder, IntoUrl, Proxy, RequestBuilder, Response, StatusCode,
+    header::RETRY_AFTER, redirect::Policy,
+};
+
+/// `User-Agent` header sent when none is configured.
+pub const DEFAULT_USER_AGENT: &str = concat!("undersea/", env!("CARGO_PKG_VERSION"));
+
+/// How long to wait for a connection when none is configured.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for each read when none is configured.
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Settings for building an [`HttpClient`], see [`crate::Config::http_client`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpOptions {
+    /// `User-Agent` header sent with every request
+    pub user_agent: String,
+    /// Proxy url to send every request through, `http://`, `https://`,
+    /// `socks5://` or `socks5h://`
+    pub proxy: Option<String>,
+    /// How long to wait for a connection to a host
+    pub connect_timeout: Duration,
+    /// How long to wait for each read from a host, so slow downloads are fine
+    /// as long as something keeps arriving
+    pub read_timeout: Duration,
+    /// When to try failed requests again
+    pub retry: Retry,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            proxy: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            retry: Retry::default(),
+        }
+    }
+}
+
+/// How often, and after how long, failed requests are tried again.
+///
+/// Requests are tried again when they could not connect, and when the server
+/// answers 429 or 503. Requests that are safe to send twice are also tried
+/// again when they time out, or the server answers 502 or 504.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retry {
+    /// How many times to try again after the first attempt
+    pub retries: u32,
+    /// How long to wait before the first retry, doubled for each one after
+    pub backoff: Duration,
+    /// The longest to wait before a retry, a `Retry-After` longer than this
+    /// is not waited for and the response is returned as it is
+    pub max_delay: Duration,
+}
+
+impl Retry {
+    /// Never try again.
+    pub const NONE: Self = Self {
+        retries: 0,
+        backoff: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            backoff: Duration::from_millis(500),
+            max_delay: Duration::from_mins(1),
+        }
+    }
+}
+
+/// A [`reqwest::Client`] that tries failed requests again, see the
+/// [module docs](self).
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: Client,
+    /// The same settings, but never following redirects, for finding out
+    /// where a feed has moved to
+    no_redirects: Client,
+    retry: Retry,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new(&HttpOptions::default()).expect("the default http client to build")
+    }
+}
+
+impl From<Client> for HttpClient {
+    /// Use a client that was set up by hand, as it is, without retries.
+    fn from(client: Client) -> Self {
+        Self {
+            client,
+            no_redirects: Client::builder()
+                .redirect(Policy::none())
+                .build()
+                .unwrap_or_default(),
+            retry: Retry::NONE,
+        }
+    }
+}
+
+impl HttpClient {
+    /// Build a client with `options`.
+    ///
+    /// # Errors
+    /// Fails if the proxy is not a valid url, or the client could not be
+    /// created.
+    pub fn new(options: &HttpOptions) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            client: builder(options)?.build()?,
+            no_redirects: builder(options)?.redirect(Policy::none()).build()?,
+            retry: options.retry,
+        })
+    }
+
+    /// Start a `GET` request, send it with [`HttpClient::send`].
+    pub fn get(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    /// Start a `POST` request, send it with [`HttpClient::send`].
+    pub fn post(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    /// The client that does not follow redirects.
+    pub(crate) fn no_redirects(&self) -> &Client {
+        &self.no_redirects
+    }
+
+    /// Send a request, trying again while it fails in a way that may pass.
+    /// Requests with a streamed body can not be sent again, so are only sent
+    /// once.
+    ///
+    /// # Errors
+    /// Fails if the last attempt fails. A response with an error status is
+    /// returned as it is, for the caller to check.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, reqwest::Error> {
+        let (client, request) = request.build_split();
+        let request = request?;
+        let idempotent = request.method().is_idempotent();
+
+        let mut attempt = 0;
+        loop {
+            let retry = (attempt < self.retry.retries)
+                .then(|| request.try_clone())
+                .flatten();
+            let Some(next) = retry else {
+                return client.execute(request).await;
+            };
+
+            let delay = match client.execute(next).await {
+                Ok(response) => match self.retry_after(&response, attempt, idempotent) {
+                    Some(delay) => delay,
+                    None => return Ok(response),
+                },
+                Err(err) if err.is_connect() || (idempotent && err.is_timeout()) => {
+                    self.backoff(attempt)
+                }
+                Err(err) => return Err(err),
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// How long to wait before trying again after `response`, [`None`] if it
+    /// should not be tried again.
+    fn retry_after(&self, response: &Response, attempt: u32, idempotent: bool) -> Option<Duration> {
+        let status = response.status();
+        let transient = matches!(
+            status,
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+        ) || (idempotent
+            && matches!(
+                status,
+                StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT
+            ));
+        if !transient {
+            return None;
+        }
+
+        let asked = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, Utc::now()));
+        match asked {
+            Some(delay) if delay > self.retry.max_delay => None,
+            Some(delay) => Some(delay),
+            None => Some(self.backoff(attempt)),
+        }
+    }
+
+    /// How long to wait before retry number `attempt`, counting from 0.
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.retry
+            .backoff
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.retry.max_delay)
+    }
+}
+
+/// A client builder with everything in `options` but the retries.
+fn builder(options: &HttpOptions) -> Result<ClientBuilder, reqwest::Error> {
+    let mut builder = Client::builder()
+        .user_agent(&options.user_agent)
+        .connect_timeout(options.connect_timeout)
+        .read_timeout(options.read_timeout)
+        .gzip(true)
+        .brotli(true);
+    if let Some(proxy) = &options.proxy {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    Ok(builder)
+}
+
+/// Read a `Retry-After` header, either a number of seconds or a date.
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.with_timezone(&Utc) - now).to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_retry_after() {
+        let now = DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_mins(2)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        // a date that has passed means now
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+        assert_eq!(parse_retry_after("-5", now), None);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit() {
+        let client = HttpClient {
+            retry: Retry {
+                retries: 10,
+                backoff: Duration::from_secs(1),
+                max_delay: Duration::from_secs(5),
+            },
+            ..HttpClient::from(Client::new())
+        };
+
+        let delays: Vec<_> = (0..5).map(|attempt| client.backoff(attempt)).collect();
+        assert_eq!(delays, [1, 2, 4, 5, 5].map(Duration::from_secs).to_vec());
+    }
+}
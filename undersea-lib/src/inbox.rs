@@ -0,0 +1,70 @@
+//! New episodes from every show in one list, to go through what came out
+//! without visiting each show.
+
+use chrono::{DateTime, Utc};
+
+use crate::{Episode, Show, Shows, show_matches};
+
+/// Which episodes are in the inbox, see [`Shows::inbox`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InboxFilter {
+    /// Only episodes released after this, such as [`Shows::inbox_visited_at`]
+    pub since: Option<DateTime<Utc>>,
+    /// Leave out finished episodes.
+    pub unplayed_only: bool,
+    /// Only episodes that have been downloaded.
+    pub downloaded_only: bool,
+    /// Only episodes from this show, by its url or name
+    pub show: Option<String>,
+    /// Only episodes from shows with this tag
+    pub tag: Option<String>,
+}
+
+impl InboxFilter {
+    /// Unplayed episodes from every show, the usual inbox.
+    #[must_use]
+    pub fn unplayed() -> Self {
+        Self {
+            unplayed_only: true,
+            ..Self::default()
+        }
+    }
+
+    fn matches(&self, show: &Show, episode: &Episode) -> bool {
+        self.since.is_none_or(|since| episode.date > since)
+            && !(self.unplayed_only && episode.finished)
+            && !(self.downloaded_only && episode.download_path.is_none())
+            && self
+                .show
+                .as_ref()
+                .is_none_or(|query| show_matches(show, query))
+            && self.tag.as_ref().is_none_or(|tag| show.has_tag(tag))
+    }
+}
+
+impl Shows {
+    /// Episodes from every show that match `filter`, newest first.
+    #[must_use]
+    pub fn inbox(&self, filter: &InboxFilter) -> Vec<(&Show, &Episode)> {
+        let mut episodes = self
+            .shows
+            .iter()
+            .flat_map(|show| show.episodes.iter().map(move |episode| (show, episode)))
+            .filter(|(show, episode)| filter.matches(show, episode))
+            .collect::<Vec<_>>();
+        episodes.sort_by_key(|(_, episode)| std::cmp::Reverse(episode.date));
+        episodes
+    }
+
+    /// When the inbox was last looked at, so that only what came out since
+    /// can be shown.
+    #[must_use]
+    pub fn inbox_visited_at(&self) -> Option<&DateTime<Utc>> {
+        self.inbox_visited_at.as_ref()
+    }
+
+    /// Remember that the inbox was looked at, this is not synced.
+    pub fn mark_inbox_visited(&mut self, at: DateTime<Utc>) {
+        self.inbox_visited_at = Some(at);
+    }
+}
@@ -1,19 +1,82 @@
 use chrono::{DateTime, Utc};
+use futures_util::{StreamExt, stream};
 use reqwest::IntoUrl;
+use serde::{Deserialize, Serialize};
+use std::time;
 
+pub mod artwork;
+pub mod bookmark;
+pub mod config;
+pub mod control;
+pub mod credentials;
+pub mod description;
+mod download;
 mod episode;
 mod error;
+mod feed;
+pub mod history;
+pub mod http;
+mod inbox;
+pub mod library;
+pub mod opml;
+pub mod player;
+pub mod playlist;
+mod settings;
 mod show;
+pub mod sync;
+pub mod transcript;
 
-pub use episode::Episode;
-pub use error::FeedError;
-pub use show::Show;
+pub use config::Config;
+use credentials::CredentialStore;
+use description::DescriptionCache;
+pub use episode::{Chapter, Episode};
+pub use error::{
+    ArtworkError, ClipError, ConfigError, ControlError, CredentialsError, DownloadError, FeedError,
+    HistoryError, LibraryError, OpmlError, PlayerError, QueryError, SyncError, TranscriptError,
+};
+use http::HttpClient;
+pub use inbox::InboxFilter;
+pub use settings::{AutoDownload, ShowSettings, SortOrder};
+use show::Refreshed;
+pub use show::{Gone, Show};
+use sync::{Change, EpisodeRef};
 
 /// All of a users shows, the main point of interaction with the library
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[allow(clippy::struct_field_names)]
 pub struct Shows {
     pub(crate) shows: Vec<Show>,
     pub(crate) last_change: DateTime<Utc>,
+    /// Ids of episodes to play next, in order
+    #[serde(default)]
+    pub(crate) queue: Vec<String>,
+    /// When the queue last changed, on any device
+    #[serde(default)]
+    pub(crate) queue_changed_at: Option<DateTime<Utc>>,
+    /// Saved smart playlists
+    #[serde(default)]
+    pub(crate) playlists: Vec<playlist::SmartPlaylist>,
+    /// When the inbox was last looked at
+    #[serde(default)]
+    pub(crate) inbox_visited_at: Option<DateTime<Utc>>,
+    /// Changes that have not been synced yet
+    #[serde(default)]
+    pub(crate) journal: Vec<sync::JournalEntry>,
+    /// Used for every request, see [`Shows::set_client`]
+    #[serde(skip)]
+    pub(crate) client: HttpClient,
+    /// Credentials for private feeds, kept apart from the library, see
+    /// [`Shows::set_credentials`]
+    #[serde(skip)]
+    pub(crate) credentials: CredentialStore,
+    /// If feed urls are synced with their secrets, see
+    /// [`Shows::set_sync_secret_urls`]
+    #[serde(skip)]
+    pub(crate) sync_secret_urls: bool,
+    /// Where descriptions are kept that are not in memory, see
+    /// [`Shows::set_description_cache`]
+    #[serde(skip)]
+    pub(crate) descriptions: Option<DescriptionCache>,
 }
 
 impl Default for Shows {
@@ -23,11 +86,26 @@ impl Default for Shows {
         Self {
             shows: Vec::new(),
             last_change: Utc::now(),
+            queue: Vec::new(),
+            queue_changed_at: None,
+            playlists: Vec::new(),
+            inbox_visited_at: None,
+            journal: Vec::new(),
+            client: HttpClient::default(),
+            credentials: CredentialStore::default(),
+            sync_secret_urls: false,
+            descriptions: None,
         }
     }
 }
 
 impl Shows {
+    /// Set the http client used to fetch feeds and media, such as one made by
+    /// [`Config::http_client`].
+    pub fn set_client(&mut self, client: impl Into<HttpClient>) {
+        self.client = client.into();
+    }
+
     /// Add a new show from a url to the list of feeds
     /// # Errors
     /// Fails if the show cannot be added, most likely because of network issues
@@ -35,11 +113,34 @@ impl Shows {
     where
         S: IntoUrl + Clone + Into<String>,
     {
-        self.shows.push(Show::new(url).await?);
-        self.last_change = Utc::now();
+        let url = self.add_unrecorded(url).await?;
+        self.record(Change::Subscribe { url });
         Ok(())
     }
 
+    /// Add a show without writing it to the journal, returns its url.
+    pub(crate) async fn add_unrecorded<S>(&mut self, url: S) -> Result<String, FeedError>
+    where
+        S: IntoUrl + Clone + Into<String>,
+    {
+        let url: String = url.into();
+        let show = Show::new(
+            url.as_str(),
+            &self.client,
+            self.credentials.get(&url),
+            self.descriptions.as_ref(),
+        )
+        .await?;
+        // credentials follow a feed that moved when it was added
+        if show.url != url {
+            self.credentials.rename(&url, &show.url);
+        }
+        let url = show.url.clone();
+        self.shows.push(show);
+        self.last_change = Utc::now();
+        Ok(url)
+    }
+
     /// Add multiple shows from urls
     /// # Errors
     /// Will fail if any of the shows were unable to be added, this will not
@@ -71,42 +172,373 @@ impl Shows {
     pub fn shows(&self) -> Vec<&Show> {
         self.shows.iter().collect()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    const TESTING_URLS: [&str; 5] = [
-        // just roll with it
-        "https://feed.podbean.com/justrollwithit/feed.xml",
-        // the magnus archives
-        "https://feeds.acast.com/public/shows/b6085bcd-3542-4a43-b6a8-021e3fd251b8",
-        // scp: find us alive
-        "https://feeds.redcircle.com/d4548587-da15-4a7e-8b83-3c78b44ec7e5",
-        // lost terminal
-        "https://www.spreaker.com/show/4488937/episodes/feed",
-        // red vally (this one can be annoying as the mp3 links redirect!)
-        "https://feeds.megaphone.fm/redvalley",
-    ];
-
-    #[tokio::test]
-    async fn add_single_from_url() {
-        let mut shows = Shows::default();
-
-        shows.add(TESTING_URLS[3]).await.expect("to add shows");
-        assert_eq!(shows.shows.len(), 1);
-    }
-
-    #[tokio::test]
-    async fn add_multiple() {
-        let mut shows = Shows::default();
-
-        shows
-            .add_multiple(TESTING_URLS)
+    /// Time of the last change made to the users shows, including progress.
+    #[must_use]
+    pub fn last_change(&self) -> &DateTime<Utc> {
+        &self.last_change
+    }
+
+    /// Find a show by its url, or by its name ignoring case. A show whose feed
+    /// has moved is also found by the urls it had before.
+    ///
+    /// # Errors
+    /// Returns [`None`] if no show matched.
+    #[must_use]
+    pub fn find_show(&self, query: &str) -> Option<&Show> {
+        self.shows.iter().find(|show| show_matches(show, query))
+    }
+
+    /// Remove a show, found in the same way as [`Shows::find_show`].
+    ///
+    /// # Errors
+    /// Fails if no show matched.
+    pub fn remove(&mut self, query: &str) -> Result<Show, LibraryError> {
+        let show = self.remove_unrecorded(query)?;
+        self.record(Change::Unsubscribe {
+            url: show.url.clone(),
+        });
+        Ok(show)
+    }
+
+    pub(crate) fn remove_unrecorded(&mut self, query: &str) -> Result<Show, LibraryError> {
+        let index = self
+            .shows
+            .iter()
+            .position(|show| show_matches(show, query))
+            .ok_or_else(|| LibraryError::ShowNotFound(query.to_string()))?;
+
+        let show = self.shows.remove(index);
+        self.queue.retain(|id| show.episode_by_id(id).is_none());
+        if let Some(descriptions) = &self.descriptions {
+            descriptions.remove(&show.url);
+        }
+        self.last_change = Utc::now();
+        Ok(show)
+    }
+
+    /// Change the settings of a show, found in the same way as [`Shows::find_show`].
+    ///
+    /// # Errors
+    /// Fails if no show matched.
+    pub fn set_show_settings(
+        &mut self,
+        query: &str,
+        settings: ShowSettings,
+    ) -> Result<(), LibraryError> {
+        let show = self
+            .shows
+            .iter_mut()
+            .find(|show| show_matches(show, query))
+            .ok_or_else(|| LibraryError::ShowNotFound(query.to_string()))?;
+
+        show.set_settings(settings);
+        self.last_change = Utc::now();
+        Ok(())
+    }
+
+    /// Every tag given to any show, sorted and ignoring case.
+    #[must_use]
+    pub fn tags(&self) -> Vec<&str> {
+        let mut tags = self
+            .shows
+            .iter()
+            .flat_map(|show| show.tags.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+        tags.sort_by_key(|tag| tag.to_lowercase());
+        tags.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+        tags
+    }
+
+    /// Shows with a tag, ignoring case.
+    #[must_use]
+    pub fn shows_tagged(&self, tag: &str) -> Vec<&Show> {
+        self.shows.iter().filter(|show| show.has_tag(tag)).collect()
+    }
+
+    /// Give a show, found in the same way as [`Shows::find_show`], some tags.
+    /// Tags are trimmed, and ones the show already has are left out.
+    ///
+    /// # Errors
+    /// Fails if no show matched.
+    pub fn add_tags<S: AsRef<str>>(&mut self, query: &str, tags: &[S]) -> Result<(), LibraryError> {
+        let show = self
+            .shows
+            .iter_mut()
+            .find(|show| show_matches(show, query))
+            .ok_or_else(|| LibraryError::ShowNotFound(query.to_string()))?;
+
+        for tag in tags {
+            let tag = tag.as_ref().trim();
+            if !tag.is_empty() && !show.has_tag(tag) {
+                show.tags.push(tag.to_string());
+            }
+        }
+        self.last_change = Utc::now();
+        Ok(())
+    }
+
+    /// Take tags away from a show, found in the same way as
+    /// [`Shows::find_show`].
+    ///
+    /// # Errors
+    /// Fails if no show matched.
+    pub fn remove_tags<S: AsRef<str>>(
+        &mut self,
+        query: &str,
+        tags: &[S],
+    ) -> Result<(), LibraryError> {
+        let show = self
+            .shows
+            .iter_mut()
+            .find(|show| show_matches(show, query))
+            .ok_or_else(|| LibraryError::ShowNotFound(query.to_string()))?;
+
+        show.tags.retain(|tag| {
+            !tags
+                .iter()
+                .any(|removed| removed.as_ref().trim().eq_ignore_ascii_case(tag))
+        });
+        self.last_change = Utc::now();
+        Ok(())
+    }
+
+    /// Episodes waiting to be played, in order, along with the show they are from.
+    #[must_use]
+    pub fn queue(&self) -> Vec<(&Show, &Episode)> {
+        self.queue
+            .iter()
+            .filter_map(|id| self.episode_by_id(id))
+            .collect()
+    }
+
+    /// Add an episode to the end of the queue, if it is not already queued.
+    ///
+    /// # Errors
+    /// Fails if there is no episode with that id.
+    pub fn enqueue(&mut self, id: &str) -> Result<(), LibraryError> {
+        if self.episode_by_id(id).is_none() {
+            return Err(LibraryError::EpisodeNotFound(id.to_string()));
+        }
+        if !self.queue.iter().any(|queued| queued == id) {
+            self.queue.push(id.to_string());
+            self.record_queue();
+        }
+        Ok(())
+    }
+
+    /// Take an episode out of the queue, returns if it was queued.
+    pub fn dequeue(&mut self, id: &str) -> bool {
+        let queued = self.queue.len();
+        self.queue.retain(|queued| queued != id);
+        let removed = self.queue.len() != queued;
+        if removed {
+            self.record_queue();
+        }
+        removed
+    }
+
+    /// Check every show for new episodes, fetching up to `concurrency` feeds
+    /// at once. A show failing to refresh does not stop the others from being
+    /// refreshed.
+    pub async fn refresh(&mut self, concurrency: usize) -> RefreshSummary {
+        self.refresh_where(concurrency, |_| true).await
+    }
+
+    /// Like [`Shows::refresh`], but only for shows that have not been checked
+    /// in the last `max_age`.
+    pub async fn refresh_stale(
+        &mut self,
+        max_age: time::Duration,
+        concurrency: usize,
+    ) -> RefreshSummary {
+        let max_age = chrono::Duration::from_std(max_age).unwrap_or(chrono::Duration::MAX);
+        let now = Utc::now();
+        self.refresh_where(concurrency, |show| now - show.last_checked >= max_age)
             .await
-            .expect("to add shows");
+    }
+
+    async fn refresh_where<F>(&mut self, concurrency: usize, should_refresh: F) -> RefreshSummary
+    where
+        F: Fn(&Show) -> bool,
+    {
+        let client = &self.client;
+        let credentials = &self.credentials;
+        let descriptions = self.descriptions.as_ref();
+        let results = stream::iter(self.shows.iter_mut().filter(|show| should_refresh(show)))
+            .map(|show| async move {
+                let result = show
+                    .refresh(client, credentials.get(&show.url), descriptions)
+                    .await;
+                (show, result)
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut summary = RefreshSummary::default();
+        let mut to_queue = Vec::new();
+        let mut moves = Vec::new();
+        for (show, result) in results {
+            match result {
+                Ok(Refreshed {
+                    new_episodes,
+                    moved_from,
+                }) => {
+                    if let Some(from) = moved_from {
+                        moves.push((from, show.url.clone()));
+                    }
+                    summary.new_episodes += new_episodes.len();
+                    summary
+                        .to_download
+                        .extend(show.episodes_to_download(&new_episodes));
+                    if show.settings.add_to_queue {
+                        to_queue.extend(
+                            new_episodes
+                                .iter()
+                                .filter_map(|id| show.episode_by_id(id))
+                                .map(|ep| (ep.date, ep.id.clone())),
+                        );
+                    }
+                }
+                Err(err) => summary.failed.push((show.url.clone(), err)),
+            }
+        }
+        self.last_change = Utc::now();
+        for (from, to) in &moves {
+            self.credentials.rename(from, to);
+            self.record(Change::Move {
+                from: from.clone(),
+                to: to.clone(),
+            });
+        }
+        summary.moved = moves;
+        // shows finish refreshing in any order, so queue new episodes by date
+        if !to_queue.is_empty() {
+            to_queue.sort();
+            self.queue.extend(to_queue.into_iter().map(|(_, id)| id));
+            self.record_queue();
+        }
+        summary
+    }
 
-        assert_eq!(shows.shows.len(), TESTING_URLS.len());
+    /// Find an episode by its [`Episode::id`], along with the show it is from.
+    ///
+    /// # Errors
+    /// Returns [`None`] if no show has an episode with that id.
+    #[must_use]
+    pub fn episode_by_id(&self, id: &str) -> Option<(&Show, &Episode)> {
+        self.shows
+            .iter()
+            .find_map(|show| show.episode_by_id(id).map(|episode| (show, episode)))
     }
+
+    pub(crate) fn episode_by_id_mut(&mut self, id: &str) -> Result<&mut Episode, LibraryError> {
+        self.shows
+            .iter_mut()
+            .find_map(|show| show.episode_by_id_mut(id))
+            .ok_or_else(|| LibraryError::EpisodeNotFound(id.to_string()))
+    }
+
+    /// Mark an episode as finished or not finished.
+    ///
+    /// # Errors
+    /// Fails if there is no episode with that id.
+    pub fn set_finished(&mut self, id: &str, finished: bool) -> Result<(), LibraryError> {
+        let now = Utc::now();
+        self.episode_by_id_mut(id)?.set_finished(finished, now);
+        self.last_change = now;
+
+        let (show, episode) = self.sync_ref(id)?;
+        self.record(Change::Finished {
+            show,
+            episode,
+            finished,
+        });
+        Ok(())
+    }
+
+    /// Set where an episode should resume playback from.
+    ///
+    /// # Errors
+    /// Fails if there is no episode with that id.
+    pub fn set_resume_time(
+        &mut self,
+        id: &str,
+        resume_time: time::Duration,
+    ) -> Result<(), LibraryError> {
+        let now = Utc::now();
+        self.episode_by_id_mut(id)?
+            .set_resume_time(resume_time, now);
+        self.last_change = now;
+
+        let (show, episode) = self.sync_ref(id)?;
+        self.record(Change::Progress {
+            show,
+            episode,
+            position: resume_time,
+            played_at: None,
+        });
+        Ok(())
+    }
+
+    /// Set where playback of an episode should resume from after playing
+    /// it, which also remembers when it was played.
+    ///
+    /// # Errors
+    /// Fails if there is no episode with that id.
+    pub fn set_played(
+        &mut self,
+        id: &str,
+        resume_time: time::Duration,
+    ) -> Result<(), LibraryError> {
+        let now = Utc::now();
+        let episode = self.episode_by_id_mut(id)?;
+        episode.set_resume_time(resume_time, now);
+        episode.last_played_at = Some(now);
+        self.last_change = now;
+
+        let (show, episode) = self.sync_ref(id)?;
+        self.record(Change::Progress {
+            show,
+            episode,
+            position: resume_time,
+            played_at: Some(now),
+        });
+        Ok(())
+    }
+
+    /// The show url and [`EpisodeRef`] used to tell other devices about an episode.
+    pub(crate) fn sync_ref(&self, id: &str) -> Result<(String, EpisodeRef), LibraryError> {
+        let (show, episode) = self
+            .episode_by_id(id)
+            .ok_or_else(|| LibraryError::EpisodeNotFound(id.to_string()))?;
+
+        Ok((
+            show.url.clone(),
+            EpisodeRef {
+                id: episode.id.clone(),
+                media_url: episode.media_url.clone(),
+                duration: episode.duration,
+            },
+        ))
+    }
+}
+
+/// The result of [`Shows::refresh`]
+#[derive(Debug, Default)]
+pub struct RefreshSummary {
+    /// How many new episodes were found across all shows.
+    pub new_episodes: usize,
+    /// Ids of episodes that should be downloaded, because of the
+    /// [`AutoDownload`] setting of their show.
+    pub to_download: Vec<String>,
+    /// The url of each show that failed to refresh, and why.
+    pub failed: Vec<(String, FeedError)>,
+    /// The old and new url of each show whose feed has moved.
+    pub moved: Vec<(String, String)>,
+}
+
+fn show_matches(show: &Show, query: &str) -> bool {
+    show.has_url(query) || show.name.eq_ignore_ascii_case(query)
 }
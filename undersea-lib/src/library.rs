@@ -0,0 +1,52 @@
+//! Reading and writing a users [`Shows`] to disk.
+//!
+//! The library is stored as json so that it is easy to read and move to other
+//! programs.
+
+use std::{fs, path::Path};
+
+use crate::{LibraryError, Shows};
+
+/// Name of the library file inside the data directory.
+pub const LIBRARY_FILE_NAME: &str = "library.json";
+
+impl Shows {
+    /// Read shows from a library file.
+    ///
+    /// # Errors
+    /// Fails if the file could not be read or is not a valid library.
+    pub fn load(path: &Path) -> Result<Self, LibraryError> {
+        let contents = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Read shows from a library file, or start with no shows if the file does
+    /// not exist yet.
+    ///
+    /// # Errors
+    /// Fails if the file exists but could not be read or is not a valid library.
+    pub fn load_or_default(path: &Path) -> Result<Self, LibraryError> {
+        if path.exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Write shows to a library file, creating any missing directories. The
+    /// file is written next to the old one and then moved over it, so a crash
+    /// will never leave a half written library.
+    ///
+    /// # Errors
+    /// Fails if the file could not be written.
+    pub fn save(&self, path: &Path) -> Result<(), LibraryError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(temp_path, path)?;
+        Ok(())
+    }
+}
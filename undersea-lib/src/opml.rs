@@ -0,0 +1,277 @@
+//! Importing and exporting subscriptions as [OPML](http://opml.org/spec2.opml),
+//! the format almost every podcast app uses to move subscriptions around.
+//!
+//! Tags are written as folders, outlines without an `xmlUrl` that hold the
+//! shows with that tag. A show is written once, in the folder of its first
+//! tag, with all of its tags in the `category` attribute so that none are lost
+//! when the file is imported again.
+
+use std::fmt::Write;
+
+use quick_xml::{Reader, escape::escape, events::Event};
+
+use crate::{OpmlError, Show, Shows};
+
+/// A feed found in an OPML file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpmlFeed {
+    pub url: String,
+    pub title: Option<String>,
+    /// Names of the folders the feed was in, outermost first, then anything
+    /// in its `category` attribute
+    pub tags: Vec<String>,
+}
+
+/// Find every feed in an OPML document. Any outline with an `xmlUrl` is
+/// counted as a feed, no matter how deeply it is nested.
+///
+/// # Errors
+/// Fails if the document is not valid xml.
+pub fn parse(contents: &str) -> Result<Vec<OpmlFeed>, OpmlError> {
+    let mut reader = Reader::from_str(contents);
+    let mut feeds = Vec::new();
+    // every open outline, with its name if it is a folder
+    let mut open: Vec<Option<String>> = Vec::new();
+
+    loop {
+        let (element, is_empty) = match reader.read_event()? {
+            Event::Start(element) => (element, false),
+            Event::Empty(element) => (element, true),
+            Event::End(element) if element.local_name().as_ref() == b"outline" => {
+                open.pop();
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+        if element.local_name().as_ref() != b"outline" {
+            continue;
+        }
+
+        let mut url = None;
+        let mut title = None;
+        let mut categories = None;
+        for attribute in element.attributes() {
+            let attribute = attribute?;
+            let value = attribute
+                .decode_and_unescape_value(reader.decoder())?
+                .into_owned();
+            match attribute.key.local_name().as_ref() {
+                b"xmlUrl" => url = Some(value),
+                b"title" => title = Some(value),
+                b"text" if title.is_none() => title = Some(value),
+                b"category" => categories = Some(value),
+                _ => {}
+            }
+        }
+
+        let Some(url) = url else {
+            if !is_empty {
+                open.push(title.filter(|title| !title.trim().is_empty()));
+            }
+            continue;
+        };
+
+        let mut tags = Vec::new();
+        let folders = open.iter().flatten().map(String::as_str);
+        // categories are paths like `/fiction`, only the names are kept
+        let categories = categories
+            .iter()
+            .flat_map(|categories| categories.split(','))
+            .map(|category| category.trim().trim_matches('/'));
+        for tag in folders.chain(categories) {
+            let tag = tag.trim();
+            if !tag.is_empty()
+                && !tags
+                    .iter()
+                    .any(|own: &String| own.eq_ignore_ascii_case(tag))
+            {
+                tags.push(tag.to_string());
+            }
+        }
+        feeds.push(OpmlFeed { url, title, tags });
+        if !is_empty {
+            open.push(None);
+        }
+    }
+
+    Ok(feeds)
+}
+
+/// Write all of a users shows as an OPML document. Secrets in feed urls are
+/// hidden, see [`Shows::redact`], unless `secrets` is set.
+#[must_use]
+pub fn write(shows: &Shows, secrets: bool) -> String {
+    let url = |show: &Show| {
+        if secrets {
+            show.url().to_string()
+        } else {
+            shows.redact(show.url())
+        }
+    };
+    let mut opml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <opml version=\"2.0\">\n  \
+         <head>\n    <title>undersea subscriptions</title>\n  </head>\n  \
+         <body>\n",
+    );
+
+    for tag in shows.tags() {
+        let folder = shows
+            .shows()
+            .into_iter()
+            .filter(|show| {
+                show.tags
+                    .first()
+                    .is_some_and(|first| first.eq_ignore_ascii_case(tag))
+            })
+            .collect::<Vec<_>>();
+        if folder.is_empty() {
+            continue;
+        }
+        let _ = writeln!(
+            opml,
+            "    <outline text=\"{0}\" title=\"{0}\">",
+            escape(tag)
+        );
+        for show in folder {
+            write_show(&mut opml, show, &url(show), "      ");
+        }
+        opml.push_str("    </outline>\n");
+    }
+    for show in shows.shows() {
+        if show.tags.is_empty() {
+            write_show(&mut opml, show, &url(show), "    ");
+        }
+    }
+
+    opml.push_str("  </body>\n</opml>\n");
+    opml
+}
+
+fn write_show(opml: &mut String, show: &Show, url: &str, indent: &str) {
+    let name = escape(show.name());
+    let category = if show.tags.is_empty() {
+        String::new()
+    } else {
+        let categories = show
+            .tags
+            .iter()
+            .map(|tag| format!("/{tag}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(" category=\"{}\"", escape(&categories))
+    };
+    let _ = writeln!(
+        opml,
+        "{indent}<outline type=\"rss\" text=\"{name}\" title=\"{name}\" xmlUrl=\"{}\"{category}/>",
+        escape(url)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn show(name: &str, url: &str, tags: &[&str]) -> Show {
+        Show {
+            url: url.to_string(),
+            name: name.to_string(),
+            episodes: Vec::new(),
+            image: None,
+            artwork: None,
+            last_checked: chrono::Utc::now(),
+            last_upload: chrono::Utc::now(),
+            settings: crate::ShowSettings::default(),
+            tags: tags.iter().map(ToString::to_string).collect(),
+            previous_urls: Vec::new(),
+            gone: None,
+        }
+    }
+
+    #[test]
+    fn parse_nested_outlines() {
+        let opml = r#"<?xml version="1.0"?>
+            <opml version="2.0">
+              <head><title>subs</title></head>
+              <body>
+                <outline text="fiction">
+                  <outline text="audio drama">
+                    <outline type="rss" text="Lost Terminal" xmlUrl="https://example.com/lost?a=1&amp;b=2"/>
+                  </outline>
+                </outline>
+                <outline type="rss" title="Red Valley" xmlUrl="https://example.com/red" category="/horror, /Fiction"></outline>
+              </body>
+            </opml>"#;
+
+        let feeds = parse(opml).expect("to parse opml");
+
+        assert_eq!(
+            feeds,
+            vec![
+                OpmlFeed {
+                    url: "https://example.com/lost?a=1&b=2".to_string(),
+                    title: Some("Lost Terminal".to_string()),
+                    tags: vec!["fiction".to_string(), "audio drama".to_string()],
+                },
+                OpmlFeed {
+                    url: "https://example.com/red".to_string(),
+                    title: Some("Red Valley".to_string()),
+                    tags: vec!["horror".to_string(), "Fiction".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn write_then_parse() {
+        let mut shows = Shows::default();
+        shows.shows.push(show(
+            "Just Roll With It & Friends",
+            "https://example.com/feed?a=1&b=2",
+            &[],
+        ));
+        shows.shows.push(show(
+            "Lost Terminal",
+            "https://example.com/lost",
+            &["fiction", "favourites"],
+        ));
+
+        let opml = write(&shows, false);
+        // written once, in the folder for its first tag
+        assert_eq!(opml.matches("https://example.com/lost").count(), 1);
+        let feeds = parse(&opml).expect("to parse opml");
+
+        assert_eq!(
+            feeds,
+            vec![
+                OpmlFeed {
+                    url: "https://example.com/lost".to_string(),
+                    title: Some("Lost Terminal".to_string()),
+                    tags: vec!["fiction".to_string(), "favourites".to_string()],
+                },
+                OpmlFeed {
+                    url: "https://example.com/feed?a=1&b=2".to_string(),
+                    title: Some("Just Roll With It & Friends".to_string()),
+                    tags: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn secrets_are_hidden_unless_asked_for() {
+        let mut shows = Shows::default();
+        shows.shows.push(show(
+            "Premium",
+            "https://www.patreon.com/rss/premium?auth=abc123",
+            &[],
+        ));
+
+        let opml = write(&shows, false);
+        assert!(!opml.contains("abc123"));
+        assert!(opml.contains("auth=REDACTED"));
+        assert!(write(&shows, true).contains("auth=abc123"));
+    }
+}
@@ -0,0 +1,38 @@
+//! Processing audio as it plays: skipping silence, evening out loudness and
+//! mixing down to mono.
+//!
+//! [`AudioSettings`] say which stages are used, a [`Backend`](super::Backend)
+//! is given them by the [`Player`](super::Player) and applies them however
+//! suits it, [`Mpv`](super::Mpv) uses its own audio filters.
+
+use std::time::Duration;
+
+/// Which processing stages are used, and how.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioSettings {
+    /// If long silences are shortened
+    pub skip_silence: bool,
+    /// Audio quieter than this, in dBFS, is silence
+    pub silence_threshold: f32,
+    /// Silences are cut down to this long, shorter ones are left alone
+    pub silence_min_length: Duration,
+    /// If loudness is evened out toward [`AudioSettings::target_lufs`]
+    pub normalize: bool,
+    /// Loudness to aim for when normalising, in LUFS
+    pub target_lufs: f32,
+    /// If every channel plays the same mix, for listening with one earbud
+    pub mono: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            skip_silence: false,
+            silence_threshold: -50.0,
+            silence_min_length: Duration::from_secs(1),
+            normalize: false,
+            target_lufs: -16.0,
+            mono: false,
+        }
+    }
+}
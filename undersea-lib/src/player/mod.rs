@@ -0,0 +1,766 @@
+//! Playing episodes, keeping their progress and the listening history up to
+//! date as they play.
+//!
+//! Decoding and audio output are left to a [`Backend`], such as [`Mpv`]. The
+//! [`Player`] decides what to play and where from, and turns the time between
+//! starting and stopping playback into [`PlaySession`]s.
+
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Episode, PlayerError, Show, Shows,
+    history::{History, PlaySession},
+};
+
+pub mod dsp;
+#[cfg(feature = "mpris")]
+pub mod mpris;
+mod mpv;
+mod sleep;
+
+pub use mpv::Mpv;
+pub use sleep::{SleepTimer, SleepUntil};
+
+use dsp::AudioSettings;
+use sleep::{Sleep, Target};
+
+/// Slowest and fastest playback speeds.
+pub const SPEEDS: std::ops::RangeInclusive<f32> = 0.25..=4.0;
+
+/// How far the position can drift from where it should be before it counts
+/// as a seek, for telling others that playback jumped.
+pub(crate) const SEEK_TOLERANCE: Duration = Duration::from_secs(1);
+
+/// Format a time as `m:ss`, or `h:mm:ss` if it is an hour or more.
+#[must_use]
+pub fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    if seconds >= 60 * 60 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// How far back to start an episode that has not been played for a while,
+/// so what was happening can be picked up again. The longer the pause the
+/// further back, from [`SmartResume::after_minute`] after a minute to
+/// [`SmartResume::after_day`] after a day or more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmartResume {
+    pub after_minute: Duration,
+    pub after_day: Duration,
+}
+
+impl Default for SmartResume {
+    fn default() -> Self {
+        Self {
+            after_minute: Duration::from_secs(3),
+            after_day: Duration::from_secs(30),
+        }
+    }
+}
+
+impl SmartResume {
+    /// How far to go back after a pause of `paused`, pauses under a minute
+    /// are not worth going back for.
+    #[must_use]
+    pub fn rewind(&self, paused: Duration) -> Duration {
+        const MINUTE: f64 = 60.0;
+        const DAY: f64 = 24.0 * 60.0 * 60.0;
+        let paused = paused.as_secs_f64();
+        if paused < MINUTE {
+            return Duration::ZERO;
+        }
+        // evenly in between on a log scale, as the first hours away matter
+        // more than the days after them
+        let between = ((paused / MINUTE).ln() / (DAY / MINUTE).ln()).min(1.0);
+        let (short, long) = (
+            self.after_minute.as_secs_f64(),
+            self.after_day.as_secs_f64(),
+        );
+        Duration::from_secs_f64(short + (long - short) * between)
+    }
+}
+
+/// Something that can play audio from a url or file.
+pub trait Backend: Send {
+    /// Start playing `source`, a url or a file path, from `start`.
+    ///
+    /// # Errors
+    /// Fails if the backend could not be told to play it.
+    fn load(&mut self, source: &str, start: Duration) -> Result<(), PlayerError>;
+
+    /// # Errors
+    /// Fails if the backend stopped responding.
+    fn set_paused(&mut self, paused: bool) -> Result<(), PlayerError>;
+
+    /// # Errors
+    /// Fails if the backend stopped responding.
+    fn seek(&mut self, position: Duration) -> Result<(), PlayerError>;
+
+    /// # Errors
+    /// Fails if the backend stopped responding.
+    fn set_speed(&mut self, speed: f32) -> Result<(), PlayerError>;
+
+    /// Where playback is up to in the media.
+    ///
+    /// # Errors
+    /// Fails if the backend stopped responding.
+    fn position(&mut self) -> Result<Duration, PlayerError>;
+
+    /// If playback reached the end of the media.
+    ///
+    /// # Errors
+    /// Fails if the backend stopped responding.
+    fn at_end(&mut self) -> Result<bool, PlayerError>;
+
+    /// Change the volume, from 0 for silent to 1 for as loud as the media.
+    ///
+    /// # Errors
+    /// Fails if the backend stopped responding.
+    fn set_volume(&mut self, volume: f32) -> Result<(), PlayerError>;
+
+    /// Change how audio is processed, for the media that is loaded and
+    /// anything loaded after it.
+    ///
+    /// # Errors
+    /// Fails if the backend stopped responding.
+    fn set_audio(&mut self, audio: &AudioSettings) -> Result<(), PlayerError>;
+
+    /// How much less time playback took because of skipped silence, since
+    /// this was last asked or the media was loaded. It is asked every so
+    /// often while playing.
+    ///
+    /// # Errors
+    /// Fails if the backend stopped responding.
+    fn take_silence_saved(&mut self) -> Result<Duration, PlayerError>;
+
+    /// Stop playing and unload the media.
+    ///
+    /// # Errors
+    /// Fails if the backend stopped responding.
+    fn stop(&mut self) -> Result<(), PlayerError>;
+}
+
+/// Something asked of the player from outside the program, such as over
+/// D-Bus or the control socket.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Control {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    /// Jump forward, or back if `forward` is false
+    Seek {
+        forward: bool,
+        by: Duration,
+    },
+    SetPosition(Duration),
+    SetRate(f64),
+    /// Close the program, which is up to its owner
+    Quit,
+}
+
+impl Control {
+    /// Do what was asked of the player. Play starts the queue if nothing is
+    /// playing, and [`Control::Quit`] does nothing, the owner of the player
+    /// should handle it.
+    ///
+    /// # Errors
+    /// Fails in the same ways as the [`Player`] method that is called.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn apply(
+        self,
+        player: &mut Player,
+        shows: &mut Shows,
+        default_speed: f32,
+    ) -> Result<(), PlayerError> {
+        match self {
+            Self::Play | Self::PlayPause if player.status() == Status::Stopped => {
+                player.next(shows, default_speed)?;
+            }
+            Self::Play => player.resume()?,
+            Self::Pause => player.pause(shows)?,
+            Self::PlayPause => player.toggle(shows)?,
+            Self::Stop => player.stop(shows)?,
+            Self::Next => {
+                player.next(shows, default_speed)?;
+            }
+            Self::Previous => player.seek(shows, Duration::ZERO)?,
+            Self::Seek { forward, by } => player.skip(shows, forward, by)?,
+            Self::SetPosition(position) => player.seek(shows, position)?,
+            Self::SetRate(rate) => player.set_speed(shows, rate as f32)?,
+            Self::Quit => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    #[default]
+    Stopped,
+    Playing,
+    Paused,
+}
+
+/// The episode being played.
+#[derive(Debug)]
+struct Current {
+    /// Url of the show
+    show: String,
+    /// [`Episode::id`] of the episode
+    episode: String,
+    /// Where to stop to skip the outro, if the show skips it
+    end: Option<Duration>,
+    /// How long the episode is, if the feed says
+    length: Option<Duration>,
+    /// Where each chapter starts
+    chapters: Vec<Duration>,
+    /// The stretch being played right now, [`None`] while paused
+    session: Option<Session>,
+}
+
+#[derive(Debug)]
+struct Session {
+    started_at: DateTime<Utc>,
+    start: Duration,
+    silence_saved: Duration,
+}
+
+/// Plays episodes through a [`Backend`], see the [module docs](self).
+///
+/// Methods that stop a stretch of playback, such as pausing or seeking, take
+/// the [`Shows`] the episode is from to save its progress.
+pub struct Player {
+    backend: Box<dyn Backend>,
+    history: History,
+    current: Option<Current>,
+    status: Status,
+    speed: f32,
+    /// Where playback was up to when the backend was last asked
+    position: Duration,
+    /// Audio processing for shows that do not change it
+    audio: AudioSettings,
+    sleep: Option<Sleep>,
+    smart_resume: Option<SmartResume>,
+    /// When playback was paused, to go back a little when it resumes
+    paused_at: Option<Instant>,
+}
+
+impl Player {
+    /// A player that records play sessions into `history`.
+    #[must_use]
+    pub fn new(backend: Box<dyn Backend>, history: History) -> Self {
+        Self {
+            backend,
+            history,
+            current: None,
+            status: Status::Stopped,
+            speed: 1.0,
+            position: Duration::ZERO,
+            audio: AudioSettings::default(),
+            sleep: None,
+            smart_resume: Some(SmartResume::default()),
+            paused_at: None,
+        }
+    }
+
+    /// Process audio with `audio`, apart from what shows change for
+    /// themselves.
+    #[must_use]
+    pub fn with_audio(mut self, audio: AudioSettings) -> Self {
+        self.audio = audio;
+        self
+    }
+
+    /// Audio processing for shows that do not change it.
+    #[must_use]
+    pub fn audio(&self) -> &AudioSettings {
+        &self.audio
+    }
+
+    /// Go back by `smart_resume` when playing after a pause, or never go back
+    /// if it is [`None`].
+    #[must_use]
+    pub fn with_smart_resume(mut self, smart_resume: Option<SmartResume>) -> Self {
+        self.smart_resume = smart_resume;
+        self
+    }
+
+    #[must_use]
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    #[must_use]
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    #[must_use]
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Where playback is up to, as of the last call to [`Player::update`] or
+    /// anything else that asks the backend.
+    #[must_use]
+    pub fn position(&self) -> Duration {
+        self.position
+    }
+
+    /// [`Episode::id`] of the episode being played, or paused.
+    #[must_use]
+    pub fn episode_id(&self) -> Option<&str> {
+        self.current
+            .as_ref()
+            .map(|current| current.episode.as_str())
+    }
+
+    /// The episode being played, or paused, and its show.
+    #[must_use]
+    pub fn now_playing<'a>(&self, shows: &'a Shows) -> Option<(&'a Show, &'a Episode)> {
+        shows.episode_by_id(self.episode_id()?)
+    }
+
+    /// Play an episode, from where it was left off and at the speed and with
+    /// the audio processing of its show, stopping anything else that was
+    /// playing. A downloaded episode is played from its file, and one that
+    /// has not been played for a while starts a little further back, see
+    /// [`SmartResume`].
+    ///
+    /// # Errors
+    /// Fails if there is no episode with that id, or the backend failed.
+    pub fn play(
+        &mut self,
+        shows: &mut Shows,
+        id: &str,
+        default_speed: f32,
+    ) -> Result<(), PlayerError> {
+        self.stop(shows)?;
+
+        let (show, episode) = shows
+            .episode_by_id(id)
+            .ok_or_else(|| PlayerError::EpisodeNotFound(id.to_string()))?;
+        let source = match episode.download_path().filter(|path| path.exists()) {
+            Some(path) => path.to_string_lossy().into_owned(),
+            None => episode.media_url().to_string(),
+        };
+        let start = self.resume_position(show, episode);
+        let speed = show.playback_speed(default_speed);
+        let audio = show.audio_settings(&self.audio);
+        let end = if show.settings().skip_outro > Duration::ZERO {
+            show.end_position(episode)
+        } else {
+            None
+        };
+        let current = Current {
+            show: show.url().to_string(),
+            episode: episode.id().to_string(),
+            end,
+            length: *episode.duration(),
+            chapters: episode
+                .chapters()
+                .iter()
+                .map(|chapter| chapter.start)
+                .collect(),
+            session: None,
+        };
+
+        self.backend.set_audio(&audio)?;
+        self.backend.load(&source, start)?;
+        self.backend.set_speed(speed)?;
+        self.current = Some(current);
+        // the end of a chapter in this episode, not the last one
+        if let Some(sleep) = &mut self.sleep
+            && matches!(sleep.target, Target::ChapterEnd(_))
+        {
+            sleep.target = Target::ChapterEnd(None);
+        }
+        self.speed = speed;
+        self.position = start;
+        self.status = Status::Playing;
+        self.begin_session();
+        Ok(())
+    }
+
+    /// # Errors
+    /// Fails if the backend failed, or the session could not be recorded.
+    pub fn pause(&mut self, shows: &mut Shows) -> Result<(), PlayerError> {
+        if self.status != Status::Playing {
+            return Ok(());
+        }
+        self.position = self.backend.position()?;
+        self.backend.set_paused(true)?;
+        self.status = Status::Paused;
+        self.paused_at = Some(Instant::now());
+        self.end_session(shows, false)
+    }
+
+    /// Carry on playing, going back a little if it was paused for a while.
+    ///
+    /// # Errors
+    /// Fails if the backend failed.
+    pub fn resume(&mut self) -> Result<(), PlayerError> {
+        if self.status != Status::Paused {
+            return Ok(());
+        }
+        let rewind = self
+            .smart_resume
+            .zip(self.paused_at.take())
+            .map_or(Duration::ZERO, |(smart_resume, paused_at)| {
+                smart_resume.rewind(paused_at.elapsed())
+            });
+        if !rewind.is_zero() {
+            self.position = self.position.saturating_sub(rewind);
+            self.backend.seek(self.position)?;
+        }
+        self.backend.set_paused(false)?;
+        self.status = Status::Playing;
+        self.begin_session();
+        Ok(())
+    }
+
+    /// Pause if playing, otherwise resume.
+    ///
+    /// # Errors
+    /// Fails in the same ways as [`Player::pause`].
+    pub fn toggle(&mut self, shows: &mut Shows) -> Result<(), PlayerError> {
+        match self.status {
+            Status::Playing => self.pause(shows),
+            Status::Paused => self.resume(),
+            Status::Stopped => Ok(()),
+        }
+    }
+
+    /// Jump to a position in the episode.
+    ///
+    /// # Errors
+    /// Fails if the backend failed, or the session could not be recorded.
+    pub fn seek(&mut self, shows: &mut Shows, position: Duration) -> Result<(), PlayerError> {
+        let Some(current) = &self.current else {
+            return Ok(());
+        };
+        let episode = current.episode.clone();
+        let playing = self.status == Status::Playing;
+        if playing {
+            self.position = self.backend.position()?;
+            self.end_session(shows, false)?;
+        }
+
+        self.backend.seek(position)?;
+        self.position = position;
+        if playing {
+            self.begin_session();
+        } else {
+            shows.set_resume_time(&episode, position)?;
+        }
+        Ok(())
+    }
+
+    /// Jump forward, or back if `forward` is false, by `by`.
+    ///
+    /// # Errors
+    /// Fails in the same ways as [`Player::seek`].
+    pub fn skip(
+        &mut self,
+        shows: &mut Shows,
+        forward: bool,
+        by: Duration,
+    ) -> Result<(), PlayerError> {
+        if self.status == Status::Playing {
+            self.position = self.backend.position()?;
+        }
+        let position = if forward {
+            self.position + by
+        } else {
+            self.position.saturating_sub(by)
+        };
+        self.seek(shows, position)
+    }
+
+    /// Change the playback speed, clamped to [`SPEEDS`].
+    ///
+    /// # Errors
+    /// Fails if the backend failed, or the session could not be recorded.
+    pub fn set_speed(&mut self, shows: &mut Shows, speed: f32) -> Result<(), PlayerError> {
+        let speed = speed.clamp(*SPEEDS.start(), *SPEEDS.end());
+        // sessions have one speed, so a new one starts
+        let playing = self.status == Status::Playing;
+        if playing {
+            self.position = self.backend.position()?;
+            self.end_session(shows, false)?;
+        }
+        self.backend.set_speed(speed)?;
+        self.speed = speed;
+        if playing {
+            self.begin_session();
+        }
+        Ok(())
+    }
+
+    /// Stop playing, saving where the episode was left off.
+    ///
+    /// # Errors
+    /// Fails if the backend failed, or the session could not be recorded.
+    pub fn stop(&mut self, shows: &mut Shows) -> Result<(), PlayerError> {
+        if self.current.is_none() {
+            return Ok(());
+        }
+        if self.status == Status::Playing {
+            self.position = self.backend.position()?;
+            self.end_session(shows, false)?;
+        }
+        self.backend.stop()?;
+        self.current = None;
+        self.status = Status::Stopped;
+        Ok(())
+    }
+
+    /// Play the episode after this one in the queue, or the first queued
+    /// episode if this one is not queued. Returns false if there is nothing
+    /// to play next.
+    ///
+    /// # Errors
+    /// Fails in the same ways as [`Player::play`].
+    pub fn next(&mut self, shows: &mut Shows, default_speed: f32) -> Result<bool, PlayerError> {
+        let Some(next) = self.next_in_queue(shows) else {
+            return Ok(false);
+        };
+        self.play(shows, &next, default_speed)?;
+        Ok(true)
+    }
+
+    /// Where to start playing an episode, going back a little from where it
+    /// was left off if that was a while ago, though not into an intro that
+    /// is skipped.
+    fn resume_position(&self, show: &Show, episode: &Episode) -> Duration {
+        let start = show.start_position(episode);
+        let paused = episode
+            .last_played_at()
+            .and_then(|at| (Utc::now() - *at).to_std().ok());
+        let rewind = self
+            .smart_resume
+            .zip(paused)
+            .map_or(Duration::ZERO, |(smart_resume, paused)| {
+                smart_resume.rewind(paused)
+            });
+        start
+            .saturating_sub(rewind)
+            .max(show.settings().skip_intro.min(start))
+    }
+
+    fn next_in_queue(&self, shows: &Shows) -> Option<String> {
+        let queue = shows.queue();
+        let current = self.episode_id();
+        let index = queue
+            .iter()
+            .position(|(_, episode)| Some(episode.id()) == current)
+            .map_or(0, |index| index + 1);
+        queue
+            .get(index)
+            .map(|(_, episode)| episode.id().to_string())
+    }
+
+    /// Check on playback, this should be called every so often while
+    /// playing. An episode that reaches its end, or the start of the outro
+    /// if the show skips it, is marked as finished and taken out of the
+    /// queue, and the next queued episode is played.
+    ///
+    /// # Errors
+    /// Fails if the backend failed, or the session could not be recorded.
+    pub fn update(&mut self, shows: &mut Shows, default_speed: f32) -> Result<(), PlayerError> {
+        if self.status != Status::Playing {
+            return Ok(());
+        }
+        let Some(current) = &self.current else {
+            return Ok(());
+        };
+        let end = current.end;
+        let id = current.episode.clone();
+        self.position = self.backend.position()?;
+        self.add_silence_saved()?;
+
+        let ended = self.backend.at_end()? || end.is_some_and(|end| self.position >= end);
+        if !ended {
+            return self.update_sleep(shows);
+        }
+
+        let next = self.next_in_queue(shows);
+        self.end_session(shows, true)?;
+        shows.dequeue(&id);
+        self.backend.stop()?;
+        self.current = None;
+        self.status = Status::Stopped;
+
+        // a timer for the end of this episode, or a chapter in it, is done
+        let asleep = self
+            .sleep
+            .as_ref()
+            .is_some_and(|sleep| !matches!(sleep.target, Target::Left(_)));
+        if asleep {
+            return self.set_sleep_timer(None);
+        }
+        match next {
+            Some(next) => self.play(shows, &next, default_speed),
+            None => Ok(()),
+        }
+    }
+
+    /// Pause playback later on, or stop the timer that was set if `timer`
+    /// is [`None`]. A timer that runs out while nothing is playing waits
+    /// until something is.
+    ///
+    /// # Errors
+    /// Fails if the backend failed to turn the volume back up.
+    pub fn set_sleep_timer(&mut self, timer: Option<SleepTimer>) -> Result<(), PlayerError> {
+        let faded = self.sleep.as_ref().is_some_and(|sleep| sleep.volume < 1.0);
+        self.sleep = timer.map(Sleep::new);
+        if faded {
+            self.backend.set_volume(1.0)?;
+        }
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn sleep_timer(&self) -> Option<&SleepTimer> {
+        self.sleep.as_ref().map(|sleep| &sleep.timer)
+    }
+
+    /// How long until the sleep timer pauses playback, as of the last call to
+    /// [`Player::update`]. It is [`None`] if no timer is set, or if it can not
+    /// be known, such as the end of an episode with no length.
+    #[must_use]
+    pub fn sleep_remaining(&self) -> Option<Duration> {
+        let end = self
+            .current
+            .as_ref()
+            .and_then(|current| current.end.or(current.length));
+        self.sleep
+            .as_ref()?
+            .remaining(self.position, end, self.speed)
+    }
+
+    /// Give the sleep timer longer, which makes it a timer for a fixed time
+    /// from now. Does nothing if no timer is set.
+    pub fn extend_sleep_timer(&mut self, by: Duration) {
+        let remaining = self.sleep_remaining().unwrap_or_default();
+        if let Some(sleep) = &mut self.sleep {
+            sleep.target = Target::Left(remaining + by);
+            sleep.checked = Instant::now();
+        }
+    }
+
+    /// Count down the sleep timer, fading out toward the end and pausing
+    /// once it runs out.
+    fn update_sleep(&mut self, shows: &mut Shows) -> Result<(), PlayerError> {
+        let (Some(sleep), Some(current)) = (&mut self.sleep, &self.current) else {
+            return Ok(());
+        };
+        sleep.count();
+        if sleep.target == Target::ChapterEnd(None) {
+            sleep.target = current
+                .chapters
+                .iter()
+                .copied()
+                .find(|start| *start > self.position)
+                .map_or(Target::EpisodeEnd, |end| Target::ChapterEnd(Some(end)));
+        }
+        let end = current.end.or(current.length);
+        let Some(remaining) = sleep.remaining(self.position, end, self.speed) else {
+            return Ok(());
+        };
+
+        // the end of the episode is left to finish it as usual
+        if remaining.is_zero() && sleep.target != Target::EpisodeEnd {
+            let rewind = sleep.timer.rewind;
+            self.sleep = None;
+            self.pause(shows)?;
+            self.backend.set_volume(1.0)?;
+            if !rewind.is_zero() {
+                self.seek(shows, self.position.saturating_sub(rewind))?;
+            }
+            return Ok(());
+        }
+        let volume = sleep.volume(remaining);
+        if (volume - sleep.volume).abs() > 0.01 {
+            sleep.volume = volume;
+            self.backend.set_volume(volume)?;
+        }
+        Ok(())
+    }
+
+    fn begin_session(&mut self) {
+        if let Some(sleep) = &mut self.sleep {
+            sleep.checked = Instant::now();
+        }
+        if let Some(current) = &mut self.current {
+            current.session = Some(Session {
+                started_at: Utc::now(),
+                start: self.position,
+                silence_saved: Duration::ZERO,
+            });
+        }
+    }
+
+    fn add_silence_saved(&mut self) -> Result<(), PlayerError> {
+        let saved = self.backend.take_silence_saved()?;
+        if let Some(session) = self
+            .current
+            .as_mut()
+            .and_then(|current| current.session.as_mut())
+        {
+            session.silence_saved += saved;
+        }
+        Ok(())
+    }
+
+    /// Save progress and record the stretch of playback that just ended.
+    fn end_session(&mut self, shows: &mut Shows, finished: bool) -> Result<(), PlayerError> {
+        self.add_silence_saved()?;
+        if let Some(sleep) = &mut self.sleep {
+            sleep.count();
+        }
+        let Some(current) = &mut self.current else {
+            return Ok(());
+        };
+        if finished {
+            shows.set_finished(&current.episode, true)?;
+        } else {
+            shows.set_played(&current.episode, self.position)?;
+        }
+
+        let Some(session) = current.session.take() else {
+            return Ok(());
+        };
+        // a pause straight after starting is not worth remembering
+        if !finished && self.position <= session.start {
+            return Ok(());
+        }
+        self.history.record(PlaySession {
+            show: current.show.clone(),
+            episode: current.episode.clone(),
+            started_at: session.started_at,
+            ended_at: Utc::now(),
+            start: session.start,
+            end: self.position.max(session.start),
+            speed: self.speed,
+            silence_saved: session.silence_saved,
+            finished,
+        })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,386 @@
+//! Controlling a [`Player`] over D-Bus with the
+//! [MPRIS](https://specifications.freedesktop.org/mpris-spec/latest/) interface, which is
+//! what media keys, `playerctl` and desktop widgets use.
+//!
+//! The player stays with whatever owns it, calls made over D-Bus arrive as
+//! [`Control`]s to be passed to [`Control::apply`]. [`Mpris::update`] should
+//! be called after anything could have changed to tell D-Bus about it.
+
+use std::{
+    collections::HashMap,
+    process,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use zbus::{
+    Connection, connection, fdo,
+    fdo::{RequestNameFlags, RequestNameReply},
+    interface,
+    object_server::{InterfaceRef, SignalEmitter},
+    zvariant::{ObjectPath, OwnedValue, Value},
+};
+
+use super::{Control, Player, SEEK_TOLERANCE, SPEEDS, Status};
+use crate::{PlayerError, Shows, artwork::cache_key};
+
+/// Bus name taken by the first player running, later ones add their pid.
+pub const BUS_NAME: &str = "org.mpris.MediaPlayer2.undersea";
+const PATH: &str = "/org/mpris/MediaPlayer2";
+/// Track id used while nothing is playing, which the spec reserves for it.
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// What is told to D-Bus about the player.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct State {
+    status: Status,
+    /// Id of the episode, title, name of the show, artwork url and media url
+    episode: Option<(String, String, String, Option<String>, String)>,
+    length: Option<Duration>,
+    position: Duration,
+    rate: f64,
+}
+
+impl State {
+    fn new(player: &Player, shows: &Shows) -> Self {
+        let now_playing = player.now_playing(shows);
+        Self {
+            status: player.status(),
+            episode: now_playing.map(|(show, episode)| {
+                (
+                    episode.id().to_string(),
+                    episode.title().to_string(),
+                    show.name().to_string(),
+                    show.artwork_for(episode).map(ToString::to_string),
+                    episode.media_url().to_string(),
+                )
+            }),
+            length: now_playing.and_then(|(_, episode)| *episode.duration()),
+            position: player.position(),
+            rate: f64::from(player.speed()),
+        }
+    }
+
+    fn track_id(&self) -> ObjectPath<'static> {
+        match &self.episode {
+            // episode ids can have anything in them, object paths can not
+            Some((id, ..)) => ObjectPath::from_string_unchecked(format!(
+                "/org/undersea/episode/{}",
+                cache_key(id)
+            )),
+            None => ObjectPath::from_static_str_unchecked(NO_TRACK),
+        }
+    }
+}
+
+/// The D-Bus side of a [`Player`], see the [module docs](self).
+pub struct Mpris {
+    connection: Connection,
+    state: State,
+    updated_at: Instant,
+}
+
+impl Mpris {
+    /// Connect to the session bus, or the bus at `address`, and start
+    /// answering calls. Calls arrive on the returned receiver.
+    ///
+    /// # Errors
+    /// Fails if there is no bus to connect to.
+    pub async fn start(
+        address: Option<&str>,
+    ) -> Result<(Self, UnboundedReceiver<Control>), PlayerError> {
+        let (controls, receiver) = mpsc::unbounded_channel();
+        let builder = match address {
+            Some(address) => connection::Builder::address(address)?,
+            None => connection::Builder::session()?,
+        };
+        let connection = builder
+            .serve_at(
+                PATH,
+                Root {
+                    controls: controls.clone(),
+                },
+            )?
+            .serve_at(
+                PATH,
+                PlayerInterface {
+                    controls,
+                    state: State::default(),
+                },
+            )?
+            .build()
+            .await?;
+
+        let reply = connection
+            .request_name_with_flags(BUS_NAME, RequestNameFlags::DoNotQueue.into())
+            .await;
+        if !matches!(
+            reply,
+            Ok(RequestNameReply::PrimaryOwner | RequestNameReply::AlreadyOwner)
+        ) {
+            let name = format!("{BUS_NAME}.instance{}", process::id());
+            connection.request_name(name).await?;
+        }
+
+        let mpris = Self {
+            connection,
+            state: State::default(),
+            updated_at: Instant::now(),
+        };
+        Ok((mpris, receiver))
+    }
+
+    /// Tell D-Bus about any changes to the player since the last update.
+    ///
+    /// # Errors
+    /// Fails if the connection to the bus was lost.
+    pub async fn update(&mut self, player: &Player, shows: &Shows) -> Result<(), PlayerError> {
+        let state = State::new(player, shows);
+        let iface: InterfaceRef<PlayerInterface> =
+            self.connection.object_server().interface(PATH).await?;
+        iface.get_mut().await.state = state.clone();
+        let emitter = iface.signal_emitter();
+        let iface = iface.get().await;
+
+        let old = &self.state;
+        if state.status != old.status {
+            iface.playback_status_changed(emitter).await?;
+        }
+        if state.episode != old.episode || state.length != old.length {
+            iface.metadata_changed(emitter).await?;
+        }
+        #[allow(clippy::float_cmp)]
+        if state.rate != old.rate {
+            iface.rate_changed(emitter).await?;
+        }
+
+        // the position is not signalled as it moves, only when it jumps
+        let expected = if old.status == Status::Playing {
+            old.position + self.updated_at.elapsed().mul_f64(old.rate)
+        } else {
+            old.position
+        };
+        if state.episode.is_some()
+            && state.episode == old.episode
+            && state.position.abs_diff(expected) > SEEK_TOLERANCE
+        {
+            PlayerInterface::seeked(emitter, micros(state.position)).await?;
+        }
+
+        self.state = state;
+        self.updated_at = Instant::now();
+        Ok(())
+    }
+}
+
+struct Root {
+    controls: UnboundedSender<Control>,
+}
+
+// D-Bus methods and properties need `self` even when they do not use it
+#[allow(clippy::unused_self)]
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {
+        let _ = self.controls.send(Control::Quit);
+    }
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> &'static str {
+        "undersea"
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+struct PlayerInterface {
+    controls: UnboundedSender<Control>,
+    state: State,
+}
+
+impl PlayerInterface {
+    fn send(&self, control: Control) {
+        let _ = self.controls.send(control);
+    }
+}
+
+#[allow(clippy::unused_self)]
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl PlayerInterface {
+    fn play(&self) {
+        self.send(Control::Play);
+    }
+
+    fn pause(&self) {
+        self.send(Control::Pause);
+    }
+
+    fn play_pause(&self) {
+        self.send(Control::PlayPause);
+    }
+
+    fn stop(&self) {
+        self.send(Control::Stop);
+    }
+
+    fn next(&self) {
+        self.send(Control::Next);
+    }
+
+    fn previous(&self) {
+        self.send(Control::Previous);
+    }
+
+    /// Offset in microseconds
+    fn seek(&self, offset: i64) {
+        self.send(Control::Seek {
+            forward: offset >= 0,
+            by: Duration::from_micros(offset.unsigned_abs()),
+        });
+    }
+
+    /// Ignored unless `track_id` is the episode playing, as the spec asks
+    #[allow(clippy::needless_pass_by_value)]
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+        if track_id == self.state.track_id() {
+            let position = Duration::from_micros(position.try_into().unwrap_or(0));
+            self.send(Control::SetPosition(position));
+        }
+    }
+
+    fn open_uri(&self, uri: &str) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(format!(
+            "can not open {uri}, episodes can only be played from the library"
+        )))
+    }
+
+    #[zbus(signal)]
+    async fn seeked(emitter: &SignalEmitter<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> &str {
+        match self.state.status {
+            Status::Playing => "Playing",
+            Status::Paused => "Paused",
+            Status::Stopped => "Stopped",
+        }
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let mut metadata = HashMap::new();
+        metadata.insert(
+            "mpris:trackid".to_string(),
+            OwnedValue::from(self.state.track_id()),
+        );
+        let Some((_, title, show, artwork, url)) = &self.state.episode else {
+            return metadata;
+        };
+
+        let mut insert = |key: &str, value: Value<'_>| {
+            if let Ok(value) = OwnedValue::try_from(value) {
+                metadata.insert(key.to_string(), value);
+            }
+        };
+        insert("xesam:title", Value::from(title.as_str()));
+        insert("xesam:album", Value::from(show.as_str()));
+        insert("xesam:artist", Value::from(vec![show.as_str()]));
+        insert("xesam:url", Value::from(url.as_str()));
+        if let Some(artwork) = artwork {
+            insert("mpris:artUrl", Value::from(artwork.as_str()));
+        }
+        if let Some(length) = self.state.length {
+            insert("mpris:length", Value::from(micros(length)));
+        }
+        metadata
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        micros(self.state.position)
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        self.state.rate
+    }
+
+    #[zbus(property)]
+    fn set_rate(&mut self, rate: f64) {
+        // the spec says a rate of 0 should pause rather than be an error
+        if rate <= 0.0 {
+            self.send(Control::Pause);
+        } else {
+            self.send(Control::SetRate(rate));
+        }
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        f64::from(*SPEEDS.start())
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        f64::from(*SPEEDS.end())
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        self.state.episode.is_some()
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        self.state.episode.is_some()
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+fn micros(duration: Duration) -> i64 {
+    i64::try_from(duration.as_micros()).unwrap_or(i64::MAX)
+}
@@ -0,0 +1,376 @@
+use std::{
+    env,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::{Value, json};
+
+use super::{Backend, dsp::AudioSettings};
+use crate::PlayerError;
+
+/// How long to wait for mpv to start, or to answer a command.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Label of the filter that finds silence, to read what it found.
+const SILENCE_FILTER: &str = "undersea-silence";
+
+/// How many times faster than the playback speed silence is played.
+const SILENCE_SPEEDUP: f32 = 4.0;
+
+/// Plays audio with [mpv](https://mpv.io), controlled over its JSON IPC
+/// socket. mpv has to be installed and on the `PATH`.
+///
+/// Audio is processed by ffmpeg filters in mpv: `loudnorm` normalises, and
+/// `silencedetect` finds silences, which are played fast once they have lasted the minimum
+/// length. Playing them fast rather than cutting them out keeps the
+/// position in the episode right.
+pub struct Mpv {
+    child: Child,
+    socket: BufReader<UnixStream>,
+    socket_path: PathBuf,
+    request_id: u64,
+    /// Where the media that was last loaded starts from
+    start: Duration,
+    speed: f32,
+    audio: AudioSettings,
+    silence: FastSilence,
+}
+
+impl Mpv {
+    /// Start mpv in the background, idle until something is loaded.
+    ///
+    /// # Errors
+    /// Fails if mpv is not installed, or did not start listening on its
+    /// socket in time.
+    pub fn spawn() -> Result<Self, PlayerError> {
+        let socket_path = env::temp_dir().join(format!("undersea-mpv-{}.sock", std::process::id()));
+        let mut child = Command::new("mpv")
+            .args([
+                "--idle=yes",
+                "--no-video",
+                "--no-terminal",
+                "--keep-open=yes",
+            ])
+            .arg(format!("--input-ipc-server={}", socket_path.display()))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let started = Instant::now();
+        let stream = loop {
+            match UnixStream::connect(&socket_path) {
+                Ok(stream) => break stream,
+                Err(_) if started.elapsed() < TIMEOUT && child.try_wait()?.is_none() => {
+                    thread::sleep(Duration::from_millis(20));
+                }
+                Err(err) => {
+                    let _ = child.kill();
+                    return Err(err.into());
+                }
+            }
+        };
+        stream.set_read_timeout(Some(TIMEOUT))?;
+
+        Ok(Self {
+            child,
+            socket: BufReader::new(stream),
+            socket_path,
+            request_id: 0,
+            start: Duration::ZERO,
+            speed: 1.0,
+            audio: AudioSettings::default(),
+            silence: FastSilence::default(),
+        })
+    }
+
+    /// Run a command and return its data, skipping over any events mpv sends
+    /// before the reply.
+    fn command(&mut self, command: &Value) -> Result<Value, PlayerError> {
+        self.request_id += 1;
+        let request = json!({ "command": command, "request_id": self.request_id });
+        let mut line = serde_json::to_string(&request).map_err(io::Error::from)?;
+        line.push('\n');
+        self.socket.get_mut().write_all(line.as_bytes())?;
+
+        loop {
+            let mut line = String::new();
+            if self.socket.read_line(&mut line)? == 0 {
+                let err = io::Error::new(io::ErrorKind::UnexpectedEof, "mpv closed its socket");
+                return Err(err.into());
+            }
+            let Ok(reply) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            if reply["request_id"] != self.request_id {
+                continue;
+            }
+            return match reply["error"].as_str() {
+                Some("success") => Ok(reply["data"].clone()),
+                Some(error) => Err(PlayerError::Backend(format!("mpv: {error}"))),
+                None => Err(PlayerError::Backend(
+                    "mpv sent a reply with no error".to_string(),
+                )),
+            };
+        }
+    }
+
+    fn set_property(&mut self, name: &str, value: &Value) -> Result<(), PlayerError> {
+        self.command(&json!(["set_property", name, value]))?;
+        Ok(())
+    }
+
+    /// If the silence filter last said a silence started, and not that it
+    /// ended.
+    fn in_silence(&mut self) -> Result<bool, PlayerError> {
+        let metadata = match self.command(&json!([
+            "get_property",
+            format!("af-metadata/{SILENCE_FILTER}")
+        ])) {
+            Ok(metadata) => metadata,
+            // nothing has been through the filter yet
+            Err(PlayerError::Backend(_)) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        Ok(metadata.get("lavfi.silence_start").is_some()
+            && metadata.get("lavfi.silence_end").is_none())
+    }
+
+    /// Go back to normal speed if silence is being played fast, counting the
+    /// time it saved if `count` is true.
+    fn end_silence(&mut self, count: bool) -> Result<(), PlayerError> {
+        if self.silence.start.is_none() {
+            return Ok(());
+        }
+        let position = if count { Some(self.position()?) } else { None };
+        self.silence.end(position, self.speed);
+        self.set_property("speed", &json!(self.speed))
+    }
+}
+
+/// Silence being played fast, and how much time that has saved.
+#[derive(Debug, Default)]
+struct FastSilence {
+    /// Where the silence being played fast is up to
+    start: Option<Duration>,
+    saved: Duration,
+}
+
+impl FastSilence {
+    /// Follow what the silence filter says at `position`, returning the
+    /// speed to play at if it changes.
+    fn update(&mut self, silent: bool, position: Duration, speed: f32) -> Option<f32> {
+        match self.start {
+            Some(start) => {
+                self.count(start, position, speed);
+                if silent {
+                    self.start = Some(position);
+                    None
+                } else {
+                    self.start = None;
+                    Some(speed)
+                }
+            }
+            None if silent => {
+                self.start = Some(position);
+                Some(speed * SILENCE_SPEEDUP)
+            }
+            None => None,
+        }
+    }
+
+    /// Stop playing silence fast, counting what was played up to `position`
+    /// if there is one.
+    fn end(&mut self, position: Option<Duration>, speed: f32) {
+        if let Some(start) = self.start.take()
+            && let Some(position) = position
+        {
+            self.count(start, position, speed);
+        }
+    }
+
+    fn count(&mut self, start: Duration, position: Duration, speed: f32) {
+        let played = position.saturating_sub(start);
+        self.saved += played
+            .div_f32(speed)
+            .saturating_sub(played.div_f32(speed * SILENCE_SPEEDUP));
+    }
+}
+
+/// mpv audio filters that do what `audio` asks.
+fn filters(audio: &AudioSettings) -> String {
+    let mut filters = Vec::new();
+    if audio.skip_silence {
+        filters.push(format!(
+            "@{SILENCE_FILTER}:lavfi=[silencedetect=noise={}dB:duration={}]",
+            audio.silence_threshold,
+            audio.silence_min_length.as_secs_f64()
+        ));
+    }
+    if audio.normalize {
+        filters.push(format!(
+            "lavfi=[loudnorm=I={}:TP=-1.5:LRA=11]",
+            audio.target_lufs
+        ));
+    }
+    filters.join(",")
+}
+
+impl Backend for Mpv {
+    fn load(&mut self, source: &str, start: Duration) -> Result<(), PlayerError> {
+        self.end_silence(false)?;
+        self.silence = FastSilence::default();
+        self.set_property("start", &json!(format!("{}", start.as_secs_f64())))?;
+        self.set_property("pause", &json!(false))?;
+        self.command(&json!(["loadfile", source, "replace"]))?;
+        self.start = start;
+        Ok(())
+    }
+
+    fn set_paused(&mut self, paused: bool) -> Result<(), PlayerError> {
+        self.set_property("pause", &json!(paused))
+    }
+
+    fn seek(&mut self, position: Duration) -> Result<(), PlayerError> {
+        self.end_silence(true)?;
+        self.command(&json!(["seek", position.as_secs_f64(), "absolute"]))?;
+        Ok(())
+    }
+
+    fn set_speed(&mut self, speed: f32) -> Result<(), PlayerError> {
+        self.end_silence(true)?;
+        self.speed = speed;
+        self.set_property("speed", &json!(speed))
+    }
+
+    fn position(&mut self) -> Result<Duration, PlayerError> {
+        // there is no position until the file has been opened, which can take
+        // a while for a stream
+        match self.command(&json!(["get_property", "time-pos"])) {
+            Ok(position) => Ok(position
+                .as_f64()
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                .unwrap_or(self.start)),
+            Err(PlayerError::Backend(_)) => Ok(self.start),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn at_end(&mut self) -> Result<bool, PlayerError> {
+        match self.command(&json!(["get_property", "eof-reached"])) {
+            Ok(eof) => Ok(eof.as_bool().unwrap_or(false)),
+            Err(PlayerError::Backend(_)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn set_volume(&mut self, volume: f32) -> Result<(), PlayerError> {
+        self.set_property("volume", &json!(volume * 100.0))
+    }
+
+    fn set_audio(&mut self, audio: &AudioSettings) -> Result<(), PlayerError> {
+        self.end_silence(false)?;
+        self.set_property("af", &json!(filters(audio)))?;
+        let channels = if audio.mono { "mono" } else { "auto-safe" };
+        self.set_property("audio-channels", &json!(channels))?;
+        self.audio = *audio;
+        Ok(())
+    }
+
+    fn take_silence_saved(&mut self) -> Result<Duration, PlayerError> {
+        if self.audio.skip_silence {
+            let silent = self.in_silence()?;
+            let position = self.position()?;
+            if let Some(speed) = self.silence.update(silent, position, self.speed) {
+                self.set_property("speed", &json!(speed))?;
+            }
+        }
+        Ok(std::mem::take(&mut self.silence.saved))
+    }
+
+    fn stop(&mut self) -> Result<(), PlayerError> {
+        self.end_silence(false)?;
+        self.command(&json!(["stop"]))?;
+        Ok(())
+    }
+}
+
+impl Drop for Mpv {
+    fn drop(&mut self) {
+        let _ = self.command(&json!(["quit"]));
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.socket_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_follow_the_settings() {
+        let mut audio = AudioSettings::default();
+        assert_eq!(filters(&audio), "");
+
+        audio.skip_silence = true;
+        assert_eq!(
+            filters(&audio),
+            "@undersea-silence:lavfi=[silencedetect=noise=-50dB:duration=1]"
+        );
+
+        audio.silence_threshold = -42.5;
+        audio.silence_min_length = Duration::from_millis(1500);
+        audio.normalize = true;
+        audio.target_lufs = -19.0;
+        assert_eq!(
+            filters(&audio),
+            "@undersea-silence:lavfi=[silencedetect=noise=-42.5dB:duration=1.5],\
+             lavfi=[loudnorm=I=-19:TP=-1.5:LRA=11]"
+        );
+
+        audio.skip_silence = false;
+        assert_eq!(filters(&audio), "lavfi=[loudnorm=I=-19:TP=-1.5:LRA=11]");
+    }
+
+    #[test]
+    fn silence_played_fast_counts_the_time_saved() {
+        let mut silence = FastSilence::default();
+        assert_eq!(silence.update(false, Duration::from_secs(5), 1.0), None);
+
+        // played 4 times as fast, so 8 seconds of silence take 2
+        assert_eq!(
+            silence.update(true, Duration::from_secs(10), 1.0),
+            Some(4.0)
+        );
+        assert_eq!(silence.update(true, Duration::from_secs(18), 1.0), None);
+        assert_eq!(silence.saved, Duration::from_secs(6));
+        assert_eq!(
+            silence.update(false, Duration::from_secs(22), 1.0),
+            Some(1.0)
+        );
+        assert_eq!(silence.saved, Duration::from_secs(9));
+        assert_eq!(silence.start, None);
+    }
+
+    #[test]
+    fn silence_saved_depends_on_speed() {
+        let mut silence = FastSilence::default();
+        assert_eq!(silence.update(true, Duration::ZERO, 2.0), Some(8.0));
+        // 8 seconds at 2x take 4, and 1 at 8x
+        silence.end(Some(Duration::from_secs(8)), 2.0);
+        assert_eq!(silence.saved, Duration::from_secs(3));
+        assert_eq!(silence.start, None);
+
+        // ended without a position, like when loading something else
+        silence.update(true, Duration::ZERO, 1.0);
+        silence.end(None, 1.0);
+        assert_eq!(silence.saved, Duration::from_secs(3));
+        assert_eq!(silence.start, None);
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+/// When a [`SleepTimer`] pauses playback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepUntil {
+    /// After this much more playing, time spent paused does not count
+    After(Duration),
+    /// When the episode playing ends, without going on to the next one
+    EndOfEpisode,
+    /// When the chapter playing ends, or the episode if it has no more
+    /// chapters
+    EndOfChapter,
+}
+
+/// Pauses playback later on, see [`Player::set_sleep_timer`](super::Player::set_sleep_timer).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SleepTimer {
+    pub until: SleepUntil,
+    /// How long before pausing the volume starts to fade out
+    pub fade: Duration,
+    /// How far to go back after pausing, so the last moments are heard again
+    /// when playback resumes
+    pub rewind: Duration,
+}
+
+/// A [`SleepTimer`] that has been set, and how it is getting on.
+#[derive(Debug)]
+pub(super) struct Sleep {
+    pub(super) timer: SleepTimer,
+    pub(super) target: Target,
+    /// When the time left for [`Target::Left`] was last counted down
+    pub(super) checked: Instant,
+    /// Volume the backend was last set to
+    pub(super) volume: f32,
+}
+
+/// What a [`Sleep`] is waiting for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Target {
+    /// This much more playing time
+    Left(Duration),
+    /// A position in the episode, [`None`] until it has been worked out from
+    /// the chapters of what is playing
+    ChapterEnd(Option<Duration>),
+    EpisodeEnd,
+}
+
+impl Sleep {
+    pub(super) fn new(timer: SleepTimer) -> Self {
+        let target = match timer.until {
+            SleepUntil::After(left) => Target::Left(left),
+            SleepUntil::EndOfEpisode => Target::EpisodeEnd,
+            SleepUntil::EndOfChapter => Target::ChapterEnd(None),
+        };
+        Self {
+            timer,
+            target,
+            checked: Instant::now(),
+            volume: 1.0,
+        }
+    }
+
+    /// Count down the time played since this was last called, or since
+    /// playback started again.
+    pub(super) fn count(&mut self) {
+        let now = Instant::now();
+        if let Target::Left(left) = &mut self.target {
+            *left = left.saturating_sub(now - self.checked);
+        }
+        self.checked = now;
+    }
+
+    /// How much longer until pausing, at `speed` from `position`. `end` is
+    /// where the episode ends, if it is known.
+    pub(super) fn remaining(
+        &self,
+        position: Duration,
+        end: Option<Duration>,
+        speed: f32,
+    ) -> Option<Duration> {
+        let at = match self.target {
+            Target::Left(left) => return Some(left),
+            Target::ChapterEnd(at) => at?,
+            Target::EpisodeEnd => end?,
+        };
+        Some(at.saturating_sub(position).div_f32(speed.max(f32::EPSILON)))
+    }
+
+    /// Volume to play at with `remaining` left, fading out to silence.
+    pub(super) fn volume(&self, remaining: Duration) -> f32 {
+        if remaining >= self.timer.fade {
+            1.0
+        } else {
+            remaining.as_secs_f32() / self.timer.fade.as_secs_f32()
+        }
+    }
+}
@@ -0,0 +1,836 @@
+//! Smart playlists, lists of episodes picked by a query instead of by hand.
+//!
+//! A query is a list of terms, an episode has to match all of them:
+//!
+//! - `unplayed`, `finished` (or `played`), `started` (or `in-progress`) and
+//!   `downloaded` match episodes in that state
+//! - `show:<name>` and `title:<text>` match part of the show name or episode
+//!   title, ignoring case, and `tag:<tag>` matches shows with that tag. Quote
+//!   values with spaces, like `show:"lost terminal"`
+//! - `duration`, `remaining`, `position` and `age` compare with `<`, `<=`,
+//!   `>`, `>=` or `=` against a length like `90s`, `30m`, `1h30m`, `2d` or
+//!   `1w`, a bare number is minutes. `remaining` is what is left to play, and
+//!   `age` is how long ago the episode came out
+//! - `or` matches either side, `not` or `-` matches the opposite, and
+//!   brackets group terms
+//! - `sort:<key>` orders the episodes by `date`, `duration`, `remaining`,
+//!   `show` or `title`, `sort:-<key>` reverses the order and later sorts
+//!   break ties. Without a sort the newest episodes come first
+//! - `limit:<count>` keeps only the first episodes
+//!
+//! So `unplayed duration<30m tag:news sort:-date` is short unplayed news,
+//! newest first, and `started sort:remaining` is everything in progress with
+//! the closest to done first.
+
+use std::{cmp::Ordering, fmt, str::FromStr, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{Episode, LibraryError, QueryError, Show, Shows};
+
+/// A named, saved [`Query`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmartPlaylist {
+    pub name: String,
+    pub query: Query,
+}
+
+/// A parsed smart playlist query, see the [module docs](self) for the syntax.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Query {
+    /// The text the query was parsed from
+    source: String,
+    filter: Option<Expr>,
+    sort: Vec<SortKey>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Flag(Flag),
+    Text(TextField, String),
+    Compare(TimeField, Op, Duration),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flag {
+    Unplayed,
+    Finished,
+    Started,
+    Downloaded,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextField {
+    Show,
+    Title,
+    Tag,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TimeField {
+    Duration,
+    Remaining,
+    Position,
+    Age,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SortKey {
+    field: SortField,
+    descending: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortField {
+    Date,
+    Duration,
+    Remaining,
+    Show,
+    Title,
+}
+
+impl Query {
+    /// Parse a query.
+    ///
+    /// # Errors
+    /// Fails if the query is not valid, the error says where.
+    pub fn parse(source: &str) -> Result<Self, QueryError> {
+        let mut sort = Vec::new();
+        let mut limit = None;
+        let mut tokens = Vec::new();
+        for token in tokenize(source)? {
+            match &token {
+                Token::Word(word) if word.starts_with("sort:") => {
+                    sort.push(parse_sort(&word["sort:".len()..])?);
+                }
+                Token::Word(word) if word.starts_with("limit:") => {
+                    let count = &word["limit:".len()..];
+                    limit = Some(
+                        count
+                            .parse()
+                            .map_err(|_| QueryError::InvalidLimit(count.to_string()))?,
+                    );
+                }
+                _ => tokens.push(token),
+            }
+        }
+
+        let mut parser = Parser { tokens, next: 0 };
+        let filter = if parser.tokens.is_empty() {
+            None
+        } else {
+            let filter = parser.or()?;
+            if let Some(token) = parser.peek() {
+                return Err(QueryError::Unexpected(token.to_string()));
+            }
+            Some(filter)
+        };
+
+        Ok(Self {
+            source: source.to_string(),
+            filter,
+            sort,
+            limit,
+        })
+    }
+
+    /// If an episode matches the query, `now` is used for `age`.
+    #[must_use]
+    pub fn matches(&self, show: &Show, episode: &Episode, now: DateTime<Utc>) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(show, episode, now))
+    }
+
+    /// Put episodes in the order the query asks for, and keep only as many as
+    /// its limit.
+    pub fn sort<'a>(&self, episodes: &mut Vec<(&'a Show, &'a Episode)>) {
+        if self.sort.is_empty() {
+            episodes.sort_by_key(|(_, episode)| std::cmp::Reverse(episode.date));
+        } else {
+            episodes.sort_by(|a, b| {
+                self.sort
+                    .iter()
+                    .map(|key| key.compare(*a, *b))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+
+        if let Some(limit) = self.limit {
+            episodes.truncate(limit);
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Self::parse(source)
+    }
+}
+
+impl TryFrom<String> for Query {
+    type Error = QueryError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Self::parse(&source)
+    }
+}
+
+impl From<Query> for String {
+    fn from(query: Query) -> Self {
+        query.source
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Expr {
+    fn matches(&self, show: &Show, episode: &Episode, now: DateTime<Utc>) -> bool {
+        match self {
+            Self::And(exprs) => exprs.iter().all(|expr| expr.matches(show, episode, now)),
+            Self::Or(exprs) => exprs.iter().any(|expr| expr.matches(show, episode, now)),
+            Self::Not(expr) => !expr.matches(show, episode, now),
+            Self::Flag(flag) => match flag {
+                Flag::Unplayed => !episode.finished,
+                Flag::Finished => episode.finished,
+                Flag::Started => !episode.finished && !episode.resume_time.is_zero(),
+                Flag::Downloaded => episode.download_path.is_some(),
+            },
+            Self::Text(field, value) => match field {
+                TextField::Show => contains_ignoring_case(&show.name, value),
+                TextField::Title => contains_ignoring_case(&episode.title, value),
+                TextField::Tag => show.has_tag(value),
+            },
+            Self::Compare(field, op, value) => {
+                let Some(time) = field.value(episode, now) else {
+                    return false;
+                };
+                match op {
+                    Op::Less => time < *value,
+                    Op::LessOrEqual => time <= *value,
+                    Op::Greater => time > *value,
+                    Op::GreaterOrEqual => time >= *value,
+                    Op::Equal => time == *value,
+                }
+            }
+        }
+    }
+}
+
+impl TimeField {
+    /// The value of the field for an episode, [`None`] if it is not known.
+    fn value(self, episode: &Episode, now: DateTime<Utc>) -> Option<Duration> {
+        match self {
+            Self::Duration => episode.duration,
+            Self::Remaining => remaining(episode),
+            Self::Position => Some(episode.resume_time),
+            Self::Age => (now - episode.date).to_std().ok(),
+        }
+    }
+}
+
+impl SortKey {
+    fn compare(self, (a_show, a): (&Show, &Episode), (b_show, b): (&Show, &Episode)) -> Ordering {
+        let directed = |ordering: Ordering| {
+            if self.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        };
+        // episodes with unknown lengths go last whichever way it is sorted
+        let unknown_last = |a: Option<Duration>, b: Option<Duration>| match (a, b) {
+            (Some(a), Some(b)) => directed(a.cmp(&b)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+
+        match self.field {
+            SortField::Date => directed(a.date.cmp(&b.date)),
+            SortField::Duration => unknown_last(a.duration, b.duration),
+            SortField::Remaining => unknown_last(remaining(a), remaining(b)),
+            SortField::Show => {
+                directed(a_show.name.to_lowercase().cmp(&b_show.name.to_lowercase()))
+            }
+            SortField::Title => directed(a.title.to_lowercase().cmp(&b.title.to_lowercase())),
+        }
+    }
+}
+
+fn remaining(episode: &Episode) -> Option<Duration> {
+    if episode.finished {
+        return Some(Duration::ZERO);
+    }
+    episode
+        .duration
+        .map(|duration| duration.saturating_sub(episode.resume_time))
+}
+
+fn contains_ignoring_case(text: &str, value: &str) -> bool {
+    text.to_lowercase().contains(&value.to_lowercase())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Word(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open => f.write_str("("),
+            Self::Close => f.write_str(")"),
+            Self::Word(word) => f.write_str(word),
+        }
+    }
+}
+
+/// Split a query into brackets and words, quotes keep spaces in a word and
+/// are removed.
+fn tokenize(source: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    chars.next();
+                    if c == '"' {
+                        loop {
+                            match chars.next() {
+                                Some('"') => break,
+                                Some(c) => word.push(c),
+                                None => return Err(QueryError::UnclosedQuote),
+                            }
+                        }
+                    } else {
+                        word.push(c);
+                    }
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn take(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn peek_word(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    /// `and ("or" and)*`
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut exprs = vec![self.and()?];
+        while self.peek_word("or") {
+            self.next += 1;
+            exprs.push(self.and()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::Or(exprs)
+        })
+    }
+
+    /// `unary+`, ending at `or`, a closing bracket or the end
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut exprs = Vec::new();
+        loop {
+            if self.peek_word("and") {
+                self.next += 1;
+            }
+            match self.peek() {
+                None | Some(Token::Close) => break,
+                _ if self.peek_word("or") => break,
+                _ => exprs.push(self.unary()?),
+            }
+        }
+
+        match exprs.len() {
+            0 => Err(match self.peek() {
+                Some(token) => QueryError::Unexpected(token.to_string()),
+                None => QueryError::UnexpectedEnd,
+            }),
+            1 => Ok(exprs.remove(0)),
+            _ => Ok(Expr::And(exprs)),
+        }
+    }
+
+    /// `("not" | "-") unary | "(" or ")" | term`
+    fn unary(&mut self) -> Result<Expr, QueryError> {
+        match self.take() {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.take() {
+                    Some(Token::Close) => Ok(expr),
+                    Some(token) => Err(QueryError::Unexpected(token.to_string())),
+                    None => Err(QueryError::UnexpectedEnd),
+                }
+            }
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("not") => {
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::Word(word)) => match word.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => Ok(Expr::Not(Box::new(parse_term(rest)?))),
+                _ => parse_term(&word),
+            },
+            Some(Token::Close) => Err(QueryError::Unexpected(")".to_string())),
+            None => Err(QueryError::UnexpectedEnd),
+        }
+    }
+}
+
+fn parse_term(word: &str) -> Result<Expr, QueryError> {
+    if let Some((key, value)) = word.split_once(':') {
+        let field = match key.to_lowercase().as_str() {
+            "show" => TextField::Show,
+            "title" => TextField::Title,
+            "tag" => TextField::Tag,
+            _ => return Err(QueryError::UnknownField(key.to_string())),
+        };
+        return Ok(Expr::Text(field, value.to_string()));
+    }
+
+    if let Some(index) = word.find(['<', '>', '=']) {
+        let (field, rest) = word.split_at(index);
+        let field = match field.to_lowercase().as_str() {
+            "duration" | "length" => TimeField::Duration,
+            "remaining" => TimeField::Remaining,
+            "position" => TimeField::Position,
+            "age" => TimeField::Age,
+            _ => return Err(QueryError::UnknownField(field.to_string())),
+        };
+        let (op, value) = [
+            ("<=", Op::LessOrEqual),
+            (">=", Op::GreaterOrEqual),
+            ("<", Op::Less),
+            (">", Op::Greater),
+            ("=", Op::Equal),
+        ]
+        .into_iter()
+        .find_map(|(symbol, op)| rest.strip_prefix(symbol).map(|value| (op, value)))
+        .ok_or_else(|| QueryError::Unexpected(word.to_string()))?;
+        return Ok(Expr::Compare(field, op, parse_length(value)?));
+    }
+
+    let flag = match word.to_lowercase().as_str() {
+        "unplayed" => Flag::Unplayed,
+        "finished" | "played" => Flag::Finished,
+        "started" | "in-progress" => Flag::Started,
+        "downloaded" => Flag::Downloaded,
+        _ => return Err(QueryError::UnknownField(word.to_string())),
+    };
+    Ok(Expr::Flag(flag))
+}
+
+fn parse_sort(value: &str) -> Result<SortKey, QueryError> {
+    let (descending, field) = match value.strip_prefix('-') {
+        Some(field) => (true, field),
+        None => (false, value),
+    };
+    let field = match field.to_lowercase().as_str() {
+        "date" => SortField::Date,
+        "duration" | "length" => SortField::Duration,
+        "remaining" => SortField::Remaining,
+        "show" => SortField::Show,
+        "title" => SortField::Title,
+        _ => return Err(QueryError::UnknownSort(value.to_string())),
+    };
+    Ok(SortKey { field, descending })
+}
+
+/// Parse a length like `1h30m`, a bare number is minutes.
+fn parse_length(value: &str) -> Result<Duration, QueryError> {
+    let invalid = || QueryError::InvalidLength(value.to_string());
+    if value.is_empty() {
+        return Err(invalid());
+    }
+    if let Ok(minutes) = value.parse::<u64>() {
+        return Ok(Duration::from_mins(minutes));
+    }
+
+    let mut total = Duration::ZERO;
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let count: u64 = number.parse().map_err(|_| invalid())?;
+        number.clear();
+        total += match c {
+            's' => Duration::from_secs(count),
+            'm' => Duration::from_mins(count),
+            'h' => Duration::from_hours(count),
+            'd' => Duration::from_hours(count * 24),
+            'w' => Duration::from_hours(count * 24 * 7),
+            _ => return Err(invalid()),
+        };
+    }
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+impl Shows {
+    /// Saved smart playlists, in the order they were added.
+    #[must_use]
+    pub fn playlists(&self) -> &[SmartPlaylist] {
+        &self.playlists
+    }
+
+    /// Save a smart playlist, replacing any with the same name.
+    pub fn add_playlist(&mut self, name: &str, query: Query) {
+        let playlist = SmartPlaylist {
+            name: name.to_string(),
+            query,
+        };
+        match self.playlists.iter_mut().find(|saved| saved.name == name) {
+            Some(saved) => *saved = playlist,
+            None => self.playlists.push(playlist),
+        }
+        self.last_change = Utc::now();
+    }
+
+    /// Remove a smart playlist by its name.
+    ///
+    /// # Errors
+    /// Fails if there is no playlist with that name.
+    pub fn remove_playlist(&mut self, name: &str) -> Result<SmartPlaylist, LibraryError> {
+        let index = self
+            .playlists
+            .iter()
+            .position(|playlist| playlist.name == name)
+            .ok_or_else(|| LibraryError::PlaylistNotFound(name.to_string()))?;
+        self.last_change = Utc::now();
+        Ok(self.playlists.remove(index))
+    }
+
+    /// Find a smart playlist by its name.
+    ///
+    /// # Errors
+    /// Returns [`None`] if there is no playlist with that name.
+    #[must_use]
+    pub fn find_playlist(&self, name: &str) -> Option<&SmartPlaylist> {
+        self.playlists.iter().find(|playlist| playlist.name == name)
+    }
+
+    /// Episodes from every show that match a query, in the order it asks for.
+    #[must_use]
+    pub fn query(&self, query: &Query) -> Vec<(&Show, &Episode)> {
+        let now = Utc::now();
+        let mut episodes = self
+            .shows
+            .iter()
+            .flat_map(|show| show.episodes.iter().map(move |episode| (show, episode)))
+            .filter(|(show, episode)| query.matches(show, episode, now))
+            .collect();
+        query.sort(&mut episodes);
+        episodes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::ShowSettings;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 4, 16, 12, 0, 0).unwrap()
+    }
+
+    fn episode(title: &str, days_old: i64, minutes: Option<u64>) -> Episode {
+        Episode {
+            id: title.to_string(),
+            media_url: format!("https://example.com/{title}.mp3"),
+            title: title.to_string(),
+            description: None,
+            date: now() - chrono::Duration::days(days_old),
+            duration: minutes.map(Duration::from_mins),
+            resume_time: Duration::ZERO,
+            finished: false,
+            download_path: None,
+            changed_at: None,
+            last_played_at: None,
+            artwork: None,
+            transcripts: Vec::new(),
+            chapters: Vec::new(),
+            bookmarks: Vec::new(),
+        }
+    }
+
+    fn show(name: &str, tags: &[&str], episodes: Vec<Episode>) -> Show {
+        Show {
+            url: format!("https://example.com/{name}"),
+            name: name.to_string(),
+            episodes,
+            image: None,
+            artwork: None,
+            last_checked: now(),
+            last_upload: now(),
+            settings: ShowSettings::default(),
+            tags: tags.iter().map(ToString::to_string).collect(),
+            previous_urls: Vec::new(),
+            gone: None,
+        }
+    }
+
+    /// Two shows with a mix of episodes:
+    ///
+    /// - the daily, tagged news: `monday` 1 day old 20m, `friday` 4 days old
+    ///   25m and finished, `long` 2 days old 45m
+    /// - lost terminal: `tunnels` 30 days old 18m and 10m in, `bugs` 20 days
+    ///   old with no length
+    fn shows() -> Vec<Show> {
+        let mut friday = episode("friday", 4, Some(25));
+        friday.finished = true;
+        let mut tunnels = episode("tunnels", 30, Some(18));
+        tunnels.resume_time = Duration::from_mins(10);
+        tunnels.download_path = Some("/tmp/tunnels.mp3".into());
+
+        vec![
+            show(
+                "The Daily",
+                &["news"],
+                vec![
+                    episode("monday", 1, Some(20)),
+                    friday,
+                    episode("long", 2, Some(45)),
+                ],
+            ),
+            show(
+                "Lost Terminal",
+                &["fiction"],
+                vec![tunnels, episode("bugs", 20, None)],
+            ),
+        ]
+    }
+
+    fn titles(query: &str) -> Vec<String> {
+        let query = Query::parse(query).expect("to parse");
+        let shows = shows();
+        let mut episodes = shows
+            .iter()
+            .flat_map(|show| show.episodes.iter().map(move |episode| (show, episode)))
+            .filter(|(show, episode)| query.matches(show, episode, now()))
+            .collect();
+        query.sort(&mut episodes);
+        episodes
+            .iter()
+            .map(|(_, episode)| episode.title.clone())
+            .collect()
+    }
+
+    #[test]
+    fn parses_terms() {
+        let query = Query::parse("unplayed duration<30m tag:news sort:-date").unwrap();
+        assert_eq!(
+            query.filter,
+            Some(Expr::And(vec![
+                Expr::Flag(Flag::Unplayed),
+                Expr::Compare(TimeField::Duration, Op::Less, Duration::from_mins(30)),
+                Expr::Text(TextField::Tag, "news".to_string()),
+            ]))
+        );
+        assert_eq!(
+            query.sort,
+            [SortKey {
+                field: SortField::Date,
+                descending: true
+            }]
+        );
+
+        let query = Query::parse("show:\"lost terminal\"").unwrap();
+        assert_eq!(
+            query.filter,
+            Some(Expr::Text(TextField::Show, "lost terminal".to_string()))
+        );
+        assert_eq!(Query::parse("").unwrap().filter, None);
+    }
+
+    #[test]
+    fn parses_precedence() {
+        // and binds tighter than or
+        let query = Query::parse("started or unplayed -downloaded").unwrap();
+        assert_eq!(
+            query.filter,
+            Some(Expr::Or(vec![
+                Expr::Flag(Flag::Started),
+                Expr::And(vec![
+                    Expr::Flag(Flag::Unplayed),
+                    Expr::Not(Box::new(Expr::Flag(Flag::Downloaded))),
+                ]),
+            ]))
+        );
+
+        let query = Query::parse("not (started or finished) and downloaded").unwrap();
+        assert_eq!(
+            query.filter,
+            Some(Expr::And(vec![
+                Expr::Not(Box::new(Expr::Or(vec![
+                    Expr::Flag(Flag::Started),
+                    Expr::Flag(Flag::Finished),
+                ]))),
+                Expr::Flag(Flag::Downloaded),
+            ]))
+        );
+    }
+
+    #[test]
+    fn parses_lengths() {
+        assert_eq!(parse_length("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_length("1h30m"), Ok(Duration::from_mins(90)));
+        assert_eq!(parse_length("45"), Ok(Duration::from_mins(45)));
+        assert_eq!(parse_length("1w"), Ok(Duration::from_hours(7 * 24)));
+        assert!(parse_length("").is_err());
+        assert!(parse_length("1h30").is_err());
+        assert!(parse_length("h").is_err());
+        assert!(parse_length("5y").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_queries() {
+        let err = |query| Query::parse(query).unwrap_err();
+        assert_eq!(err("unplayed or"), QueryError::UnexpectedEnd);
+        assert_eq!(err("(unplayed"), QueryError::UnexpectedEnd);
+        assert_eq!(err("unplayed)"), QueryError::Unexpected(")".to_string()));
+        assert_eq!(err("()"), QueryError::Unexpected(")".to_string()));
+        assert_eq!(
+            err("unheard"),
+            QueryError::UnknownField("unheard".to_string())
+        );
+        assert_eq!(err("size>5m"), QueryError::UnknownField("size".to_string()));
+        assert_eq!(
+            err("rating:5"),
+            QueryError::UnknownField("rating".to_string())
+        );
+        assert_eq!(
+            err("duration<soon"),
+            QueryError::InvalidLength("soon".to_string())
+        );
+        assert_eq!(
+            err("sort:rating"),
+            QueryError::UnknownSort("rating".to_string())
+        );
+        assert_eq!(
+            err("limit:many"),
+            QueryError::InvalidLimit("many".to_string())
+        );
+        assert_eq!(err("show:\"lost"), QueryError::UnclosedQuote);
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(
+            titles("unplayed duration<30m tag:news sort:-date"),
+            ["monday"]
+        );
+        assert_eq!(titles("started"), ["tunnels"]);
+        assert_eq!(
+            titles("finished or downloaded sort:title"),
+            ["friday", "tunnels"]
+        );
+        assert_eq!(titles("show:terminal -downloaded"), ["bugs"]);
+        assert_eq!(titles("title:DAY"), ["monday", "friday"]);
+        assert_eq!(titles("age<=2d sort:date"), ["long", "monday"]);
+        // episodes without a length never match a comparison on it
+        assert_eq!(titles("not duration>0 "), ["bugs"]);
+        assert_eq!(titles("remaining<=8m"), ["friday", "tunnels"]);
+    }
+
+    #[test]
+    fn sorts() {
+        // newest first by default
+        assert_eq!(titles(""), ["monday", "long", "friday", "bugs", "tunnels"]);
+        // unknown lengths go last
+        assert_eq!(
+            titles("sort:remaining"),
+            ["friday", "tunnels", "monday", "long", "bugs"]
+        );
+        assert_eq!(
+            titles("sort:show sort:-duration"),
+            ["tunnels", "bugs", "long", "friday", "monday"]
+        );
+        assert_eq!(
+            titles("unplayed sort:-duration limit:2"),
+            ["long", "monday"]
+        );
+    }
+
+    #[test]
+    fn round_trips_as_text() {
+        let playlist = SmartPlaylist {
+            name: "short news".to_string(),
+            query: Query::parse("unplayed duration<30m tag:news").unwrap(),
+        };
+        let json = serde_json::to_string(&playlist).unwrap();
+        assert_eq!(
+            json,
+            r#"{"name":"short news","query":"unplayed duration<30m tag:news"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<SmartPlaylist>(&json).unwrap(),
+            playlist
+        );
+        assert!(serde_json::from_str::<SmartPlaylist>(r#"{"name":"bad","query":"("}"#).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use std::time;
+
+/// Settings for a single [`crate::Show`], saved with the library.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShowSettings {
+    /// Playback speed for this show, if not set the speed from the config is used.
+    pub playback_speed: Option<f32>,
+    /// How much of the start of each episode to skip.
+    pub skip_intro: time::Duration,
+    /// How much of the end of each episode to skip.
+    pub skip_outro: time::Duration,
+    /// Which order episodes are listed in.
+    pub sort_order: SortOrder,
+    /// Which episodes are downloaded without being asked for.
+    pub auto_download: AutoDownload,
+    /// If new episodes are added to the end of the queue.
+    pub add_to_queue: bool,
+    /// If finished episodes are hidden from the episode list.
+    pub hide_played: bool,
+    /// If long silences are skipped, if not set the setting from the config is used.
+    pub skip_silence: Option<bool>,
+    /// If loudness is normalised, if not set the setting from the config is used.
+    pub normalize: Option<bool>,
+    /// If audio is mixed down to mono, if not set the setting from the config is used.
+    pub mono: Option<bool>,
+}
+
+impl Default for ShowSettings {
+    fn default() -> Self {
+        Self {
+            playback_speed: None,
+            skip_intro: time::Duration::ZERO,
+            skip_outro: time::Duration::ZERO,
+            sort_order: SortOrder::default(),
+            auto_download: AutoDownload::default(),
+            add_to_queue: false,
+            hide_played: false,
+            skip_silence: None,
+            normalize: None,
+            mono: None,
+        }
+    }
+}
+
+/// The order that the episodes of a show are kept in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// Oldest episodes first, best for serial fiction
+    #[default]
+    OldestFirst,
+    /// Newest episodes first, best for news and talk shows
+    NewestFirst,
+}
+
+/// When episodes of a show are downloaded automatically.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoDownload {
+    /// Only download episodes when asked to
+    #[default]
+    Never,
+    /// Download every new episode found when refreshing
+    New,
+    /// Keep this many of the newest unplayed episodes downloaded
+    Latest(usize),
+}
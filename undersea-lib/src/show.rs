@@ -1,74 +1,269 @@
-use chrono::{DateTime, Utc};
-use reqwest::{IntoUrl, get};
+use chrono::{DateTime, TimeDelta, Utc};
+use reqwest::{StatusCode, Url, header::LOCATION};
 use rss::Channel;
+use serde::{Deserialize, Serialize};
 use std::time;
 
-use crate::{Episode, FeedError};
+use crate::{
+    AutoDownload, Chapter, Episode, FeedError, ShowSettings, SortOrder,
+    credentials::{Credentials, authorize},
+    description::{DescriptionCache, KeptDescriptions},
+    feed::FeedReader,
+    http::HttpClient,
+    player::dsp::AudioSettings,
+    transcript::TranscriptLink,
+};
 
 /// A podcast, contains the URL, name and a list of [`Episode`]s.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Show {
     pub(crate) url: String,
     pub(crate) name: String,
     pub(crate) episodes: Vec<Episode>,
     pub(crate) image: Option<rss::Image>,
+    /// Url of the `itunes:image`, or the rss image if there is none
+    #[serde(default)]
+    pub(crate) artwork: Option<String>,
     pub(crate) last_checked: DateTime<Utc>,
     pub(crate) last_upload: DateTime<Utc>,
+    #[serde(default)]
+    pub(crate) settings: ShowSettings,
+    /// Tags the user has given the show, to group and filter shows
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
+    /// Urls the feed was at before it moved, so the show can still be found
+    /// by them, for example by changes synced from devices that have not
+    /// seen the move yet
+    #[serde(default)]
+    pub(crate) previous_urls: Vec<String>,
+    /// Set while the feed is missing from its url
+    #[serde(default)]
+    pub(crate) gone: Option<Gone>,
+}
+
+/// How many refreshes in a row a feed has to be missing before it counts as
+/// dead, see [`Gone::is_dead`].
+const DEAD_AFTER_REFRESHES: u32 = 3;
+/// How long a feed has to be missing before it counts as dead.
+const DEAD_AFTER_DAYS: i64 = 7;
+/// How many redirects are followed to find out where a feed has moved to.
+const MAX_REDIRECTS: usize = 10;
+
+/// A feed that has answered that it is not there, with a 404 or 410, on
+/// every refresh since `since`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Gone {
+    /// When the feed was first found missing
+    pub since: DateTime<Utc>,
+    /// How many refreshes in a row found it missing
+    pub refreshes: u32,
+}
+
+impl Gone {
+    /// If the feed has been missing often enough and for long enough by
+    /// `now` that it is probably not coming back. A feed that is only down
+    /// for a little while, or was only checked once, is not dead.
+    #[must_use]
+    pub fn is_dead(&self, now: DateTime<Utc>) -> bool {
+        self.refreshes >= DEAD_AFTER_REFRESHES
+            && now - self.since >= TimeDelta::days(DEAD_AFTER_DAYS)
+    }
+}
+
+/// What refreshing a show found.
+pub(crate) struct Refreshed {
+    /// Ids of the new episodes
+    pub(crate) new_episodes: Vec<String>,
+    /// The url the feed was at, if it has moved
+    pub(crate) moved_from: Option<String>,
 }
 
 impl Show {
-    pub(crate) async fn new<S>(url: S) -> Result<Show, FeedError>
+    /// Fetch a feed to make a show of it. If the feed has moved, the show
+    /// gets the new url.
+    pub(crate) async fn new<S>(
+        url: S,
+        client: &HttpClient,
+        credentials: Option<&Credentials>,
+        descriptions: Option<&DescriptionCache>,
+    ) -> Result<Show, FeedError>
     where
-        S: IntoUrl + Clone + Into<String>,
+        S: Into<String>,
     {
-        let response = get(url.clone()).await?.bytes().await?;
-        let channel = Channel::read_from(&response[..]).unwrap();
-        let mut episodes: Vec<Episode> = Vec::new();
-
-        for item in channel.items() {
-            let date = DateTime::parse_from_rfc2822(item.pub_date().unwrap()).unwrap();
-
-            let media_url = if let Some(enclosure) = item.enclosure() {
-                enclosure.url().to_string()
-            } else {
-                // skip ep if no attached file
-                // TODO: handle this a bit better
-                continue;
-            };
-
-            let url_copy = url.clone();
-            let title = if let Some(episode_title) = item.title() {
-                episode_title.to_string()
-            } else {
-                url_copy.into()
-            };
-
-            let description = item.content().map(|i| i.to_string());
-
-            episodes.push(Episode {
-                media_url,
-                title,
-                description,
-                date: date.into(),
-                // TODO: Implement this
-                duration: None,
-                resume_time: time::Duration::from_secs(0),
-                finished: false,
-            });
+        let url: String = url.into();
+        let Fetched {
+            channel,
+            mut episodes,
+            moved_to,
+            kept,
+        } = fetch_channel(&url, client, credentials, descriptions).await?;
+        let artwork = channel_artwork(&channel);
+        let (url, previous_urls) = match moved_to {
+            Some(moved_to) if moved_to != url => (moved_to, vec![url]),
+            _ => (url, Vec::new()),
+        };
+        if let Some(kept) = kept {
+            kept.finish(&mut episodes, &url, previous_urls.first().unwrap_or(&url));
         }
 
-        episodes.sort_by_key(|ep| ep.date);
-
-        Ok(Self {
-            url: url.into(),
+        let mut show = Self {
+            url,
             name: channel.title,
             episodes,
+            artwork,
             image: channel.image,
             last_checked: Utc::now(),
             last_upload: Utc::now(),
+            settings: ShowSettings::default(),
+            tags: Vec::new(),
+            previous_urls,
+            gone: None,
+        };
+        show.sort_episodes();
+        show.update_last_upload();
+        Ok(show)
+    }
+
+    /// Fetch the feed again, adding any new episodes and updating the details
+    /// of existing ones. Progress on existing episodes is kept, also when the
+    /// feed has moved to a new url, which the show then uses from now on.
+    ///
+    /// A feed that is missing is counted towards it being [`Gone`].
+    pub(crate) async fn refresh(
+        &mut self,
+        client: &HttpClient,
+        credentials: Option<&Credentials>,
+        descriptions: Option<&DescriptionCache>,
+    ) -> Result<Refreshed, FeedError> {
+        let Fetched {
+            channel,
+            mut episodes,
+            moved_to,
+            kept,
+        } = match fetch_channel(&self.url, client, credentials, descriptions).await {
+            Ok(fetched) => fetched,
+            Err(err) => {
+                if is_missing(&err) {
+                    self.mark_missing();
+                }
+                return Err(err);
+            }
+        };
+        self.gone = None;
+        // a feed pointing back to where it was is ignored, so two urls that
+        // point at each other do not keep swapping
+        let moved_from = moved_to
+            .filter(|to| *to != self.url && !self.previous_urls.contains(to))
+            .map(|to| self.move_to(to));
+        if let Some(kept) = kept {
+            kept.finish(
+                &mut episodes,
+                &self.url,
+                moved_from.as_ref().unwrap_or(&self.url),
+            );
+        }
+        let mut new_episodes = Vec::new();
+
+        for fetched in episodes {
+            if let Some(existing) = self.episodes.iter_mut().find(|ep| ep.id == fetched.id) {
+                existing.media_url = fetched.media_url;
+                existing.title = fetched.title;
+                // with a description cache, older episodes lose theirs here,
+                // it is in the cache for `Shows::description`
+                existing.description = fetched.description;
+                existing.date = fetched.date;
+                existing.duration = fetched.duration.or(existing.duration);
+                existing.artwork = fetched.artwork;
+                existing.transcripts = fetched.transcripts;
+                existing.chapters = fetched.chapters;
+            } else {
+                new_episodes.push(fetched.id.clone());
+                self.episodes.push(fetched);
+            }
+        }
+
+        self.sort_episodes();
+        self.artwork = channel_artwork(&channel);
+        self.name = channel.title;
+        self.image = channel.image;
+        self.last_checked = Utc::now();
+        self.update_last_upload();
+
+        Ok(Refreshed {
+            new_episodes,
+            moved_from,
         })
     }
 
+    /// Use `url` for the show from now on, returning the url it had.
+    pub(crate) fn move_to(&mut self, url: String) -> String {
+        let from = std::mem::replace(&mut self.url, url);
+        self.previous_urls.retain(|previous| *previous != self.url);
+        self.previous_urls.push(from.clone());
+        from
+    }
+
+    fn mark_missing(&mut self) {
+        match &mut self.gone {
+            Some(gone) => gone.refreshes += 1,
+            None => {
+                self.gone = Some(Gone {
+                    since: Utc::now(),
+                    refreshes: 1,
+                });
+            }
+        }
+    }
+
+    fn sort_episodes(&mut self) {
+        match self.settings.sort_order {
+            SortOrder::OldestFirst => self.episodes.sort_by_key(|ep| ep.date),
+            SortOrder::NewestFirst => self.episodes.sort_by_key(|ep| std::cmp::Reverse(ep.date)),
+        }
+    }
+
+    pub(crate) fn set_settings(&mut self, settings: ShowSettings) {
+        self.settings = settings;
+        self.sort_episodes();
+    }
+
+    /// Ids of episodes that should be downloaded because of the shows
+    /// [`AutoDownload`] setting, `new_episodes` are the ones that were just
+    /// found by refreshing. Episodes that are already downloaded are left out.
+    pub(crate) fn episodes_to_download(&self, new_episodes: &[String]) -> Vec<String> {
+        let not_downloaded = |ep: &&Episode| ep.download_path.is_none();
+
+        match self.settings.auto_download {
+            AutoDownload::Never => Vec::new(),
+            AutoDownload::New => new_episodes
+                .iter()
+                .filter_map(|id| self.episode_by_id(id))
+                .filter(not_downloaded)
+                .map(|ep| ep.id.clone())
+                .collect(),
+            AutoDownload::Latest(count) => {
+                let mut unplayed = self
+                    .episodes
+                    .iter()
+                    .filter(|ep| !ep.finished)
+                    .collect::<Vec<_>>();
+                unplayed.sort_by_key(|ep| std::cmp::Reverse(ep.date));
+                unplayed
+                    .into_iter()
+                    .take(count)
+                    .filter(not_downloaded)
+                    .map(|ep| ep.id.clone())
+                    .collect()
+            }
+        }
+    }
+
+    fn update_last_upload(&mut self) {
+        if let Some(latest) = self.episodes.iter().map(|ep| ep.date).max() {
+            self.last_upload = latest;
+        }
+    }
+
     /// Time of the last time the feed was checked for new episodes and other changes.
     #[must_use]
     pub fn last_check(&self) -> &DateTime<Utc> {
@@ -88,19 +283,61 @@ impl Show {
         &self.url
     }
 
+    /// Urls the feed was at before it moved, oldest first.
+    #[must_use]
+    pub fn previous_urls(&self) -> &[String] {
+        &self.previous_urls
+    }
+
+    /// If the show is at `url`, or was before its feed moved.
+    #[must_use]
+    pub fn has_url(&self, url: &str) -> bool {
+        self.url == url || self.previous_urls.iter().any(|previous| previous == url)
+    }
+
+    /// Set while the feed has been missing on every refresh, see
+    /// [`Show::is_dead`].
+    #[must_use]
+    pub fn gone(&self) -> Option<&Gone> {
+        self.gone.as_ref()
+    }
+
+    /// If the feed has been missing for so long that it is probably not
+    /// coming back.
+    #[must_use]
+    pub fn is_dead(&self) -> bool {
+        self.gone.is_some_and(|gone| gone.is_dead(Utc::now()))
+    }
+
     /// Returns the title of a show
     #[must_use]
     pub fn name(&self) -> &str {
         &self.name
     }
 
-    /// Returns the image as [`rss::Image`], you will need to figure out your
-    /// own way of downloading and displaying the image
+    /// Returns the rss image as [`rss::Image`], most feeds only have an
+    /// `itunes:image`, so [`Show::artwork_url`] is usually what you want.
     #[must_use]
     pub fn image(&self) -> Option<&rss::Image> {
         self.image.as_ref()
     }
 
+    /// Returns the url of the shows artwork, which can be downloaded with an
+    /// [`crate::artwork::ArtworkCache`].
+    #[must_use]
+    pub fn artwork_url(&self) -> Option<&str> {
+        self.artwork
+            .as_deref()
+            .or_else(|| self.image.as_ref().map(rss::Image::url))
+    }
+
+    /// Returns the url of the artwork for an episode, its own if it has any,
+    /// otherwise the shows.
+    #[must_use]
+    pub fn artwork_for<'a>(&'a self, episode: &'a Episode) -> Option<&'a str> {
+        episode.artwork_url().or_else(|| self.artwork_url())
+    }
+
     /// Returns referances to all episodes added
     #[must_use]
     pub fn episodes(&self) -> Vec<&Episode> {
@@ -111,6 +348,69 @@ impl Show {
         episode_refs
     }
 
+    /// Returns the episodes that should be listed, which leaves out finished
+    /// episodes if [`ShowSettings::hide_played`] is set.
+    #[must_use]
+    pub fn visible_episodes(&self) -> Vec<&Episode> {
+        self.episodes
+            .iter()
+            .filter(|ep| !(self.settings.hide_played && ep.finished))
+            .collect()
+    }
+
+    /// Tags the user has given the show.
+    #[must_use]
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// If the show has a tag, ignoring case.
+    #[must_use]
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|own| own.eq_ignore_ascii_case(tag))
+    }
+
+    /// Returns the settings for this show.
+    #[must_use]
+    pub fn settings(&self) -> &ShowSettings {
+        &self.settings
+    }
+
+    /// Playback speed for episodes of this show, `default` is used if the show
+    /// does not have its own speed.
+    #[must_use]
+    pub fn playback_speed(&self, default: f32) -> f32 {
+        self.settings.playback_speed.unwrap_or(default)
+    }
+
+    /// How audio is processed for episodes of this show, `default` with any
+    /// stages the show turns on or off itself.
+    #[must_use]
+    pub fn audio_settings(&self, default: &AudioSettings) -> AudioSettings {
+        AudioSettings {
+            skip_silence: self.settings.skip_silence.unwrap_or(default.skip_silence),
+            normalize: self.settings.normalize.unwrap_or(default.normalize),
+            mono: self.settings.mono.unwrap_or(default.mono),
+            ..*default
+        }
+    }
+
+    /// Where playback of an episode should start, skipping the intro unless
+    /// the episode has already been played past it.
+    #[must_use]
+    pub fn start_position(&self, episode: &Episode) -> time::Duration {
+        episode.resume_time.max(self.settings.skip_intro)
+    }
+
+    /// Where playback of an episode should stop to skip the outro, if the
+    /// length of the episode is known.
+    #[must_use]
+    pub fn end_position(&self, episode: &Episode) -> Option<time::Duration> {
+        episode
+            .duration
+            .map(|duration| duration.saturating_sub(self.settings.skip_outro))
+    }
+
     /// Returns the episode by its index in the list.
     ///
     /// # Errors
@@ -119,4 +419,258 @@ impl Show {
     pub fn episode_by_index(&self, index: usize) -> Option<&Episode> {
         self.episodes.get(index)
     }
+
+    /// Returns the episode with the given [`Episode::id`].
+    ///
+    /// # Errors
+    /// Returns [`None`] if the show has no episode with that id.
+    #[must_use]
+    pub fn episode_by_id(&self, id: &str) -> Option<&Episode> {
+        self.episodes.iter().find(|ep| ep.id == id)
+    }
+
+    pub(crate) fn episode_by_id_mut(&mut self, id: &str) -> Option<&mut Episode> {
+        self.episodes.iter_mut().find(|ep| ep.id == id)
+    }
+}
+
+/// A fetched feed.
+struct Fetched {
+    /// The channel, without its items
+    channel: Channel,
+    episodes: Vec<Episode>,
+    /// Where the feed says it has moved to, or was permanently redirected to
+    moved_to: Option<String>,
+    /// The descriptions written to the cache, to be put in place once the
+    /// url of the show is known
+    kept: Option<KeptDescriptions>,
+}
+
+/// Fetch and read a feed, turning its items into episodes as they arrive,
+/// so the whole feed is never held in memory. With a `descriptions` cache
+/// only the newest descriptions are kept in memory.
+///
+/// Errors leave out the url, as it may have secrets in it, the caller knows
+/// which feed failed.
+async fn fetch_channel(
+    url: &str,
+    client: &HttpClient,
+    credentials: Option<&Credentials>,
+    descriptions: Option<&DescriptionCache>,
+) -> Result<Fetched, FeedError> {
+    let response = client
+        .send(authorize(client.get(url), credentials))
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(reqwest::Error::without_url)?;
+    let redirected = Url::parse(url).is_ok_and(|requested| requested != *response.url());
+
+    let mut feed = FeedReader::new(response);
+    let mut kept = descriptions.map(|descriptions| descriptions.start(url));
+    let mut episodes = Vec::new();
+    let mut undated = Vec::new();
+    while let Some(item) = feed.next_item().await? {
+        let Some(episode) = episode_from_item(&item, url) else {
+            continue;
+        };
+        if parse_date(item.pub_date()).is_none() {
+            undated.push(episodes.len());
+        }
+        episodes.push(episode);
+        if let Some(kept) = &mut kept {
+            kept.add(&mut episodes);
+        }
+    }
+    let channel = feed.channel()?;
+
+    // used for episodes that are missing a date, so they are at least close to
+    // where they should be
+    let channel_date = parse_date(channel.pub_date())
+        .or_else(|| parse_date(channel.last_build_date()))
+        .unwrap_or(DateTime::UNIX_EPOCH);
+    for index in undated {
+        episodes[index].date = channel_date;
+    }
+
+    let new_feed_url = channel
+        .itunes_ext()
+        .and_then(|itunes| itunes.new_feed_url())
+        .map(str::trim)
+        .filter(|new_url| !new_url.is_empty())
+        .map(ToString::to_string);
+    let moved_to = match new_feed_url.filter(|new_url| new_url != url) {
+        Some(new_url) => Some(new_url),
+        None if redirected => permanent_location(url, client, credentials).await,
+        None => None,
+    };
+    Ok(Fetched {
+        channel,
+        episodes,
+        moved_to,
+        kept,
+    })
+}
+
+/// Follow the redirects from `url` one at a time, for as long as they are
+/// permanent (301 or 308), and return the last url reached. A temporary
+/// redirect anywhere in the chain means the feed has not moved past it.
+///
+/// Returns [`None`] if the first redirect is not permanent, or if any request
+/// fails, so a feed is never moved on a guess.
+async fn permanent_location(
+    url: &str,
+    client: &HttpClient,
+    credentials: Option<&Credentials>,
+) -> Option<String> {
+    // has the same timeouts as every other request
+    let client = client.no_redirects();
+    let mut current = Url::parse(url).ok()?;
+    let mut moved_to = None;
+    for _ in 0..MAX_REDIRECTS {
+        let request = client.get(current.clone());
+        let response = authorize(request, credentials).send().await.ok()?;
+        if !matches!(
+            response.status(),
+            StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT
+        ) {
+            break;
+        }
+        let location = response.headers().get(LOCATION)?.to_str().ok()?;
+        current = current.join(location).ok()?;
+        moved_to = Some(current.to_string());
+    }
+    moved_to
+}
+
+/// If a feed failed because it is not at its url any more.
+fn is_missing(err: &FeedError) -> bool {
+    match err {
+        FeedError::NetworkError(err) => {
+            matches!(err.status(), Some(StatusCode::NOT_FOUND | StatusCode::GONE))
+        }
+        FeedError::InvalidFeed(_) => false,
+    }
+}
+
+/// Make an episode of an item, [`None`] if it has no media. Items without
+/// a date get the oldest possible one, for the caller to replace.
+fn episode_from_item(item: &rss::Item, url: &str) -> Option<Episode> {
+    let date = parse_date(item.pub_date()).unwrap_or(DateTime::UNIX_EPOCH);
+
+    // skip ep if no attached file
+    // TODO: handle this a bit better
+    let media_url = item.enclosure()?.url().to_string();
+
+    let title = if let Some(episode_title) = item.title() {
+        episode_title.to_string()
+    } else {
+        url.to_string()
+    };
+
+    let id = item
+        .guid()
+        .map_or_else(|| media_url.clone(), |guid| guid.value().to_string());
+
+    let description = item.content().map(ToString::to_string);
+
+    let duration = item
+        .itunes_ext()
+        .and_then(|itunes| itunes.duration())
+        .and_then(parse_duration);
+
+    let artwork = item
+        .itunes_ext()
+        .and_then(|itunes| itunes.image())
+        .map(ToString::to_string);
+
+    Some(Episode {
+        id,
+        media_url,
+        title,
+        description,
+        date,
+        duration,
+        resume_time: time::Duration::from_secs(0),
+        finished: false,
+        download_path: None,
+        changed_at: None,
+        last_played_at: None,
+        artwork,
+        transcripts: item_transcripts(item),
+        chapters: item_chapters(item),
+        bookmarks: Vec::new(),
+    })
+}
+
+/// The `podcast:transcript` tags of an item that have a url.
+fn item_transcripts(item: &rss::Item) -> Vec<TranscriptLink> {
+    item.extensions()
+        .get("podcast")
+        .and_then(|podcast| podcast.get("transcript"))
+        .into_iter()
+        .flatten()
+        .filter_map(|transcript| {
+            let attrs = transcript.attrs();
+            Some(TranscriptLink {
+                url: attrs.get("url")?.clone(),
+                mime_type: attrs.get("type").cloned().unwrap_or_default(),
+                language: attrs.get("language").cloned(),
+            })
+        })
+        .collect()
+}
+
+/// The Podlove Simple Chapters of an item, in order. Chapters without a
+/// valid start time are left out.
+fn item_chapters(item: &rss::Item) -> Vec<Chapter> {
+    let mut chapters = item
+        .extensions()
+        .get("psc")
+        .and_then(|psc| psc.get("chapters"))
+        .into_iter()
+        .flatten()
+        .filter_map(|chapters| chapters.children().get("chapter"))
+        .flatten()
+        .filter_map(|chapter| {
+            let attrs = chapter.attrs();
+            Some(Chapter {
+                start: parse_duration(attrs.get("start")?)?,
+                title: attrs.get("title").cloned().unwrap_or_default(),
+            })
+        })
+        .collect::<Vec<_>>();
+    chapters.sort_by_key(|chapter| chapter.start);
+    chapters
+}
+
+/// The `itunes:image` of a channel, which more feeds have than an rss image.
+fn channel_artwork(channel: &Channel) -> Option<String> {
+    channel
+        .itunes_ext()
+        .and_then(|itunes| itunes.image())
+        .or_else(|| channel.image().map(rss::Image::url))
+        .map(ToString::to_string)
+}
+
+/// Parse an `itunes:duration`, which is either a number of seconds, or
+/// `mm:ss` or `hh:mm:ss`.
+fn parse_duration(duration: &str) -> Option<time::Duration> {
+    let mut seconds = 0.0;
+    for part in duration.trim().split(':') {
+        let part: f64 = part.parse().ok()?;
+        if part < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + part;
+    }
+    time::Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Feeds are meant to use RFC 2822 dates, but some use RFC 3339 instead.
+fn parse_date(date: Option<&str>) -> Option<DateTime<Utc>> {
+    let date = date?.trim();
+    DateTime::parse_from_rfc2822(date)
+        .or_else(|_| DateTime::parse_from_rfc3339(date))
+        .ok()
+        .map(Into::into)
 }
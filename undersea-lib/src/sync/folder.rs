@@ -0,0 +1,366 @@
+//! Sync through a folder that is copied between devices by something else,
+//! such as Syncthing, Dropbox or git.
+//!
+//! Each device only ever writes to its own log, `<device>.jsonl`, adding one
+//! [`LogEntry`] per line, and only reads the logs of other devices. Since no
+//! two devices write to the same file, there is nothing for the tool copying
+//! the folder to get a conflict on.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{Change, JournalEntry, SyncBackend};
+use crate::SyncError;
+
+/// Name of the file, in the data directory, that a [`FolderState`] is kept in.
+pub const STATE_FILE_NAME: &str = "folder-sync.json";
+
+/// Extension of the log files in the folder.
+const LOG_EXTENSION: &str = "jsonl";
+
+/// A log is compacted once it has this many entries, and at least twice as
+/// many as compacting it would keep, so a log that only has entries that
+/// matter is not rewritten on every push.
+const COMPACT_AFTER: usize = 1000;
+
+/// Where the shared folder is, the `[sync_folder]` table in the config.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FolderConfig {
+    pub path: PathBuf,
+    /// Name of this device, which must be different on every device
+    pub device: String,
+}
+
+/// One line of a log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// Counts up from 1 in each log, and is kept when a log is compacted so
+    /// that other devices know which entries they have already applied
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    pub change: Change,
+}
+
+/// Which entries of other devices logs have been applied, this needs to be
+/// kept between syncs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FolderState {
+    /// The highest `seq` applied from each device
+    pub seen: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub last_sync: Option<DateTime<Utc>>,
+}
+
+/// A [`SyncBackend`] for the logs in a shared folder.
+#[derive(Debug, Clone)]
+pub struct FolderSync {
+    config: FolderConfig,
+    state: FolderState,
+    state_path: Option<PathBuf>,
+}
+
+impl FolderSync {
+    /// Sync with a state that is not saved, [`FolderSync::state`] can be used
+    /// to save it.
+    #[must_use]
+    pub fn new(config: FolderConfig, state: FolderState) -> Self {
+        Self {
+            config,
+            state,
+            state_path: None,
+        }
+    }
+
+    /// Sync with the state kept in `state_path`, saving it after each sync.
+    ///
+    /// # Errors
+    /// Fails if the state could not be read.
+    pub fn open(config: FolderConfig, state_path: PathBuf) -> Result<Self, SyncError> {
+        Ok(Self {
+            config,
+            state: super::load_state(&state_path)?,
+            state_path: Some(state_path),
+        })
+    }
+
+    /// Which entries have been applied.
+    #[must_use]
+    pub fn state(&self) -> &FolderState {
+        &self.state
+    }
+
+    fn log_path(&self) -> PathBuf {
+        self.config
+            .path
+            .join(format!("{}.{LOG_EXTENSION}", self.config.device))
+    }
+
+    /// Rewrite this devices log with only the entries that still matter, the
+    /// latest one for each show and episode.
+    ///
+    /// # Errors
+    /// Fails if the log could not be read or written.
+    pub fn compact(&self) -> Result<(), SyncError> {
+        let log_path = self.log_path();
+        let entries = super::compact(read_log(&log_path)?, |entry| &entry.change);
+        replace_log(&log_path, &entries)
+    }
+
+    /// The logs of every other device in the folder, along with the device.
+    fn other_logs(&self) -> Result<Vec<(String, PathBuf)>, SyncError> {
+        let mut logs = Vec::new();
+        for dir_entry in fs::read_dir(&self.config.path)? {
+            let path = dir_entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(LOG_EXTENSION) {
+                continue;
+            }
+            let Some(device) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            // conflict copies made by syncthing, which should never happen
+            // as nothing else writes to a log, but just in case
+            if device == self.config.device || device.contains(".sync-conflict-") {
+                continue;
+            }
+            logs.push((device.to_string(), path.clone()));
+        }
+        Ok(logs)
+    }
+}
+
+#[async_trait]
+impl SyncBackend for FolderSync {
+    fn name(&self) -> &'static str {
+        "folder"
+    }
+
+    fn last_sync(&self) -> Option<DateTime<Utc>> {
+        self.state.last_sync
+    }
+
+    /// Append changes to this devices log.
+    async fn push(&mut self, changes: &[JournalEntry]) -> Result<(), SyncError> {
+        fs::create_dir_all(&self.config.path)?;
+        let mut own = read_log(&self.log_path())?;
+        let mut seq = own.iter().map(|entry| entry.seq).max().unwrap_or(0);
+        let entries = changes
+            .iter()
+            .map(|entry| {
+                seq += 1;
+                LogEntry {
+                    seq,
+                    at: entry.at,
+                    change: entry.change.clone(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        append_log(&self.log_path(), &entries)?;
+        own.extend(entries);
+        if own.len() >= COMPACT_AFTER {
+            let len = own.len();
+            let compacted = super::compact(own, |entry| &entry.change);
+            if needs_compacting(len, compacted.len()) {
+                replace_log(&self.log_path(), &compacted)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Entries from other devices logs that have not been seen yet, other
+    /// than ones older than a change in this devices log.
+    async fn pull(&mut self) -> Result<Vec<JournalEntry>, SyncError> {
+        fs::create_dir_all(&self.config.path)?;
+        let mut latest_own = HashMap::new();
+        for entry in read_log(&self.log_path())? {
+            latest_own.insert(entry.change.key(), entry.at);
+        }
+
+        let mut pulled = Vec::new();
+        for (device, path) in self.other_logs()? {
+            let seen = self.state.seen.get(&device).copied().unwrap_or(0);
+            let entries = read_log(&path)?;
+            if let Some(newest) = entries.iter().map(|entry| entry.seq).max() {
+                self.state.seen.insert(device, newest.max(seen));
+            }
+            pulled.extend(
+                entries
+                    .into_iter()
+                    .filter(|entry| entry.seq > seen)
+                    .filter(|entry| {
+                        latest_own
+                            .get(&entry.change.key())
+                            .is_none_or(|own| *own < entry.at)
+                    })
+                    .map(|entry| JournalEntry {
+                        at: entry.at,
+                        change: entry.change,
+                    }),
+            );
+        }
+        Ok(pulled)
+    }
+
+    fn finish(&mut self, at: DateTime<Utc>) -> Result<(), SyncError> {
+        self.state.last_sync = Some(at);
+        if let Some(path) = &self.state_path {
+            super::save_state(path, &self.state)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read a log, a missing log is empty. Lines that can not be read are
+/// skipped, as the last line may only have been partly copied so far.
+fn read_log(path: &Path) -> Result<Vec<LogEntry>, SyncError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Whether a log of `len` entries, of which compacting keeps `kept`, should
+/// be compacted.
+fn needs_compacting(len: usize, kept: usize) -> bool {
+    len >= COMPACT_AFTER && len >= kept.saturating_mul(2)
+}
+
+/// Append entries to a log. A partial last line, from a write that was cut
+/// short, is ended first so the new entries are not lost with it.
+fn append_log(path: &Path, entries: &[LogEntry]) -> Result<(), SyncError> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)?;
+    let mut lines = String::new();
+    if file.metadata()?.len() > 0 {
+        let mut last = [0];
+        file.seek(SeekFrom::End(-1))?;
+        file.read_exact(&mut last)?;
+        if last != *b"\n" {
+            lines.push('\n');
+        }
+    }
+    for entry in entries {
+        lines.push_str(&serde_json::to_string(entry)?);
+        lines.push('\n');
+    }
+
+    file.write_all(lines.as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Replace a log with `entries`, writing then renaming so other devices never
+/// see half a log.
+fn replace_log(path: &Path, entries: &[LogEntry]) -> Result<(), SyncError> {
+    let tmp_path = path.with_extension(format!("{LOG_EXTENSION}.tmp"));
+    let _ = fs::remove_file(&tmp_path);
+    append_log(&tmp_path, entries)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::sync::EpisodeRef;
+
+    fn entry(seq: u64, change: Change) -> LogEntry {
+        LogEntry {
+            seq,
+            at: Utc::now(),
+            change,
+        }
+    }
+
+    fn progress(show: &str, id: &str, secs: u64) -> Change {
+        Change::Progress {
+            show: show.into(),
+            episode: EpisodeRef {
+                id: id.into(),
+                media_url: format!("{id}.mp3"),
+                duration: None,
+            },
+            position: Duration::from_secs(secs),
+            played_at: None,
+        }
+    }
+
+    #[test]
+    fn compaction_keeps_latest() {
+        let entries = vec![
+            entry(1, Change::Subscribe { url: "a".into() }),
+            entry(2, Change::Subscribe { url: "b".into() }),
+            entry(3, progress("a", "a1", 10)),
+            entry(4, progress("b", "b1", 10)),
+            entry(5, progress("a", "a1", 20)),
+            entry(6, Change::Unsubscribe { url: "b".into() }),
+        ];
+
+        let seqs = crate::sync::compact(entries, |entry| &entry.change)
+            .iter()
+            .map(|entry| entry.seq)
+            .collect::<Vec<_>>();
+        assert_eq!(seqs, [1, 5, 6]);
+    }
+
+    #[test]
+    fn partial_lines_are_skipped() {
+        let dir = std::env::temp_dir().join(format!("undersea-folder-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("laptop.jsonl");
+
+        append_log(&path, &[entry(1, Change::Subscribe { url: "a".into() })]).unwrap();
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"seq\":2,\"at\":")
+            .unwrap();
+        assert_eq!(read_log(&path).unwrap().len(), 1);
+
+        // the next entry starts on a line of its own
+        append_log(&path, &[entry(3, Change::Subscribe { url: "b".into() })]).unwrap();
+        let seqs = read_log(&path)
+            .unwrap()
+            .iter()
+            .map(|entry| entry.seq)
+            .collect::<Vec<_>>();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(seqs, [1, 3]);
+    }
+
+    #[test]
+    fn compacted_logs_are_left_until_they_double() {
+        assert!(!needs_compacting(COMPACT_AFTER - 1, 10));
+        assert!(needs_compacting(COMPACT_AFTER, 10));
+        // a big library keeps a big log after compacting, which is only
+        // compacted again once it has grown as much again
+        assert!(!needs_compacting(1500, 1200));
+        assert!(!needs_compacting(2399, 1200));
+        assert!(needs_compacting(2400, 1200));
+    }
+}
@@ -0,0 +1,593 @@
+//! Sync with a [gpodder.net](https://gpoddernet.readthedocs.io/en/latest/api/reference/)
+//! server, or a Nextcloud server running the
+//! [gPodder Sync](https://github.com/thrillfall/nextcloud-gpodder) app.
+//!
+//! [`GpodderSync`] is a [`SyncBackend`] for either. Both speak version 2 of
+//! the gpodder api, so the same subscriptions and episode actions are
+//! understood by other podcast apps too.
+
+use std::{path::PathBuf, time};
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+
+use super::{Change, EpisodeRef, JournalEntry, SyncBackend};
+use crate::{SyncError, http::HttpClient};
+
+/// Name of the file, in the data directory, that a [`GpodderState`] is kept in.
+pub const STATE_FILE_NAME: &str = "gpodder.json";
+
+/// Which flavour of the gpodder api a server speaks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Api {
+    /// gpodder.net, or a server that copies it such as `mygpo`
+    #[default]
+    Gpodder,
+    /// The Nextcloud gPodder Sync app
+    Nextcloud,
+}
+
+/// How to connect to a gpodder server, the `[gpodder]` table in the config.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GpodderConfig {
+    /// Base url of the server, such as `https://gpodder.net` or
+    /// `https://cloud.example.com`
+    pub server: String,
+    pub username: String,
+    pub password: String,
+    /// Name of this device on the server, only used by gpodder.net
+    #[serde(default = "default_device")]
+    pub device: String,
+    #[serde(default)]
+    pub api: Api,
+}
+
+fn default_device() -> String {
+    "undersea".to_string()
+}
+
+/// Subscriptions that were added or removed since a timestamp.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubscriptionChanges {
+    pub add: Vec<String>,
+    pub remove: Vec<String>,
+    pub timestamp: i64,
+}
+
+/// What happened to an episode on some device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActionKind {
+    Download,
+    Delete,
+    Play,
+    New,
+    #[serde(other)]
+    Unknown,
+}
+
+/// An episode action, as sent to and from the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpisodeAction {
+    /// Feed url of the show
+    pub podcast: String,
+    /// Media url of the episode
+    pub episode: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guid: Option<String>,
+    pub action: ActionKind,
+    /// When the action happened, in UTC, as `yyyy-mm-ddThh:mm:ss`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    /// Seconds into the episode playback started at, only for [`ActionKind::Play`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started: Option<u64>,
+    /// Seconds into the episode playback stopped at, only for [`ActionKind::Play`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<u64>,
+    /// Length of the episode in seconds, only for [`ActionKind::Play`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EpisodeActions {
+    actions: Vec<EpisodeAction>,
+    timestamp: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct SubscriptionUpload<'a> {
+    add: &'a [String],
+    remove: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadResponse {
+    timestamp: i64,
+}
+
+/// A client for the gpodder api, each method is one request.
+#[derive(Debug, Clone)]
+pub struct GpodderClient {
+    client: HttpClient,
+    config: GpodderConfig,
+}
+
+impl GpodderClient {
+    #[must_use]
+    pub fn new(config: GpodderConfig, client: impl Into<HttpClient>) -> Self {
+        Self {
+            client: client.into(),
+            config,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{path}", self.config.server.trim_end_matches('/'))
+    }
+
+    fn authed(&self, request: RequestBuilder) -> RequestBuilder {
+        request.basic_auth(&self.config.username, Some(&self.config.password))
+    }
+
+    fn subscriptions_url(&self) -> String {
+        match self.config.api {
+            Api::Gpodder => self.url(&format!(
+                "api/2/subscriptions/{}/{}.json",
+                self.config.username, self.config.device
+            )),
+            Api::Nextcloud => self.url("index.php/apps/gpoddersync/subscriptions"),
+        }
+    }
+
+    fn subscription_upload_url(&self) -> String {
+        match self.config.api {
+            Api::Gpodder => self.subscriptions_url(),
+            Api::Nextcloud => self.url("index.php/apps/gpoddersync/subscription_change/create"),
+        }
+    }
+
+    fn episodes_url(&self) -> String {
+        match self.config.api {
+            Api::Gpodder => self.url(&format!("api/2/episodes/{}.json", self.config.username)),
+            Api::Nextcloud => self.url("index.php/apps/gpoddersync/episode_action"),
+        }
+    }
+
+    fn episodes_upload_url(&self) -> String {
+        match self.config.api {
+            Api::Gpodder => self.episodes_url(),
+            Api::Nextcloud => self.url("index.php/apps/gpoddersync/episode_action/create"),
+        }
+    }
+
+    /// Subscriptions added or removed since `since`, use 0 to get all of them.
+    ///
+    /// # Errors
+    /// Fails if the request fails or the server rejects it.
+    pub async fn subscriptions(&self, since: i64) -> Result<SubscriptionChanges, SyncError> {
+        let request = self
+            .client
+            .get(self.subscriptions_url())
+            .query(&[("since", since)]);
+        Ok(self
+            .client
+            .send(self.authed(request))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    /// Tell the server about subscriptions added or removed on this device.
+    ///
+    /// # Errors
+    /// Fails if the request fails or the server rejects it.
+    pub async fn upload_subscriptions(
+        &self,
+        add: &[String],
+        remove: &[String],
+    ) -> Result<i64, SyncError> {
+        let request = self
+            .client
+            .post(self.subscription_upload_url())
+            .json(&SubscriptionUpload { add, remove });
+        let response: UploadResponse = self
+            .client
+            .send(self.authed(request))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.timestamp)
+    }
+
+    /// Episode actions from every device since `since`, use 0 to get all of them.
+    ///
+    /// # Errors
+    /// Fails if the request fails or the server rejects it.
+    pub async fn episode_actions(
+        &self,
+        since: i64,
+    ) -> Result<(Vec<EpisodeAction>, i64), SyncError> {
+        let request = self
+            .client
+            .get(self.episodes_url())
+            .query(&[("since", since)]);
+        let response: EpisodeActions = self
+            .client
+            .send(self.authed(request))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok((response.actions, response.timestamp))
+    }
+
+    /// Send episode actions that happened on this device.
+    ///
+    /// # Errors
+    /// Fails if the request fails or the server rejects it.
+    pub async fn upload_episode_actions(
+        &self,
+        actions: &[EpisodeAction],
+    ) -> Result<i64, SyncError> {
+        let request = self.client.post(self.episodes_upload_url()).json(actions);
+        let response: UploadResponse = self
+            .client
+            .send(self.authed(request))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.timestamp)
+    }
+}
+
+/// How far this device has synced, this needs to be kept between syncs so
+/// that only new changes are fetched.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GpodderState {
+    pub subscriptions_since: i64,
+    pub actions_since: i64,
+    #[serde(default)]
+    pub last_sync: Option<DateTime<Utc>>,
+}
+
+/// A [`SyncBackend`] for a gpodder server.
+#[derive(Debug, Clone)]
+pub struct GpodderSync {
+    client: GpodderClient,
+    state: GpodderState,
+    state_path: Option<PathBuf>,
+}
+
+impl GpodderSync {
+    /// Sync with a state that is not saved, [`GpodderSync::state`] can be
+    /// used to save it.
+    #[must_use]
+    pub fn new(client: GpodderClient, state: GpodderState) -> Self {
+        Self {
+            client,
+            state,
+            state_path: None,
+        }
+    }
+
+    /// Sync with the state kept in `state_path`, saving it after each sync.
+    ///
+    /// # Errors
+    /// Fails if the state could not be read.
+    pub fn open(client: GpodderClient, state_path: PathBuf) -> Result<Self, SyncError> {
+        Ok(Self {
+            client,
+            state: super::load_state(&state_path)?,
+            state_path: Some(state_path),
+        })
+    }
+
+    /// How far this device has synced.
+    #[must_use]
+    pub fn state(&self) -> &GpodderState {
+        &self.state
+    }
+}
+
+#[async_trait]
+impl SyncBackend for GpodderSync {
+    fn name(&self) -> &'static str {
+        "gpodder"
+    }
+
+    fn last_sync(&self) -> Option<DateTime<Utc>> {
+        self.state.last_sync
+    }
+
+    async fn push(&mut self, changes: &[JournalEntry]) -> Result<(), SyncError> {
+        let (add, remove) = subscription_changes(changes);
+        if !add.is_empty() || !remove.is_empty() {
+            self.client.upload_subscriptions(&add, &remove).await?;
+        }
+
+        let actions = changes
+            .iter()
+            .filter_map(|entry| to_action(entry, &self.client.config.device))
+            .collect::<Vec<_>>();
+        if !actions.is_empty() {
+            self.client.upload_episode_actions(&actions).await?;
+        }
+        Ok(())
+    }
+
+    async fn pull(&mut self) -> Result<Vec<JournalEntry>, SyncError> {
+        // the server does not say when subscriptions changed, so they are
+        // treated as older than everything else. This applies them before
+        // any actions on their episodes, and lets changes just pushed win.
+        let unknown = DateTime::UNIX_EPOCH;
+        let subscriptions = self
+            .client
+            .subscriptions(self.state.subscriptions_since)
+            .await?;
+        let (actions, actions_since) = self
+            .client
+            .episode_actions(self.state.actions_since)
+            .await?;
+
+        let mut entries = Vec::new();
+        for url in subscriptions.add {
+            entries.push(JournalEntry {
+                at: unknown,
+                change: Change::Subscribe { url },
+            });
+        }
+        for url in subscriptions.remove {
+            entries.push(JournalEntry {
+                at: unknown,
+                change: Change::Unsubscribe { url },
+            });
+        }
+        for action in actions {
+            let at = action
+                .timestamp
+                .as_deref()
+                .and_then(parse_timestamp)
+                .unwrap_or(unknown);
+            if let Some(change) = from_action(action) {
+                entries.push(JournalEntry { at, change });
+            }
+        }
+
+        self.state.subscriptions_since = subscriptions.timestamp;
+        self.state.actions_since = actions_since;
+        Ok(entries)
+    }
+
+    fn finish(&mut self, at: DateTime<Utc>) -> Result<(), SyncError> {
+        self.state.last_sync = Some(at);
+        if let Some(path) = &self.state_path {
+            super::save_state(path, &self.state)?;
+        }
+        Ok(())
+    }
+}
+
+/// The net subscription changes in the journal, as urls to add and remove.
+fn subscription_changes(entries: &[JournalEntry]) -> (Vec<String>, Vec<String>) {
+    let mut add: Vec<String> = Vec::new();
+    let mut remove: Vec<String> = Vec::new();
+
+    let subscribe = |url: &String, add: &mut Vec<String>, remove: &mut Vec<String>| {
+        remove.retain(|removed| removed != url);
+        if !add.contains(url) {
+            add.push(url.clone());
+        }
+    };
+    let unsubscribe = |url: &String, add: &mut Vec<String>, remove: &mut Vec<String>| {
+        add.retain(|added| added != url);
+        if !remove.contains(url) {
+            remove.push(url.clone());
+        }
+    };
+
+    for entry in entries {
+        match &entry.change {
+            Change::Subscribe { url } => subscribe(url, &mut add, &mut remove),
+            Change::Unsubscribe { url } => unsubscribe(url, &mut add, &mut remove),
+            // gpodder only knows subscriptions, so a moved feed is swapped
+            // for its new url
+            Change::Move { from, to } => {
+                unsubscribe(from, &mut add, &mut remove);
+                subscribe(to, &mut add, &mut remove);
+            }
+            Change::Progress { .. }
+            | Change::Finished { .. }
+            | Change::Queue { .. }
+            | Change::Bookmark { .. }
+            | Change::RemoveBookmark { .. } => {}
+        }
+    }
+
+    (add, remove)
+}
+
+fn to_action(entry: &JournalEntry, device: &str) -> Option<EpisodeAction> {
+    let (show, episode, action, position) = match &entry.change {
+        Change::Progress {
+            show,
+            episode,
+            position,
+            ..
+        } => (show, episode, ActionKind::Play, Some(position.as_secs())),
+        // finished episodes are played all the way to the end, like AntennaPod does
+        Change::Finished {
+            show,
+            episode,
+            finished: true,
+        } => {
+            let total = episode
+                .duration
+                .map_or(1, |duration| duration.as_secs().max(1));
+            (show, episode, ActionKind::Play, Some(total))
+        }
+        Change::Finished {
+            show,
+            episode,
+            finished: false,
+        } => (show, episode, ActionKind::New, None),
+        // gpodder has no queue or bookmarks, so they are not synced
+        Change::Subscribe { .. }
+        | Change::Unsubscribe { .. }
+        | Change::Move { .. }
+        | Change::Queue { .. }
+        | Change::Bookmark { .. }
+        | Change::RemoveBookmark { .. } => {
+            return None;
+        }
+    };
+
+    // the time of a play action is when it was played, which is not when
+    // it was sent if it came from another device
+    let at = match &entry.change {
+        Change::Progress {
+            played_at: Some(played_at),
+            ..
+        } => played_at,
+        _ => &entry.at,
+    };
+    let total = match (action, &entry.change) {
+        (ActionKind::Play, Change::Finished { .. }) => position,
+        (ActionKind::Play, _) => episode.duration.map(|duration| duration.as_secs()),
+        _ => None,
+    };
+
+    Some(EpisodeAction {
+        podcast: show.clone(),
+        episode: episode.media_url.clone(),
+        guid: Some(episode.id.clone()),
+        action,
+        timestamp: Some(at.format("%Y-%m-%dT%H:%M:%S").to_string()),
+        started: position.map(|_| 0),
+        position,
+        total,
+        device: Some(device.to_string()),
+    })
+}
+
+fn from_action(action: EpisodeAction) -> Option<Change> {
+    let episode = EpisodeRef {
+        id: action.guid.unwrap_or_else(|| action.episode.clone()),
+        media_url: action.episode,
+        duration: action.total.map(time::Duration::from_secs),
+    };
+
+    match action.action {
+        ActionKind::Play => {
+            let position = action.position?;
+            let finished = action
+                .total
+                .is_some_and(|total| total > 0 && position >= total);
+            Some(if finished {
+                Change::Finished {
+                    show: action.podcast,
+                    episode,
+                    finished: true,
+                }
+            } else {
+                // gpodder play actions are always playing
+                let played_at = action
+                    .timestamp
+                    .as_deref()
+                    .and_then(|timestamp| {
+                        NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S").ok()
+                    })
+                    .map(|played_at| played_at.and_utc());
+                Change::Progress {
+                    show: action.podcast,
+                    episode,
+                    position: time::Duration::from_secs(position),
+                    played_at,
+                }
+            })
+        }
+        ActionKind::New => Some(Change::Finished {
+            show: action.podcast,
+            episode,
+            finished: false,
+        }),
+        ActionKind::Download | ActionKind::Delete | ActionKind::Unknown => None,
+    }
+}
+
+/// Timestamps are meant to be UTC without a timezone, but some servers add one.
+fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S")
+        .map(|naive| naive.and_utc())
+        .or_else(|_| DateTime::parse_from_rfc3339(timestamp).map(Into::into))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(change: Change) -> JournalEntry {
+        JournalEntry {
+            at: Utc::now(),
+            change,
+        }
+    }
+
+    #[test]
+    fn net_subscription_changes() {
+        let entries = [
+            entry(Change::Subscribe { url: "a".into() }),
+            entry(Change::Subscribe { url: "b".into() }),
+            entry(Change::Unsubscribe { url: "a".into() }),
+            entry(Change::Unsubscribe { url: "c".into() }),
+            entry(Change::Subscribe { url: "c".into() }),
+        ];
+
+        let (add, remove) = subscription_changes(&entries);
+
+        assert_eq!(add, ["b", "c"]);
+        assert_eq!(remove, ["a"]);
+    }
+
+    #[test]
+    fn finished_is_played_to_the_end() {
+        let episode = EpisodeRef {
+            id: "guid".into(),
+            media_url: "https://example.com/1.mp3".into(),
+            duration: Some(time::Duration::from_secs(1234)),
+        };
+        let finished = entry(Change::Finished {
+            show: "https://example.com/feed".into(),
+            episode,
+            finished: true,
+        });
+
+        let action = to_action(&finished, "laptop").unwrap();
+        assert_eq!(action.action, ActionKind::Play);
+        assert_eq!(action.position, Some(1234));
+        assert_eq!(action.total, Some(1234));
+
+        assert_eq!(from_action(action), Some(finished.change));
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(
+            parse_timestamp("2009-12-12T09:00:00").unwrap().to_rfc3339(),
+            "2009-12-12T09:00:00+00:00"
+        );
+        assert!(parse_timestamp("2009-12-12T09:00:00Z").is_some());
+        assert!(parse_timestamp("yesterday").is_none());
+    }
+}
@@ -0,0 +1,107 @@
+//! A sync backend that keeps everything in memory, for testing frontends and
+//! the sync logic without a server or folder.
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::{JournalEntry, SyncBackend};
+use crate::SyncError;
+
+#[derive(Debug, Default)]
+struct Store {
+    /// Every change ever pushed, along with the device that pushed it
+    changes: Vec<(String, JournalEntry)>,
+    offline: bool,
+}
+
+/// Shared storage for [`MemoryBackend`]s, like a server that every device
+/// syncs with.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore(Arc<Mutex<Store>>);
+
+impl MemoryStore {
+    fn lock(&self) -> MutexGuard<'_, Store> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// A backend for a device that syncs with this store.
+    #[must_use]
+    pub fn backend(&self, device: &str) -> MemoryBackend {
+        MemoryBackend {
+            store: self.clone(),
+            device: device.to_string(),
+            seen: 0,
+            last_sync: None,
+        }
+    }
+
+    /// Make every push and pull fail, or work again.
+    pub fn set_offline(&self, offline: bool) {
+        self.lock().offline = offline;
+    }
+
+    /// Every change pushed so far, along with the device that pushed it.
+    #[must_use]
+    pub fn changes(&self) -> Vec<(String, JournalEntry)> {
+        self.lock().changes.clone()
+    }
+}
+
+/// A [`SyncBackend`] that syncs through a [`MemoryStore`].
+#[derive(Debug, Clone)]
+pub struct MemoryBackend {
+    store: MemoryStore,
+    device: String,
+    /// How many of the changes in the store have been pulled
+    seen: usize,
+    last_sync: Option<DateTime<Utc>>,
+}
+
+#[async_trait]
+impl SyncBackend for MemoryBackend {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn last_sync(&self) -> Option<DateTime<Utc>> {
+        self.last_sync
+    }
+
+    async fn push(&mut self, changes: &[JournalEntry]) -> Result<(), SyncError> {
+        let mut store = self.store.lock();
+        if store.offline {
+            return Err(SyncError::Unavailable(
+                "memory store is offline".to_string(),
+            ));
+        }
+        store.changes.extend(
+            changes
+                .iter()
+                .map(|entry| (self.device.clone(), entry.clone())),
+        );
+        Ok(())
+    }
+
+    async fn pull(&mut self) -> Result<Vec<JournalEntry>, SyncError> {
+        let store = self.store.lock();
+        if store.offline {
+            return Err(SyncError::Unavailable(
+                "memory store is offline".to_string(),
+            ));
+        }
+        let pulled = store.changes[self.seen..]
+            .iter()
+            .filter(|(device, _)| *device != self.device)
+            .map(|(_, entry)| entry.clone())
+            .collect();
+        self.seen = store.changes.len();
+        Ok(pulled)
+    }
+
+    fn finish(&mut self, at: DateTime<Utc>) -> Result<(), SyncError> {
+        self.last_sync = Some(at);
+        Ok(())
+    }
+}
@@ -0,0 +1,722 @@
+//! Keeping subscriptions and progress the same across devices.
+//!
+//! Every change made through [`Shows`] is written to a journal, which
+//! [`Shows::sync`] sends to a [`SyncBackend`] before clearing it. Changes that
+//! come from another device are applied without being written to the
+//! journal, so they are never sent back.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::Path,
+    time,
+};
+
+use crate::{
+    Config, FeedError, Shows, SyncError,
+    bookmark::Bookmark,
+    credentials::{is_redacted, redact_url},
+    http::HttpClient,
+};
+
+pub mod folder;
+pub mod gpodder;
+pub mod memory;
+pub mod server;
+
+/// Somewhere changes can be sent to and fetched from, so that frontends do
+/// not need to care how syncing is done.
+#[async_trait]
+pub trait SyncBackend: Send {
+    /// Name to show people, such as `gpodder` or `folder`
+    fn name(&self) -> &str;
+
+    /// When this device last finished syncing with the backend, or [`None`]
+    /// if it never has.
+    fn last_sync(&self) -> Option<DateTime<Utc>>;
+
+    /// Send changes made on this device, oldest first.
+    async fn push(&mut self, changes: &[JournalEntry]) -> Result<(), SyncError>;
+
+    /// Fetch changes made on other devices since the last pull.
+    async fn pull(&mut self) -> Result<Vec<JournalEntry>, SyncError>;
+
+    /// Remember that a sync finished, saving anything that is needed next time.
+    ///
+    /// # Errors
+    /// Fails if the state of the backend could not be saved.
+    fn finish(&mut self, at: DateTime<Utc>) -> Result<(), SyncError>;
+}
+
+/// Every sync backend set up in the config, along with their state from the
+/// last sync.
+///
+/// # Errors
+/// Fails if the state of a backend could not be read.
+pub fn backends(
+    config: &Config,
+    client: HttpClient,
+) -> Result<Vec<Box<dyn SyncBackend>>, SyncError> {
+    let mut backends: Vec<Box<dyn SyncBackend>> = Vec::new();
+    if let Some(folder) = &config.sync_folder {
+        backends.push(Box::new(folder::FolderSync::open(
+            folder.clone(),
+            config.dirs.data.join(folder::STATE_FILE_NAME),
+        )?));
+    }
+    if let Some(server) = &config.server {
+        backends.push(Box::new(server::ServerSync::open(
+            server.clone(),
+            client.clone(),
+            config.dirs.data.join(server::STATE_FILE_NAME),
+        )?));
+    }
+    if let Some(gpodder) = &config.gpodder {
+        backends.push(Box::new(gpodder::GpodderSync::open(
+            gpodder::GpodderClient::new(gpodder.clone(), client),
+            config.dirs.data.join(gpodder::STATE_FILE_NAME),
+        )?));
+    }
+    Ok(backends)
+}
+
+/// How syncing is going, for frontends to show.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncStatus {
+    /// There is nothing to sync with
+    Disabled,
+    /// Not syncing right now, with when the last sync finished
+    Idle {
+        last_sync: Option<DateTime<Utc>>,
+    },
+    Syncing,
+    /// The last sync failed, with why
+    Failed(String),
+}
+
+/// A change to a users shows that other devices need to know about.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Change {
+    /// Subscribed to the show with this feed url
+    Subscribe { url: String },
+    /// Unsubscribed from the show with this feed url
+    Unsubscribe { url: String },
+    /// The feed of the show at `from` moved to `to`
+    Move { from: String, to: String },
+    /// Playback of an episode reached `position`
+    Progress {
+        show: String,
+        episode: EpisodeRef,
+        position: time::Duration,
+        /// When the episode was played up to there, if it was played rather
+        /// than moved by hand
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        played_at: Option<DateTime<Utc>>,
+    },
+    /// An episode was marked as finished, or as not finished
+    Finished {
+        show: String,
+        episode: EpisodeRef,
+        finished: bool,
+    },
+    /// The queue changed, this is the whole queue in order
+    Queue { episodes: Vec<QueuedEpisode> },
+    /// A bookmark was added to an episode, or changed
+    Bookmark {
+        show: String,
+        episode: EpisodeRef,
+        bookmark: Bookmark,
+    },
+    /// The bookmark made at `created_at` was removed from an episode
+    RemoveBookmark {
+        show: String,
+        episode: EpisodeRef,
+        created_at: DateTime<Utc>,
+    },
+}
+
+/// Everything needed to find an episode on another device, which may not have
+/// the same id for it if the feed has no guids.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpisodeRef {
+    pub id: String,
+    pub media_url: String,
+    /// Length of the episode, if it is known
+    pub duration: Option<time::Duration>,
+}
+
+/// An episode in a [`Change::Queue`], along with the feed url of its show.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedEpisode {
+    pub show: String,
+    pub episode: EpisodeRef,
+}
+
+/// What happened during a sync.
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// Changes sent to other devices
+    pub pushed: usize,
+    /// Changes from other devices that were applied
+    pub pulled: usize,
+    /// Shows that another device subscribed to, but could not be added here
+    pub failed: Vec<(String, FeedError)>,
+}
+
+impl SyncReport {
+    /// Add up the reports of several syncs.
+    pub fn merge(&mut self, other: SyncReport) {
+        self.pushed += other.pushed;
+        self.pulled += other.pulled;
+        self.failed.extend(other.failed);
+    }
+}
+
+/// A [`Change`] along with when it was made.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub at: DateTime<Utc>,
+    pub change: Change,
+}
+
+impl Change {
+    /// What this change is about, only the latest change about something
+    /// matters.
+    pub(crate) fn key(&self) -> String {
+        match self {
+            Change::Subscribe { url } | Change::Unsubscribe { url } => format!("show {url}"),
+            Change::Move { from, .. } => format!("move {from}"),
+            Change::Progress { show, episode, .. } | Change::Finished { show, episode, .. } => {
+                format!("episode {show} {}", episode.id)
+            }
+            Change::Queue { .. } => "queue".to_string(),
+            Change::Bookmark {
+                show,
+                episode,
+                bookmark: Bookmark { created_at, .. },
+            }
+            | Change::RemoveBookmark {
+                show,
+                episode,
+                created_at,
+            } => format!("bookmark {show} {} {}", episode.id, created_at.to_rfc3339()),
+        }
+    }
+
+    /// The change with the urls of shows passed through `redact`, and any
+    /// secrets in episode media urls hidden, see [`redact_url`]. Episode
+    /// ids are hidden too, as feeds without guids use their media urls.
+    #[must_use]
+    pub fn redacted(self, redact: impl Fn(&str) -> String) -> Change {
+        let episode = |episode: EpisodeRef| EpisodeRef {
+            id: redact_url(&episode.id),
+            media_url: redact_url(&episode.media_url),
+            duration: episode.duration,
+        };
+        match self {
+            Change::Subscribe { url } => Change::Subscribe { url: redact(&url) },
+            Change::Unsubscribe { url } => Change::Unsubscribe { url: redact(&url) },
+            Change::Move { from, to } => Change::Move {
+                from: redact(&from),
+                to: redact(&to),
+            },
+            Change::Progress {
+                show,
+                episode: episode_ref,
+                position,
+                played_at,
+            } => Change::Progress {
+                show: redact(&show),
+                episode: episode(episode_ref),
+                position,
+                played_at,
+            },
+            Change::Finished {
+                show,
+                episode: episode_ref,
+                finished,
+            } => Change::Finished {
+                show: redact(&show),
+                episode: episode(episode_ref),
+                finished,
+            },
+            Change::Queue { episodes } => Change::Queue {
+                episodes: episodes
+                    .into_iter()
+                    .map(|queued| QueuedEpisode {
+                        show: redact(&queued.show),
+                        episode: episode(queued.episode),
+                    })
+                    .collect(),
+            },
+            Change::Bookmark {
+                show,
+                episode: episode_ref,
+                bookmark,
+            } => Change::Bookmark {
+                show: redact(&show),
+                episode: episode(episode_ref),
+                bookmark,
+            },
+            Change::RemoveBookmark {
+                show,
+                episode: episode_ref,
+                created_at,
+            } => Change::RemoveBookmark {
+                show: redact(&show),
+                episode: episode(episode_ref),
+                created_at,
+            },
+        }
+    }
+
+    /// The episode this change is about, if it is about one.
+    #[must_use]
+    pub fn episode(&self) -> Option<&EpisodeRef> {
+        match self {
+            Change::Progress { episode, .. }
+            | Change::Finished { episode, .. }
+            | Change::Bookmark { episode, .. }
+            | Change::RemoveBookmark { episode, .. } => Some(episode),
+            Change::Subscribe { .. }
+            | Change::Unsubscribe { .. }
+            | Change::Move { .. }
+            | Change::Queue { .. } => None,
+        }
+    }
+}
+
+/// Keep only the latest entry about each thing, and drop changes to episodes
+/// of shows that were unsubscribed from. What is left still brings an empty
+/// library up to date, in fewer changes.
+pub fn compact<T>(entries: Vec<T>, change: impl Fn(&T) -> &Change) -> Vec<T> {
+    let mut latest = HashMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        latest.insert(change(entry).key(), index);
+    }
+
+    let unsubscribed = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(index, entry)| match change(entry) {
+            Change::Unsubscribe { url } if latest[&change(entry).key()] == index => {
+                Some(url.clone())
+            }
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    entries
+        .into_iter()
+        .enumerate()
+        .filter(|(index, entry)| {
+            let show = match change(entry) {
+                Change::Progress { show, .. }
+                | Change::Finished { show, .. }
+                | Change::Bookmark { show, .. }
+                | Change::RemoveBookmark { show, .. } => Some(show),
+                Change::Subscribe { .. }
+                | Change::Unsubscribe { .. }
+                | Change::Move { .. }
+                | Change::Queue { .. } => None,
+            };
+            latest[&change(entry).key()] == *index
+                && show.is_none_or(|show| !unsubscribed.contains(show))
+        })
+        .map(|(_, entry)| entry)
+        .collect()
+}
+
+/// Read the state a sync backend keeps between syncs, which is the default
+/// state if the file does not exist yet.
+///
+/// # Errors
+/// Fails if the file could not be read or is not valid.
+pub fn load_state<T: DeserializeOwned + Default>(path: &Path) -> Result<T, SyncError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(serde_json::from_str(&contents)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Save the state a sync backend keeps between syncs.
+///
+/// # Errors
+/// Fails if the file could not be written.
+pub fn save_state<T: Serialize>(path: &Path, state: &T) -> Result<(), SyncError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_string_pretty(state)?)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+impl Shows {
+    /// Changes that have not been synced yet, oldest first.
+    #[must_use]
+    pub fn journal(&self) -> &[JournalEntry] {
+        &self.journal
+    }
+
+    /// Remove the first `count` entries from the journal, once a sync backend
+    /// has sent them.
+    pub fn acknowledge_journal(&mut self, count: usize) {
+        self.journal.drain(..count.min(self.journal.len()));
+    }
+
+    /// Send the journal to `backend`, then apply changes from other devices.
+    ///
+    /// The first time a backend is used, changes from other devices are
+    /// applied first and then the whole library is sent, as shows added
+    /// before sync was set up are not in the journal.
+    ///
+    /// # Errors
+    /// Fails if the backend fails. The journal is only cleared once it has
+    /// been sent.
+    pub async fn sync(&mut self, backend: &mut dyn SyncBackend) -> Result<SyncReport, SyncError> {
+        let mut report = SyncReport::default();
+        let journal_len = self.journal.len();
+
+        if backend.last_sync().is_none() {
+            let pulled = backend.pull().await?;
+            self.apply_pulled(pulled, &[], &mut report).await;
+
+            let now = Utc::now();
+            let snapshot = self
+                .snapshot()
+                .into_iter()
+                .map(|change| JournalEntry {
+                    at: now,
+                    change: self.outgoing(change),
+                })
+                .collect::<Vec<_>>();
+            if !snapshot.is_empty() {
+                backend.push(&snapshot).await?;
+            }
+            report.pushed = snapshot.len();
+            self.acknowledge_journal(journal_len);
+        } else {
+            let pushed = self
+                .journal
+                .iter()
+                .map(|entry| JournalEntry {
+                    at: entry.at,
+                    change: self.outgoing(entry.change.clone()),
+                })
+                .collect::<Vec<_>>();
+            if !pushed.is_empty() {
+                backend.push(&pushed).await?;
+            }
+            report.pushed = pushed.len();
+            self.acknowledge_journal(journal_len);
+
+            let pulled = backend.pull().await?;
+            self.apply_pulled(pulled, &pushed, &mut report).await;
+        }
+
+        backend.finish(Utc::now())?;
+        Ok(report)
+    }
+
+    /// A change as it is sent to other devices, with the secrets in urls
+    /// hidden unless [`Shows::set_sync_secret_urls`] says otherwise.
+    fn outgoing(&self, change: Change) -> Change {
+        if self.sync_secret_urls {
+            change
+        } else {
+            change.redacted(|url| self.redact(url))
+        }
+    }
+
+    /// Apply changes from other devices oldest first, skipping any that are
+    /// older than a change this device just sent about the same thing.
+    async fn apply_pulled(
+        &mut self,
+        mut pulled: Vec<JournalEntry>,
+        pushed: &[JournalEntry],
+        report: &mut SyncReport,
+    ) {
+        let mut pushed_at = HashMap::new();
+        for entry in pushed {
+            pushed_at.insert(entry.change.key(), entry.at);
+        }
+
+        pulled.sort_by_key(|entry| entry.at);
+        for entry in pulled {
+            if pushed_at
+                .get(&entry.change.key())
+                .is_some_and(|pushed| *pushed >= entry.at)
+            {
+                continue;
+            }
+
+            let url = match &entry.change {
+                Change::Subscribe { url } => Some(url.clone()),
+                _ => None,
+            };
+            match self.apply_remote(entry).await {
+                Ok(changed) => report.pulled += usize::from(changed),
+                Err(err) => report.failed.extend(url.map(|url| (url, err))),
+            }
+        }
+    }
+
+    /// Changes that would bring an empty library up to date with this one.
+    #[must_use]
+    pub fn snapshot(&self) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for show in &self.shows {
+            changes.push(Change::Subscribe {
+                url: show.url.clone(),
+            });
+            for episode in &show.episodes {
+                let episode_ref = EpisodeRef {
+                    id: episode.id.clone(),
+                    media_url: episode.media_url.clone(),
+                    duration: episode.duration,
+                };
+                for bookmark in &episode.bookmarks {
+                    changes.push(Change::Bookmark {
+                        show: show.url.clone(),
+                        episode: episode_ref.clone(),
+                        bookmark: bookmark.clone(),
+                    });
+                }
+                if episode.finished {
+                    changes.push(Change::Finished {
+                        show: show.url.clone(),
+                        episode: episode_ref,
+                        finished: true,
+                    });
+                } else if !episode.resume_time.is_zero() {
+                    changes.push(Change::Progress {
+                        show: show.url.clone(),
+                        episode: episode_ref,
+                        position: episode.resume_time,
+                        played_at: episode.last_played_at,
+                    });
+                }
+            }
+        }
+        if !self.queue.is_empty() {
+            changes.push(self.queue_change());
+        }
+        changes
+    }
+
+    /// The whole queue as a [`Change::Queue`].
+    fn queue_change(&self) -> Change {
+        let episodes = self
+            .queue
+            .iter()
+            .filter_map(|id| self.sync_ref(id).ok())
+            .map(|(show, episode)| QueuedEpisode { show, episode })
+            .collect();
+        Change::Queue { episodes }
+    }
+
+    /// Write the queue to the journal after it changed. Only the latest queue
+    /// matters, so it replaces any earlier one that has not been synced.
+    pub(crate) fn record_queue(&mut self) {
+        self.last_change = Utc::now();
+        self.queue_changed_at = Some(self.last_change);
+        self.journal
+            .retain(|entry| !matches!(entry.change, Change::Queue { .. }));
+        self.record(self.queue_change());
+    }
+
+    /// Write a change to the journal. Progress replaces any earlier progress
+    /// for the same episode that has not been synced, so playing an episode
+    /// does not fill up the journal.
+    pub(crate) fn record(&mut self, change: Change) {
+        if let Change::Progress { episode, .. } = &change {
+            self.journal.retain(|entry| {
+                !matches!(&entry.change, Change::Progress { episode: old, .. } if old.id == episode.id)
+            });
+        }
+
+        self.journal.push(JournalEntry {
+            at: self.last_change,
+            change,
+        });
+    }
+
+    /// Apply a change made on another device, without writing it to the
+    /// journal. Changes to episodes or shows that do not exist are ignored,
+    /// as are changes to episodes that are older than the last change made
+    /// to them. Returns if anything was changed.
+    ///
+    /// # Errors
+    /// Fails if subscribing to a show fails.
+    pub async fn apply_remote(&mut self, entry: JournalEntry) -> Result<bool, FeedError> {
+        let JournalEntry { at, change } = entry;
+        let changed = match change {
+            Change::Subscribe { url } => {
+                // a private feed has to be added on each device by itself, as
+                // its url was not sent with its secrets
+                if self.find_show(&url).is_some()
+                    || self.remote_show_index(&url).is_some()
+                    || is_redacted(&url)
+                {
+                    return Ok(false);
+                }
+                self.add_unrecorded(url.as_str()).await?;
+                true
+            }
+            Change::Unsubscribe { url } => match self.remote_show_index(&url) {
+                Some(index) => {
+                    let url = self.shows[index].url.clone();
+                    self.remove_unrecorded(&url).is_ok()
+                }
+                None => false,
+            },
+            // the feed may already have been found to have moved here
+            Change::Move { from, to } => {
+                if self.find_show(&to).is_some() || is_redacted(&to) {
+                    return Ok(false);
+                }
+                match self.remote_show_index(&from) {
+                    Some(index) if self.shows[index].url != to => {
+                        let from = self.shows[index].move_to(to.clone());
+                        self.credentials.rename(&from, &to);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            Change::Progress {
+                show,
+                episode,
+                position,
+                played_at,
+            } => match self.find_episode_mut(&show, &episode, at) {
+                Some(episode) => {
+                    // when it was played is kept, but only counts as a change
+                    // along with the progress, as backends that cannot tell
+                    // playing from moving send back a newer time
+                    episode.last_played_at = played_at.max(episode.last_played_at);
+                    let changed = episode.finished || episode.resume_time != position;
+                    if changed {
+                        episode.set_resume_time(position, at);
+                    }
+                    changed
+                }
+                None => false,
+            },
+            Change::Finished {
+                show,
+                episode,
+                finished,
+            } => match self.find_episode_mut(&show, &episode, at) {
+                Some(episode) if episode.finished != finished => {
+                    episode.set_finished(finished, at);
+                    true
+                }
+                _ => false,
+            },
+            Change::Queue { episodes } => {
+                if self.queue_changed_at.is_some_and(|changed| changed >= at) {
+                    return Ok(false);
+                }
+                let queue = episodes
+                    .iter()
+                    .filter_map(|queued| {
+                        let show = &self.shows[self.remote_show_index(&queued.show)?];
+                        let index = episode_index(show, &queued.episode)?;
+                        Some(show.episodes[index].id.clone())
+                    })
+                    .collect::<Vec<_>>();
+                self.queue_changed_at = Some(at);
+                let changed = queue != self.queue;
+                self.queue = queue;
+                changed
+            }
+            // bookmarks are only ever added or removed, so every change to
+            // them counts however old it is
+            Change::Bookmark {
+                show,
+                episode,
+                bookmark,
+            } => self
+                .remote_episode_mut(&show, &episode)
+                .is_some_and(|episode| episode.put_bookmark(bookmark)),
+            Change::RemoveBookmark {
+                show,
+                episode,
+                created_at,
+            } => self
+                .remote_episode_mut(&show, &episode)
+                .and_then(|episode| episode.take_bookmark(&created_at))
+                .is_some(),
+        };
+
+        if changed {
+            self.last_change = Utc::now();
+        }
+        Ok(changed)
+    }
+
+    /// Find an episode from another device, if it has not been changed since
+    /// `at`.
+    fn find_episode_mut(
+        &mut self,
+        show_url: &str,
+        episode: &EpisodeRef,
+        at: DateTime<Utc>,
+    ) -> Option<&mut crate::Episode> {
+        self.remote_episode_mut(show_url, episode)
+            .filter(|episode| episode.changed_at.is_none_or(|changed| changed < at))
+    }
+
+    /// Find an episode from another device, which may still know its show by
+    /// the url it had before it moved.
+    fn remote_episode_mut(
+        &mut self,
+        show_url: &str,
+        episode: &EpisodeRef,
+    ) -> Option<&mut crate::Episode> {
+        let show = self.remote_show_index(show_url)?;
+        let show = &mut self.shows[show];
+        let index = episode_index(show, episode)?;
+        show.episodes.get_mut(index)
+    }
+
+    /// Where a show from another device is, by any url it has had, or by its
+    /// url with the secrets hidden.
+    fn remote_show_index(&self, url: &str) -> Option<usize> {
+        self.shows
+            .iter()
+            .position(|show| show.has_url(url))
+            .or_else(|| {
+                is_redacted(url)
+                    .then(|| {
+                        self.shows
+                            .iter()
+                            .position(|show| self.redact(&show.url) == url)
+                    })
+                    .flatten()
+            })
+    }
+}
+
+/// Where an episode from another device is in a show, found by its id or
+/// media url, which may have had their secrets hidden.
+fn episode_index(show: &crate::Show, episode: &EpisodeRef) -> Option<usize> {
+    let matches =
+        |own: &str, other: &str| own == other || (is_redacted(other) && redact_url(own) == other);
+    show.episodes
+        .iter()
+        .position(|ep| matches(&ep.id, &episode.id))
+        .or_else(|| {
+            show.episodes
+                .iter()
+                .position(|ep| matches(&ep.media_url, &episode.media_url))
+        })
+}
@@ -0,0 +1,177 @@
+//! Sync with an `undersea-server`, a small server that anyone can run.
+//!
+//! The api sends the same [`JournalEntry`]s that are in the journal, as json:
+//!
+//! - `POST /api/v1/changes` with a [`PushRequest`], responds with a [`PushResponse`]
+//! - `GET /api/v1/changes?device=<device>&since=<seq>` responds with a
+//!   [`PullResponse`] of changes from other devices
+//!
+//! Every request needs an `Authorization: Bearer <token>` header.
+
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{JournalEntry, SyncBackend};
+use crate::{SyncError, http::HttpClient};
+
+/// Name of the file, in the data directory, that a [`ServerState`] is kept in.
+pub const STATE_FILE_NAME: &str = "server-sync.json";
+
+/// How to connect to a server, the `[server]` table in the config.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Base url of the server, such as `https://undersea.example.com`
+    pub url: String,
+    pub token: String,
+    /// Name of this device, which must be different on every device
+    pub device: String,
+}
+
+/// Body of a push.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PushRequest {
+    pub device: String,
+    pub changes: Vec<JournalEntry>,
+}
+
+/// Response to a push.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PushResponse {
+    /// Sequence number of the last change the server has
+    pub seq: u64,
+}
+
+/// Query of a pull.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PullQuery {
+    pub device: String,
+    /// Only changes after this sequence number are sent
+    #[serde(default)]
+    pub since: u64,
+}
+
+/// Response to a pull.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullResponse {
+    /// Changes from other devices, oldest first
+    pub changes: Vec<JournalEntry>,
+    /// Sequence number of the last change the server has, to pull since next time
+    pub seq: u64,
+}
+
+/// How far this device has synced, this needs to be kept between syncs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerState {
+    pub since: u64,
+    #[serde(default)]
+    pub last_sync: Option<DateTime<Utc>>,
+}
+
+/// A [`SyncBackend`] for an `undersea-server`.
+#[derive(Debug, Clone)]
+pub struct ServerSync {
+    client: HttpClient,
+    config: ServerConfig,
+    state: ServerState,
+    state_path: Option<PathBuf>,
+}
+
+impl ServerSync {
+    /// Sync with a state that is not saved, [`ServerSync::state`] can be used
+    /// to save it.
+    #[must_use]
+    pub fn new(config: ServerConfig, client: impl Into<HttpClient>, state: ServerState) -> Self {
+        Self {
+            client: client.into(),
+            config,
+            state,
+            state_path: None,
+        }
+    }
+
+    /// Sync with the state kept in `state_path`, saving it after each sync.
+    ///
+    /// # Errors
+    /// Fails if the state could not be read.
+    pub fn open(
+        config: ServerConfig,
+        client: impl Into<HttpClient>,
+        state_path: PathBuf,
+    ) -> Result<Self, SyncError> {
+        Ok(Self {
+            client: client.into(),
+            config,
+            state: super::load_state(&state_path)?,
+            state_path: Some(state_path),
+        })
+    }
+
+    /// How far this device has synced.
+    #[must_use]
+    pub fn state(&self) -> &ServerState {
+        &self.state
+    }
+
+    fn changes_url(&self) -> String {
+        format!("{}/api/v1/changes", self.config.url.trim_end_matches('/'))
+    }
+}
+
+#[async_trait]
+impl SyncBackend for ServerSync {
+    fn name(&self) -> &'static str {
+        "server"
+    }
+
+    fn last_sync(&self) -> Option<DateTime<Utc>> {
+        self.state.last_sync
+    }
+
+    async fn push(&mut self, changes: &[JournalEntry]) -> Result<(), SyncError> {
+        let request = PushRequest {
+            device: self.config.device.clone(),
+            changes: changes.to_vec(),
+        };
+        let request = self
+            .client
+            .post(self.changes_url())
+            .bearer_auth(&self.config.token)
+            .json(&request);
+        self.client.send(request).await?.error_for_status()?;
+        Ok(())
+    }
+
+    async fn pull(&mut self) -> Result<Vec<JournalEntry>, SyncError> {
+        let query = PullQuery {
+            device: self.config.device.clone(),
+            since: self.state.since,
+        };
+        let request = self
+            .client
+            .get(self.changes_url())
+            .bearer_auth(&self.config.token)
+            .query(&query);
+        let response: PullResponse = self
+            .client
+            .send(request)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        self.state.since = response.seq;
+        Ok(response.changes)
+    }
+
+    fn finish(&mut self, at: DateTime<Utc>) -> Result<(), SyncError> {
+        self.state.last_sync = Some(at);
+        if let Some(path) = &self.state_path {
+            super::save_state(path, &self.state)?;
+        }
+        Ok(())
+    }
+}
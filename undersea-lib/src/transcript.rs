@@ -0,0 +1,545 @@
+//! Episode transcripts, from the Podcasting 2.0 `podcast:transcript` tag.
+//!
+//! Feeds link transcripts as SRT, VTT, JSON or HTML, which are all parsed
+//! into the same [`Transcript`] of timed [`Cue`]s. Downloaded transcripts are
+//! kept in a [`TranscriptCache`], as with artwork they are never fetched
+//! again once cached.
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    TranscriptError,
+    artwork::{cache_key, write_atomic},
+    http::HttpClient,
+};
+
+/// JSON transcripts often have a segment for every word, these are joined
+/// into cues no longer than this.
+const MAX_JOINED_CUE: Duration = Duration::from_secs(15);
+
+/// A `podcast:transcript` of an episode, which can be loaded with a
+/// [`TranscriptCache`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptLink {
+    pub url: String,
+    /// The `type` attribute, such as `text/vtt`
+    pub mime_type: String,
+    pub language: Option<String>,
+}
+
+impl TranscriptLink {
+    /// What the transcript is written in, going by its type or else the
+    /// extension of its url. [`None`] if it can not be parsed, such as plain
+    /// text without times.
+    #[must_use]
+    pub fn format(&self) -> Option<Format> {
+        Format::from_mime_type(&self.mime_type).or_else(|| {
+            let path = self.url.split(['?', '#']).next().unwrap_or_default();
+            let extension = path.rsplit_once('.')?.1;
+            Format::from_extension(extension)
+        })
+    }
+}
+
+/// The formats a transcript can be parsed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Srt,
+    Vtt,
+    Json,
+    Html,
+}
+
+impl Format {
+    #[must_use]
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        let mime_type = mime_type.split(';').next()?.trim().to_ascii_lowercase();
+        match mime_type.as_str() {
+            "application/srt" | "application/x-subrip" | "text/srt" => Some(Self::Srt),
+            "text/vtt" => Some(Self::Vtt),
+            "application/json" => Some(Self::Json),
+            "text/html" => Some(Self::Html),
+            _ => None,
+        }
+    }
+
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "srt" => Some(Self::Srt),
+            "vtt" => Some(Self::Vtt),
+            "json" => Some(Self::Json),
+            "html" | "htm" => Some(Self::Html),
+            _ => None,
+        }
+    }
+}
+
+/// One line of a transcript, and when it is said.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cue {
+    pub start: Duration,
+    pub end: Duration,
+    pub speaker: Option<String>,
+    pub text: String,
+}
+
+/// A transcript with its cues in order, however it was written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcript {
+    cues: Vec<Cue>,
+}
+
+impl Transcript {
+    /// Parse a transcript written in `format`.
+    ///
+    /// # Errors
+    /// Fails if the transcript is not valid for its format.
+    pub fn parse(text: &str, format: Format) -> Result<Self, TranscriptError> {
+        let text = text.trim_start_matches('\u{feff}');
+        let mut cues = match format {
+            Format::Srt => parse_srt(text)?,
+            Format::Vtt => parse_vtt(text)?,
+            Format::Json => parse_json(text)?,
+            Format::Html => parse_html(text),
+        };
+        cues.sort_by_key(|cue| cue.start);
+        Ok(Self { cues })
+    }
+
+    #[must_use]
+    pub fn cues(&self) -> &[Cue] {
+        &self.cues
+    }
+
+    /// Index of the cue being said at `position`. Between cues this is the
+    /// one before, so something stays highlighted through pauses.
+    #[must_use]
+    pub fn cue_at(&self, position: Duration) -> Option<usize> {
+        self.cues
+            .partition_point(|cue| cue.start <= position)
+            .checked_sub(1)
+    }
+
+    /// Indexes of cues whose text or speaker contains `query`, ignoring case.
+    #[must_use]
+    pub fn search(&self, query: &str) -> Vec<usize> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        self.cues
+            .iter()
+            .enumerate()
+            .filter(|(_, cue)| {
+                cue.text.to_lowercase().contains(&query)
+                    || cue
+                        .speaker
+                        .as_ref()
+                        .is_some_and(|speaker| speaker.to_lowercase().contains(&query))
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+/// A directory of downloaded transcripts, see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct TranscriptCache {
+    dir: PathBuf,
+    client: HttpClient,
+}
+
+impl TranscriptCache {
+    /// Use `dir` for the cache, such as [`crate::Config::transcript_dir`].
+    /// It is created when something is first cached.
+    #[must_use]
+    pub fn new(dir: PathBuf, client: impl Into<HttpClient>) -> Self {
+        Self {
+            dir,
+            client: client.into(),
+        }
+    }
+
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Load a transcript, fetching it if it is not cached yet.
+    ///
+    /// # Errors
+    /// Fails if the transcript is in a format that can not be parsed, could
+    /// not be fetched, is not valid, or could not be written to the cache.
+    pub async fn load(&self, link: &TranscriptLink) -> Result<Transcript, TranscriptError> {
+        let format = link
+            .format()
+            .ok_or_else(|| TranscriptError::UnsupportedFormat(link.mime_type.clone()))?;
+        let path = self.dir.join(cache_key(&link.url));
+        if path.exists() {
+            return Transcript::parse(&fs::read_to_string(path)?, format);
+        }
+
+        let text = self
+            .client
+            .send(self.client.get(&link.url))
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        // only cache what can be shown, not an error page served with a 200
+        let transcript = Transcript::parse(&text, format)?;
+        write_atomic(&path, text.as_bytes())?;
+        Ok(transcript)
+    }
+}
+
+fn invalid(line: usize, reason: impl fmt::Display) -> TranscriptError {
+    TranscriptError::Invalid(format!("line {line}: {reason}"))
+}
+
+/// Blocks of lines separated by blank lines, with the line number each
+/// starts on.
+fn blocks(text: &str) -> Vec<(usize, Vec<&str>)> {
+    let mut blocks = Vec::new();
+    let mut current: Option<(usize, Vec<&str>)> = None;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        if line.trim().is_empty() {
+            blocks.extend(current.take());
+        } else {
+            current
+                .get_or_insert_with(|| (index + 1, Vec::new()))
+                .1
+                .push(line);
+        }
+    }
+    blocks.extend(current);
+    blocks
+}
+
+/// Parse `hh:mm:ss,mmm` or `mm:ss.mmm`, as used by SRT and VTT. The hours
+/// and the fraction are optional.
+fn parse_timestamp(time: &str) -> Option<Duration> {
+    let time = time.trim().replace(',', ".");
+    let parts = time.split(':').collect::<Vec<_>>();
+    if !(2..=3).contains(&parts.len()) {
+        return None;
+    }
+    let mut seconds = 0.0;
+    for (index, part) in parts.iter().enumerate() {
+        let is_last = index == parts.len() - 1;
+        if part.is_empty() || (!is_last && !part.bytes().all(|byte| byte.is_ascii_digit())) {
+            return None;
+        }
+        let value: f64 = part.parse().ok()?;
+        if value < 0.0 || (index > 0 && value >= 60.0) {
+            return None;
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Parse a `start --> end` line, anything after the end time is ignored.
+fn parse_timing(line: &str, number: usize) -> Result<(Duration, Duration), TranscriptError> {
+    let (start, rest) = line
+        .split_once("-->")
+        .ok_or_else(|| invalid(number, "expected a \"start --> end\" line"))?;
+    let end = rest.split_whitespace().next().unwrap_or_default();
+    let start = parse_timestamp(start)
+        .ok_or_else(|| invalid(number, format!("invalid start time \"{}\"", start.trim())))?;
+    let end = parse_timestamp(end)
+        .ok_or_else(|| invalid(number, format!("invalid end time \"{end}\"")))?;
+    Ok((start, end))
+}
+
+fn parse_srt(text: &str) -> Result<Vec<Cue>, TranscriptError> {
+    let mut cues = Vec::new();
+    for (number, lines) in blocks(text) {
+        // the counter before the times is optional in practice
+        let (timing, number, text) = if lines[0].contains("-->") {
+            (lines[0], number, &lines[1..])
+        } else if let Some(timing) = lines.get(1) {
+            (*timing, number + 1, &lines[2..])
+        } else {
+            return Err(invalid(number, "expected a \"start --> end\" line"));
+        };
+        let (start, end) = parse_timing(timing, number)?;
+        cues.push(Cue {
+            start,
+            end,
+            speaker: None,
+            text: strip_tags(&text.join(" ")),
+        });
+    }
+    Ok(cues)
+}
+
+fn parse_vtt(text: &str) -> Result<Vec<Cue>, TranscriptError> {
+    let blocks = blocks(text);
+    let Some(((_, header), blocks)) = blocks.split_first() else {
+        return Err(invalid(1, "missing \"WEBVTT\" header"));
+    };
+    if !header[0].starts_with("WEBVTT") {
+        return Err(invalid(1, "missing \"WEBVTT\" header"));
+    }
+
+    let mut cues = Vec::new();
+    for (number, lines) in blocks {
+        if ["NOTE", "STYLE", "REGION"]
+            .iter()
+            .any(|kind| lines[0].starts_with(kind))
+        {
+            continue;
+        }
+        // cues can have an identifier before their times
+        let (timing, number, text) = if lines[0].contains("-->") {
+            (lines[0], *number, &lines[1..])
+        } else if let Some(timing) = lines.get(1) {
+            (*timing, number + 1, &lines[2..])
+        } else {
+            return Err(invalid(*number, "expected a \"start --> end\" line"));
+        };
+        let (start, end) = parse_timing(timing, number)?;
+        let text = text.join(" ");
+        cues.push(Cue {
+            start,
+            end,
+            speaker: vtt_speaker(&text),
+            text: strip_tags(&text),
+        });
+    }
+    Ok(cues)
+}
+
+/// The name in a `<v Name>` or `<v.class Name>` voice span.
+fn vtt_speaker(text: &str) -> Option<String> {
+    let rest = text.trim_start().strip_prefix("<v")?;
+    let (tag, _) = rest.split_once('>')?;
+    if !tag.starts_with([' ', '.']) {
+        return None;
+    }
+    let name = tag.split_once(' ')?.1.trim();
+    (!name.is_empty()).then(|| decode_entities(name))
+}
+
+#[derive(Deserialize)]
+struct JsonTranscript {
+    segments: Vec<JsonSegment>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonSegment {
+    speaker: Option<String>,
+    start_time: f64,
+    end_time: f64,
+    body: String,
+}
+
+/// Parse the Podcasting 2.0 JSON format, joining a speakers segments into
+/// sentences.
+fn parse_json(text: &str) -> Result<Vec<Cue>, TranscriptError> {
+    let transcript: JsonTranscript = serde_json::from_str(text)?;
+    let mut cues: Vec<Cue> = Vec::new();
+    for (index, segment) in transcript.segments.into_iter().enumerate() {
+        let time = |seconds: f64| {
+            Duration::try_from_secs_f64(seconds)
+                .map_err(|err| TranscriptError::Invalid(format!("segment {}: {err}", index + 1)))
+        };
+        let start = time(segment.start_time)?;
+        let end = time(segment.end_time)?;
+        let body = segment.body.trim();
+        if body.is_empty() {
+            continue;
+        }
+
+        if let Some(last) = cues.last_mut()
+            && last.speaker == segment.speaker
+            && !last.text.ends_with(['.', '?', '!'])
+            && start.saturating_sub(last.start) < MAX_JOINED_CUE
+        {
+            last.text.push(' ');
+            last.text.push_str(body);
+            last.end = last.end.max(end);
+            continue;
+        }
+        cues.push(Cue {
+            start,
+            end,
+            speaker: segment.speaker,
+            text: body.to_string(),
+        });
+    }
+    Ok(cues)
+}
+
+/// Parse the Podcasting 2.0 HTML format, where each paragraph follows the
+/// `<cite>` of its speaker and the `<time>` it starts at:
+///
+/// ```html
+/// <cite>Seth:</cite>
+/// <time>0:00</time>
+/// <p>Hello, is anyone out there?</p>
+/// ```
+///
+/// HTML is rarely well formed, so tags are picked out of the text rather
+/// than parsed as XML. A cue ends where the next one starts.
+fn parse_html(text: &str) -> Vec<Cue> {
+    let mut cues: Vec<Cue> = Vec::new();
+    let mut speaker = None;
+    let mut start = Duration::ZERO;
+
+    let mut rest = text;
+    while let Some(open) = rest.find('<') {
+        rest = &rest[open + 1..];
+        let Some(close) = rest.find('>') else {
+            break;
+        };
+        let tag = rest[..close]
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        rest = &rest[close + 1..];
+        if !matches!(tag.as_str(), "cite" | "time" | "p") {
+            continue;
+        }
+
+        let end = find_ignore_case(rest, &format!("</{tag}")).unwrap_or(rest.len());
+        let content = strip_tags(&rest[..end]);
+        rest = &rest[end..];
+        match tag.as_str() {
+            "cite" => {
+                let name = content.trim_end_matches(':').trim();
+                speaker = (!name.is_empty()).then(|| name.to_string());
+            }
+            "time" => start = parse_timestamp(&content).unwrap_or(start),
+            _ if !content.is_empty() => cues.push(Cue {
+                start,
+                end: start,
+                speaker: speaker.clone(),
+                text: content,
+            }),
+            _ => {}
+        }
+    }
+
+    let starts = cues.iter().skip(1).map(|cue| cue.start).collect::<Vec<_>>();
+    for (cue, next) in cues.iter_mut().zip(starts) {
+        cue.end = next.max(cue.start);
+    }
+    cues
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .char_indices()
+        .map(|(index, _)| index)
+        .find(|&index| {
+            haystack
+                .get(index..index + needle.len())
+                .is_some_and(|found| found.eq_ignore_ascii_case(needle))
+        })
+}
+
+/// Text without any markup, with entities decoded and whitespace collapsed.
+fn strip_tags(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => stripped.push(c),
+            _ => {}
+        }
+    }
+    decode_entities(&stripped)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end])?, end)));
+        if let Some((c, end)) = entity {
+            decoded.push(c);
+            rest = &rest[end + 1..];
+        } else {
+            decoded.push('&');
+            rest = &rest[1..];
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let number = name.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(
+            parse_timestamp("00:01:02,500"),
+            Some(Duration::from_millis(62_500))
+        );
+        assert_eq!(
+            parse_timestamp("01:02.250"),
+            Some(Duration::from_millis(62_250))
+        );
+        assert_eq!(parse_timestamp("1:00:00"), Some(Duration::from_hours(1)));
+        assert_eq!(parse_timestamp("0:61"), None);
+        assert_eq!(parse_timestamp("12"), None);
+        assert_eq!(parse_timestamp("a:00"), None);
+    }
+
+    #[test]
+    fn markup_is_stripped() {
+        assert_eq!(
+            strip_tags("<i>Hello</i>,\n  <b>world</b> &amp; &#8220;more&#x201D;"),
+            "Hello, world & \u{201c}more\u{201d}"
+        );
+        assert_eq!(
+            decode_entities("fish & chips &unknown;"),
+            "fish & chips &unknown;"
+        );
+        assert_eq!(vtt_speaker("<v.loud Seth>Hi</v>"), Some("Seth".to_string()));
+        assert_eq!(vtt_speaker("<v Dr. Who>Hi"), Some("Dr. Who".to_string()));
+        assert_eq!(vtt_speaker("<i>Hi</i>"), None);
+    }
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use std::fs;
+
+use common::{TestServer, temp_dir};
+use undersea_lib::{ArtworkError, Shows, artwork::ArtworkCache};
+
+#[tokio::test]
+async fn artwork_urls() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+    let url = server.url("feeds/lost_terminal.xml");
+    shows.add(url.as_str()).await.expect("to add show");
+
+    let show = shows.find_show(&url).unwrap();
+    let artwork = "https://d3wo5wojvuv7l.cloudfront.net/t_rss_itunes_square_1400/images.spreaker.com/original/lost-terminal.jpg";
+    assert_eq!(show.artwork_url(), Some(artwork));
+    // episodes without their own artwork use the shows
+    let episode = show.episodes()[0];
+    assert_eq!(episode.artwork_url(), None);
+    assert_eq!(show.artwork_for(episode), Some(artwork));
+}
+
+#[tokio::test]
+async fn scaled_and_cached() {
+    let server = TestServer::start().await;
+    let dir = temp_dir("artwork");
+    let cache = ArtworkCache::new(dir.clone(), reqwest::Client::new());
+    let url = server.url("image/300/150");
+
+    let image = cache.load(&url, 100).await.expect("to load artwork");
+    assert_eq!(image.dimensions(), (100, 50));
+    // small artwork is not scaled up
+    let image = cache.load(&url, 1000).await.expect("to load artwork");
+    assert_eq!(image.dimensions(), (300, 150));
+
+    let original = cache.original(&url).await.unwrap();
+    let scaled = cache.scaled(&url, 100).await.unwrap();
+    assert!(original.starts_with(&dir) && scaled.starts_with(&dir));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+
+    cache.clear().unwrap();
+    assert!(!dir.exists());
+}
+
+#[tokio::test]
+async fn bad_artwork_is_not_cached() {
+    let server = TestServer::start().await;
+    let dir = temp_dir("bad-artwork");
+    let cache = ArtworkCache::new(dir.clone(), reqwest::Client::new());
+
+    let err = cache
+        .original(&server.url("feeds/lost_terminal.xml"))
+        .await
+        .expect_err("a feed to not be an image");
+    assert!(matches!(err, ArtworkError::ImageError(_)));
+    let err = cache
+        .scaled(&server.url("status/404"), 100)
+        .await
+        .expect_err("a missing image to fail");
+    assert!(matches!(err, ArtworkError::NetworkError(_)));
+    assert!(!dir.exists());
+}
@@ -0,0 +1,155 @@
+mod common;
+
+use std::{path::Path, time::Duration};
+
+use common::TestServer;
+use undersea_lib::{
+    ClipError, LibraryError, Shows,
+    bookmark::{Bookmark, export_clip},
+    sync::memory::MemoryStore,
+};
+
+const TUNNELS: &str = "https://api.spreaker.com/episode/39000001";
+const BUGS: &str = "https://api.spreaker.com/episode/39000002";
+
+async fn lost_terminal(server: &TestServer) -> Shows {
+    let mut shows = Shows::default();
+    shows
+        .add(server.url("feeds/lost_terminal.xml"))
+        .await
+        .expect("to add show");
+    shows
+}
+
+fn notes(shows: &Shows, id: &str) -> Vec<String> {
+    let (_, episode) = shows.episode_by_id(id).unwrap();
+    episode
+        .bookmarks()
+        .iter()
+        .map(|bookmark| bookmark.note.clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn adding_and_removing() {
+    let server = TestServer::start().await;
+    let mut shows = lost_terminal(&server).await;
+
+    let quote = Bookmark::new(Duration::from_mins(5), None, "  a good quote ");
+    shows.add_bookmark(TUNNELS, quote.clone()).unwrap();
+    let clip = Bookmark::new(
+        Duration::from_mins(1),
+        Some(Duration::from_secs(80)),
+        "the intro",
+    );
+    shows.add_bookmark(TUNNELS, clip).unwrap();
+    // in order of where they start, with the notes trimmed
+    assert_eq!(notes(&shows, TUNNELS), ["the intro", "a good quote"]);
+
+    let backwards = Bookmark::new(Duration::from_mins(2), Some(Duration::from_mins(1)), "");
+    let err = shows.add_bookmark(TUNNELS, backwards).unwrap_err();
+    assert!(matches!(err, LibraryError::InvalidBookmark));
+    let err = shows
+        .add_bookmark("missing", Bookmark::new(Duration::ZERO, None, ""))
+        .unwrap_err();
+    assert!(matches!(err, LibraryError::EpisodeNotFound(_)));
+
+    let removed = shows.remove_bookmark(TUNNELS, &quote.created_at).unwrap();
+    assert_eq!(removed.note, "a good quote");
+    assert_eq!(notes(&shows, TUNNELS), ["the intro"]);
+    let err = shows
+        .remove_bookmark(TUNNELS, &quote.created_at)
+        .unwrap_err();
+    assert!(matches!(err, LibraryError::BookmarkNotFound(_)));
+}
+
+#[tokio::test]
+async fn markdown_export() {
+    let server = TestServer::start().await;
+    let mut shows = lost_terminal(&server).await;
+    assert_eq!(shows.bookmarks_markdown(), "# Bookmarks\n");
+
+    shows
+        .add_bookmark(
+            TUNNELS,
+            Bookmark::new(Duration::from_secs(95), None, "the tunnels"),
+        )
+        .unwrap();
+    shows
+        .add_bookmark(
+            TUNNELS,
+            Bookmark::new(Duration::from_hours(1), Some(Duration::from_secs(3630)), ""),
+        )
+        .unwrap();
+    shows
+        .add_bookmark(BUGS, Bookmark::new(Duration::ZERO, None, "bugs!"))
+        .unwrap();
+
+    assert_eq!(
+        shows.bookmarks_markdown(),
+        "# Bookmarks\n\
+        \n## Lost Terminal\n\
+        \n### S1E1 - Tunnels\n\n\
+        - **1:35** the tunnels\n\
+        - **1:00:00–1:00:30**\n\
+        \n### S1E2 - Bugs\n\n\
+        - **0:00** bugs!\n"
+    );
+}
+
+#[tokio::test]
+async fn clips_need_an_end_and_a_download() {
+    let server = TestServer::start().await;
+    let shows = lost_terminal(&server).await;
+    let (_, episode) = shows.episode_by_id(TUNNELS).unwrap();
+    let out = Path::new("clip.mp3");
+
+    let moment = Bookmark::new(Duration::from_mins(1), None, "");
+    let err = export_clip(episode, &moment, out).unwrap_err();
+    assert!(matches!(err, ClipError::NoEnd));
+
+    let clip = Bookmark::new(Duration::from_mins(1), Some(Duration::from_mins(2)), "");
+    let err = export_clip(episode, &clip, out).unwrap_err();
+    assert!(matches!(err, ClipError::NotDownloaded(_)));
+}
+
+#[tokio::test]
+async fn bookmarks_sync() {
+    let server = TestServer::start().await;
+    let store = MemoryStore::default();
+
+    let mut laptop_shows = lost_terminal(&server).await;
+    let first = Bookmark::new(Duration::from_mins(1), None, "first");
+    laptop_shows.add_bookmark(TUNNELS, first.clone()).unwrap();
+    laptop_shows
+        .add_bookmark(
+            TUNNELS,
+            Bookmark::new(Duration::from_mins(2), None, "second"),
+        )
+        .unwrap();
+    let mut laptop = store.backend("laptop");
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+
+    // the whole library is sent on the first sync, bookmarks too
+    let mut phone_shows = Shows::default();
+    let mut phone = store.backend("phone");
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    assert_eq!(notes(&phone_shows, TUNNELS), ["first", "second"]);
+
+    laptop_shows
+        .remove_bookmark(TUNNELS, &first.created_at)
+        .unwrap();
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+    phone_shows
+        .add_bookmark(
+            TUNNELS,
+            Bookmark::new(Duration::from_mins(3), None, "third"),
+        )
+        .unwrap();
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    assert_eq!(notes(&phone_shows, TUNNELS), ["second", "third"]);
+
+    let report = laptop_shows.sync(&mut laptop).await.expect("to sync");
+    assert_eq!(report.pulled, 1);
+    assert_eq!(notes(&laptop_shows, TUNNELS), ["second", "third"]);
+}
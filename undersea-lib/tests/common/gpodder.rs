@@ -0,0 +1,212 @@
+//! A fake gpodder server that keeps everything in memory, speaking both the
+//! gpodder.net and Nextcloud flavours of the api.
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+use undersea_lib::sync::gpodder::{Api, GpodderConfig};
+
+pub const USERNAME: &str = "alice";
+pub const PASSWORD: &str = "secret";
+/// `alice:secret` in base64
+const BASIC_AUTH: &str = "Basic YWxpY2U6c2VjcmV0";
+
+#[derive(Default)]
+struct Store {
+    /// Every change ever made, along with the timestamp it was made at
+    subscriptions: Vec<(i64, String, bool)>,
+    actions: Vec<(i64, Value)>,
+    clock: i64,
+}
+
+#[derive(Clone, Default)]
+struct ServerState(Arc<Mutex<Store>>);
+
+#[derive(Deserialize)]
+struct Since {
+    #[serde(default)]
+    since: i64,
+}
+
+#[derive(Deserialize)]
+struct SubscriptionUpload {
+    add: Vec<String>,
+    remove: Vec<String>,
+}
+
+pub struct GpodderServer {
+    addr: SocketAddr,
+    state: ServerState,
+}
+
+impl GpodderServer {
+    pub async fn start() -> Self {
+        let state = ServerState::default();
+        let app = Router::new()
+            .route(
+                "/api/2/subscriptions/{user}/{device}",
+                get(subscriptions).post(upload_subscriptions),
+            )
+            .route(
+                "/api/2/episodes/{user}",
+                get(episode_actions).post(upload_episode_actions),
+            )
+            .route(
+                "/index.php/apps/gpoddersync/subscriptions",
+                get(subscriptions),
+            )
+            .route(
+                "/index.php/apps/gpoddersync/subscription_change/create",
+                post(upload_subscriptions),
+            )
+            .route(
+                "/index.php/apps/gpoddersync/episode_action",
+                get(episode_actions),
+            )
+            .route(
+                "/index.php/apps/gpoddersync/episode_action/create",
+                post(upload_episode_actions),
+            )
+            .with_state(state.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("to bind gpodder server");
+        let addr = listener
+            .local_addr()
+            .expect("to get gpodder server address");
+        tokio::spawn(async move {
+            axum::serve(listener, app)
+                .await
+                .expect("gpodder server to run");
+        });
+
+        Self { addr, state }
+    }
+
+    /// Config for a device called `device` that syncs with this server.
+    pub fn config(&self, device: &str, api: Api) -> GpodderConfig {
+        GpodderConfig {
+            server: format!("http://{}", self.addr),
+            username: USERNAME.to_string(),
+            password: PASSWORD.to_string(),
+            device: device.to_string(),
+            api,
+        }
+    }
+
+    /// Every episode action the server has been sent.
+    pub fn actions(&self) -> Vec<Value> {
+        let store = self.state.0.lock().unwrap();
+        store
+            .actions
+            .iter()
+            .map(|(_, action)| action.clone())
+            .collect()
+    }
+}
+
+fn authorised(headers: &HeaderMap) -> Result<(), StatusCode> {
+    match headers.get(header::AUTHORIZATION) {
+        Some(value) if value == BASIC_AUTH => Ok(()),
+        _ => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+async fn subscriptions(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Query(Since { since }): Query<Since>,
+) -> Response {
+    if let Err(status) = authorised(&headers) {
+        return status.into_response();
+    }
+    let store = state.0.lock().unwrap();
+
+    let mut add: Vec<&String> = Vec::new();
+    let mut remove: Vec<&String> = Vec::new();
+    for (_, url, subscribed) in store.subscriptions.iter().filter(|(at, ..)| *at > since) {
+        add.retain(|added| *added != url);
+        remove.retain(|removed| *removed != url);
+        if *subscribed {
+            add.push(url);
+        } else {
+            remove.push(url);
+        }
+    }
+
+    Json(json!({ "add": add, "remove": remove, "timestamp": store.clock })).into_response()
+}
+
+async fn upload_subscriptions(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Json(upload): Json<SubscriptionUpload>,
+) -> Response {
+    if let Err(status) = authorised(&headers) {
+        return status.into_response();
+    }
+    let mut store = state.0.lock().unwrap();
+    store.clock += 1;
+    let at = store.clock;
+
+    for url in upload.add {
+        store.subscriptions.push((at, url, true));
+    }
+    for url in upload.remove {
+        store.subscriptions.push((at, url, false));
+    }
+
+    Json(json!({ "timestamp": at, "update_urls": [] })).into_response()
+}
+
+async fn episode_actions(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Query(Since { since }): Query<Since>,
+) -> Response {
+    if let Err(status) = authorised(&headers) {
+        return status.into_response();
+    }
+    let store = state.0.lock().unwrap();
+
+    let actions = store
+        .actions
+        .iter()
+        .filter(|(at, _)| *at > since)
+        .map(|(_, action)| action)
+        .collect::<Vec<_>>();
+
+    Json(json!({ "actions": actions, "timestamp": store.clock })).into_response()
+}
+
+async fn upload_episode_actions(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Json(actions): Json<Vec<Value>>,
+) -> Response {
+    if let Err(status) = authorised(&headers) {
+        return status.into_response();
+    }
+    let mut store = state.0.lock().unwrap();
+    store.clock += 1;
+    let at = store.clock;
+
+    store
+        .actions
+        .extend(actions.into_iter().map(|action| (at, action)));
+
+    Json(json!({ "timestamp": at, "update_urls": [] })).into_response()
+}
@@ -0,0 +1,338 @@
+//! A local http server that serves the feeds in `tests/fixtures`, so that
+//! tests never touch the network.
+
+#![allow(dead_code)]
+
+pub mod gpodder;
+pub mod player;
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::Write as _,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, Uri, header},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+};
+use flate2::{Compression, write::GzEncoder};
+use tokio::net::TcpListener;
+
+/// Fixture feeds for the shows the library was first tested against.
+pub const TESTING_FEEDS: [&str; 5] = [
+    // just roll with it
+    "just_roll_with_it.xml",
+    // the magnus archives
+    "the_magnus_archives.xml",
+    // scp: find us alive
+    "scp_find_us_alive.xml",
+    // lost terminal
+    "lost_terminal.xml",
+    // red vally (the mp3 links redirect in the real feed)
+    "red_valley.xml",
+];
+
+/// Headers that each get into `/private` routes: basic auth for `me` and
+/// `hunter2`, a bearer token, or a custom header.
+pub const PRIVATE_CREDENTIALS: [(&str, &str); 3] = [
+    ("authorization", "Basic bWU6aHVudGVyMg=="),
+    ("authorization", "Bearer abc123"),
+    ("x-token", "abc123"),
+];
+
+#[derive(Clone, Default)]
+struct ServerState {
+    /// Bodies that tests can change while the server is running
+    dynamic: Arc<Mutex<HashMap<String, String>>>,
+    /// Dynamic bodies that have moved, and the path they moved to
+    moved: Arc<Mutex<HashMap<String, String>>>,
+    /// How many times each `/busy` url has been asked for
+    attempts: Arc<Mutex<HashMap<String, u32>>>,
+}
+
+pub struct TestServer {
+    addr: SocketAddr,
+    state: ServerState,
+}
+
+impl TestServer {
+    /// Start a server on a random local port, it runs until the test ends.
+    ///
+    /// - `/feeds/{file}` serves a file from `tests/fixtures`
+    /// - `/malformed/{file}` serves a file from `tests/fixtures/malformed`
+    /// - `/dynamic/{name}` serves whatever was given to [`TestServer::set`],
+    ///   or redirects permanently after [`TestServer::move_to`]
+    /// - `/status/{code}` responds with that status code
+    /// - `/redirect/{count}/{*path}` redirects `count` times, then to `/{path}`
+    /// - `/slow/{millis}/{*path}` waits before serving `/{path}`
+    /// - `/busy/{status}/{times}/{file}` answers with that status and a
+    ///   `Retry-After` of 0 the first `times` it is asked for, then serves a
+    ///   file from `tests/fixtures`
+    /// - `/gzip/{file}` serves a file from `tests/fixtures` compressed
+    /// - `/picky/{file}` serves a file from `tests/fixtures`, but answers 403
+    ///   to requests without an undersea `User-Agent`
+    /// - `/huge/{items}` serves a generated feed with that many episodes
+    /// - `/media/{bytes}` serves that many bytes of fake audio
+    /// - `/image/{width}/{height}` serves a png of that size
+    /// - `/private/{file}` and `/private-media/{bytes}` are like `/feeds` and
+    ///   `/media`, but answer 401 without one of the [`PRIVATE_CREDENTIALS`]
+    pub async fn start() -> Self {
+        let state = ServerState::default();
+        let app = Router::new()
+            .route("/feeds/{file}", get(fixture))
+            .route("/malformed/{file}", get(malformed))
+            .route("/dynamic/{name}", get(dynamic))
+            .route("/status/{code}", get(status))
+            .route("/redirect/{count}/{*path}", get(redirect))
+            .route("/slow/{millis}/{*path}", get(slow))
+            .route("/busy/{status}/{times}/{file}", get(busy))
+            .route("/gzip/{file}", get(gzip))
+            .route("/picky/{file}", get(picky))
+            .route("/huge/{items}", get(huge))
+            .route("/media/{bytes}", get(media))
+            .route("/image/{width}/{height}", get(image))
+            .route("/private/{file}", get(private))
+            .route("/private-media/{bytes}", get(private_media))
+            .with_state(state.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("to bind test server");
+        let addr = listener.local_addr().expect("to get test server address");
+        tokio::spawn(async move {
+            axum::serve(listener, app)
+                .await
+                .expect("test server to run");
+        });
+
+        Self { addr, state }
+    }
+
+    /// Full url for a path on the server.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}/{}", self.addr, path.trim_start_matches('/'))
+    }
+
+    /// Urls for each of the [`TESTING_FEEDS`].
+    pub fn testing_urls(&self) -> Vec<String> {
+        TESTING_FEEDS
+            .iter()
+            .map(|file| self.url(&format!("feeds/{file}")))
+            .collect()
+    }
+
+    /// Set what `/dynamic/{name}` responds with.
+    pub fn set(&self, name: &str, body: impl Into<String>) {
+        self.state
+            .dynamic
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), body.into());
+    }
+
+    /// Make `/dynamic/{name}` answer 404, until it is set again.
+    pub fn remove(&self, name: &str) {
+        self.state.dynamic.lock().unwrap().remove(name);
+    }
+
+    /// How many times a `/busy` path has been asked for.
+    pub fn attempts(&self, path: &str) -> u32 {
+        let path = format!("/{}", path.trim_start_matches('/'));
+        self.state
+            .attempts
+            .lock()
+            .unwrap()
+            .get(&path)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Make `/dynamic/{name}` redirect to `/{path}` with a 301.
+    pub fn move_to(&self, name: &str, path: &str) {
+        self.state
+            .moved
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), path.to_string());
+    }
+}
+
+/// An empty directory for a test to use, named so that tests running at the
+/// same time do not share one.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("undersea-test-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Read a file from `tests/fixtures`.
+pub fn read_fixture(path: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(path);
+    std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("failed to read fixture {}: {err}", path.display()))
+}
+
+fn xml(body: String) -> Response {
+    ([(header::CONTENT_TYPE, "application/rss+xml")], body).into_response()
+}
+
+async fn fixture(Path(file): Path<String>) -> Response {
+    xml(read_fixture(&file))
+}
+
+async fn malformed(Path(file): Path<String>) -> Response {
+    xml(read_fixture(&format!("malformed/{file}")))
+}
+
+async fn dynamic(State(state): State<ServerState>, Path(name): Path<String>) -> Response {
+    if let Some(path) = state.moved.lock().unwrap().get(&name) {
+        return (
+            StatusCode::MOVED_PERMANENTLY,
+            [(header::LOCATION, format!("/{path}"))],
+        )
+            .into_response();
+    }
+    match state.dynamic.lock().unwrap().get(&name) {
+        Some(body) => xml(body.clone()),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn status(Path(code): Path<u16>) -> StatusCode {
+    StatusCode::from_u16(code).expect("a valid status code")
+}
+
+async fn redirect(Path((count, path)): Path<(u32, String)>) -> Redirect {
+    if count <= 1 {
+        Redirect::permanent(&format!("/{path}"))
+    } else {
+        Redirect::permanent(&format!("/redirect/{}/{path}", count - 1))
+    }
+}
+
+async fn slow(Path((millis, path)): Path<(u64, String)>) -> Redirect {
+    tokio::time::sleep(Duration::from_millis(millis)).await;
+    Redirect::temporary(&format!("/{path}"))
+}
+
+async fn busy(
+    State(state): State<ServerState>,
+    uri: Uri,
+    Path((status, times, file)): Path<(u16, u32, String)>,
+) -> Response {
+    let attempt = {
+        let mut attempts = state.attempts.lock().unwrap();
+        let attempt = attempts.entry(uri.path().to_string()).or_default();
+        *attempt += 1;
+        *attempt
+    };
+    if attempt <= times {
+        let status = StatusCode::from_u16(status).expect("a valid status code");
+        return (status, [(header::RETRY_AFTER, "0")]).into_response();
+    }
+    fixture(Path(file)).await
+}
+
+async fn gzip(Path(file): Path<String>) -> Response {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(read_fixture(&file).as_bytes())
+        .expect("to compress fixture");
+    let body = encoder.finish().expect("to compress fixture");
+    (
+        [
+            (header::CONTENT_TYPE, "application/rss+xml"),
+            (header::CONTENT_ENCODING, "gzip"),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+async fn picky(headers: HeaderMap, Path(file): Path<String>) -> Response {
+    let undersea = headers
+        .get(header::USER_AGENT)
+        .and_then(|agent| agent.to_str().ok())
+        .is_some_and(|agent| agent.starts_with("undersea/"));
+    if !undersea {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    fixture(Path(file)).await
+}
+
+fn authorized(headers: &HeaderMap) -> bool {
+    PRIVATE_CREDENTIALS
+        .iter()
+        .any(|(name, value)| headers.get(*name).is_some_and(|sent| sent == value))
+}
+
+async fn private(headers: HeaderMap, Path(file): Path<String>) -> Response {
+    if !authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    fixture(Path(file)).await
+}
+
+async fn private_media(headers: HeaderMap, Path(bytes): Path<usize>) -> Response {
+    if !authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    media(Path(bytes)).await
+}
+
+async fn huge(Path(items): Path<usize>) -> Response {
+    xml(huge_feed(items))
+}
+
+async fn media(Path(bytes): Path<usize>) -> Response {
+    ([(header::CONTENT_TYPE, "audio/mpeg")], vec![0xAA_u8; bytes]).into_response()
+}
+
+async fn image(Path((width, height)): Path<(u32, u32)>) -> Response {
+    let image = image::RgbImage::from_fn(width, height, |x, y| {
+        image::Rgb([x.to_le_bytes()[0], y.to_le_bytes()[0], 128])
+    });
+    let mut png = std::io::Cursor::new(Vec::new());
+    image
+        .write_to(&mut png, image::ImageFormat::Png)
+        .expect("to encode png");
+    ([(header::CONTENT_TYPE, "image/png")], png.into_inner()).into_response()
+}
+
+/// A feed with `items` episodes, each with a long description, like the
+/// back catalogues of long running shows.
+pub fn huge_feed(items: usize) -> String {
+    let description = "Lorem ipsum dolor sit amet. ".repeat(100);
+    let mut feed = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <rss version=\"2.0\" xmlns:content=\"http://purl.org/rss/1.0/modules/content/\">\n\
+         <channel><title>Huge Show</title><link>https://example.com</link>\
+         <description>So many episodes</description>\n",
+    );
+    for item in 0..items {
+        let day = item % 28 + 1;
+        let year = 2000 + item / 336;
+        let month = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ][item / 28 % 12];
+        let _ = writeln!(
+            feed,
+            "<item><title>Episode {item}</title><guid>huge-{item}</guid>\
+             <pubDate>{day:02} {month} {year} 00:00:00 +0000</pubDate>\
+             <content:encoded><![CDATA[<p>{description}</p>]]></content:encoded>\
+             <enclosure url=\"https://example.com/{item}.mp3\" length=\"0\" type=\"audio/mpeg\"/></item>"
+        );
+    }
+    feed.push_str("</channel></rss>\n");
+    feed
+}
@@ -0,0 +1,82 @@
+//! A [`Backend`] that plays nothing, for testing what the player asks of it.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use undersea_lib::{
+    PlayerError,
+    player::{Backend, dsp::AudioSettings},
+};
+
+/// What the [`FakeBackend`] was told to do, and where it is up to, which
+/// tests change to act out playback.
+#[derive(Debug, Default)]
+pub struct Fake {
+    pub source: Option<String>,
+    pub position: Duration,
+    pub paused: bool,
+    pub speed: f32,
+    pub at_end: bool,
+    /// Only set once the player has changed it
+    pub volume: Option<f32>,
+    pub audio: Option<AudioSettings>,
+    /// Silence skipped but not yet asked for
+    pub silence_saved: Duration,
+}
+
+pub struct FakeBackend(pub Arc<Mutex<Fake>>);
+
+impl Backend for FakeBackend {
+    fn load(&mut self, source: &str, start: Duration) -> Result<(), PlayerError> {
+        let mut fake = self.0.lock().unwrap();
+        fake.source = Some(source.to_string());
+        fake.position = start;
+        fake.paused = false;
+        fake.at_end = false;
+        Ok(())
+    }
+
+    fn set_paused(&mut self, paused: bool) -> Result<(), PlayerError> {
+        self.0.lock().unwrap().paused = paused;
+        Ok(())
+    }
+
+    fn seek(&mut self, position: Duration) -> Result<(), PlayerError> {
+        self.0.lock().unwrap().position = position;
+        Ok(())
+    }
+
+    fn set_speed(&mut self, speed: f32) -> Result<(), PlayerError> {
+        self.0.lock().unwrap().speed = speed;
+        Ok(())
+    }
+
+    fn position(&mut self) -> Result<Duration, PlayerError> {
+        Ok(self.0.lock().unwrap().position)
+    }
+
+    fn at_end(&mut self) -> Result<bool, PlayerError> {
+        Ok(self.0.lock().unwrap().at_end)
+    }
+
+    fn set_volume(&mut self, volume: f32) -> Result<(), PlayerError> {
+        self.0.lock().unwrap().volume = Some(volume);
+        Ok(())
+    }
+
+    fn set_audio(&mut self, audio: &AudioSettings) -> Result<(), PlayerError> {
+        self.0.lock().unwrap().audio = Some(*audio);
+        Ok(())
+    }
+
+    fn take_silence_saved(&mut self) -> Result<Duration, PlayerError> {
+        Ok(std::mem::take(&mut self.0.lock().unwrap().silence_saved))
+    }
+
+    fn stop(&mut self) -> Result<(), PlayerError> {
+        self.0.lock().unwrap().source = None;
+        Ok(())
+    }
+}
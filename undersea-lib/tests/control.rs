@@ -0,0 +1,235 @@
+mod common;
+
+use std::{fs, path::Path, time::Duration};
+
+use common::temp_dir;
+use serde_json::{Value, json};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+    sync::mpsc::UnboundedReceiver,
+};
+use undersea_lib::{
+    ControlError,
+    control::{Call, Client, Command, EpisodeSummary, Event, NowPlaying, RpcError, Server},
+    player::{Control, Status},
+};
+
+/// Answer every call with what it was parsed into, or a not found error for
+/// enqueueing.
+fn answer(mut calls: UnboundedReceiver<Call>) {
+    tokio::spawn(async move {
+        while let Some(call) = calls.recv().await {
+            let result = match &call.command {
+                Command::Enqueue(id) => Err(RpcError::new(
+                    RpcError::NOT_FOUND,
+                    format!("no episode with id \"{id}\""),
+                )),
+                command => Ok(json!(format!("{command:?}"))),
+            };
+            call.reply(result);
+        }
+    });
+}
+
+fn now_playing(status: Status, position: f64, speed: f32) -> NowPlaying {
+    NowPlaying {
+        status,
+        episode: Some(EpisodeSummary {
+            id: "1".to_string(),
+            title: "S1E1 - Tunnels".to_string(),
+            show: "Lost Terminal".to_string(),
+            duration: Some(1080.0),
+        }),
+        position,
+        speed,
+    }
+}
+
+#[tokio::test]
+async fn requests_become_commands() {
+    let dir = temp_dir("control-commands");
+    let path = dir.join("control.sock");
+    let (_server, calls) = Server::bind(&path).expect("to bind the socket");
+    answer(calls);
+
+    let mut client = Client::connect(&path).await.unwrap();
+    let mut call = async |method: &str, params: Value| {
+        client
+            .call(method, params)
+            .await
+            .map(|result| result.as_str().unwrap().to_string())
+    };
+
+    assert_eq!(call("status", Value::Null).await.unwrap(), "Status");
+    assert_eq!(
+        call("play", json!({ "id": "abc" })).await.unwrap(),
+        format!("{:?}", Command::Play(Some("abc".to_string())))
+    );
+    assert_eq!(
+        call("play", Value::Null).await.unwrap(),
+        format!("{:?}", Command::Control(Control::Play))
+    );
+    assert_eq!(
+        call("seek", json!({ "by": -10 })).await.unwrap(),
+        format!(
+            "{:?}",
+            Command::Control(Control::Seek {
+                forward: false,
+                by: Duration::from_secs(10)
+            })
+        )
+    );
+    assert_eq!(
+        call("seek", json!({ "position": 90.5 })).await.unwrap(),
+        format!(
+            "{:?}",
+            Command::Control(Control::SetPosition(Duration::from_secs_f64(90.5)))
+        )
+    );
+    assert_eq!(
+        call("add", json!({ "url": "https://example.com/feed" }))
+            .await
+            .unwrap(),
+        format!("{:?}", Command::Add("https://example.com/feed".to_string()))
+    );
+
+    let code = async |result: Result<String, ControlError>| match result {
+        Err(ControlError::Rpc(err)) => err.code,
+        other => panic!("expected an error, got {other:?}"),
+    };
+    assert_eq!(
+        code(call("enqueue", json!({ "id": "nothing" })).await).await,
+        RpcError::NOT_FOUND
+    );
+    assert_eq!(
+        code(call("dance", Value::Null).await).await,
+        RpcError::METHOD_NOT_FOUND
+    );
+    assert_eq!(
+        code(call("speed", json!({ "speed": "fast" })).await).await,
+        RpcError::INVALID_PARAMS
+    );
+    assert_eq!(
+        code(call("seek", json!({})).await).await,
+        RpcError::INVALID_PARAMS
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn raw_json_rpc() {
+    let dir = temp_dir("control-raw");
+    let path = dir.join("control.sock");
+    let (_server, calls) = Server::bind(&path).unwrap();
+    answer(calls);
+
+    let (read, mut write) = UnixStream::connect(&path).await.unwrap().into_split();
+    let mut lines = BufReader::new(read).lines();
+    let mut request = async |line: &str| {
+        write
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .unwrap();
+    };
+
+    request("this is not json").await;
+    // a notification gets no response, so the next line is for the pause
+    request(r#"{"jsonrpc":"2.0","method":"next"}"#).await;
+    request(r#"{"jsonrpc":"2.0","id":"a","method":"pause"}"#).await;
+
+    let mut response = async || -> Value {
+        serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap()
+    };
+    let parse_error = response().await;
+    assert_eq!(parse_error["id"], Value::Null);
+    assert_eq!(parse_error["error"]["code"], RpcError::PARSE_ERROR);
+    let pause = response().await;
+    assert_eq!(pause["jsonrpc"], "2.0");
+    assert_eq!(pause["id"], "a");
+    assert_eq!(
+        pause["result"],
+        format!("{:?}", Command::Control(Control::Pause))
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn events_go_to_subscribers() {
+    let dir = temp_dir("control-events");
+    let path = dir.join("control.sock");
+    let (server, calls) = Server::bind(&path).unwrap();
+    answer(calls);
+
+    let mut subscriber = Client::connect(&path).await.unwrap();
+    subscriber.subscribe().await.unwrap();
+    let mut other = Client::connect(&path).await.unwrap();
+
+    let playing = Event::Status(now_playing(Status::Playing, 12.0, 1.0));
+    let added = Event::Added {
+        url: "https://example.com/feed".to_string(),
+        name: "Example".to_string(),
+    };
+    server.notify(&playing);
+    server.notify(&added);
+
+    // events that arrive while waiting for a response are kept
+    subscriber.call("status", Value::Null).await.unwrap();
+    assert_eq!(subscriber.next_event().await.unwrap(), playing);
+    assert_eq!(subscriber.next_event().await.unwrap(), added);
+
+    // the other client only gets responses
+    assert_eq!(other.call("status", Value::Null).await.unwrap(), "Status");
+
+    drop(server);
+    assert!(matches!(
+        subscriber.next_event().await,
+        Err(ControlError::Closed)
+    ));
+    assert!(!path.exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn one_server_per_socket() {
+    let dir = temp_dir("control-bind");
+    let path = dir.join("control.sock");
+
+    let (server, _calls) = Server::bind(&path).unwrap();
+    assert!(matches!(Server::bind(&path), Err(ControlError::InUse(_))));
+    drop(server);
+
+    // a socket left behind by a crash is replaced
+    std::os::unix::net::UnixListener::bind(&path).unwrap();
+    assert!(path.exists());
+    let (_server, _calls) = Server::bind(&path).expect("to replace a stale socket");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn not_running() {
+    let result = Client::connect(Path::new("/nonexistent/undersea/control.sock")).await;
+    assert!(matches!(result, Err(ControlError::NotRunning(_))));
+}
+
+#[test]
+fn only_real_changes_are_events() {
+    let elapsed = Duration::from_secs(2);
+    let old = now_playing(Status::Playing, 10.0, 1.5);
+
+    // playing on as expected
+    assert!(!now_playing(Status::Playing, 13.0, 1.5).changed_from(&old, elapsed));
+    // jumping ahead
+    assert!(now_playing(Status::Playing, 40.0, 1.5).changed_from(&old, elapsed));
+    assert!(now_playing(Status::Paused, 13.0, 1.5).changed_from(&old, elapsed));
+    assert!(now_playing(Status::Playing, 14.0, 2.0).changed_from(&old, elapsed));
+
+    // nothing moves while paused
+    let paused = now_playing(Status::Paused, 10.0, 1.5);
+    assert!(!now_playing(Status::Paused, 10.0, 1.5).changed_from(&paused, elapsed));
+    assert!(now_playing(Status::Paused, 0.0, 1.5).changed_from(&paused, elapsed));
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{TestServer, read_fixture, temp_dir};
+use undersea_lib::{
+    FeedError, Shows,
+    credentials::{Auth, CredentialStore, Credentials},
+    sync::memory::MemoryStore,
+};
+
+const TUNNELS: &str = "https://api.spreaker.com/episode/39000001";
+const BUGS: &str = "https://api.spreaker.com/episode/39000002";
+
+fn basic() -> Credentials {
+    Credentials {
+        auth: Some(Auth::Basic {
+            username: "me".to_string(),
+            password: "hunter2".to_string(),
+        }),
+        ..Credentials::default()
+    }
+}
+
+#[tokio::test]
+async fn private_feeds_need_credentials() {
+    let server = TestServer::start().await;
+    let url = server.url("private/lost_terminal.xml");
+
+    let mut shows = Shows::default();
+    let err = shows.add(url.as_str()).await.unwrap_err();
+    let FeedError::NetworkError(err) = err else {
+        panic!("expected a network error, got {err}");
+    };
+    assert_eq!(err.status().map(|status| status.as_u16()), Some(401));
+
+    let bearer = Credentials {
+        auth: Some(Auth::Bearer {
+            token: "abc123".to_string(),
+        }),
+        ..Credentials::default()
+    };
+    let header = Credentials {
+        headers: vec![("X-Token".to_string(), "abc123".to_string())],
+        ..Credentials::default()
+    };
+    for credentials in [basic(), bearer, header] {
+        let mut shows = Shows::default();
+        shows.credentials_mut().set(&url, credentials);
+        shows.add(url.as_str()).await.expect("to add private show");
+        assert_eq!(shows.shows()[0].name(), "Lost Terminal");
+        assert!(shows.refresh(1).await.failed.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn credentials_are_kept_apart_from_the_library() {
+    let server = TestServer::start().await;
+    let dir = temp_dir("credentials");
+    let url = server.url("private/lost_terminal.xml");
+
+    let mut shows = Shows::default();
+    shows.set_credentials(CredentialStore::load(&dir.join("credentials.json"), false).unwrap());
+    shows.credentials_mut().set(&url, basic());
+    shows.add(url.as_str()).await.expect("to add private show");
+    shows.save(&dir.join("library.json")).unwrap();
+    shows.credentials_mut().save().unwrap();
+
+    let library = std::fs::read_to_string(dir.join("library.json")).unwrap();
+    assert!(!library.contains("hunter2"));
+    let path = dir.join("credentials.json");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let store = CredentialStore::load(&path, false).unwrap();
+    assert_eq!(store.get(&url), Some(&basic()));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn media_gets_credentials_only_on_the_same_host() {
+    let server = TestServer::start().await;
+    let dir = temp_dir("private-media");
+    let same_host = server.url("private-media/100");
+    // the same server, but by another name
+    let other_host = same_host.replace("127.0.0.1", "localhost");
+    let feed = read_fixture("lost_terminal.xml")
+        .replace(
+            "https://api.spreaker.com/download/episode/39000001/s1e1_tunnels.mp3",
+            &same_host,
+        )
+        .replace(
+            "https://api.spreaker.com/download/episode/39000002/s1e2_bugs.mp3",
+            &other_host,
+        );
+    server.set("private", feed);
+    let url = server.url("dynamic/private");
+
+    let mut shows = Shows::default();
+    shows.credentials_mut().set(&url, basic());
+    shows.add(url.as_str()).await.expect("to add show");
+
+    let path = shows.download(TUNNELS, &dir).await.expect("to download");
+    assert_eq!(std::fs::metadata(path).unwrap().len(), 100);
+    let err = shows.download(BUGS, &dir).await.unwrap_err();
+    // the url of the media is left out of the error
+    assert!(!err.to_string().contains("private-media"));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn secrets_are_not_synced() {
+    let server = TestServer::start().await;
+    let store = MemoryStore::default();
+    let url = server.url("feeds/lost_terminal.xml?auth=abc123");
+
+    let mut laptop_shows = Shows::default();
+    laptop_shows.add(url.as_str()).await.expect("to add show");
+    laptop_shows
+        .set_resume_time(TUNNELS, Duration::from_mins(1))
+        .unwrap();
+    let mut laptop = store.backend("laptop");
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+    let sent = serde_json::to_string(&store.changes()).unwrap();
+    assert!(!sent.contains("abc123"));
+    assert!(sent.contains("auth=REDACTED"));
+
+    // another device cannot subscribe without the secret, but once it has
+    // the show itself the progress finds it
+    let mut phone_shows = Shows::default();
+    let mut phone = store.backend("phone");
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    assert!(phone_shows.shows().is_empty());
+
+    let mut tablet_shows = Shows::default();
+    tablet_shows.add(url.as_str()).await.expect("to add show");
+    tablet_shows.acknowledge_journal(1);
+    let mut tablet = store.backend("tablet");
+    tablet_shows.sync(&mut tablet).await.expect("to sync");
+    let (_, episode) = tablet_shows.episode_by_id(TUNNELS).unwrap();
+    assert_eq!(*episode.resume_time(), Duration::from_mins(1));
+
+    // unless secrets are explicitly synced
+    laptop_shows.set_sync_secret_urls(true);
+    laptop_shows.set_finished(TUNNELS, true).unwrap();
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+    let sent = serde_json::to_string(&store.changes()).unwrap();
+    assert!(sent.contains("abc123"));
+}
@@ -0,0 +1,554 @@
+mod common;
+
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use common::{TESTING_FEEDS, TestServer, read_fixture, temp_dir};
+use undersea_lib::{
+    FeedError, Gone, Shows,
+    description::DescriptionCache,
+    http::{HttpClient, HttpOptions, Retry},
+    sync::Change,
+};
+
+#[tokio::test]
+async fn add_single_from_url() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+
+    shows
+        .add(server.url("feeds/lost_terminal.xml"))
+        .await
+        .expect("to add shows");
+
+    assert_eq!(shows.shows().len(), 1);
+    let show = shows.shows()[0];
+    assert_eq!(show.name(), "Lost Terminal");
+    assert_eq!(show.episodes().len(), 4);
+    assert_eq!(show.episodes()[0].title(), "S1E1 - Tunnels");
+    assert_eq!(
+        show.episodes()[0].id(),
+        "https://api.spreaker.com/episode/39000001"
+    );
+}
+
+#[tokio::test]
+async fn add_multiple() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+
+    shows
+        .add_multiple(server.testing_urls())
+        .await
+        .expect("to add shows");
+
+    assert_eq!(shows.shows().len(), TESTING_FEEDS.len());
+    for show in shows.shows() {
+        assert!(
+            !show.episodes().is_empty(),
+            "{} has no episodes",
+            show.name()
+        );
+    }
+}
+
+#[tokio::test]
+async fn episodes_are_sorted_by_date() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+
+    // the trailer is the last item in the feed but the oldest episode
+    shows
+        .add(server.url("feeds/scp_find_us_alive.xml"))
+        .await
+        .expect("to add show");
+
+    let titles = shows.shows()[0]
+        .episodes()
+        .iter()
+        .map(|ep| ep.title().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        titles,
+        [
+            "Trailer",
+            "SCP: Find Us Alive | Episode 1 - The Hunt",
+            "SCP: Find Us Alive | Episode 2 - Voices"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn refresh_keeps_progress() {
+    let server = TestServer::start().await;
+    let feed = read_fixture("lost_terminal.xml");
+    // start with the feed missing its newest episode
+    let start = feed.find("    <item>\n        <title>S1E4").unwrap();
+    let end = feed.rfind("</channel>").unwrap();
+    server.set(
+        "lost_terminal",
+        format!("{}{}", &feed[..start], &feed[end..]),
+    );
+
+    let mut shows = Shows::default();
+    shows
+        .add(server.url("dynamic/lost_terminal"))
+        .await
+        .expect("to add show");
+    assert_eq!(shows.shows()[0].episodes().len(), 3);
+
+    let first = "https://api.spreaker.com/episode/39000001";
+    let second = "https://api.spreaker.com/episode/39000002";
+    shows.set_finished(first, true).unwrap();
+    shows
+        .set_resume_time(second, Duration::from_secs(123))
+        .unwrap();
+
+    server.set("lost_terminal", feed);
+    let summary = shows.refresh(4).await;
+
+    assert_eq!(summary.new_episodes, 1);
+    assert!(summary.failed.is_empty());
+    let (_, episode) = shows.episode_by_id(first).unwrap();
+    assert!(episode.finished());
+    let (_, episode) = shows.episode_by_id(second).unwrap();
+    assert_eq!(*episode.resume_time(), Duration::from_secs(123));
+    assert_eq!(shows.shows()[0].episodes().len(), 4);
+}
+
+#[tokio::test]
+async fn refresh_reports_failures_without_stopping() {
+    let server = TestServer::start().await;
+    let feed = read_fixture("red_valley.xml");
+    server.set("red_valley", feed);
+
+    let mut shows = Shows::default();
+    shows
+        .add_multiple([
+            server.url("dynamic/red_valley"),
+            server.url("feeds/lost_terminal.xml"),
+        ])
+        .await
+        .expect("to add shows");
+
+    server.set("red_valley", "not a feed any more");
+    let summary = shows.refresh(1).await;
+
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(summary.failed[0].0, server.url("dynamic/red_valley"));
+    assert!(matches!(summary.failed[0].1, FeedError::InvalidFeed(_)));
+}
+
+#[tokio::test]
+async fn missing_fields_are_tolerated() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+
+    shows
+        .add(server.url("malformed/missing_fields.xml"))
+        .await
+        .expect("to add show");
+
+    let show = shows.shows()[0];
+    // the episode with no enclosure is skipped
+    assert_eq!(show.episodes().len(), 5);
+
+    let (_, no_title) = shows.episode_by_id("no-title").unwrap();
+    assert_eq!(no_title.title(), server.url("malformed/missing_fields.xml"));
+
+    let (_, no_guid) = shows
+        .episode_by_id("https://example.com/no-guid.mp3")
+        .unwrap();
+    assert_eq!(no_guid.title(), "No guid");
+
+    let (_, iso_date) = shows.episode_by_id("iso-date").unwrap();
+    assert_eq!(iso_date.date().to_rfc3339(), "2025-01-02T10:00:00+00:00");
+
+    // episodes without a usable date get the date of the feed
+    let (_, no_date) = shows.episode_by_id("no-date").unwrap();
+    assert_eq!(no_date.date().to_rfc3339(), "2025-01-06T00:00:00+00:00");
+}
+
+#[tokio::test]
+async fn invalid_feeds_fail() {
+    let server = TestServer::start().await;
+
+    for path in ["malformed/not_a_feed.html", "malformed/truncated.xml"] {
+        let mut shows = Shows::default();
+        let err = shows
+            .add(server.url(path))
+            .await
+            .expect_err("adding an invalid feed to fail");
+
+        assert!(matches!(err, FeedError::InvalidFeed(_)), "{path}: {err}");
+        assert!(shows.shows().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn error_statuses_fail() {
+    let server = TestServer::start().await;
+
+    for code in [404, 410, 500, 503] {
+        let mut shows = Shows::default();
+        let err = shows
+            .add(server.url(&format!("status/{code}")))
+            .await
+            .expect_err("an error status to fail");
+
+        let FeedError::NetworkError(err) = err else {
+            panic!("{code} was not a network error: {err}");
+        };
+        assert_eq!(err.status().map(|status| status.as_u16()), Some(code));
+    }
+}
+
+#[tokio::test]
+async fn redirects_are_followed() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+
+    shows
+        .add(server.url("redirect/3/feeds/red_valley.xml"))
+        .await
+        .expect("to add show through redirects");
+
+    assert_eq!(shows.shows()[0].name(), "Red Valley");
+    // the redirects were all permanent, so the show is added where they end
+    let moved = server.url("feeds/red_valley.xml");
+    assert_eq!(shows.shows()[0].url(), moved);
+    let show = shows.find_show(&server.url("redirect/3/feeds/red_valley.xml"));
+    assert_eq!(show.unwrap().url(), moved);
+}
+
+#[tokio::test]
+async fn temporary_redirects_keep_the_url() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+
+    let temporary = server.url("slow/0/feeds/red_valley.xml");
+    shows.add(temporary.as_str()).await.expect("to add show");
+    assert_eq!(shows.shows()[0].url(), temporary);
+
+    // moved for good up to the temporary redirect, but no further
+    shows
+        .add(server.url("redirect/2/slow/0/feeds/lost_terminal.xml"))
+        .await
+        .expect("to add show");
+    assert_eq!(
+        shows.shows()[1].url(),
+        server.url("slow/0/feeds/lost_terminal.xml")
+    );
+}
+
+#[tokio::test]
+async fn moved_feeds_keep_progress() {
+    let server = TestServer::start().await;
+    server.set("lost_terminal", read_fixture("lost_terminal.xml"));
+    let old = server.url("dynamic/lost_terminal");
+    let mut shows = Shows::default();
+    shows.add(old.as_str()).await.expect("to add show");
+
+    let tunnels = "https://api.spreaker.com/episode/39000001";
+    shows
+        .set_resume_time(tunnels, Duration::from_secs(123))
+        .unwrap();
+    server.move_to("lost_terminal", "feeds/lost_terminal.xml");
+    let summary = shows.refresh(1).await;
+
+    let new = server.url("feeds/lost_terminal.xml");
+    assert_eq!(summary.moved, [(old.clone(), new.clone())]);
+    assert_eq!(shows.shows()[0].url(), new);
+    assert_eq!(shows.shows()[0].previous_urls(), std::slice::from_ref(&old));
+    let (_, episode) = shows.episode_by_id(tunnels).unwrap();
+    assert_eq!(*episode.resume_time(), Duration::from_secs(123));
+    assert!(shows.journal().iter().any(|entry| entry.change
+        == Change::Move {
+            from: old.clone(),
+            to: new.clone(),
+        }));
+
+    // already at the new url, so nothing moves again
+    let summary = shows.refresh(1).await;
+    assert!(summary.moved.is_empty());
+    assert!(shows.find_show(&old).is_some());
+}
+
+#[tokio::test]
+async fn new_feed_url_moves_shows() {
+    let server = TestServer::start().await;
+    let feed = read_fixture("lost_terminal.xml");
+    server.set("lost_terminal", feed.clone());
+    let old = server.url("dynamic/lost_terminal");
+    let mut shows = Shows::default();
+    shows.add(old.as_str()).await.expect("to add show");
+
+    let new = server.url("feeds/red_valley.xml");
+    server.set(
+        "lost_terminal",
+        feed.replacen(
+            "<channel>",
+            &format!("<channel>\n<itunes:new-feed-url>{new}</itunes:new-feed-url>"),
+            1,
+        ),
+    );
+    let summary = shows.refresh(1).await;
+
+    assert_eq!(summary.moved, [(old, new.clone())]);
+    assert_eq!(shows.shows()[0].url(), new);
+}
+
+#[tokio::test]
+async fn missing_feeds_are_counted() {
+    let server = TestServer::start().await;
+    let feed = read_fixture("lost_terminal.xml");
+    server.set("lost_terminal", feed.clone());
+    let mut shows = Shows::default();
+    shows
+        .add(server.url("dynamic/lost_terminal"))
+        .await
+        .expect("to add show");
+
+    server.remove("lost_terminal");
+    shows.refresh(1).await;
+    shows.refresh(1).await;
+    // a feed that says it is gone for good counts too
+    server.move_to("lost_terminal", "status/410");
+    let summary = shows.refresh(1).await;
+    assert_eq!(summary.failed.len(), 1);
+
+    let show = shows.shows()[0];
+    let gone = *show.gone().expect("the feed to be missing");
+    assert_eq!(gone.refreshes, 3);
+    // missing often enough, but not for long enough
+    assert!(!show.is_dead());
+    assert!(gone.is_dead(gone.since + TimeDelta::days(8)));
+    let once = Gone {
+        since: Utc::now() - TimeDelta::days(30),
+        refreshes: 1,
+    };
+    assert!(!once.is_dead(Utc::now()));
+
+    // other failures do not count, and the feed coming back resets it
+    server.move_to("lost_terminal", "status/500");
+    shows.refresh(1).await;
+    assert_eq!(shows.shows()[0].gone().unwrap().refreshes, 3);
+    server.move_to("lost_terminal", "feeds/lost_terminal.xml");
+    let summary = shows.refresh(1).await;
+    assert!(summary.failed.is_empty());
+    assert!(shows.shows()[0].gone().is_none());
+}
+
+#[tokio::test]
+async fn slow_feeds_time_out() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+    shows.set_client(
+        reqwest::Client::builder()
+            .timeout(Duration::from_millis(100))
+            .build()
+            .unwrap(),
+    );
+
+    let err = shows
+        .add(server.url("slow/2000/feeds/lost_terminal.xml"))
+        .await
+        .expect_err("a slow feed to time out");
+
+    let FeedError::NetworkError(err) = err else {
+        panic!("timing out was not a network error: {err}");
+    };
+    assert!(err.is_timeout());
+
+    // the same client is fine with a quick enough feed
+    shows
+        .add(server.url("slow/10/feeds/lost_terminal.xml"))
+        .await
+        .expect("to add show");
+}
+
+#[tokio::test]
+async fn hosts_that_stop_sending_time_out() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+    shows.set_client(
+        HttpClient::new(&HttpOptions {
+            read_timeout: Duration::from_millis(100),
+            retry: Retry::NONE,
+            ..HttpOptions::default()
+        })
+        .unwrap(),
+    );
+
+    let err = shows
+        .add(server.url("slow/2000/feeds/lost_terminal.xml"))
+        .await
+        .expect_err("a slow feed to time out");
+    let FeedError::NetworkError(err) = err else {
+        panic!("timing out was not a network error: {err}");
+    };
+    assert!(err.is_timeout());
+}
+
+#[tokio::test]
+async fn busy_hosts_are_tried_again() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+
+    for status in [503, 429] {
+        let path = format!("busy/{status}/2/lost_terminal.xml");
+        shows.add(server.url(&path)).await.expect("to add show");
+        assert_eq!(server.attempts(&path), 3);
+    }
+
+    // but not for ever
+    shows.set_client(
+        HttpClient::new(&HttpOptions {
+            retry: Retry {
+                retries: 1,
+                ..Retry::default()
+            },
+            ..HttpOptions::default()
+        })
+        .unwrap(),
+    );
+    let path = "busy/503/5/the_magnus_archives.xml";
+    let err = shows.add(server.url(path)).await.unwrap_err();
+    let FeedError::NetworkError(err) = err else {
+        panic!("a busy host was not a network error: {err}");
+    };
+    assert_eq!(err.status().map(|status| status.as_u16()), Some(503));
+    assert_eq!(server.attempts(path), 2);
+
+    // other errors are not going to pass
+    let path = "busy/500/1/the_magnus_archives.xml";
+    shows.add(server.url(path)).await.unwrap_err();
+    assert_eq!(server.attempts(path), 1);
+}
+
+#[tokio::test]
+async fn compressed_feeds() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+
+    shows
+        .add(server.url("gzip/lost_terminal.xml"))
+        .await
+        .expect("to add show");
+    assert_eq!(shows.shows()[0].name(), "Lost Terminal");
+}
+
+#[tokio::test]
+async fn user_agent_is_sent() {
+    let server = TestServer::start().await;
+    let url = server.url("picky/lost_terminal.xml");
+
+    let mut shows = Shows::default();
+    shows.add(url.as_str()).await.expect("to add show");
+
+    for client in [
+        HttpClient::from(reqwest::Client::new()),
+        HttpClient::new(&HttpOptions {
+            user_agent: "podcatcher/1.0".to_string(),
+            ..HttpOptions::default()
+        })
+        .unwrap(),
+    ] {
+        let mut shows = Shows::default();
+        shows.set_client(client);
+        let err = shows.add(url.as_str()).await.unwrap_err();
+        let FeedError::NetworkError(err) = err else {
+            panic!("a blocked user agent was not a network error: {err}");
+        };
+        assert_eq!(err.status().map(|status| status.as_u16()), Some(403));
+    }
+}
+
+#[tokio::test]
+async fn items_read_the_same_as_the_whole_feed() {
+    let server = TestServer::start().await;
+
+    for file in TESTING_FEEDS {
+        let channel = rss::Channel::read_from(read_fixture(file).as_bytes()).unwrap();
+        let mut shows = Shows::default();
+        shows
+            .add(server.url(&format!("feeds/{file}")))
+            .await
+            .expect("to add show");
+
+        let show = shows.shows()[0];
+        assert_eq!(show.name(), channel.title());
+        for item in channel.items() {
+            let Some(enclosure) = item.enclosure() else {
+                continue;
+            };
+            let id = item.guid().map_or(enclosure.url(), |guid| guid.value());
+            let episode = show.episode_by_id(id).expect("the item to be an episode");
+            assert_eq!(episode.media_url(), enclosure.url());
+            assert_eq!(Some(episode.title()), item.title());
+            assert_eq!(episode.descrpition(), item.content());
+        }
+    }
+}
+
+#[tokio::test]
+async fn huge_feeds() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+
+    shows
+        .add(server.url("huge/5000"))
+        .await
+        .expect("to add a huge feed");
+
+    assert_eq!(shows.shows()[0].episodes().len(), 5000);
+}
+
+#[tokio::test]
+async fn descriptions_are_kept_on_disk() {
+    let server = TestServer::start().await;
+    let dir = temp_dir("descriptions");
+    let mut shows = Shows::default();
+    shows.set_description_cache(DescriptionCache::new(dir.clone(), 10));
+
+    shows
+        .add(server.url("huge/100"))
+        .await
+        .expect("to add a huge feed");
+    let in_memory = |shows: &Shows| {
+        shows.shows()[0]
+            .episodes()
+            .iter()
+            .filter(|episode| episode.descrpition().is_some())
+            .map(|episode| episode.id().to_string())
+            .collect::<Vec<_>>()
+    };
+    let newest = (90..100)
+        .map(|item| format!("huge-{item}"))
+        .collect::<Vec<_>>();
+    assert_eq!(in_memory(&shows), newest);
+
+    // the rest are loaded when they are asked for
+    let oldest = shows.description("huge-0").expect("a description on disk");
+    assert!(oldest.starts_with("<p>Lorem ipsum"));
+    let (_, latest) = shows.episode_by_id("huge-99").unwrap();
+    assert_eq!(
+        shows.description("huge-99").as_deref(),
+        latest.descrpition()
+    );
+
+    // and stay the same through a refresh
+    assert!(shows.refresh(1).await.failed.is_empty());
+    assert_eq!(in_memory(&shows), newest);
+    assert_eq!(shows.description("huge-0"), Some(oldest));
+
+    // the library does not have them either
+    let library = dir.join("library.json");
+    shows.save(&library).unwrap();
+    let saved = std::fs::read_to_string(&library).unwrap();
+    assert_eq!(saved.matches("<p>Lorem").count(), 10);
+
+    shows.remove("Huge Show").unwrap();
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    let _ = std::fs::remove_dir_all(dir);
+}
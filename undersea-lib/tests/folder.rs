@@ -0,0 +1,130 @@
+mod common;
+
+use std::{fs, path::Path, time::Duration};
+
+use common::{TestServer, temp_dir};
+use undersea_lib::{
+    Shows,
+    sync::folder::{FolderConfig, FolderState, FolderSync},
+};
+
+const TUNNELS: &str = "https://api.spreaker.com/episode/39000001";
+const BUGS: &str = "https://api.spreaker.com/episode/39000002";
+
+fn device(dir: &Path, name: &str) -> FolderSync {
+    let config = FolderConfig {
+        path: dir.to_path_buf(),
+        device: name.to_string(),
+    };
+    FolderSync::new(config, FolderState::default())
+}
+
+async fn lost_terminal(server: &TestServer) -> Shows {
+    let mut shows = Shows::default();
+    shows
+        .add(server.url("feeds/lost_terminal.xml"))
+        .await
+        .expect("to add show");
+    shows
+}
+
+#[tokio::test]
+async fn syncs_between_devices() {
+    let server = TestServer::start().await;
+    let dir = temp_dir("folder-sync");
+
+    let mut laptop_shows = lost_terminal(&server).await;
+    laptop_shows
+        .set_resume_time(TUNNELS, Duration::from_mins(2))
+        .unwrap();
+    let mut laptop = device(&dir, "laptop");
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+    assert!(laptop_shows.journal().is_empty());
+    assert!(dir.join("laptop.jsonl").exists());
+
+    let mut phone_shows = Shows::default();
+    let mut phone = device(&dir, "phone");
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    assert_eq!(phone_shows.shows().len(), 1);
+    let (_, tunnels) = phone_shows.episode_by_id(TUNNELS).unwrap();
+    assert_eq!(*tunnels.resume_time(), Duration::from_mins(2));
+    assert_eq!(phone.state().seen["laptop"], 2);
+
+    // and back again
+    phone_shows.set_finished(BUGS, true).unwrap();
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    let report = laptop_shows.sync(&mut laptop).await.expect("to sync");
+    assert_eq!(report.pulled, 1);
+    assert!(laptop_shows.episode_by_id(BUGS).unwrap().1.finished());
+
+    // nothing new, nothing applied
+    let report = laptop_shows.sync(&mut laptop).await.expect("to sync");
+    assert_eq!(report.pushed, 0);
+    assert_eq!(report.pulled, 0);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn newer_local_changes_win() {
+    let server = TestServer::start().await;
+    let dir = temp_dir("folder-sync-conflict");
+
+    let mut laptop_shows = lost_terminal(&server).await;
+    let mut laptop = device(&dir, "laptop");
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+    let mut phone_shows = Shows::default();
+    let mut phone = device(&dir, "phone");
+    phone_shows.sync(&mut phone).await.expect("to sync");
+
+    // the phone listens first, then the laptop listens further
+    phone_shows
+        .set_resume_time(TUNNELS, Duration::from_mins(5))
+        .unwrap();
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    laptop_shows
+        .set_resume_time(TUNNELS, Duration::from_mins(10))
+        .unwrap();
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+
+    let (_, tunnels) = laptop_shows.episode_by_id(TUNNELS).unwrap();
+    assert_eq!(*tunnels.resume_time(), Duration::from_mins(10));
+
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    let (_, tunnels) = phone_shows.episode_by_id(TUNNELS).unwrap();
+    assert_eq!(*tunnels.resume_time(), Duration::from_mins(10));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn compaction_keeps_state() {
+    let server = TestServer::start().await;
+    let dir = temp_dir("folder-sync-compact");
+
+    let mut laptop_shows = lost_terminal(&server).await;
+    let mut laptop = device(&dir, "laptop");
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+    for minute in 1..=20 {
+        // sync after each so that progress is not coalesced in the journal
+        laptop_shows
+            .set_resume_time(TUNNELS, Duration::from_mins(minute))
+            .unwrap();
+        laptop_shows.sync(&mut laptop).await.expect("to sync");
+    }
+    let log = dir.join("laptop.jsonl");
+    assert_eq!(fs::read_to_string(&log).unwrap().lines().count(), 21);
+
+    laptop.compact().expect("to compact");
+    assert_eq!(fs::read_to_string(&log).unwrap().lines().count(), 2);
+
+    let mut phone_shows = Shows::default();
+    phone_shows
+        .sync(&mut device(&dir, "phone"))
+        .await
+        .expect("to sync");
+    let (_, tunnels) = phone_shows.episode_by_id(TUNNELS).unwrap();
+    assert_eq!(*tunnels.resume_time(), Duration::from_mins(20));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{TestServer, gpodder::GpodderServer};
+use undersea_lib::{
+    Shows, SyncError,
+    sync::gpodder::{Api, GpodderClient, GpodderState, GpodderSync},
+};
+
+const TUNNELS: &str = "https://api.spreaker.com/episode/39000001";
+const BUGS: &str = "https://api.spreaker.com/episode/39000002";
+
+fn device(gpodder: &GpodderServer, name: &str, api: Api) -> GpodderSync {
+    let client = GpodderClient::new(gpodder.config(name, api), reqwest::Client::new());
+    GpodderSync::new(client, GpodderState::default())
+}
+
+/// Subscribe to lost terminal, listen to some of the first episode and finish
+/// the second.
+async fn listen_on_laptop(server: &TestServer) -> Shows {
+    let mut shows = Shows::default();
+    shows
+        .add(server.url("feeds/lost_terminal.xml"))
+        .await
+        .expect("to add show");
+    shows
+        .set_resume_time(TUNNELS, Duration::from_mins(2))
+        .unwrap();
+    shows.set_finished(BUGS, true).unwrap();
+    shows
+}
+
+async fn syncs_between_devices(api: Api) {
+    let server = TestServer::start().await;
+    let gpodder = GpodderServer::start().await;
+
+    let mut laptop_shows = listen_on_laptop(&server).await;
+    let mut laptop = device(&gpodder, "laptop", api);
+    let report = laptop_shows.sync(&mut laptop).await.expect("to sync");
+    assert_eq!(report.pushed, 3);
+    assert!(laptop_shows.journal().is_empty());
+
+    let mut phone_shows = Shows::default();
+    let mut phone = device(&gpodder, "phone", api);
+    let report = phone_shows.sync(&mut phone).await.expect("to sync");
+    assert!(report.failed.is_empty());
+
+    assert_eq!(phone_shows.shows().len(), 1);
+    let (_, tunnels) = phone_shows.episode_by_id(TUNNELS).unwrap();
+    assert_eq!(*tunnels.resume_time(), Duration::from_mins(2));
+    let (_, bugs) = phone_shows.episode_by_id(BUGS).unwrap();
+    assert!(bugs.finished());
+
+    // changes from the server are not sent back
+    assert!(phone_shows.journal().is_empty());
+}
+
+#[tokio::test]
+async fn gpodder_net() {
+    syncs_between_devices(Api::Gpodder).await;
+}
+
+#[tokio::test]
+async fn nextcloud() {
+    syncs_between_devices(Api::Nextcloud).await;
+}
+
+#[tokio::test]
+async fn finished_episodes_are_played_to_the_end() {
+    let server = TestServer::start().await;
+    let gpodder = GpodderServer::start().await;
+
+    let mut shows = listen_on_laptop(&server).await;
+    shows
+        .sync(&mut device(&gpodder, "laptop", Api::Gpodder))
+        .await
+        .expect("to sync");
+
+    let actions = gpodder.actions();
+    assert_eq!(actions.len(), 2);
+    assert_eq!(actions[0]["action"], "play");
+    assert_eq!(actions[0]["position"], 120);
+    assert_eq!(actions[0]["total"], 1080);
+    assert_eq!(actions[1]["action"], "play");
+    assert_eq!(actions[1]["position"], actions[1]["total"]);
+    assert_eq!(actions[1]["device"], "laptop");
+}
+
+#[tokio::test]
+async fn only_new_changes_are_pulled() {
+    let server = TestServer::start().await;
+    let gpodder = GpodderServer::start().await;
+
+    let mut laptop_shows = listen_on_laptop(&server).await;
+    let mut laptop = device(&gpodder, "laptop", Api::Gpodder);
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+
+    let mut phone_shows = Shows::default();
+    let mut phone = device(&gpodder, "phone", Api::Gpodder);
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    let state = phone.state().clone();
+    assert!(state.subscriptions_since > 0);
+    assert!(state.actions_since > 0);
+
+    let report = phone_shows.sync(&mut phone).await.expect("to sync again");
+    assert_eq!(report.pushed, 0);
+    assert_eq!(report.pulled, 0);
+
+    // progress made on the phone reaches the laptop, and is not undone by
+    // the older progress the laptop sent. Action timestamps only have whole
+    // seconds, so wait for this to be newer.
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    phone_shows
+        .set_resume_time(TUNNELS, Duration::from_mins(10))
+        .unwrap();
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    let report = laptop_shows.sync(&mut laptop).await.expect("to sync");
+    assert_eq!(report.pulled, 1);
+    let (_, tunnels) = laptop_shows.episode_by_id(TUNNELS).unwrap();
+    assert_eq!(*tunnels.resume_time(), Duration::from_mins(10));
+}
+
+#[tokio::test]
+async fn unsubscribing_syncs() {
+    let server = TestServer::start().await;
+    let gpodder = GpodderServer::start().await;
+
+    let mut laptop_shows = listen_on_laptop(&server).await;
+    let mut laptop = device(&gpodder, "laptop", Api::Gpodder);
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+
+    let mut phone_shows = Shows::default();
+    let mut phone = device(&gpodder, "phone", Api::Gpodder);
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    assert_eq!(phone_shows.shows().len(), 1);
+
+    laptop_shows.remove("lost terminal").unwrap();
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    assert!(phone_shows.shows().is_empty());
+}
+
+#[tokio::test]
+async fn wrong_password_fails() {
+    let server = TestServer::start().await;
+    let gpodder = GpodderServer::start().await;
+
+    let mut config = gpodder.config("laptop", Api::Gpodder);
+    config.password = "wrong".to_string();
+    let mut sync = GpodderSync::new(
+        GpodderClient::new(config, reqwest::Client::new()),
+        GpodderState::default(),
+    );
+
+    let mut shows = listen_on_laptop(&server).await;
+    let err = shows.sync(&mut sync).await.expect_err("to fail");
+    assert!(matches!(err, SyncError::NetworkError(_)));
+    // nothing was sent, so the journal is kept for next time
+    assert_eq!(shows.journal().len(), 3);
+}
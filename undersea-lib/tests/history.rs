@@ -0,0 +1,174 @@
+mod common;
+
+use std::{fs, time::Duration};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc};
+use common::temp_dir;
+use undersea_lib::history::{History, PlaySession};
+
+const LOST_TERMINAL: &str = "https://www.spreaker.com/show/4488937/episodes/feed";
+const RED_VALLEY: &str = "https://feeds.megaphone.fm/redvalley";
+
+/// Brisbane time, so that days start at 14:00 utc.
+fn brisbane() -> FixedOffset {
+    FixedOffset::east_opt(10 * 60 * 60).unwrap()
+}
+
+fn at(day: u32, hour: u32) -> DateTime<Utc> {
+    brisbane()
+        .with_ymd_and_hms(2025, 4, day, hour, 0, 0)
+        .unwrap()
+        .to_utc()
+}
+
+/// Twenty minutes of an episode, played on `day` at `speed`.
+fn session(show: &str, day: u32, speed: f32, finished: bool) -> PlaySession {
+    let start = Duration::from_mins(10);
+    let end = Duration::from_mins(30);
+    PlaySession {
+        show: show.to_string(),
+        episode: format!("{show}-{day}"),
+        started_at: at(day, 21),
+        ended_at: at(day, 21) + Duration::from_mins(20).div_f32(speed),
+        start,
+        end,
+        speed,
+        silence_saved: Duration::ZERO,
+        finished,
+    }
+}
+
+#[test]
+fn record_and_load() {
+    let dir = temp_dir("history");
+    let path = dir.join("history.jsonl");
+
+    let mut history = History::load(&path).expect("missing history to be empty");
+    history
+        .record(session(LOST_TERMINAL, 1, 1.0, false))
+        .unwrap();
+    history.record(session(RED_VALLEY, 2, 1.5, true)).unwrap();
+    // a line cut short by a crash
+    let mut contents = fs::read_to_string(&path).unwrap();
+    contents.push_str("{\"show\":\"https://");
+    fs::write(&path, contents).unwrap();
+
+    let loaded = History::load(&path).expect("to load history");
+    assert_eq!(loaded.sessions(), history.sessions());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn totals() {
+    let mut history = History::default();
+    history
+        .record(session(LOST_TERMINAL, 1, 1.0, false))
+        .unwrap();
+    history
+        .record(session(LOST_TERMINAL, 2, 2.0, true))
+        .unwrap();
+    history
+        .record(PlaySession {
+            silence_saved: Duration::from_mins(2),
+            ..session(RED_VALLEY, 2, 1.0, true)
+        })
+        .unwrap();
+
+    let stats = history.stats(&brisbane().with_ymd_and_hms(2025, 4, 2, 23, 0, 0).unwrap());
+    assert_eq!(stats.sessions, 3);
+    assert_eq!(stats.listened, Duration::from_mins(48));
+    assert_eq!(stats.time_saved, Duration::from_mins(12));
+    assert_eq!(stats.silence_saved, Duration::from_mins(2));
+    assert_eq!(stats.episodes_finished, 2);
+
+    assert_eq!(stats.shows[0].show, LOST_TERMINAL);
+    assert_eq!(stats.shows[0].listened, Duration::from_mins(30));
+    assert_eq!(stats.shows[0].sessions, 2);
+    assert_eq!(stats.shows[0].episodes_finished, 1);
+    assert_eq!(stats.shows[1].show, RED_VALLEY);
+    assert_eq!(stats.shows[1].listened, Duration::from_mins(18));
+
+    assert_eq!(
+        stats.last_days(3),
+        [
+            Duration::ZERO,
+            Duration::from_mins(20),
+            Duration::from_mins(28)
+        ]
+    );
+}
+
+#[test]
+fn finished_by_week_and_month() {
+    let mut history = History::default();
+    // tuesday the 1st and sunday the 6th are in the same week
+    for day in [1, 6, 7, 30] {
+        history
+            .record(session(LOST_TERMINAL, day, 1.0, true))
+            .unwrap();
+    }
+    history
+        .record(session(LOST_TERMINAL, 8, 1.0, false))
+        .unwrap();
+
+    let stats = history.stats(&brisbane().with_ymd_and_hms(2025, 5, 1, 12, 0, 0).unwrap());
+    let date = |month, day| NaiveDate::from_ymd_opt(2025, month, day).unwrap();
+    assert_eq!(
+        stats.finished_by_week,
+        [(date(3, 31), 2), (date(4, 7), 1), (date(4, 28), 1)]
+    );
+    assert_eq!(stats.finished_by_month, [(date(4, 1), 4)]);
+}
+
+#[test]
+fn streaks() {
+    let mut history = History::default();
+    for day in [1, 2, 3, 4, 10, 11, 12] {
+        history
+            .record(session(LOST_TERMINAL, day, 1.0, false))
+            .unwrap();
+    }
+
+    let evening = |day| brisbane().with_ymd_and_hms(2025, 4, day, 22, 0, 0).unwrap();
+    let stats = history.stats(&evening(12));
+    assert_eq!(stats.longest_streak, 4);
+    assert_eq!(stats.current_streak, 3);
+
+    // not listening yet today does not end the streak, but missing a day does
+    assert_eq!(history.stats(&evening(13)).current_streak, 3);
+    assert_eq!(history.stats(&evening(14)).current_streak, 0);
+
+    // in utc the sessions at 21:00 in brisbane were at 11:00 on the same day
+    let stats = history.stats(&Utc.with_ymd_and_hms(2025, 4, 12, 12, 0, 0).unwrap());
+    assert_eq!(stats.current_streak, 3);
+}
+
+#[test]
+fn export() {
+    let mut history = History::default();
+    let mut played = session(LOST_TERMINAL, 1, 1.5, true);
+    played.episode = "s1e1, \"tunnels\"".to_string();
+    history.record(played).unwrap();
+
+    let mut csv = Vec::new();
+    history.write_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines = csv.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines[0],
+        "show,episode,started_at,ended_at,start,end,speed,finished"
+    );
+    assert_eq!(
+        lines[1],
+        format!(
+            "{LOST_TERMINAL},\"s1e1, \"\"tunnels\"\"\",2025-04-01T11:00:00+00:00,\
+            2025-04-01T11:13:20+00:00,600,1800,1.5,true"
+        )
+    );
+
+    let mut json = Vec::new();
+    history.write_json(&mut json).unwrap();
+    let json: Vec<PlaySession> = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json, history.sessions());
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use std::{fs, time::Duration};
+
+use chrono::{TimeZone, Utc};
+use common::{TestServer, temp_dir};
+use undersea_lib::{DownloadError, InboxFilter, LibraryError, Shows};
+
+#[tokio::test]
+async fn save_and_load() {
+    let server = TestServer::start().await;
+    let dir = temp_dir("save-and-load");
+    let path = dir.join("library.json");
+
+    let mut shows = Shows::default();
+    shows
+        .add_multiple(server.testing_urls())
+        .await
+        .expect("to add shows");
+    shows
+        .set_resume_time("5e8b1f3a8d4b2c0012345678", Duration::from_secs(42))
+        .unwrap();
+    shows.save(&path).expect("to save library");
+
+    let loaded = Shows::load(&path).expect("to load library");
+
+    assert_eq!(loaded.shows().len(), shows.shows().len());
+    let (show, episode) = loaded.episode_by_id("5e8b1f3a8d4b2c0012345678").unwrap();
+    assert_eq!(show.name(), "The Magnus Archives");
+    assert_eq!(*episode.resume_time(), Duration::from_secs(42));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn download_episode() {
+    let server = TestServer::start().await;
+    let dir = temp_dir("download");
+    let feed = common::read_fixture("lost_terminal.xml").replace(
+        "https://api.spreaker.com/download/episode/39000001/s1e1_tunnels.mp3",
+        &server.url("media/2048"),
+    );
+    server.set("lost_terminal", feed);
+
+    let mut shows = Shows::default();
+    shows
+        .add(server.url("dynamic/lost_terminal"))
+        .await
+        .expect("to add show");
+
+    let id = "https://api.spreaker.com/episode/39000001";
+    let path = shows.download(id, &dir).await.expect("to download");
+
+    assert_eq!(fs::read(&path).unwrap().len(), 2048);
+    assert!(path.starts_with(dir.join("Lost Terminal")));
+    let (_, episode) = shows.episode_by_id(id).unwrap();
+    assert_eq!(episode.download_path(), Some(&path));
+
+    let err = shows
+        .download("missing", &dir)
+        .await
+        .expect_err("an unknown episode to fail");
+    assert!(matches!(err, DownloadError::EpisodeNotFound(_)));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn inbox() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+    shows
+        .add_multiple([
+            server.url("feeds/lost_terminal.xml"),
+            server.url("feeds/red_valley.xml"),
+        ])
+        .await
+        .expect("to add shows");
+    shows
+        .set_finished("https://api.spreaker.com/episode/39000004", true)
+        .unwrap();
+
+    let ids = |filter: &InboxFilter| {
+        shows
+            .inbox(filter)
+            .iter()
+            .map(|(_, episode)| episode.id().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    // newest first, across both shows
+    assert_eq!(
+        ids(&InboxFilter::unplayed()),
+        [
+            "4c9e1f0a-1111-11eb-9d3a-000000000002",
+            "4c9e1f0a-1111-11eb-9d3a-000000000001",
+            "https://api.spreaker.com/episode/39000003",
+            "https://api.spreaker.com/episode/39000002",
+            "https://api.spreaker.com/episode/39000001",
+        ]
+    );
+    assert_eq!(shows.inbox(&InboxFilter::default()).len(), 6);
+
+    let filter = InboxFilter {
+        since: Some(Utc.with_ymd_and_hms(2020, 4, 15, 0, 0, 0).unwrap()),
+        show: Some("lost terminal".to_string()),
+        ..InboxFilter::default()
+    };
+    assert_eq!(
+        ids(&filter),
+        [
+            "https://api.spreaker.com/episode/39000004",
+            "https://api.spreaker.com/episode/39000003",
+        ]
+    );
+
+    let filter = InboxFilter {
+        downloaded_only: true,
+        ..InboxFilter::default()
+    };
+    assert!(shows.inbox(&filter).is_empty());
+}
+
+#[tokio::test]
+async fn tags() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+    let lost_terminal = server.url("feeds/lost_terminal.xml");
+    let red_valley = server.url("feeds/red_valley.xml");
+    shows
+        .add_multiple([lost_terminal.clone(), red_valley.clone()])
+        .await
+        .expect("to add shows");
+
+    shows
+        .add_tags(&lost_terminal, &["fiction", " Sci-Fi ", "", "Fiction"])
+        .unwrap();
+    shows.add_tags(&red_valley, &["fiction", "horror"]).unwrap();
+    assert_eq!(
+        shows.find_show(&lost_terminal).unwrap().tags(),
+        ["fiction", "Sci-Fi"]
+    );
+    assert_eq!(shows.tags(), ["fiction", "horror", "Sci-Fi"]);
+    assert_eq!(shows.shows_tagged("FICTION").len(), 2);
+
+    let filter = InboxFilter {
+        tag: Some("horror".to_string()),
+        ..InboxFilter::default()
+    };
+    assert!(
+        shows
+            .inbox(&filter)
+            .iter()
+            .all(|(show, _)| show.url() == red_valley)
+    );
+    assert_eq!(shows.inbox(&filter).len(), 2);
+
+    shows.remove_tags(&red_valley, &["HORROR"]).unwrap();
+    assert_eq!(shows.find_show(&red_valley).unwrap().tags(), ["fiction"]);
+    assert!(shows.inbox(&filter).is_empty());
+
+    let err = shows.add_tags("missing", &["fiction"]).unwrap_err();
+    assert!(matches!(err, LibraryError::ShowNotFound(_)));
+}
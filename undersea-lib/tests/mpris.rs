@@ -0,0 +1,151 @@
+#![cfg(feature = "mpris")]
+
+mod common;
+
+use std::{
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::{
+    TestServer,
+    player::{Fake, FakeBackend},
+};
+use undersea_lib::{
+    Shows,
+    history::History,
+    player::{
+        Control, Player,
+        mpris::{BUS_NAME, Mpris},
+    },
+};
+use zbus::{Connection, Proxy, proxy::CacheProperties, zvariant::OwnedValue};
+
+const PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+const FIRST: &str = "https://api.spreaker.com/episode/39000001";
+
+/// A bus of its own for a test, so that nothing on the real session bus is
+/// touched. Killed when dropped.
+struct Bus {
+    daemon: Child,
+    address: String,
+}
+
+impl Bus {
+    /// [`None`] if `dbus-daemon` is not installed.
+    fn start() -> Option<Self> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--print-address", "--nofork"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take()?)
+            .read_line(&mut address)
+            .ok()?;
+        Some(Self {
+            daemon,
+            address: address.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+async fn proxy<'a>(connection: &Connection, interface: &'a str) -> Proxy<'a> {
+    zbus::proxy::Builder::new(connection)
+        .destination(BUS_NAME)
+        .unwrap()
+        .path(PATH)
+        .unwrap()
+        .interface(interface)
+        .unwrap()
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "needs dbus-daemon, run with `cargo test -- --ignored`"]
+async fn controls_and_properties() {
+    let bus = Bus::start().expect("dbus-daemon to be installed");
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+    shows
+        .add(server.url("feeds/lost_terminal.xml"))
+        .await
+        .expect("to add show");
+    let fake = Arc::new(Mutex::new(Fake::default()));
+    let mut player = Player::new(Box::new(FakeBackend(Arc::clone(&fake))), History::default());
+
+    let (mut mpris, mut controls) = Mpris::start(Some(&bus.address))
+        .await
+        .expect("to connect to the test bus");
+    let client = zbus::connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+    let root = proxy(&client, "org.mpris.MediaPlayer2").await;
+    let remote = proxy(&client, PLAYER).await;
+
+    let identity: String = root.get_property("Identity").await.unwrap();
+    assert_eq!(identity, "undersea");
+    let status: String = remote.get_property("PlaybackStatus").await.unwrap();
+    assert_eq!(status, "Stopped");
+
+    // calls arrive as controls, for whatever owns the player to apply
+    remote.call_method("PlayPause", &()).await.unwrap();
+    remote.call_method("Seek", &(-5_000_000_i64)).await.unwrap();
+    remote.set_property("Rate", 2.0_f64).await.unwrap();
+    root.call_method("Quit", &()).await.unwrap();
+    assert_eq!(controls.recv().await, Some(Control::PlayPause));
+    assert_eq!(
+        controls.recv().await,
+        Some(Control::Seek {
+            forward: false,
+            by: Duration::from_secs(5)
+        })
+    );
+    assert_eq!(controls.recv().await, Some(Control::SetRate(2.0)));
+    assert_eq!(controls.recv().await, Some(Control::Quit));
+
+    player.play(&mut shows, FIRST, 1.0).unwrap();
+    fake.lock().unwrap().position = Duration::from_secs(30);
+    player.update(&mut shows, 1.0).unwrap();
+    mpris.update(&player, &shows).await.unwrap();
+
+    let status: String = remote.get_property("PlaybackStatus").await.unwrap();
+    assert_eq!(status, "Playing");
+    let position: i64 = remote.get_property("Position").await.unwrap();
+    assert_eq!(position, 30_000_000);
+
+    let metadata: std::collections::HashMap<String, OwnedValue> =
+        remote.get_property("Metadata").await.unwrap();
+    let text = |key: &str| String::try_from(metadata[key].clone()).unwrap();
+    assert_eq!(text("xesam:title"), "S1E1 - Tunnels");
+    assert_eq!(text("xesam:album"), "Lost Terminal");
+    assert_eq!(
+        i64::try_from(metadata["mpris:length"].clone()).unwrap(),
+        1_080_000_000
+    );
+    assert!(metadata.contains_key("mpris:trackid"));
+
+    // applying a control changes the player, which the next update reports
+    Control::PlayPause
+        .apply(&mut player, &mut shows, 1.0)
+        .unwrap();
+    mpris.update(&player, &shows).await.unwrap();
+    let status: String = remote.get_property("PlaybackStatus").await.unwrap();
+    assert_eq!(status, "Paused");
+}
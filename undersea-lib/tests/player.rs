@@ -0,0 +1,468 @@
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{TimeDelta, Utc};
+use common::{
+    TestServer,
+    player::{Fake, FakeBackend},
+};
+use undersea_lib::{
+    PlayerError, ShowSettings, Shows,
+    history::History,
+    player::{
+        Player, SleepTimer, SleepUntil, SmartResume, Status, dsp::AudioSettings, format_time,
+    },
+    sync::{Change, JournalEntry},
+};
+
+const FIRST: &str = "https://api.spreaker.com/episode/39000001";
+const SECOND: &str = "https://api.spreaker.com/episode/39000002";
+const THIRD: &str = "https://api.spreaker.com/episode/39000003";
+
+async fn setup() -> (Shows, Player, Arc<Mutex<Fake>>) {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+    shows
+        .add(server.url("feeds/lost_terminal.xml"))
+        .await
+        .expect("to add show");
+
+    let fake = Arc::new(Mutex::new(Fake::default()));
+    let player = Player::new(Box::new(FakeBackend(Arc::clone(&fake))), History::default());
+    (shows, player, fake)
+}
+
+#[tokio::test]
+async fn sessions_and_progress() {
+    let (mut shows, mut player, fake) = setup().await;
+    shows
+        .set_resume_time(FIRST, Duration::from_mins(2))
+        .unwrap();
+
+    player.play(&mut shows, FIRST, 1.0).unwrap();
+    assert_eq!(player.status(), Status::Playing);
+    assert_eq!(player.episode_id(), Some(FIRST));
+    {
+        let fake = fake.lock().unwrap();
+        assert_eq!(
+            fake.source.as_deref(),
+            Some("https://api.spreaker.com/download/episode/39000001/s1e1_tunnels.mp3")
+        );
+        assert_eq!(fake.position, Duration::from_mins(2));
+    }
+
+    fake.lock().unwrap().position = Duration::from_mins(5);
+    player.pause(&mut shows).unwrap();
+    assert_eq!(player.status(), Status::Paused);
+    assert!(fake.lock().unwrap().paused);
+    let (_, episode) = shows.episode_by_id(FIRST).unwrap();
+    assert_eq!(*episode.resume_time(), Duration::from_mins(5));
+
+    let sessions = player.history().sessions();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].episode, FIRST);
+    assert_eq!(sessions[0].start, Duration::from_mins(2));
+    assert_eq!(sessions[0].end, Duration::from_mins(5));
+    assert!(!sessions[0].finished);
+
+    // pausing again straight after resuming is not a session
+    player.toggle(&mut shows).unwrap();
+    assert_eq!(player.status(), Status::Playing);
+    player.toggle(&mut shows).unwrap();
+    assert_eq!(player.history().sessions().len(), 1);
+
+    player.resume().unwrap();
+    fake.lock().unwrap().position = Duration::from_mins(6);
+    player.stop(&mut shows).unwrap();
+    assert_eq!(player.status(), Status::Stopped);
+    assert_eq!(player.episode_id(), None);
+    assert_eq!(fake.lock().unwrap().source, None);
+    assert_eq!(player.history().sessions().len(), 2);
+}
+
+#[tokio::test]
+async fn seeking_and_speed_split_sessions() {
+    let (mut shows, mut player, fake) = setup().await;
+    let settings = ShowSettings {
+        playback_speed: Some(1.5),
+        ..ShowSettings::default()
+    };
+    shows.set_show_settings("lost terminal", settings).unwrap();
+
+    player.play(&mut shows, FIRST, 1.0).unwrap();
+    assert!((fake.lock().unwrap().speed - 1.5).abs() < f32::EPSILON);
+
+    fake.lock().unwrap().position = Duration::from_mins(1);
+    player
+        .skip(&mut shows, true, Duration::from_secs(30))
+        .unwrap();
+    assert_eq!(fake.lock().unwrap().position, Duration::from_secs(90));
+
+    fake.lock().unwrap().position = Duration::from_mins(3);
+    player.set_speed(&mut shows, 10.0).unwrap();
+    assert!((player.speed() - 4.0).abs() < f32::EPSILON);
+
+    fake.lock().unwrap().position = Duration::from_mins(4);
+    player.pause(&mut shows).unwrap();
+
+    let sessions = player.history().sessions();
+    let stretches = sessions
+        .iter()
+        .map(|session| {
+            (
+                session.start.as_secs(),
+                session.end.as_secs(),
+                session.speed,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(stretches, [(0, 60, 1.5), (90, 180, 1.5), (180, 240, 4.0)]);
+
+    // seeking while paused only moves where it resumes from
+    player
+        .skip(&mut shows, false, Duration::from_mins(10))
+        .unwrap();
+    assert_eq!(player.position(), Duration::ZERO);
+    let (_, episode) = shows.episode_by_id(FIRST).unwrap();
+    assert_eq!(*episode.resume_time(), Duration::ZERO);
+    assert_eq!(player.history().sessions().len(), 3);
+}
+
+#[tokio::test]
+async fn finishing_plays_the_next_queued() {
+    let (mut shows, mut player, fake) = setup().await;
+    shows.enqueue(FIRST).unwrap();
+    shows.enqueue(SECOND).unwrap();
+
+    assert!(player.next(&mut shows, 1.0).unwrap());
+    assert_eq!(player.episode_id(), Some(FIRST));
+
+    fake.lock().unwrap().position = Duration::from_mins(10);
+    player.update(&mut shows, 1.0).unwrap();
+    assert_eq!(player.position(), Duration::from_mins(10));
+    assert_eq!(player.episode_id(), Some(FIRST));
+
+    {
+        let mut fake = fake.lock().unwrap();
+        fake.position = Duration::from_mins(18);
+        fake.at_end = true;
+    }
+    player.update(&mut shows, 1.0).unwrap();
+
+    let (_, first) = shows.episode_by_id(FIRST).unwrap();
+    assert!(first.finished());
+    assert_eq!(player.episode_id(), Some(SECOND));
+    assert_eq!(player.status(), Status::Playing);
+    let queue = shows.queue();
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue[0].1.id(), SECOND);
+
+    let sessions = player.history().sessions();
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].finished);
+
+    // nothing after the last queued episode
+    assert!(!player.next(&mut shows, 1.0).unwrap());
+    assert_eq!(player.episode_id(), Some(SECOND));
+}
+
+#[tokio::test]
+async fn skipping_the_outro_finishes_early() {
+    let (mut shows, mut player, fake) = setup().await;
+    let settings = ShowSettings {
+        skip_outro: Duration::from_mins(1),
+        ..ShowSettings::default()
+    };
+    shows.set_show_settings("lost terminal", settings).unwrap();
+
+    player.play(&mut shows, THIRD, 1.0).unwrap();
+    // 1002 seconds long, so the outro starts at 942
+    fake.lock().unwrap().position = Duration::from_secs(941);
+    player.update(&mut shows, 1.0).unwrap();
+    assert_eq!(player.status(), Status::Playing);
+
+    fake.lock().unwrap().position = Duration::from_secs(942);
+    player.update(&mut shows, 1.0).unwrap();
+    assert_eq!(player.status(), Status::Stopped);
+    let (_, episode) = shows.episode_by_id(THIRD).unwrap();
+    assert!(episode.finished());
+}
+
+#[tokio::test]
+async fn audio_settings_and_silence_saved() {
+    let (mut shows, player, fake) = setup().await;
+    let defaults = AudioSettings {
+        skip_silence: true,
+        normalize: true,
+        ..AudioSettings::default()
+    };
+    let mut player = player.with_audio(defaults);
+    let settings = ShowSettings {
+        normalize: Some(false),
+        mono: Some(true),
+        ..ShowSettings::default()
+    };
+    shows.set_show_settings("lost terminal", settings).unwrap();
+
+    player.play(&mut shows, FIRST, 1.0).unwrap();
+    // the show turns some stages on or off, the rest come from the config
+    assert_eq!(
+        fake.lock().unwrap().audio,
+        Some(AudioSettings {
+            skip_silence: true,
+            normalize: false,
+            mono: true,
+            ..defaults
+        })
+    );
+
+    // saved silence adds up over the session, from updates and the pause
+    fake.lock().unwrap().position = Duration::from_mins(5);
+    fake.lock().unwrap().silence_saved = Duration::from_secs(20);
+    player.update(&mut shows, 1.0).unwrap();
+    fake.lock().unwrap().position = Duration::from_mins(10);
+    fake.lock().unwrap().silence_saved = Duration::from_secs(10);
+    player.pause(&mut shows).unwrap();
+
+    let sessions = player.history().sessions();
+    assert_eq!(sessions[0].silence_saved, Duration::from_secs(30));
+    assert_eq!(sessions[0].listened(), Duration::from_secs(570));
+    let stats = player.history().stats(&Utc::now());
+    assert_eq!(stats.silence_saved, Duration::from_secs(30));
+    assert_eq!(stats.time_saved, Duration::from_secs(30));
+}
+
+/// Leave `episode` at `position`, as if it was last played `ago`.
+async fn played_ago(shows: &mut Shows, episode: &str, position: Duration, ago: TimeDelta) {
+    shows.set_resume_time(episode, position).unwrap();
+    let Some(Change::Progress {
+        show,
+        episode,
+        position,
+        ..
+    }) = shows.journal().last().map(|entry| entry.change.clone())
+    else {
+        panic!("no progress was recorded");
+    };
+    let entry = JournalEntry {
+        at: Utc::now(),
+        change: Change::Progress {
+            show,
+            episode,
+            position,
+            played_at: Some(Utc::now() - ago),
+        },
+    };
+    shows.apply_remote(entry).await.unwrap();
+}
+
+#[test]
+fn smart_resume_rewinds() {
+    let smart_resume = SmartResume::default();
+    assert_eq!(smart_resume.rewind(Duration::from_secs(59)), Duration::ZERO);
+    assert_eq!(
+        smart_resume.rewind(Duration::from_mins(1)),
+        Duration::from_secs(3)
+    );
+    let hour = smart_resume.rewind(Duration::from_hours(1));
+    assert!(hour > Duration::from_secs(10) && hour < Duration::from_secs(20));
+    assert_eq!(
+        smart_resume.rewind(Duration::from_hours(24)),
+        Duration::from_secs(30)
+    );
+    assert_eq!(
+        smart_resume.rewind(Duration::from_hours(24 * 30)),
+        Duration::from_secs(30)
+    );
+}
+
+#[test]
+fn times_are_formatted() {
+    assert_eq!(format_time(Duration::ZERO), "0:00");
+    assert_eq!(format_time(Duration::from_secs(65)), "1:05");
+    assert_eq!(format_time(Duration::from_secs(59 * 60 + 59)), "59:59");
+    assert_eq!(
+        format_time(Duration::from_secs(3600 + 2 * 60 + 3)),
+        "1:02:03"
+    );
+}
+
+#[tokio::test]
+async fn resuming_after_a_long_pause() {
+    let (mut shows, mut player, fake) = setup().await;
+    played_ago(
+        &mut shows,
+        FIRST,
+        Duration::from_mins(5),
+        TimeDelta::days(2),
+    )
+    .await;
+    player.play(&mut shows, FIRST, 1.0).unwrap();
+    assert_eq!(fake.lock().unwrap().position, Duration::from_secs(270));
+
+    // playing records when, so straight after there is nothing to go back for
+    player.stop(&mut shows).unwrap();
+    let (_, episode) = shows.episode_by_id(FIRST).unwrap();
+    assert!(episode.last_played_at().is_some());
+    player.play(&mut shows, FIRST, 1.0).unwrap();
+    assert_eq!(fake.lock().unwrap().position, Duration::from_secs(270));
+    player.stop(&mut shows).unwrap();
+
+    // not back into an intro that is skipped
+    let settings = ShowSettings {
+        skip_intro: Duration::from_secs(290),
+        ..ShowSettings::default()
+    };
+    shows.set_show_settings("lost terminal", settings).unwrap();
+    played_ago(
+        &mut shows,
+        SECOND,
+        Duration::from_mins(5),
+        TimeDelta::days(2),
+    )
+    .await;
+    player.play(&mut shows, SECOND, 1.0).unwrap();
+    assert_eq!(fake.lock().unwrap().position, Duration::from_secs(290));
+    player.stop(&mut shows).unwrap();
+
+    // or at all when turned off
+    let mut player = player.with_smart_resume(None);
+    played_ago(
+        &mut shows,
+        THIRD,
+        Duration::from_mins(5),
+        TimeDelta::days(2),
+    )
+    .await;
+    player.play(&mut shows, THIRD, 1.0).unwrap();
+    assert_eq!(fake.lock().unwrap().position, Duration::from_mins(5));
+}
+
+fn sleep_timer(until: SleepUntil) -> SleepTimer {
+    SleepTimer {
+        until,
+        fade: Duration::from_secs(30),
+        rewind: Duration::from_secs(10),
+    }
+}
+
+#[tokio::test]
+async fn sleeping_at_the_end_of_a_chapter() {
+    let (mut shows, mut player, fake) = setup().await;
+    let (_, episode) = shows.episode_by_id(FIRST).unwrap();
+    let chapters = episode
+        .chapters()
+        .iter()
+        .map(|chapter| (chapter.start.as_millis(), chapter.title.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        chapters,
+        [(0, "Hello?"), (90_000, "The tunnels"), (720_500, "Down")]
+    );
+    assert_eq!(
+        episode.chapter_end(Duration::from_mins(1)),
+        Some(Duration::from_secs(90))
+    );
+
+    player.play(&mut shows, FIRST, 1.0).unwrap();
+    player
+        .set_sleep_timer(Some(sleep_timer(SleepUntil::EndOfChapter)))
+        .unwrap();
+    fake.lock().unwrap().position = Duration::from_mins(1);
+    player.update(&mut shows, 1.0).unwrap();
+    assert_eq!(player.sleep_remaining(), Some(Duration::from_secs(30)));
+    assert_eq!(fake.lock().unwrap().volume, None);
+
+    // fading out over the last 30 seconds
+    fake.lock().unwrap().position = Duration::from_secs(75);
+    player.update(&mut shows, 1.0).unwrap();
+    assert_eq!(fake.lock().unwrap().volume, Some(0.5));
+
+    // then pausing, and going back a little
+    fake.lock().unwrap().position = Duration::from_secs(90);
+    player.update(&mut shows, 1.0).unwrap();
+    assert_eq!(player.status(), Status::Paused);
+    assert_eq!(player.sleep_timer(), None);
+    let fake = fake.lock().unwrap();
+    assert_eq!(fake.volume, Some(1.0));
+    assert_eq!(fake.position, Duration::from_secs(80));
+    let (_, episode) = shows.episode_by_id(FIRST).unwrap();
+    assert_eq!(*episode.resume_time(), Duration::from_secs(80));
+    assert_eq!(player.history().sessions()[0].end, Duration::from_secs(90));
+}
+
+#[tokio::test]
+async fn sleeping_at_the_end_of_an_episode() {
+    let (mut shows, mut player, fake) = setup().await;
+    shows.enqueue(FIRST).unwrap();
+    shows.enqueue(SECOND).unwrap();
+
+    player.play(&mut shows, FIRST, 2.0).unwrap();
+    player
+        .set_sleep_timer(Some(sleep_timer(SleepUntil::EndOfEpisode)))
+        .unwrap();
+    fake.lock().unwrap().position = Duration::from_mins(17);
+    player.update(&mut shows, 2.0).unwrap();
+    // 18 minutes long, played at double speed
+    assert_eq!(player.sleep_remaining(), Some(Duration::from_secs(30)));
+
+    fake.lock().unwrap().at_end = true;
+    player.update(&mut shows, 2.0).unwrap();
+    // the episode is finished as usual, but the next one is not played
+    assert_eq!(player.status(), Status::Stopped);
+    assert_eq!(player.sleep_timer(), None);
+    let (_, episode) = shows.episode_by_id(FIRST).unwrap();
+    assert!(episode.finished());
+    assert_eq!(shows.queue()[0].1.id(), SECOND);
+}
+
+#[tokio::test]
+async fn sleeping_after_a_while() {
+    let (mut shows, mut player, fake) = setup().await;
+    player.play(&mut shows, FIRST, 1.0).unwrap();
+    player
+        .set_sleep_timer(Some(sleep_timer(SleepUntil::After(Duration::ZERO))))
+        .unwrap();
+
+    // extending it gives it time from now
+    player.extend_sleep_timer(Duration::from_mins(5));
+    fake.lock().unwrap().position = Duration::from_mins(1);
+    player.update(&mut shows, 1.0).unwrap();
+    assert_eq!(player.status(), Status::Playing);
+    let remaining = player.sleep_remaining().unwrap();
+    assert!(remaining > Duration::from_secs(299) && remaining <= Duration::from_mins(5));
+
+    // turning it off turns the volume back up if it had faded
+    player
+        .set_sleep_timer(Some(sleep_timer(SleepUntil::After(Duration::from_secs(
+            15,
+        )))))
+        .unwrap();
+    player.update(&mut shows, 1.0).unwrap();
+    assert!(fake.lock().unwrap().volume.unwrap() <= 0.5);
+    player.set_sleep_timer(None).unwrap();
+    assert_eq!(fake.lock().unwrap().volume, Some(1.0));
+
+    player
+        .set_sleep_timer(Some(sleep_timer(SleepUntil::After(Duration::ZERO))))
+        .unwrap();
+    fake.lock().unwrap().position = Duration::from_mins(2);
+    player.update(&mut shows, 1.0).unwrap();
+    assert_eq!(player.status(), Status::Paused);
+    let (_, episode) = shows.episode_by_id(FIRST).unwrap();
+    assert_eq!(*episode.resume_time(), Duration::from_secs(110));
+}
+
+#[tokio::test]
+async fn unknown_episode() {
+    let (mut shows, mut player, _) = setup().await;
+    assert!(matches!(
+        player.play(&mut shows, "nothing", 1.0),
+        Err(PlayerError::EpisodeNotFound(_))
+    ));
+    assert_eq!(player.status(), Status::Stopped);
+}
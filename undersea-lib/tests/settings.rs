@@ -0,0 +1,150 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{TestServer, read_fixture};
+use undersea_lib::{AutoDownload, ShowSettings, Shows, SortOrder};
+
+/// Serve the lost terminal fixture without its newest episode, returning the
+/// full feed so it can be served later to look like a new episode came out.
+async fn lost_terminal_without_newest(server: &TestServer, shows: &mut Shows) -> String {
+    let feed = read_fixture("lost_terminal.xml");
+    let start = feed.find("    <item>\n        <title>S1E4").unwrap();
+    let end = feed.rfind("</channel>").unwrap();
+    server.set(
+        "lost_terminal",
+        format!("{}{}", &feed[..start], &feed[end..]),
+    );
+
+    shows
+        .add(server.url("dynamic/lost_terminal"))
+        .await
+        .expect("to add show");
+    feed
+}
+
+#[tokio::test]
+async fn sort_order() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+    let feed = lost_terminal_without_newest(&server, &mut shows).await;
+
+    let settings = ShowSettings {
+        sort_order: SortOrder::NewestFirst,
+        ..ShowSettings::default()
+    };
+    shows.set_show_settings("lost terminal", settings).unwrap();
+    assert_eq!(shows.shows()[0].episodes()[0].title(), "S1E3 - Radio");
+
+    // new episodes go in the right place too
+    server.set("lost_terminal", feed);
+    shows.refresh(1).await;
+    assert_eq!(shows.shows()[0].episodes()[0].title(), "S1E4 - Plants");
+    assert_eq!(shows.shows()[0].episodes()[3].title(), "S1E1 - Tunnels");
+}
+
+#[tokio::test]
+async fn hide_played() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+    shows
+        .add(server.url("feeds/lost_terminal.xml"))
+        .await
+        .expect("to add show");
+    shows
+        .set_finished("https://api.spreaker.com/episode/39000001", true)
+        .unwrap();
+
+    assert_eq!(shows.shows()[0].visible_episodes().len(), 4);
+
+    let settings = ShowSettings {
+        hide_played: true,
+        ..ShowSettings::default()
+    };
+    shows.set_show_settings("Lost Terminal", settings).unwrap();
+
+    let visible = shows.shows()[0].visible_episodes();
+    assert_eq!(visible.len(), 3);
+    assert_eq!(visible[0].title(), "S1E2 - Bugs");
+}
+
+#[tokio::test]
+async fn new_episodes_are_queued_and_downloaded() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+    let feed = lost_terminal_without_newest(&server, &mut shows).await;
+
+    let settings = ShowSettings {
+        add_to_queue: true,
+        auto_download: AutoDownload::New,
+        ..ShowSettings::default()
+    };
+    shows.set_show_settings("lost terminal", settings).unwrap();
+
+    server.set("lost_terminal", feed);
+    let summary = shows.refresh(1).await;
+
+    let newest = "https://api.spreaker.com/episode/39000004";
+    assert_eq!(summary.to_download, [newest]);
+    let queue = shows.queue();
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue[0].1.id(), newest);
+}
+
+#[tokio::test]
+async fn latest_unplayed_are_downloaded() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+    let feed = lost_terminal_without_newest(&server, &mut shows).await;
+
+    shows
+        .set_finished("https://api.spreaker.com/episode/39000003", true)
+        .unwrap();
+    let settings = ShowSettings {
+        auto_download: AutoDownload::Latest(2),
+        ..ShowSettings::default()
+    };
+    shows.set_show_settings("lost terminal", settings).unwrap();
+
+    server.set("lost_terminal", feed);
+    let summary = shows.refresh(1).await;
+
+    assert_eq!(
+        summary.to_download,
+        [
+            "https://api.spreaker.com/episode/39000004",
+            "https://api.spreaker.com/episode/39000002",
+        ]
+    );
+    // nothing was queued as the show does not ask for it
+    assert!(shows.queue().is_empty());
+}
+
+#[tokio::test]
+async fn skip_intro_and_playback_speed() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+    shows
+        .add(server.url("feeds/lost_terminal.xml"))
+        .await
+        .expect("to add show");
+
+    let settings = ShowSettings {
+        playback_speed: Some(1.5),
+        skip_intro: Duration::from_secs(90),
+        ..ShowSettings::default()
+    };
+    shows.set_show_settings("lost terminal", settings).unwrap();
+
+    let first = "https://api.spreaker.com/episode/39000001";
+    let (show, episode) = shows.episode_by_id(first).unwrap();
+    assert_eq!(show.start_position(episode), Duration::from_secs(90));
+    assert!((show.playback_speed(1.0) - 1.5).abs() < f32::EPSILON);
+
+    // resuming past the intro is not changed
+    shows
+        .set_resume_time(first, Duration::from_mins(10))
+        .unwrap();
+    let (show, episode) = shows.episode_by_id(first).unwrap();
+    assert_eq!(show.start_position(episode), Duration::from_mins(10));
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{TestServer, read_fixture};
+use undersea_lib::{
+    Shows, SyncError,
+    sync::{Change, SyncBackend, memory::MemoryStore},
+};
+
+const TUNNELS: &str = "https://api.spreaker.com/episode/39000001";
+const BUGS: &str = "https://api.spreaker.com/episode/39000002";
+
+async fn lost_terminal(server: &TestServer) -> Shows {
+    let mut shows = Shows::default();
+    shows
+        .add(server.url("feeds/lost_terminal.xml"))
+        .await
+        .expect("to add show");
+    shows
+}
+
+#[tokio::test]
+async fn first_sync_sends_whole_library() {
+    let server = TestServer::start().await;
+    let store = MemoryStore::default();
+
+    let mut shows = lost_terminal(&server).await;
+    shows.set_finished(BUGS, true).unwrap();
+    // synced before the show was added, so only the progress is in the journal
+    shows.acknowledge_journal(1);
+    assert_eq!(shows.journal().len(), 1);
+
+    let mut backend = store.backend("laptop");
+    assert_eq!(backend.last_sync(), None);
+    let report = shows.sync(&mut backend).await.expect("to sync");
+
+    assert_eq!(report.pushed, 2);
+    assert!(shows.journal().is_empty());
+    assert!(backend.last_sync().is_some());
+    let changes = store.changes();
+    assert!(matches!(changes[0].1.change, Change::Subscribe { .. }));
+    assert!(matches!(
+        changes[1].1.change,
+        Change::Finished { finished: true, .. }
+    ));
+}
+
+#[tokio::test]
+async fn changes_reach_other_devices() {
+    let server = TestServer::start().await;
+    let store = MemoryStore::default();
+
+    let mut laptop_shows = lost_terminal(&server).await;
+    let mut laptop = store.backend("laptop");
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+
+    let mut phone_shows = Shows::default();
+    let mut phone = store.backend("phone");
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    assert_eq!(phone_shows.shows().len(), 1);
+
+    laptop_shows
+        .set_played(TUNNELS, Duration::from_mins(3))
+        .unwrap();
+    laptop_shows.set_finished(BUGS, true).unwrap();
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+
+    let report = phone_shows.sync(&mut phone).await.expect("to sync");
+    assert_eq!(report.pulled, 2);
+    let (_, tunnels) = phone_shows.episode_by_id(TUNNELS).unwrap();
+    assert_eq!(*tunnels.resume_time(), Duration::from_mins(3));
+    // so the phone knows to go back a little when it resumes
+    let (_, laptop_tunnels) = laptop_shows.episode_by_id(TUNNELS).unwrap();
+    assert!(tunnels.last_played_at().is_some());
+    assert_eq!(tunnels.last_played_at(), laptop_tunnels.last_played_at());
+    assert!(phone_shows.episode_by_id(BUGS).unwrap().1.finished());
+
+    laptop_shows.remove("lost terminal").unwrap();
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    assert!(phone_shows.shows().is_empty());
+}
+
+#[tokio::test]
+async fn newer_local_changes_win() {
+    let server = TestServer::start().await;
+    let store = MemoryStore::default();
+
+    let mut laptop_shows = lost_terminal(&server).await;
+    let mut laptop = store.backend("laptop");
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+    let mut phone_shows = Shows::default();
+    let mut phone = store.backend("phone");
+    phone_shows.sync(&mut phone).await.expect("to sync");
+
+    // the laptop listens first, but syncs after the phone
+    laptop_shows
+        .set_resume_time(TUNNELS, Duration::from_mins(5))
+        .unwrap();
+    phone_shows
+        .set_resume_time(TUNNELS, Duration::from_mins(10))
+        .unwrap();
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+
+    let (_, tunnels) = laptop_shows.episode_by_id(TUNNELS).unwrap();
+    assert_eq!(*tunnels.resume_time(), Duration::from_mins(10));
+
+    // and the older progress from the laptop does not undo it on the phone
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    let (_, tunnels) = phone_shows.episode_by_id(TUNNELS).unwrap();
+    assert_eq!(*tunnels.resume_time(), Duration::from_mins(10));
+}
+
+#[tokio::test]
+async fn failed_sync_keeps_journal() {
+    let server = TestServer::start().await;
+    let store = MemoryStore::default();
+
+    let mut shows = lost_terminal(&server).await;
+    let mut backend = store.backend("laptop");
+    shows.sync(&mut backend).await.expect("to sync");
+    let last_sync = backend.last_sync();
+
+    shows.set_finished(BUGS, true).unwrap();
+    store.set_offline(true);
+    let err = shows.sync(&mut backend).await.expect_err("to fail");
+    assert!(matches!(err, SyncError::Unavailable(_)));
+    assert_eq!(shows.journal().len(), 1);
+    assert_eq!(backend.last_sync(), last_sync);
+
+    store.set_offline(false);
+    shows.sync(&mut backend).await.expect("to sync");
+    assert!(shows.journal().is_empty());
+}
+
+#[tokio::test]
+async fn queue_syncs() {
+    let server = TestServer::start().await;
+    let store = MemoryStore::default();
+
+    let mut laptop_shows = lost_terminal(&server).await;
+    laptop_shows.enqueue(BUGS).unwrap();
+    laptop_shows.enqueue(TUNNELS).unwrap();
+    let mut laptop = store.backend("laptop");
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+
+    let mut phone_shows = Shows::default();
+    let mut phone = store.backend("phone");
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    let queue = phone_shows
+        .queue()
+        .iter()
+        .map(|(_, episode)| episode.id().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(queue, [BUGS, TUNNELS]);
+
+    assert!(phone_shows.dequeue(BUGS));
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+    assert_eq!(laptop_shows.queue().len(), 1);
+}
+
+#[tokio::test]
+async fn moved_feeds_reach_other_devices() {
+    let server = TestServer::start().await;
+    let store = MemoryStore::default();
+    server.set("lost_terminal", read_fixture("lost_terminal.xml"));
+    let old = server.url("dynamic/lost_terminal");
+
+    let mut laptop_shows = Shows::default();
+    laptop_shows.add(old.as_str()).await.expect("to add show");
+    let mut laptop = store.backend("laptop");
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+    let mut phone_shows = Shows::default();
+    let mut phone = store.backend("phone");
+    phone_shows.sync(&mut phone).await.expect("to sync");
+
+    // the phone plays on before it has heard of the move
+    server.move_to("lost_terminal", "feeds/lost_terminal.xml");
+    let new = server.url("feeds/lost_terminal.xml");
+    laptop_shows.refresh(1).await;
+    phone_shows
+        .set_resume_time(TUNNELS, Duration::from_secs(90))
+        .unwrap();
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+    let (show, episode) = laptop_shows.episode_by_id(TUNNELS).unwrap();
+    assert_eq!(show.url(), new);
+    assert_eq!(*episode.resume_time(), Duration::from_secs(90));
+
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    let (show, episode) = phone_shows.episode_by_id(TUNNELS).unwrap();
+    assert_eq!(show.url(), new);
+    assert_eq!(show.previous_urls(), [old]);
+    assert_eq!(*episode.resume_time(), Duration::from_secs(90));
+}
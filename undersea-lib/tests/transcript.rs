@@ -0,0 +1,259 @@
+mod common;
+
+use std::{fs, time::Duration};
+
+use common::{TestServer, read_fixture, temp_dir};
+use undersea_lib::{
+    Shows, TranscriptError,
+    transcript::{Format, Transcript, TranscriptCache, TranscriptLink},
+};
+
+fn parse(file: &str, format: Format) -> Transcript {
+    Transcript::parse(&read_fixture(&format!("transcripts/{file}")), format)
+        .unwrap_or_else(|err| panic!("to parse {file}: {err}"))
+}
+
+fn texts(transcript: &Transcript) -> Vec<&str> {
+    transcript
+        .cues()
+        .iter()
+        .map(|cue| cue.text.as_str())
+        .collect()
+}
+
+fn speakers(transcript: &Transcript) -> Vec<Option<&str>> {
+    transcript
+        .cues()
+        .iter()
+        .map(|cue| cue.speaker.as_deref())
+        .collect()
+}
+
+fn starts(transcript: &Transcript) -> Vec<Duration> {
+    transcript.cues().iter().map(|cue| cue.start).collect()
+}
+
+const TEXTS: [&str; 3] = [
+    "Hello? Is anyone out there?",
+    "This is Seth, on the station. It has been a long time.",
+    "I found the tunnels & they go down.",
+];
+
+fn expected_starts() -> Vec<Duration> {
+    vec![
+        Duration::ZERO,
+        Duration::from_millis(4200),
+        Duration::from_secs(10),
+    ]
+}
+
+#[test]
+fn srt() {
+    let transcript = parse("s1e1_tunnels.srt", Format::Srt);
+    assert_eq!(texts(&transcript), TEXTS);
+    assert_eq!(starts(&transcript), expected_starts());
+    assert_eq!(transcript.cues()[1].end, Duration::from_millis(9750));
+    assert_eq!(speakers(&transcript), [None, None, None]);
+}
+
+#[test]
+fn vtt() {
+    let transcript = parse("s1e1_tunnels.vtt", Format::Vtt);
+    assert_eq!(texts(&transcript), TEXTS);
+    assert_eq!(starts(&transcript), expected_starts());
+    assert_eq!(transcript.cues()[2].end, Duration::from_secs(13));
+    assert_eq!(
+        speakers(&transcript),
+        [Some("Seth"), Some("Seth"), Some("Station Computer")]
+    );
+}
+
+#[test]
+fn json_words_are_joined() {
+    let transcript = parse("s1e1_tunnels.json", Format::Json);
+    // "Hello?" ends a sentence, so the words after it start a new cue
+    assert_eq!(
+        texts(&transcript),
+        [
+            "Hello?",
+            "Is anyone out there?",
+            "This is Seth, on the station. It has been a long time.",
+            "I found the tunnels & they go down."
+        ]
+    );
+    assert_eq!(transcript.cues()[1].start, Duration::from_millis(800));
+    assert_eq!(transcript.cues()[1].end, Duration::from_millis(4200));
+    assert_eq!(
+        speakers(&transcript),
+        [
+            Some("Seth"),
+            Some("Seth"),
+            Some("Seth"),
+            Some("Station Computer")
+        ]
+    );
+}
+
+#[test]
+fn html() {
+    let transcript = parse("s1e1_tunnels.html", Format::Html);
+    assert_eq!(texts(&transcript), TEXTS);
+    assert_eq!(starts(&transcript), expected_starts());
+    // cues end where the next starts
+    assert_eq!(transcript.cues()[0].end, Duration::from_millis(4200));
+    assert_eq!(
+        speakers(&transcript),
+        [Some("Seth"), Some("Seth"), Some("Station Computer")]
+    );
+}
+
+#[test]
+fn invalid_transcripts() {
+    let err = |text: &str, format| Transcript::parse(text, format).expect_err(text);
+
+    assert!(matches!(
+        err("1\n00:00:00,000 -> 00:00:01,000\nHi", Format::Srt),
+        TranscriptError::Invalid(_)
+    ));
+    let message = err("1\n00:00:00,000 --> soon\nHi", Format::Srt).to_string();
+    assert!(message.contains("line 2"), "{message}");
+    assert!(matches!(
+        err("00:00.000 --> 00:01.000\nHi", Format::Vtt),
+        TranscriptError::Invalid(_)
+    ));
+    assert!(matches!(
+        err("{\"segments\": [{\"startTime\": -1}]}", Format::Json),
+        TranscriptError::Json(_)
+    ));
+    // html has nothing required, so it is never invalid
+    assert_eq!(
+        Transcript::parse("<h1>Not a transcript", Format::Html).unwrap(),
+        Transcript::default()
+    );
+}
+
+#[test]
+fn following_along_and_searching() {
+    let transcript = parse("s1e1_tunnels.vtt", Format::Vtt);
+
+    assert_eq!(transcript.cue_at(Duration::ZERO), Some(0));
+    assert_eq!(transcript.cue_at(Duration::from_secs(5)), Some(1));
+    // between cues the last one stays current
+    assert_eq!(transcript.cue_at(Duration::from_millis(9900)), Some(1));
+    assert_eq!(transcript.cue_at(Duration::from_mins(1)), Some(2));
+    assert_eq!(Transcript::default().cue_at(Duration::ZERO), None);
+
+    assert_eq!(transcript.search("TUNNELS"), [2]);
+    assert_eq!(transcript.search("seth"), [0, 1]);
+    assert_eq!(transcript.search("computer"), [2]);
+    assert!(transcript.search("  ").is_empty());
+}
+
+#[test]
+fn formats() {
+    let link = |url: &str, mime_type: &str| TranscriptLink {
+        url: url.to_string(),
+        mime_type: mime_type.to_string(),
+        language: None,
+    };
+
+    assert_eq!(
+        link("a", "application/x-subrip").format(),
+        Some(Format::Srt)
+    );
+    assert_eq!(
+        link("a", "text/html; charset=utf-8").format(),
+        Some(Format::Html)
+    );
+    // the extension is used when the type is missing or unknown
+    assert_eq!(
+        link("https://example.com/a.VTT?token=1", "").format(),
+        Some(Format::Vtt)
+    );
+    assert_eq!(
+        link("https://example.com/a.txt", "text/plain").format(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn transcripts_from_feeds() {
+    let server = TestServer::start().await;
+    let mut shows = Shows::default();
+    let url = server.url("feeds/lost_terminal.xml");
+    shows.add(url.as_str()).await.expect("to add show");
+
+    let show = shows.find_show(&url).unwrap();
+    let episode = show
+        .episodes()
+        .into_iter()
+        .find(|episode| episode.title() == "S1E1 - Tunnels")
+        .unwrap();
+    assert_eq!(episode.transcripts().len(), 3);
+    assert_eq!(episode.transcripts()[1].language.as_deref(), Some("en"));
+    // vtt has the most precise times, and plain text can not be followed
+    assert_eq!(
+        episode.transcript().map(|link| link.url.as_str()),
+        Some("https://lostterminal.com/transcripts/s1e1.vtt")
+    );
+
+    let other = show
+        .episodes()
+        .into_iter()
+        .find(|episode| episode.title() == "S1E2 - Bugs")
+        .unwrap();
+    assert!(other.transcripts().is_empty());
+    assert_eq!(other.transcript(), None);
+}
+
+#[tokio::test]
+async fn cached() {
+    let server = TestServer::start().await;
+    let dir = temp_dir("transcripts");
+    let cache = TranscriptCache::new(dir.clone(), reqwest::Client::new());
+    server.set("s1e1.vtt", read_fixture("transcripts/s1e1_tunnels.vtt"));
+    let link = TranscriptLink {
+        url: server.url("dynamic/s1e1.vtt"),
+        mime_type: "text/vtt".to_string(),
+        language: None,
+    };
+
+    let transcript = cache.load(&link).await.expect("to load the transcript");
+    assert_eq!(texts(&transcript), TEXTS);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    // once cached it is not fetched again
+    server.set("s1e1.vtt", "WEBVTT\n\n00:00.000 --> 00:01.000\nChanged\n");
+    assert_eq!(cache.load(&link).await.unwrap(), transcript);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn bad_transcripts_are_not_cached() {
+    let server = TestServer::start().await;
+    let dir = temp_dir("bad-transcripts");
+    let cache = TranscriptCache::new(dir.clone(), reqwest::Client::new());
+    let link = |url: String, mime_type: &str| TranscriptLink {
+        url,
+        mime_type: mime_type.to_string(),
+        language: None,
+    };
+
+    let err = cache
+        .load(&link(server.url("feeds/lost_terminal.xml"), "text/vtt"))
+        .await
+        .expect_err("a feed to not be a transcript");
+    assert!(matches!(err, TranscriptError::Invalid(_)));
+    let err = cache
+        .load(&link(server.url("status/404"), "text/vtt"))
+        .await
+        .expect_err("a missing transcript to fail");
+    assert!(matches!(err, TranscriptError::NetworkError(_)));
+    let err = cache
+        .load(&link(server.url("status/404"), "text/plain"))
+        .await
+        .expect_err("plain text to not be supported");
+    assert!(matches!(err, TranscriptError::UnsupportedFormat(_)));
+    assert!(!dir.exists());
+}
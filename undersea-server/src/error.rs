@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum StoreError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("failed to parse: {0}")]
+    ParseError(#[from] serde_json::Error),
+    #[error("user \"{0}\" already exists")]
+    UserExists(String),
+    #[error("user \"{0}\" does not exist")]
+    UserNotFound(String),
+    #[error("invalid user name \"{0}\", only letters, numbers, - and _ are allowed")]
+    InvalidName(String),
+    #[error("failed to generate a token: {0}")]
+    Random(getrandom::Error),
+}
@@ -0,0 +1,116 @@
+//! A small server that undersea can sync with, see [`undersea_lib::sync::server`]
+//! for the api it serves.
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde_json::json;
+use tokio::net::TcpListener;
+use undersea_lib::sync::server::{PullQuery, PullResponse, PushRequest, PushResponse};
+
+mod error;
+pub mod store;
+
+pub use error::StoreError;
+pub use store::Store;
+
+type SharedStore = Arc<Mutex<Store>>;
+
+/// Routes of the api, backed by `store`.
+pub fn router(store: Store) -> Router {
+    Router::new()
+        .route("/api/v1/changes", get(pull).post(push))
+        .with_state(Arc::new(Mutex::new(store)))
+}
+
+/// Serve the api on `listener` until the process is stopped.
+///
+/// # Errors
+/// Fails if the listener stops accepting connections.
+pub async fn serve(listener: TcpListener, store: Store) -> std::io::Result<()> {
+    axum::serve(listener, router(store)).await
+}
+
+/// Why a request failed, sent back as json along with a status code.
+#[derive(Debug)]
+enum ApiError {
+    Unauthorized,
+    BadRequest(&'static str),
+    Store(StoreError),
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            Self::Unauthorized => (
+                StatusCode::UNAUTHORIZED,
+                "missing or unknown token".to_string(),
+            ),
+            Self::BadRequest(message) => (StatusCode::BAD_REQUEST, message.to_string()),
+            Self::Store(err) => {
+                eprintln!("error: {err}");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "failed to store changes".to_string(),
+                )
+            }
+        };
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+fn lock(store: &SharedStore) -> MutexGuard<'_, Store> {
+    store.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The user whose token is in the `Authorization` header.
+fn authenticate(store: &Store, headers: &HeaderMap) -> Result<String, ApiError> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| store.user_for_token(token.trim()))
+        .map(str::to_string)
+        .ok_or(ApiError::Unauthorized)
+}
+
+fn check_device(device: &str) -> Result<(), ApiError> {
+    if device.is_empty() {
+        return Err(ApiError::BadRequest("device must not be empty"));
+    }
+    Ok(())
+}
+
+async fn push(
+    State(store): State<SharedStore>,
+    headers: HeaderMap,
+    Json(request): Json<PushRequest>,
+) -> Result<Json<PushResponse>, ApiError> {
+    let mut store = lock(&store);
+    let user = authenticate(&store, &headers)?;
+    check_device(&request.device)?;
+
+    let seq = store
+        .push(&user, &request.device, request.changes)
+        .map_err(ApiError::Store)?;
+    Ok(Json(PushResponse { seq }))
+}
+
+async fn pull(
+    State(store): State<SharedStore>,
+    headers: HeaderMap,
+    Query(query): Query<PullQuery>,
+) -> Result<Json<PullResponse>, ApiError> {
+    let store = lock(&store);
+    let user = authenticate(&store, &headers)?;
+    check_device(&query.device)?;
+
+    let (changes, seq) = store.pull(&user, &query.device, query.since);
+    Ok(Json(PullResponse { changes, seq }))
+}
@@ -0,0 +1,61 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use tokio::net::TcpListener;
+use undersea_lib::config::Dirs;
+use undersea_server::Store;
+
+/// Sync server for undersea
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// Directory to keep users and their changes in, instead of the default
+    /// one
+    #[arg(long, env = "UNDERSEA_SERVER_DIR")]
+    dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Serve the sync api
+    Serve {
+        /// Address to listen on
+        #[arg(long, env = "UNDERSEA_SERVER_LISTEN", default_value = "127.0.0.1:7070")]
+        listen: SocketAddr,
+    },
+    /// Add a user, printing the token their devices should use
+    AddUser { name: String },
+    /// Give a user a new token, the old one stops working
+    ResetToken { name: String },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let dir = match args.dir {
+        Some(dir) => dir,
+        None => Dirs::from_env()
+            .context("HOME is not set, use --dir to choose where to keep data")?
+            .data
+            .join("server"),
+    };
+    let mut store =
+        Store::open(&dir).with_context(|| format!("failed to open {}", dir.display()))?;
+
+    match args.command {
+        Command::Serve { listen } => {
+            let listener = TcpListener::bind(listen)
+                .await
+                .with_context(|| format!("failed to listen on {listen}"))?;
+            eprintln!("listening on {listen}, keeping data in {}", dir.display());
+            undersea_server::serve(listener, store).await?;
+        }
+        Command::AddUser { name } => println!("{}", store.add_user(&name)?),
+        Command::ResetToken { name } => println!("{}", store.reset_token(&name)?),
+    }
+    Ok(())
+}
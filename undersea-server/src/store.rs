@@ -0,0 +1,341 @@
+//! Where the server keeps everything, as plain files in one directory:
+//!
+//! - `users.json`, every user along with a hash of their token
+//! - `changes/<user>.jsonl`, every change a user has pushed, one per line
+//!
+//! Changes are compacted when the server starts, so a log only grows while
+//! the server is running.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use undersea_lib::sync::{self, Change, JournalEntry};
+
+use crate::StoreError;
+
+/// Name of the file, in the store directory, that users are kept in.
+pub const USERS_FILE_NAME: &str = "users.json";
+
+/// A change as it is stored, along with which device pushed it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredChange {
+    /// Counts up from 1 for each user, and is kept when changes are compacted
+    pub seq: u64,
+    pub device: String,
+    pub at: DateTime<Utc>,
+    pub change: Change,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Users {
+    users: BTreeMap<String, User>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct User {
+    /// Sha256 of the token, in hex, so that the file is no use if it leaks
+    token_hash: String,
+}
+
+/// Every users changes, kept in memory and written to disk as they come in.
+#[derive(Debug)]
+pub struct Store {
+    dir: PathBuf,
+    users: Users,
+    changes: HashMap<String, Vec<StoredChange>>,
+}
+
+impl Store {
+    /// Open the store in `dir`, creating it if it does not exist yet.
+    ///
+    /// # Errors
+    /// Fails if the files could not be read or written.
+    pub fn open(dir: &Path) -> Result<Self, StoreError> {
+        fs::create_dir_all(dir.join("changes"))?;
+        let users: Users = match fs::read_to_string(dir.join(USERS_FILE_NAME)) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Users::default(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut store = Self {
+            dir: dir.to_path_buf(),
+            users,
+            changes: HashMap::new(),
+        };
+        for name in store.users.users.keys().cloned().collect::<Vec<_>>() {
+            let changes = sync::compact(read_changes(&store.changes_path(&name))?, |stored| {
+                &stored.change
+            });
+            write_changes(&store.changes_path(&name), &changes)?;
+            store.changes.insert(name, changes);
+        }
+        Ok(store)
+    }
+
+    fn changes_path(&self, user: &str) -> PathBuf {
+        self.dir.join("changes").join(format!("{user}.jsonl"))
+    }
+
+    fn save_users(&self) -> Result<(), StoreError> {
+        let path = self.dir.join(USERS_FILE_NAME);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(&self.users)?)?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    /// Add a user, returning their token.
+    ///
+    /// # Errors
+    /// Fails if the name is taken or not valid, or the users could not be saved.
+    pub fn add_user(&mut self, name: &str) -> Result<String, StoreError> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(StoreError::InvalidName(name.to_string()));
+        }
+        if self.users.users.contains_key(name) {
+            return Err(StoreError::UserExists(name.to_string()));
+        }
+
+        let token = new_token()?;
+        self.users.users.insert(
+            name.to_string(),
+            User {
+                token_hash: hash(&token),
+            },
+        );
+        self.save_users()?;
+        self.changes.insert(name.to_string(), Vec::new());
+        Ok(token)
+    }
+
+    /// Give a user a new token, the old one stops working.
+    ///
+    /// # Errors
+    /// Fails if the user does not exist, or the users could not be saved.
+    pub fn reset_token(&mut self, name: &str) -> Result<String, StoreError> {
+        let token = new_token()?;
+        self.users
+            .users
+            .get_mut(name)
+            .ok_or_else(|| StoreError::UserNotFound(name.to_string()))?
+            .token_hash = hash(&token);
+        self.save_users()?;
+        Ok(token)
+    }
+
+    /// The user a token belongs to.
+    #[must_use]
+    pub fn user_for_token(&self, token: &str) -> Option<&str> {
+        let token_hash = hash(token);
+        self.users
+            .users
+            .iter()
+            .find(|(_, user)| user.token_hash == token_hash)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Store changes pushed by a device, returning the sequence number of the
+    /// last one.
+    ///
+    /// # Errors
+    /// Fails if the changes could not be written.
+    pub fn push(
+        &mut self,
+        user: &str,
+        device: &str,
+        changes: Vec<JournalEntry>,
+    ) -> Result<u64, StoreError> {
+        let path = self.changes_path(user);
+        let stored = self.changes.entry(user.to_string()).or_default();
+        let mut seq = stored.last().map_or(0, |change| change.seq);
+        let new = changes
+            .into_iter()
+            .map(|entry| {
+                seq += 1;
+                StoredChange {
+                    seq,
+                    device: device.to_string(),
+                    at: entry.at,
+                    change: entry.change,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        append_changes(&path, &new)?;
+        stored.extend(new);
+        Ok(seq)
+    }
+
+    /// Changes made by other devices after `since`, along with the sequence
+    /// number of the last change.
+    #[must_use]
+    pub fn pull(&self, user: &str, device: &str, since: u64) -> (Vec<JournalEntry>, u64) {
+        let Some(stored) = self.changes.get(user) else {
+            return (Vec::new(), 0);
+        };
+
+        let changes = stored
+            .iter()
+            .filter(|change| change.seq > since && change.device != device)
+            .map(|change| JournalEntry {
+                at: change.at,
+                change: change.change.clone(),
+            })
+            .collect();
+        (changes, stored.last().map_or(0, |change| change.seq))
+    }
+}
+
+fn new_token() -> Result<String, StoreError> {
+    let mut bytes = [0; 32];
+    getrandom::fill(&mut bytes).map_err(StoreError::Random)?;
+    Ok(hex(&bytes))
+}
+
+fn hash(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(hex, "{byte:02x}");
+    }
+    hex
+}
+
+fn read_changes(path: &Path) -> Result<Vec<StoredChange>, StoreError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+fn write_changes(path: &Path, changes: &[StoredChange]) -> Result<(), StoreError> {
+    let tmp_path = path.with_extension("jsonl.tmp");
+    let _ = fs::remove_file(&tmp_path);
+    append_changes(&tmp_path, changes)?;
+    if changes.is_empty() {
+        fs::write(&tmp_path, "")?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn append_changes(path: &Path, changes: &[StoredChange]) -> Result<(), StoreError> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    let mut lines = String::new();
+    for change in changes {
+        lines.push_str(&serde_json::to_string(change)?);
+        lines.push('\n');
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(lines.as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use undersea_lib::sync::EpisodeRef;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("undersea-server-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn progress(secs: u64) -> JournalEntry {
+        JournalEntry {
+            at: Utc::now(),
+            change: Change::Progress {
+                show: "https://example.com/feed".into(),
+                episode: EpisodeRef {
+                    id: "1".into(),
+                    media_url: "https://example.com/1.mp3".into(),
+                    duration: None,
+                },
+                position: Duration::from_secs(secs),
+                played_at: None,
+            },
+        }
+    }
+
+    #[test]
+    fn tokens() {
+        let dir = temp_dir("tokens");
+        let mut store = Store::open(&dir).unwrap();
+
+        let token = store.add_user("alice").unwrap();
+        assert_eq!(store.user_for_token(&token), Some("alice"));
+        assert!(matches!(
+            store.add_user("alice"),
+            Err(StoreError::UserExists(_))
+        ));
+        assert!(matches!(
+            store.add_user("../alice"),
+            Err(StoreError::InvalidName(_))
+        ));
+
+        let new_token = store.reset_token("alice").unwrap();
+        assert_eq!(store.user_for_token(&token), None);
+        assert_eq!(store.user_for_token(&new_token), Some("alice"));
+
+        // the token itself is never written down
+        let users = fs::read_to_string(dir.join(USERS_FILE_NAME)).unwrap();
+        assert!(!users.contains(&new_token));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compacted_on_open() {
+        let dir = temp_dir("compact");
+        let mut store = Store::open(&dir).unwrap();
+        store.add_user("alice").unwrap();
+        for secs in 1..=10 {
+            store.push("alice", "laptop", vec![progress(secs)]).unwrap();
+        }
+
+        let store = Store::open(&dir).unwrap();
+        let (changes, seq) = store.pull("alice", "phone", 0);
+        assert_eq!(
+            changes,
+            [progress(10)].map(|entry| JournalEntry {
+                at: changes[0].at,
+                ..entry
+            })
+        );
+        // sequence numbers carry on from where they were
+        assert_eq!(seq, 10);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+//! Run the server on a random local port, and sync devices through it with
+//! the library's [`ServerSync`].
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use axum::{Router, http::header, routing::get};
+use tokio::net::TcpListener;
+use undersea_lib::{
+    Shows, SyncError,
+    sync::server::{ServerConfig, ServerState, ServerSync},
+};
+use undersea_server::Store;
+
+const LOST_TERMINAL: &str = include_str!("../../undersea-lib/tests/fixtures/lost_terminal.xml");
+const TUNNELS: &str = "https://api.spreaker.com/episode/39000001";
+const BUGS: &str = "https://api.spreaker.com/episode/39000002";
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "undersea-server-test-{name}-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn spawn(router: Router) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    addr
+}
+
+/// Serve the lost terminal fixture, returning its url.
+async fn feed() -> String {
+    let router = Router::new().route(
+        "/lost_terminal.xml",
+        get(|| async {
+            (
+                [(header::CONTENT_TYPE, "application/rss+xml")],
+                LOST_TERMINAL,
+            )
+        }),
+    );
+    format!("http://{}/lost_terminal.xml", spawn(router).await)
+}
+
+/// Start a server with one user, returning its url and their token.
+async fn server(dir: &Path) -> (String, String) {
+    let mut store = Store::open(dir).unwrap();
+    let token = store.add_user("alice").unwrap();
+    let addr = spawn(undersea_server::router(store)).await;
+    (format!("http://{addr}"), token)
+}
+
+fn device(url: &str, token: &str, name: &str) -> ServerSync {
+    let config = ServerConfig {
+        url: url.to_string(),
+        token: token.to_string(),
+        device: name.to_string(),
+    };
+    ServerSync::new(config, reqwest::Client::new(), ServerState::default())
+}
+
+#[tokio::test]
+async fn syncs_between_devices() {
+    let dir = temp_dir("sync");
+    let (url, token) = server(&dir).await;
+
+    let mut laptop_shows = Shows::default();
+    laptop_shows.add(feed().await).await.expect("to add show");
+    laptop_shows
+        .set_resume_time(TUNNELS, Duration::from_mins(2))
+        .unwrap();
+    laptop_shows.enqueue(BUGS).unwrap();
+    let mut laptop = device(&url, &token, "laptop");
+    let report = laptop_shows.sync(&mut laptop).await.expect("to sync");
+    assert_eq!(report.pushed, 3);
+
+    let mut phone_shows = Shows::default();
+    let mut phone = device(&url, &token, "phone");
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    assert_eq!(phone_shows.shows().len(), 1);
+    let (_, tunnels) = phone_shows.episode_by_id(TUNNELS).unwrap();
+    assert_eq!(*tunnels.resume_time(), Duration::from_mins(2));
+    assert_eq!(phone_shows.queue().len(), 1);
+    assert_eq!(phone.state().since, 3);
+
+    // and back again, without the laptop getting its own changes
+    phone_shows.set_finished(BUGS, true).unwrap();
+    phone_shows.sync(&mut phone).await.expect("to sync");
+    let report = laptop_shows.sync(&mut laptop).await.expect("to sync");
+    assert_eq!(report.pulled, 1);
+    assert!(laptop_shows.episode_by_id(BUGS).unwrap().1.finished());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn wrong_token_is_rejected() {
+    let dir = temp_dir("token");
+    let (url, _) = server(&dir).await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{url}/api/v1/changes?device=laptop"))
+        .bearer_auth("wrong")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let mut shows = Shows::default();
+    shows.add(feed().await).await.expect("to add show");
+    let err = shows
+        .sync(&mut device(&url, "wrong", "laptop"))
+        .await
+        .expect_err("to fail");
+    assert!(matches!(err, SyncError::NetworkError(_)));
+    assert_eq!(shows.journal().len(), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn changes_survive_a_restart() {
+    let dir = temp_dir("restart");
+    let (url, token) = server(&dir).await;
+
+    let mut laptop_shows = Shows::default();
+    laptop_shows.add(feed().await).await.expect("to add show");
+    let mut laptop = device(&url, &token, "laptop");
+    laptop_shows.sync(&mut laptop).await.expect("to sync");
+    for minute in 1..=5 {
+        laptop_shows
+            .set_resume_time(TUNNELS, Duration::from_mins(minute))
+            .unwrap();
+        laptop_shows.sync(&mut laptop).await.expect("to sync");
+    }
+
+    // a new server on the same directory, the token still works
+    let addr = spawn(undersea_server::router(Store::open(&dir).unwrap())).await;
+    let mut phone_shows = Shows::default();
+    let mut phone = device(&format!("http://{addr}"), &token, "phone");
+    let report = phone_shows.sync(&mut phone).await.expect("to sync");
+    // only the latest progress is kept
+    assert_eq!(report.pulled, 2);
+    let (_, tunnels) = phone_shows.episode_by_id(TUNNELS).unwrap();
+    assert_eq!(*tunnels.resume_time(), Duration::from_mins(5));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
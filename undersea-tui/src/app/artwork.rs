@@ -0,0 +1,148 @@
+use std::{fs, io};
+
+use image::RgbaImage;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    widgets::{Paragraph, Wrap},
+};
+use tokio::sync::oneshot::{self, error::TryRecvError};
+use undersea_lib::{ArtworkError, artwork::ArtworkCache};
+
+use crate::{
+    graphics::{self, Protocol},
+    theme::Theme,
+    widgets::artwork::ArtworkWidget,
+};
+
+/// Artwork is cached at this size, which is plenty for the episode info pane.
+const SIZE: u32 = 512;
+
+/// Artwork for the selected episode, and where it is on the screen.
+pub struct Artwork {
+    cache: ArtworkCache,
+    protocol: Protocol,
+    /// Size of a cell in pixels
+    cell: (u32, u32),
+    /// Url of the artwork that should be shown
+    url: Option<String>,
+    /// The artwork being downloaded in the background
+    pending: Option<oneshot::Receiver<Result<Loaded, ArtworkError>>>,
+    loaded: Option<Loaded>,
+    /// Where the artwork was drawn in the last frame
+    drawn: Option<Rect>,
+    /// Where the artwork was last written to the terminal, if the protocol
+    /// writes it
+    placed: Option<Rect>,
+}
+
+struct Loaded {
+    png: Vec<u8>,
+    image: RgbaImage,
+}
+
+impl Artwork {
+    pub fn new(cache: ArtworkCache, protocol: Protocol) -> Self {
+        Self {
+            cache,
+            protocol,
+            cell: graphics::cell_size(),
+            url: None,
+            pending: None,
+            loaded: None,
+            drawn: None,
+            placed: None,
+        }
+    }
+
+    /// Show the artwork at `url`, downloading it in the background if it is
+    /// not cached. Artwork that can not be loaded is not shown, a missing
+    /// picture is not worth interrupting anyone over.
+    pub fn load(&mut self, url: Option<&str>) {
+        if self.url.as_deref() == url {
+            return;
+        }
+        self.url = url.map(ToString::to_string);
+        self.placed = None;
+        self.loaded = None;
+        // the download of artwork no longer wanted still finishes, and is
+        // cached for next time
+        self.pending = url.map(|url| {
+            let (sender, receiver) = oneshot::channel();
+            let cache = self.cache.clone();
+            let url = url.to_string();
+            tokio::spawn(async move {
+                let _ = sender.send(fetch(&cache, &url).await);
+            });
+            receiver
+        });
+    }
+
+    /// Draw the artwork into `area`, or say it is on its way, returning
+    /// false if there is nothing to draw.
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect, theme: &Theme) -> bool {
+        self.poll();
+        if self.pending.is_some() {
+            let placeholder = Paragraph::new("loading artwork…")
+                .style(theme.text_dim())
+                .wrap(Wrap { trim: true });
+            frame.render_widget(placeholder, area);
+            return true;
+        }
+        match self.widget(area) {
+            Some(widget) => {
+                frame.render_widget(widget, area);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pick up the artwork once it has been downloaded.
+    fn poll(&mut self) {
+        let Some(pending) = &mut self.pending else {
+            return;
+        };
+        match pending.try_recv() {
+            Ok(loaded) => {
+                self.loaded = loaded.ok();
+                self.placed = None;
+                self.pending = None;
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Closed) => self.pending = None,
+        }
+    }
+
+    /// A widget drawing the artwork into `area`, remembering where it went.
+    fn widget(&mut self, area: Rect) -> Option<ArtworkWidget<'_>> {
+        let loaded = self.loaded.as_ref()?;
+        self.drawn = Some(graphics::fit(&loaded.image, area, self.cell));
+        Some(ArtworkWidget::new(&loaded.image, self.protocol, self.cell))
+    }
+
+    /// Write the artwork to the terminal once a frame has been drawn, if it
+    /// moved or changed, or remove it if it was not drawn.
+    pub fn after_draw(&mut self) -> io::Result<()> {
+        let drawn = self.drawn.take();
+        if drawn == self.placed || !self.protocol.is_escape() {
+            return Ok(());
+        }
+
+        match (drawn, &self.loaded) {
+            (Some(area), Some(loaded)) if !area.is_empty() => {
+                graphics::place(self.protocol, &loaded.png, &loaded.image, area, self.cell)?;
+            }
+            _ => graphics::clear(self.protocol)?,
+        }
+        self.placed = drawn;
+        Ok(())
+    }
+}
+
+async fn fetch(cache: &ArtworkCache, url: &str) -> Result<Loaded, ArtworkError> {
+    let path = cache.scaled(url, SIZE).await?;
+    let png = fs::read(path)?;
+    let image = image::load_from_memory(&png)?.into_rgba8();
+    Ok(Loaded { png, image })
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use ratatui::{crossterm::event::KeyCode, prelude::*};
+use undersea_lib::{bookmark::Bookmark, player::format_time};
+
+use crate::theme::Theme;
+
+/// A bookmark at the playing position, while its note is typed.
+pub struct BookmarkInput {
+    episode: String,
+    position: Duration,
+    note: String,
+}
+
+/// What a key press while typing a note asked for.
+pub enum InputKey {
+    Typing,
+    Cancelled,
+    /// Save the bookmark to the episode with this id
+    Done(String, Bookmark),
+}
+
+impl BookmarkInput {
+    pub fn new(episode: &str, position: Duration) -> Self {
+        Self {
+            episode: episode.to_string(),
+            position,
+            note: String::new(),
+        }
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> InputKey {
+        match code {
+            KeyCode::Char(c) => self.note.push(c),
+            KeyCode::Backspace => {
+                self.note.pop();
+            }
+            KeyCode::Enter => {
+                let bookmark = Bookmark::new(self.position, None, &self.note);
+                return InputKey::Done(std::mem::take(&mut self.episode), bookmark);
+            }
+            KeyCode::Esc => return InputKey::Cancelled,
+            _ => {}
+        }
+        InputKey::Typing
+    }
+
+    /// The line shown in place of what is playing while the note is typed.
+    pub fn line(&self, theme: &Theme) -> Line<'static> {
+        Line::from(vec![
+            Span::from(format!(" bookmark at {}: ", format_time(self.position)))
+                .style(theme.text_dim()),
+            Span::from(format!("{}▏", self.note)).style(theme.text()),
+        ])
+    }
+}
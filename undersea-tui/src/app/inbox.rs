@@ -0,0 +1,68 @@
+use chrono::{DateTime, Utc};
+use ratatui::crossterm::event::KeyCode;
+use undersea_lib::InboxFilter;
+
+/// Filters for the inbox, which can be toggled while it is selected.
+pub struct Inbox {
+    /// When the inbox was looked at before this run of the app
+    last_visit: Option<DateTime<Utc>>,
+    new_only: bool,
+    unplayed_only: bool,
+    downloaded_only: bool,
+}
+
+impl Inbox {
+    pub fn new(last_visit: Option<DateTime<Utc>>) -> Self {
+        Self {
+            last_visit,
+            new_only: false,
+            unplayed_only: true,
+            downloaded_only: false,
+        }
+    }
+
+    /// The filter for the inbox, only with shows that have `tag` if given.
+    pub fn filter(&self, tag: Option<&str>) -> InboxFilter {
+        InboxFilter {
+            since: self.last_visit.filter(|_| self.new_only),
+            unplayed_only: self.unplayed_only,
+            downloaded_only: self.downloaded_only,
+            show: None,
+            tag: tag.map(ToString::to_string),
+        }
+    }
+
+    /// Title for the episode list, naming the filters that are on.
+    pub fn title(&self) -> String {
+        let filters = [
+            (self.new_only, "new"),
+            (self.unplayed_only, "unplayed"),
+            (self.downloaded_only, "downloaded"),
+        ]
+        .into_iter()
+        .filter_map(|(on, name)| on.then_some(name))
+        .collect::<Vec<_>>();
+
+        if filters.is_empty() {
+            "inbox".to_string()
+        } else {
+            format!("inbox: {}", filters.join(", "))
+        }
+    }
+
+    /// Toggle a filter, returns if the key was used.
+    ///
+    /// - `n` only episodes released since the last visit
+    /// - `u` only unplayed episodes
+    /// - `d` only downloaded episodes
+    pub fn handle_key(&mut self, code: KeyCode) -> bool {
+        let filter = match code {
+            KeyCode::Char('n') => &mut self.new_only,
+            KeyCode::Char('u') => &mut self.unplayed_only,
+            KeyCode::Char('d') => &mut self.downloaded_only,
+            _ => return false,
+        };
+        *filter = !*filter;
+        true
+    }
+}
@@ -1,18 +1,71 @@
+use std::time::Duration;
+
+use chrono::{Local, Utc};
 use ratatui::{
     crossterm::event::{self, KeyCode, KeyEvent, KeyEventKind},
     prelude::*,
     widgets::{Block, BorderType, ListState},
 };
-use style::Stylize;
-use undersea_lib::Shows;
+use tokio::runtime::Handle;
+use undersea_lib::{Episode, InboxFilter, Show, Shows, player::Control, sync::SyncStatus};
 
-use crate::widgets::{
-    episode_info::EpisodeInfoWidget, episodes::EpisodesWidget, shows::ShowsWidget,
+use crate::{
+    theme::Theme,
+    widgets::{
+        episode_info::EpisodeInfoWidget,
+        episodes::EpisodesWidget,
+        now_playing::NowPlayingWidget,
+        shows::{ShowsWidget, SidebarRow},
+        stats::StatsWidget,
+    },
 };
 
+mod artwork;
+mod bookmarks;
+mod inbox;
+mod playback;
+mod remote;
+mod sidebar;
+mod sync;
+mod transcript;
+
+pub use artwork::Artwork;
+use bookmarks::{BookmarkInput, InputKey};
+use inbox::Inbox;
+pub use playback::{Playback, Unavailable};
+pub use remote::Remote;
+use sidebar::Sidebar;
+pub use sync::Syncer;
+use transcript::PaneKey;
+pub use transcript::TranscriptPane;
+
+/// How often the player is checked on when no keys are pressed.
+const TICK: Duration = Duration::from_millis(250);
+/// How much `[` and `]` change the playback speed by.
+const SPEED_STEP: f32 = 0.1;
+
 pub struct App {
     shows: Shows,
+    theme: Theme,
+    syncer: Syncer,
+    playback: Playback,
+    /// Answers the control socket, if it could be opened
+    remote: Option<Remote>,
+    /// Artwork for the selected episode, if artwork is turned on
+    artwork: Option<Artwork>,
+    transcript: TranscriptPane,
+    /// A bookmark being made, while its note is typed
+    bookmark_input: Option<BookmarkInput>,
+    inbox: Inbox,
+    sidebar: Sidebar,
+    /// If the stats screen is shown instead of the shows
+    show_stats: bool,
+    /// If the transcript of the playing episode is shown instead of the
+    /// episodes
+    show_transcript: bool,
     selected_episode: Option<usize>,
+    /// The bookmark picked in the info of the selected episode
+    selected_bookmark: Option<usize>,
     selection_state: SelectionState,
     show_list_state: ListState,
     episode_list_state: ListState,
@@ -26,18 +79,36 @@ enum SelectionState {
 }
 
 impl App {
-    pub async fn new() -> Self {
-        let mut shows = Shows::default();
-        shows.add_multiple(super::TESTING_URLS).await.unwrap();
-
+    pub fn new(
+        shows: Shows,
+        theme: Theme,
+        syncer: Syncer,
+        playback: Playback,
+        remote: Option<Remote>,
+        artwork: Option<Artwork>,
+        transcript: TranscriptPane,
+    ) -> Self {
         let show_list_state = ListState::default().with_selected(Some(0));
 
         let episode_list_state = ListState::default();
+        let inbox = Inbox::new(shows.inbox_visited_at().copied());
 
         App {
             shows,
+            theme,
+            syncer,
+            playback,
+            remote,
+            artwork,
+            transcript,
+            bookmark_input: None,
+            inbox,
+            sidebar: Sidebar::default(),
+            show_stats: false,
+            show_transcript: false,
             exit: false,
             selected_episode: None,
+            selected_bookmark: None,
             selection_state: SelectionState::Shows,
             show_list_state,
             episode_list_state,
@@ -47,33 +118,63 @@ impl App {
     pub fn run(&mut self, terminal: &mut ratatui::DefaultTerminal) -> anyhow::Result<()> {
         while !self.exit {
             terminal.draw(|frame| self.draw(frame))?;
-            self.handle_events()?;
+            if let Some(artwork) = &mut self.artwork {
+                artwork.after_draw()?;
+            }
+
+            // sync once the status has been drawn, the ui waits for it
+            if *self.syncer.status() == SyncStatus::Syncing {
+                tokio::task::block_in_place(|| {
+                    Handle::current().block_on(self.syncer.sync(&mut self.shows));
+                });
+                continue;
+            }
+
+            if self.playback.tick(&mut self.shows) {
+                self.exit();
+            }
+            if let Some(remote) = &mut self.remote {
+                remote.tick(&mut self.shows, &mut self.playback);
+            }
+            if self.show_transcript {
+                let player = self.playback.player();
+                self.transcript.load(&self.shows, player.episode_id());
+                self.transcript.follow(player.position());
+            }
+            if event::poll(TICK)? {
+                self.handle_events()?;
+            }
         }
         Ok(())
     }
 
     fn draw(&mut self, frame: &mut Frame) {
+        let area = self.draw_now_playing(frame);
+
+        if self.show_stats {
+            let stats = self.playback.player().history().stats(&Local::now());
+            let widget = StatsWidget::new(&stats, &self.shows, &self.theme);
+            frame.render_widget(widget, area);
+            return;
+        }
+
         let layout = Layout::new(
             Direction::Horizontal,
             Constraint::from_percentages([25, 75]),
         )
-        .split(frame.area());
+        .split(area);
         let sidebar = layout[0];
         let main = layout[1];
-        let border_style = Style::new().blue();
+        let theme = &self.theme;
 
         // Sidebar: Show selection
-        let block = match self.selection_state {
-            SelectionState::Shows => Block::bordered()
-                .style(border_style)
-                .border_type(BorderType::Thick)
-                .title(Line::from(" shows ").blue().bold()),
-            SelectionState::Episodes => Block::bordered()
-                .style(border_style)
-                .title(Line::from(" shows ").blue()),
-        };
-
-        let shows_widget = ShowsWidget::new(&self.shows);
+        let block = self.sidebar_block();
+        let rows = self.sidebar.rows(&self.shows);
+        let inbox_count = self
+            .shows
+            .inbox(&self.inbox.filter(self.sidebar.tag()))
+            .len();
+        let shows_widget = ShowsWidget::new(&rows, inbox_count, theme);
         frame.render_widget(&block, sidebar);
         frame.render_stateful_widget(
             shows_widget,
@@ -81,6 +182,11 @@ impl App {
             &mut self.show_list_state,
         );
 
+        if self.show_transcript {
+            self.draw_transcript(frame, main);
+            return;
+        }
+
         // Main: episodes list
         // if an episode is selected split off a footer
         let (main, footer) = if self.selected_episode.is_some() {
@@ -93,84 +199,325 @@ impl App {
             (main, main)
         };
 
-        if let Some(selected_episode_id) = self.selected_episode {
-            let episode = self
-                .shows
-                .get_show_by_index(self.show_list_state.selected().unwrap())
-                .unwrap()
-                .episode_by_index(selected_episode_id)
-                .unwrap();
+        let row = self
+            .show_list_state
+            .selected()
+            .and_then(|index| rows.get(index));
+        let episodes =
+            row.map(|row| listed_episodes(&self.shows, &self.inbox, self.sidebar.tag(), row));
 
+        if let Some((_, episode)) = self
+            .selected_episode
+            .zip(episodes.as_ref())
+            .and_then(|(index, episodes)| episodes.get(index))
+        {
             let block_title = episode.title();
 
             let block = Block::bordered()
-                .title(Line::from(block_title).blue().bold())
-                .border_style(Style::new().blue());
-
-            let widget = EpisodeInfoWidget::new(episode);
+                .title(Line::from(block_title).style(theme.title(true)))
+                .border_style(theme.border(false));
 
-            frame.render_widget(widget, block.inner(footer));
+            let widget =
+                EpisodeInfoWidget::new(episode, &self.shows, self.selected_bookmark, theme);
+            let mut info = block.inner(footer);
             frame.render_widget(block, footer);
-        };
 
-        let block_title = self
-            .show_list_state
-            .selected()
-            .and_then(|index| self.shows.get_show_by_index(index))
-            .map_or(" ... ", |show| show.name());
+            // artwork is about square, and cells are about twice as tall as wide
+            if let Some(artwork) = &mut self.artwork {
+                let [art, _, rest] = Layout::horizontal([
+                    Constraint::Length(info.height * 2),
+                    Constraint::Length(1),
+                    Constraint::Min(20),
+                ])
+                .areas(info);
+                if artwork.draw(frame, art, theme) {
+                    info = rest;
+                }
+            }
+            frame.render_widget(widget, info);
+        }
+
+        let block_title = self.episodes_title(row);
+        // everything but a show has episodes from several shows, so say which
+        let show_names = !matches!(row, Some(SidebarRow::Show { .. }) | None);
 
         let block = match self.selection_state {
             SelectionState::Shows => Block::bordered()
-                .style(border_style)
-                .title(Line::from(block_title).blue()),
+                .style(theme.border(false))
+                .title(Line::from(block_title).style(theme.title(false))),
             SelectionState::Episodes => Block::bordered()
-                .style(border_style)
+                .style(theme.border(true))
                 .border_type(BorderType::Thick)
-                .title(Line::from(format!(" {block_title} ")).blue().bold()),
+                .title(Line::from(format!(" {block_title} ")).style(theme.title(true))),
         };
 
         // render block around the episodes widget
         frame.render_widget(&block, main);
 
-        if let Some(show) = self
-            .show_list_state
-            .selected()
-            .and_then(|index| self.shows.get_show_by_index(index))
-        {
-            let episodes = show.episodes();
-            let episodes_widget = EpisodesWidget::new(&episodes, self.selected_episode);
+        if let Some(episodes) = episodes {
+            let episodes_widget =
+                EpisodesWidget::new(&episodes, self.selected_episode, show_names, theme);
             frame.render_stateful_widget(
                 episodes_widget,
                 block.inner(main),
                 &mut self.episode_list_state,
             );
         } else {
-            let no_episode_found = Line::from("no episodes!").bold().red();
+            let no_episode_found = Line::from("no episodes!").style(theme.error());
             frame.render_widget(no_episode_found, block.inner(main));
         }
     }
 
+    /// The title of the episodes list for a row in the sidebar.
+    fn episodes_title(&self, row: Option<&SidebarRow>) -> String {
+        match row {
+            Some(SidebarRow::Inbox) => self.inbox.title(),
+            Some(SidebarRow::Playlist(playlist)) => playlist.name.clone(),
+            Some(SidebarRow::Folder { tag, .. }) => (*tag).to_string(),
+            Some(SidebarRow::Show { show, .. }) => show.name().to_string(),
+            None => " ... ".to_string(),
+        }
+    }
+
+    /// Draw the transcript of the playing episode where the episodes go.
+    fn draw_transcript(&mut self, frame: &mut Frame, area: Rect) {
+        let theme = &self.theme;
+        let block = Block::bordered()
+            .style(theme.border(true))
+            .border_type(BorderType::Thick)
+            .title(Line::from(" transcript ").style(theme.title(true)));
+        frame.render_widget(&block, area);
+        let (widget, state) = self.transcript.widget(theme);
+        frame.render_stateful_widget(widget, block.inner(area), state);
+    }
+
+    /// Draw a line along the bottom for what is playing, if anything, and
+    /// return the area left above it.
+    fn draw_now_playing(&self, frame: &mut Frame) -> Rect {
+        let player = self.playback.player();
+        let now_playing = player.now_playing(&self.shows);
+        let error = self.playback.error();
+        if now_playing.is_none() && error.is_none() {
+            return frame.area();
+        }
+
+        let [area, bar] =
+            Layout::vertical([Constraint::Min(5), Constraint::Length(1)]).areas(frame.area());
+        if let Some(input) = &self.bookmark_input {
+            frame.render_widget(input.line(&self.theme), bar);
+            return area;
+        }
+        let widget = NowPlayingWidget::new(player, now_playing, error, &self.theme);
+        frame.render_widget(widget, bar);
+        area
+    }
+
+    /// The block around the sidebar, with the tag being filtered by and the
+    /// sync status.
+    fn sidebar_block(&self) -> Block<'static> {
+        let theme = &self.theme;
+        let title = match self.sidebar.tag() {
+            Some(tag) => format!(" shows: {tag} "),
+            None => " shows ".to_string(),
+        };
+        let mut block = match self.selection_state {
+            SelectionState::Shows => Block::bordered()
+                .style(theme.border(true))
+                .border_type(BorderType::Thick)
+                .title(Line::from(title).style(theme.title(true))),
+            SelectionState::Episodes => Block::bordered()
+                .style(theme.border(false))
+                .title(Line::from(title).style(theme.title(false))),
+        };
+
+        if let Some(status) = self.syncer.describe(self.shows.journal().len()) {
+            let style = match self.syncer.status() {
+                SyncStatus::Failed(_) => theme.error(),
+                _ => theme.text_dim(),
+            };
+            block = block.title_bottom(Line::from(format!(" {status} ")).style(style));
+        }
+        block
+    }
+
+    /// Collapse or expand the folder that is selected in the sidebar.
+    fn toggle_folder(&mut self) {
+        let rows = self.sidebar.rows(&self.shows);
+        let tag = match self
+            .show_list_state
+            .selected()
+            .and_then(|index| rows.get(index))
+        {
+            Some(SidebarRow::Folder { tag, .. }) => (*tag).to_string(),
+            _ => return,
+        };
+        self.sidebar.toggle(&tag);
+        self.selected_episode = None;
+    }
+
     fn select_hovered_episode(&mut self) {
         self.selected_episode = self.episode_list_state.selected();
+        self.selected_bookmark = None;
+
+        let Some(artwork) = &mut self.artwork else {
+            return;
+        };
+        let rows = self.sidebar.rows(&self.shows);
+        let episodes = self
+            .show_list_state
+            .selected()
+            .and_then(|index| rows.get(index))
+            .map(|row| listed_episodes(&self.shows, &self.inbox, self.sidebar.tag(), row));
+        let url = self
+            .selected_episode
+            .zip(episodes.as_ref())
+            .and_then(|(index, episodes)| episodes.get(index))
+            .and_then(|(show, episode)| show.artwork_for(episode));
+        artwork.load(url);
+    }
+
+    /// The episode hovered in the episodes list.
+    fn hovered_episode_id(&self) -> Option<String> {
+        let rows = self.sidebar.rows(&self.shows);
+        let row = rows.get(self.show_list_state.selected()?)?;
+        let episodes = listed_episodes(&self.shows, &self.inbox, self.sidebar.tag(), row);
+        let (_, episode) = episodes.get(self.episode_list_state.selected()?)?;
+        Some(episode.id().to_string())
+    }
+
+    /// The episode whose info is shown.
+    fn selected_episode(&self) -> Option<&Episode> {
+        let rows = self.sidebar.rows(&self.shows);
+        let row = rows.get(self.show_list_state.selected()?)?;
+        let episodes = listed_episodes(&self.shows, &self.inbox, self.sidebar.tag(), row);
+        let (_, episode) = episodes.get(self.selected_episode?)?;
+        Some(episode)
+    }
+
+    /// Start typing the note of a bookmark at the playing position.
+    fn start_bookmark(&mut self) {
+        let player = self.playback.player();
+        if let Some(id) = player.episode_id() {
+            self.bookmark_input = Some(BookmarkInput::new(id, player.position()));
+        }
+    }
+
+    fn handle_bookmark_key(&mut self, code: KeyCode) {
+        let Some(input) = &mut self.bookmark_input else {
+            return;
+        };
+        match input.handle_key(code) {
+            InputKey::Typing => {}
+            InputKey::Cancelled => self.bookmark_input = None,
+            InputKey::Done(id, bookmark) => {
+                self.bookmark_input = None;
+                // only fails if the episode went away while the note was typed
+                let _ = self.shows.add_bookmark(&id, bookmark);
+            }
+        }
+    }
+
+    /// Pick the next or previous bookmark of the selected episode, wrapping
+    /// around.
+    fn select_bookmark(&mut self, forward: bool) {
+        let count = self
+            .selected_episode()
+            .map_or(0, |episode| episode.bookmarks().len());
+        if count == 0 {
+            return;
+        }
+        self.selected_bookmark = Some(match (self.selected_bookmark, forward) {
+            (None, true) => 0,
+            (None, false) => count - 1,
+            (Some(index), true) => (index + 1) % count,
+            (Some(index), false) => (index + count - 1) % count,
+        });
+    }
+
+    /// Play the selected episode from the picked bookmark.
+    fn jump_to_bookmark(&mut self) {
+        let Some((id, start)) = self.selected_episode().and_then(|episode| {
+            let bookmark = episode.bookmarks().get(self.selected_bookmark?)?;
+            Some((episode.id().to_string(), bookmark.start))
+        }) else {
+            return;
+        };
+        if self.playback.player().episode_id() != Some(id.as_str()) {
+            self.playback.play(&mut self.shows, &id);
+            if self.playback.error().is_some() {
+                return;
+            }
+        }
+        self.playback
+            .control(&mut self.shows, Control::SetPosition(start));
     }
 
     fn exit(&mut self) {
+        self.playback.stop(&mut self.shows);
+        self.shows.mark_inbox_visited(Utc::now());
         self.exit = true;
     }
 
+    pub fn shows_mut(&mut self) -> &mut Shows {
+        &mut self.shows
+    }
+
     fn handle_events(&mut self) -> anyhow::Result<()> {
         match event::read()? {
             event::Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
                 self.handle_key_event(key_event);
             }
             _ => {}
-        };
+        }
         Ok(())
     }
 
     fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if self.show_transcript && self.transcript.is_typing() {
+            self.transcript.handle_key(key_event.code);
+            return;
+        }
+        if self.bookmark_input.is_some() {
+            self.handle_bookmark_key(key_event.code);
+            return;
+        }
+
+        let shows = &mut self.shows;
+        match key_event.code {
+            KeyCode::Char(' ') => self.playback.toggle(shows),
+            KeyCode::Char(',') => self.playback.skip(shows, false),
+            KeyCode::Char('.') => self.playback.skip(shows, true),
+            KeyCode::Char('[') => self.playback.change_speed(shows, -SPEED_STEP),
+            KeyCode::Char(']') => self.playback.change_speed(shows, SPEED_STEP),
+            KeyCode::Char('>') => self.playback.next(shows),
+            KeyCode::Char('z') => self.playback.cycle_sleep_timer(),
+            KeyCode::Char('Z') => self.playback.extend_sleep_timer(),
+            KeyCode::Char('b') => self.start_bookmark(),
+            _ => {}
+        }
+
         match key_event.code {
             KeyCode::Char('q') => self.exit(),
+            KeyCode::Char('s') => self.syncer.request(),
+            KeyCode::Char('t') => self.show_stats = !self.show_stats,
+            KeyCode::Char('r') => self.show_transcript = !self.show_transcript,
+            _ => {}
+        }
+
+        if self.show_transcript {
+            match self.transcript.handle_key(key_event.code) {
+                PaneKey::Unhandled => {}
+                PaneKey::Handled => return,
+                PaneKey::Seek(start) => {
+                    self.playback
+                        .control(&mut self.shows, Control::SetPosition(start));
+                    return;
+                }
+            }
+        }
+
+        match key_event.code {
+            KeyCode::Esc => self.show_stats = false,
             KeyCode::Char('h') => {
                 self.selection_state = SelectionState::Shows;
                 self.episode_list_state.select(None);
@@ -182,15 +529,29 @@ impl App {
             _ => {}
         }
 
+        if self.show_list_state.selected() == Some(0) && self.inbox.handle_key(key_event.code) {
+            self.selected_episode = None;
+            self.episode_list_state.select(Some(0));
+            return;
+        }
+
+        if key_event.code == KeyCode::Char('f') {
+            self.sidebar.next_tag(&self.shows);
+            self.show_list_state.select(Some(0));
+            self.selected_episode = None;
+            return;
+        }
+
         if self.selection_state == SelectionState::Shows {
             match key_event.code {
+                KeyCode::Enter => self.toggle_folder(),
                 KeyCode::Char('j') => {
                     self.show_list_state.select_next();
-                    self.selected_episode = None
+                    self.selected_episode = None;
                 }
                 KeyCode::Char('k') => {
                     self.show_list_state.select_previous();
-                    self.selected_episode = None
+                    self.selected_episode = None;
                 }
                 _ => {}
             }
@@ -201,8 +562,45 @@ impl App {
                 KeyCode::Char('j') => self.episode_list_state.select_next(),
                 KeyCode::Char('k') => self.episode_list_state.select_previous(),
                 KeyCode::Enter => self.select_hovered_episode(),
+                KeyCode::Char('}') => self.select_bookmark(true),
+                KeyCode::Char('{') => self.select_bookmark(false),
+                KeyCode::Char('g') => self.jump_to_bookmark(),
+                KeyCode::Char('p') => {
+                    if let Some(id) = self.hovered_episode_id() {
+                        self.playback.play(&mut self.shows, &id);
+                    }
+                }
                 _ => {}
             }
         }
     }
 }
+
+/// Episodes to list for a row in the sidebar. Everything but a single show
+/// only has episodes from shows with `tag`, if it is given.
+fn listed_episodes<'a>(
+    shows: &'a Shows,
+    inbox: &Inbox,
+    tag: Option<&str>,
+    row: &SidebarRow<'a>,
+) -> Vec<(&'a Show, &'a Episode)> {
+    match row {
+        SidebarRow::Inbox => shows.inbox(&inbox.filter(tag)),
+        SidebarRow::Playlist(playlist) => {
+            let mut episodes = shows.query(&playlist.query);
+            if let Some(tag) = tag {
+                episodes.retain(|(show, _)| show.has_tag(tag));
+            }
+            episodes
+        }
+        SidebarRow::Folder { tag, .. } => shows.inbox(&InboxFilter {
+            tag: Some((*tag).to_string()),
+            ..InboxFilter::default()
+        }),
+        SidebarRow::Show { show, .. } => show
+            .visible_episodes()
+            .into_iter()
+            .map(|episode| (*show, episode))
+            .collect(),
+    }
+}
@@ -0,0 +1,210 @@
+use std::time::Duration;
+
+use tokio::{runtime::Handle, sync::mpsc::UnboundedReceiver};
+use undersea_lib::{
+    Config, PlayerError, Shows,
+    player::{Backend, Control, Player, SleepTimer, SleepUntil, dsp::AudioSettings, mpris::Mpris},
+};
+
+/// The sleep timers to pick from, in order.
+const SLEEP_TIMERS: [SleepUntil; 5] = [
+    SleepUntil::After(Duration::from_mins(15)),
+    SleepUntil::After(Duration::from_mins(30)),
+    SleepUntil::After(Duration::from_hours(1)),
+    SleepUntil::EndOfChapter,
+    SleepUntil::EndOfEpisode,
+];
+
+/// How much longer extending the sleep timer gives it.
+const SLEEP_EXTEND: Duration = Duration::from_mins(5);
+
+/// Plays episodes for the app, keeping D-Bus up to date and holding on to
+/// the last error so it can be shown.
+pub struct Playback {
+    player: Player,
+    mpris: Option<(Mpris, UnboundedReceiver<Control>)>,
+    skip_forward: Duration,
+    skip_back: Duration,
+    default_speed: f32,
+    sleep_fade: Duration,
+    sleep_rewind: Duration,
+    /// What went wrong with the last thing the player was asked to do
+    error: Option<String>,
+}
+
+impl Playback {
+    pub fn new(player: Player, config: &Config) -> Self {
+        Self {
+            player,
+            mpris: None,
+            skip_forward: config.skip_forward,
+            skip_back: config.skip_back,
+            default_speed: config.playback_speed,
+            sleep_fade: config.sleep_fade,
+            sleep_rewind: config.sleep_rewind,
+            error: None,
+        }
+    }
+
+    /// Take calls from media keys and desktop widgets.
+    pub fn with_mpris(mut self, mpris: (Mpris, UnboundedReceiver<Control>)) -> Self {
+        self.mpris = Some(mpris);
+        self
+    }
+
+    pub fn player(&self) -> &Player {
+        &self.player
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn play(&mut self, shows: &mut Shows, id: &str) {
+        self.error = None;
+        let result = self.player.play(shows, id, self.default_speed);
+        self.report(result);
+    }
+
+    pub fn toggle(&mut self, shows: &mut Shows) {
+        self.control(shows, Control::PlayPause);
+    }
+
+    pub fn skip(&mut self, shows: &mut Shows, forward: bool) {
+        let by = if forward {
+            self.skip_forward
+        } else {
+            self.skip_back
+        };
+        self.control(shows, Control::Seek { forward, by });
+    }
+
+    pub fn change_speed(&mut self, shows: &mut Shows, by: f32) {
+        self.error = None;
+        let speed = self.player.speed() + by;
+        let result = self.player.set_speed(shows, speed);
+        self.report(result);
+    }
+
+    pub fn next(&mut self, shows: &mut Shows) {
+        self.control(shows, Control::Next);
+    }
+
+    /// Set the next sleep timer in [`SLEEP_TIMERS`], or turn it off after the
+    /// last one.
+    pub fn cycle_sleep_timer(&mut self) {
+        let next = match self.player.sleep_timer() {
+            Some(timer) => SLEEP_TIMERS
+                .iter()
+                .position(|until| *until == timer.until)
+                .and_then(|index| SLEEP_TIMERS.get(index + 1)),
+            None => SLEEP_TIMERS.first(),
+        };
+        let timer = next.map(|&until| SleepTimer {
+            until,
+            fade: self.sleep_fade,
+            rewind: self.sleep_rewind,
+        });
+        self.error = None;
+        let result = self.player.set_sleep_timer(timer);
+        self.report(result);
+    }
+
+    pub fn extend_sleep_timer(&mut self) {
+        self.player.extend_sleep_timer(SLEEP_EXTEND);
+    }
+
+    /// Stop playing, saving where the episode was left off.
+    pub fn stop(&mut self, shows: &mut Shows) {
+        self.control(shows, Control::Stop);
+    }
+
+    /// Check on the player and handle anything asked over D-Bus, this is
+    /// called every time around the event loop. Returns true if D-Bus asked
+    /// the app to quit.
+    pub fn tick(&mut self, shows: &mut Shows) -> bool {
+        let result = self.player.update(shows, self.default_speed);
+        self.report(result);
+
+        let mut controls = Vec::new();
+        if let Some((_, receiver)) = &mut self.mpris {
+            while let Ok(control) = receiver.try_recv() {
+                controls.push(control);
+            }
+        }
+        let mut quit = false;
+        for control in controls {
+            quit |= control == Control::Quit;
+            self.control(shows, control);
+        }
+
+        if let Some((mpris, _)) = &mut self.mpris {
+            let result = tokio::task::block_in_place(|| {
+                Handle::current().block_on(mpris.update(&self.player, shows))
+            });
+            if let Err(err) = result {
+                self.error = Some(err.to_string());
+            }
+        }
+        quit
+    }
+
+    /// Do what was asked by a key, D-Bus or the control socket.
+    pub fn control(&mut self, shows: &mut Shows, control: Control) {
+        self.error = None;
+        let result = control.apply(&mut self.player, shows, self.default_speed);
+        self.report(result);
+    }
+
+    fn report(&mut self, result: Result<(), PlayerError>) {
+        if let Err(err) = result {
+            self.error = Some(err.to_string());
+        }
+    }
+}
+
+/// Stands in for a backend that could not be started, so the rest of the
+/// app still works.
+pub struct Unavailable(pub String);
+
+impl Backend for Unavailable {
+    fn load(&mut self, _: &str, _: Duration) -> Result<(), PlayerError> {
+        Err(PlayerError::Backend(self.0.clone()))
+    }
+
+    fn set_paused(&mut self, _: bool) -> Result<(), PlayerError> {
+        Ok(())
+    }
+
+    fn seek(&mut self, _: Duration) -> Result<(), PlayerError> {
+        Ok(())
+    }
+
+    fn set_speed(&mut self, _: f32) -> Result<(), PlayerError> {
+        Ok(())
+    }
+
+    fn position(&mut self) -> Result<Duration, PlayerError> {
+        Ok(Duration::ZERO)
+    }
+
+    fn at_end(&mut self) -> Result<bool, PlayerError> {
+        Ok(false)
+    }
+
+    fn set_volume(&mut self, _: f32) -> Result<(), PlayerError> {
+        Ok(())
+    }
+
+    fn set_audio(&mut self, _: &AudioSettings) -> Result<(), PlayerError> {
+        Ok(())
+    }
+
+    fn take_silence_saved(&mut self) -> Result<Duration, PlayerError> {
+        Ok(Duration::ZERO)
+    }
+
+    fn stop(&mut self) -> Result<(), PlayerError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use std::time::Instant;
+
+use serde_json::{Value, json};
+use tokio::{runtime::Handle, sync::mpsc::UnboundedReceiver};
+use undersea_lib::{
+    Shows,
+    control::{Call, Command, EpisodeSummary, Event, NowPlaying, RpcError, Server},
+    player::Control,
+};
+
+use super::Playback;
+
+/// Answers requests from the control socket and tells subscribers about
+/// changes, however they were made.
+pub struct Remote {
+    server: Server,
+    calls: UnboundedReceiver<Call>,
+    /// What subscribers were last told about
+    last: Option<NowPlaying>,
+    checked_at: Instant,
+    queue: Vec<String>,
+}
+
+impl Remote {
+    pub fn new(server: (Server, UnboundedReceiver<Call>), shows: &Shows) -> Self {
+        let (server, calls) = server;
+        Self {
+            server,
+            calls,
+            last: None,
+            checked_at: Instant::now(),
+            queue: queue_ids(shows),
+        }
+    }
+
+    /// Answer anything that was asked since the last time, then send events
+    /// for what changed. Called every time around the event loop.
+    pub fn tick(&mut self, shows: &mut Shows, playback: &mut Playback) {
+        while let Ok(call) = self.calls.try_recv() {
+            let result = self.answer(&call.command, shows, playback);
+            call.reply(result);
+        }
+
+        let now_playing = NowPlaying::new(playback.player(), shows);
+        let elapsed = self.checked_at.elapsed();
+        self.checked_at = Instant::now();
+        let changed = self
+            .last
+            .as_ref()
+            .is_none_or(|last| now_playing.changed_from(last, elapsed));
+        if changed {
+            self.server.notify(&Event::Status(now_playing.clone()));
+        }
+        self.last = Some(now_playing);
+
+        let queue = queue_ids(shows);
+        if queue != self.queue {
+            self.server.notify(&Event::Queue {
+                episodes: queued(shows),
+            });
+            self.queue = queue;
+        }
+    }
+
+    fn answer(
+        &mut self,
+        command: &Command,
+        shows: &mut Shows,
+        playback: &mut Playback,
+    ) -> Result<Value, RpcError> {
+        match command {
+            Command::Status => Ok(json!(NowPlaying::new(playback.player(), shows))),
+            Command::Queue => Ok(json!(queued(shows))),
+            Command::Play(Some(id)) => {
+                if shows.episode_by_id(id).is_none() {
+                    return Err(not_found(id));
+                }
+                playback.play(shows, id);
+                result(playback)
+            }
+            Command::Play(None) => {
+                playback.control(shows, Control::Play);
+                result(playback)
+            }
+            Command::Control(control) => {
+                playback.control(shows, control.clone());
+                result(playback)
+            }
+            Command::Enqueue(id) => match shows.enqueue(id) {
+                Ok(()) => Ok(Value::Null),
+                Err(_) => Err(not_found(id)),
+            },
+            Command::Add(url) => {
+                tokio::task::block_in_place(|| Handle::current().block_on(shows.add(url.clone())))
+                    .map_err(|err| RpcError::new(RpcError::FAILED, err.to_string()))?;
+                // new shows go on the end, and the feed may have moved
+                let (url, name) = shows.shows().last().map_or_else(
+                    || (url.clone(), String::new()),
+                    |show| (show.url().to_string(), show.name().to_string()),
+                );
+                self.server.notify(&Event::Added {
+                    url: url.clone(),
+                    name: name.clone(),
+                });
+                Ok(json!({ "url": url, "name": name }))
+            }
+        }
+    }
+}
+
+/// What to answer after asking the player to do something, which has
+/// already shown any error in the tui.
+fn result(playback: &Playback) -> Result<Value, RpcError> {
+    match playback.error() {
+        Some(error) => Err(RpcError::new(RpcError::FAILED, error)),
+        None => Ok(Value::Null),
+    }
+}
+
+fn not_found(id: &str) -> RpcError {
+    RpcError::new(RpcError::NOT_FOUND, format!("no episode with id \"{id}\""))
+}
+
+fn queued(shows: &Shows) -> Vec<EpisodeSummary> {
+    shows
+        .queue()
+        .into_iter()
+        .map(|(show, episode)| EpisodeSummary::new(show, episode))
+        .collect()
+}
+
+fn queue_ids(shows: &Shows) -> Vec<String> {
+    shows
+        .queue()
+        .into_iter()
+        .map(|(_, episode)| episode.id().to_string())
+        .collect()
+}
@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+
+use undersea_lib::Shows;
+
+use crate::widgets::shows::SidebarRow;
+
+/// Which tag folders are collapsed, and the tag the library is filtered by.
+#[derive(Default)]
+pub struct Sidebar {
+    /// Collapsed folders, by tag in lowercase
+    collapsed: HashSet<String>,
+    tag: Option<String>,
+}
+
+impl Sidebar {
+    /// The rows to list: the inbox, smart playlists, a folder for each tag
+    /// with the shows that have it, and then shows without tags. A show with
+    /// several tags is in several folders.
+    ///
+    /// While filtering by a tag only the shows with that tag are listed, with
+    /// no folders.
+    pub fn rows<'a>(&self, shows: &'a Shows) -> Vec<SidebarRow<'a>> {
+        let mut rows = vec![SidebarRow::Inbox];
+        rows.extend(shows.playlists().iter().map(SidebarRow::Playlist));
+
+        if let Some(tag) = &self.tag {
+            rows.extend(
+                shows
+                    .shows_tagged(tag)
+                    .into_iter()
+                    .map(|show| SidebarRow::Show {
+                        show,
+                        nested: false,
+                    }),
+            );
+            return rows;
+        }
+
+        for tag in shows.tags() {
+            let tagged = shows.shows_tagged(tag);
+            let collapsed = self.is_collapsed(tag);
+            rows.push(SidebarRow::Folder {
+                tag,
+                collapsed,
+                shows: tagged.len(),
+            });
+            if !collapsed {
+                rows.extend(
+                    tagged
+                        .into_iter()
+                        .map(|show| SidebarRow::Show { show, nested: true }),
+                );
+            }
+        }
+        rows.extend(
+            shows
+                .shows()
+                .into_iter()
+                .filter(|show| show.tags().is_empty())
+                .map(|show| SidebarRow::Show {
+                    show,
+                    nested: false,
+                }),
+        );
+        rows
+    }
+
+    fn is_collapsed(&self, tag: &str) -> bool {
+        self.collapsed.contains(&tag.to_lowercase())
+    }
+
+    /// Collapse a folder, or expand it if it is collapsed.
+    pub fn toggle(&mut self, tag: &str) {
+        let tag = tag.to_lowercase();
+        if !self.collapsed.remove(&tag) {
+            self.collapsed.insert(tag);
+        }
+    }
+
+    /// The tag everything is filtered by, if any.
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    /// Filter by the next tag, in order, and then by none.
+    pub fn next_tag(&mut self, shows: &Shows) {
+        let tags = shows.tags();
+        let next = match &self.tag {
+            None => tags.first(),
+            Some(current) => tags
+                .iter()
+                .position(|tag| tag.eq_ignore_ascii_case(current))
+                .and_then(|index| tags.get(index + 1)),
+        };
+        self.tag = next.map(ToString::to_string);
+    }
+}
@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use undersea_lib::{
+    Shows,
+    sync::{SyncBackend, SyncStatus},
+};
+
+/// Syncs the library with every backend in the config, and keeps track of
+/// how it went so it can be shown.
+pub struct Syncer {
+    backends: Vec<Box<dyn SyncBackend>>,
+    status: SyncStatus,
+    /// Where to save the library after syncing, [`None`] for the testing shows
+    library_path: Option<PathBuf>,
+}
+
+impl Syncer {
+    pub fn new(backends: Vec<Box<dyn SyncBackend>>, library_path: Option<PathBuf>) -> Self {
+        let status = if backends.is_empty() {
+            SyncStatus::Disabled
+        } else {
+            SyncStatus::Idle {
+                last_sync: backends
+                    .iter()
+                    .filter_map(|backend| backend.last_sync())
+                    .min(),
+            }
+        };
+
+        Self {
+            backends,
+            status,
+            library_path,
+        }
+    }
+
+    pub fn status(&self) -> &SyncStatus {
+        &self.status
+    }
+
+    /// Mark a sync as wanted, it is done by [`Syncer::sync`] after the next draw
+    /// so that the status can be seen while it runs.
+    pub fn request(&mut self) {
+        if self.status != SyncStatus::Disabled {
+            self.status = SyncStatus::Syncing;
+        }
+    }
+
+    pub async fn sync(&mut self, shows: &mut Shows) {
+        if self.status == SyncStatus::Disabled {
+            return;
+        }
+
+        self.status = SyncStatus::Idle {
+            last_sync: Some(Utc::now()),
+        };
+        for backend in &mut self.backends {
+            match shows.sync(backend.as_mut()).await {
+                Ok(report) => {
+                    if let Some((url, err)) = report.failed.first() {
+                        let url = shows.redact(url);
+                        self.status = SyncStatus::Failed(format!("failed to add {url}: {err}"));
+                    }
+                }
+                Err(err) => {
+                    self.status = SyncStatus::Failed(format!("{}: {err}", backend.name()));
+                }
+            }
+        }
+
+        if let Some(path) = &self.library_path
+            && let Err(err) = shows.save(path)
+        {
+            self.status = SyncStatus::Failed(format!("failed to save library: {err}"));
+        }
+        // feeds that moved on other devices take their credentials along
+        if self.library_path.is_some()
+            && let Err(err) = shows.credentials_mut().save()
+        {
+            self.status = SyncStatus::Failed(format!("failed to save credentials: {err}"));
+        }
+    }
+
+    /// A short description of the status, [`None`] if there is nothing to sync
+    /// with.
+    pub fn describe(&self, unsynced: usize) -> Option<String> {
+        let status = match &self.status {
+            SyncStatus::Disabled => return None,
+            SyncStatus::Syncing => "syncing...".to_string(),
+            SyncStatus::Failed(err) => format!("sync failed, {err}"),
+            SyncStatus::Idle { last_sync: None } => "not synced".to_string(),
+            SyncStatus::Idle {
+                last_sync: Some(last_sync),
+            } => format!("synced {}", ago(*last_sync)),
+        };
+
+        Some(match unsynced {
+            0 => status,
+            1 => format!("{status}, 1 change"),
+            _ => format!("{status}, {unsynced} changes"),
+        })
+    }
+}
+
+fn ago(time: DateTime<Utc>) -> String {
+    let elapsed = Utc::now() - time;
+    if elapsed.num_minutes() < 1 {
+        "just now".to_string()
+    } else if elapsed.num_hours() < 1 {
+        format!("{}m ago", elapsed.num_minutes())
+    } else if elapsed.num_days() < 1 {
+        format!("{}h ago", elapsed.num_hours())
+    } else {
+        format!("{}d ago", elapsed.num_days())
+    }
+}
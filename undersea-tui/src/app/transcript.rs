@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use ratatui::{crossterm::event::KeyCode, widgets::ListState};
+use tokio::sync::oneshot::{self, error::TryRecvError};
+use undersea_lib::{
+    Shows,
+    transcript::{Transcript, TranscriptCache},
+};
+
+use crate::{theme::Theme, widgets::transcript::TranscriptWidget};
+
+/// The transcript of the playing episode, following along with playback
+/// until a line is picked by hand.
+pub struct TranscriptPane {
+    cache: TranscriptCache,
+    /// Id of the episode it is for
+    episode: Option<String>,
+    /// The transcript or why there is none, [`None`] while it downloads
+    loaded: Option<Result<Transcript, String>>,
+    /// The transcript being downloaded in the background
+    pending: Option<oneshot::Receiver<Result<Transcript, String>>>,
+    list_state: ListState,
+    /// The line being said
+    current: Option<usize>,
+    /// If the selected line moves with playback
+    following: bool,
+    /// A search being typed after `/`
+    input: Option<String>,
+    query: String,
+    /// Lines matching the query
+    matches: Vec<usize>,
+}
+
+/// What a key press in the pane asked for.
+pub enum PaneKey {
+    /// The key is not used by the pane
+    Unhandled,
+    Handled,
+    /// Jump playback to a line
+    Seek(Duration),
+}
+
+impl TranscriptPane {
+    pub fn new(cache: TranscriptCache) -> Self {
+        Self {
+            cache,
+            episode: None,
+            loaded: None,
+            pending: None,
+            list_state: ListState::default(),
+            current: None,
+            following: true,
+            input: None,
+            query: String::new(),
+            matches: Vec::new(),
+        }
+    }
+
+    /// Load the transcript for `episode` in the background, if it is not
+    /// the one shown already.
+    pub fn load(&mut self, shows: &Shows, episode: Option<&str>) {
+        self.poll();
+        if self.episode.as_deref() == episode {
+            return;
+        }
+        self.episode = episode.map(ToString::to_string);
+        self.list_state.select(None);
+        self.current = None;
+        self.following = true;
+        self.matches.clear();
+        self.loaded = None;
+        self.pending = None;
+
+        let Some(id) = episode else {
+            return;
+        };
+        let link = shows
+            .episode_by_id(id)
+            .and_then(|(_, episode)| episode.transcript());
+        let Some(link) = link.cloned() else {
+            self.loaded = Some(Err("this episode has no transcript".to_string()));
+            return;
+        };
+        let (sender, receiver) = oneshot::channel();
+        let cache = self.cache.clone();
+        tokio::spawn(async move {
+            let transcript = cache
+                .load(&link)
+                .await
+                .map_err(|err| format!("could not load the transcript: {err}"));
+            let _ = sender.send(transcript);
+        });
+        self.pending = Some(receiver);
+    }
+
+    /// Pick up the transcript once it has been downloaded.
+    fn poll(&mut self) {
+        let Some(pending) = &mut self.pending else {
+            return;
+        };
+        match pending.try_recv() {
+            Ok(transcript) => {
+                self.loaded = Some(transcript);
+                self.pending = None;
+                self.search();
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Closed) => {
+                self.loaded = Some(Err("could not load the transcript".to_string()));
+                self.pending = None;
+            }
+        }
+    }
+
+    /// Highlight the line being said at `position`, and select it unless
+    /// another was picked.
+    pub fn follow(&mut self, position: Duration) {
+        self.current = self
+            .transcript()
+            .and_then(|transcript| transcript.cue_at(position));
+        if self.following {
+            self.list_state.select(self.current);
+        }
+    }
+
+    /// If a search is being typed, so every key is for the pane.
+    pub fn is_typing(&self) -> bool {
+        self.input.is_some()
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> PaneKey {
+        if let Some(input) = &mut self.input {
+            match code {
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Enter => {
+                    self.query = self.input.take().unwrap_or_default();
+                    self.search();
+                    self.next_match(true);
+                }
+                KeyCode::Esc => self.input = None,
+                _ => {}
+            }
+            return PaneKey::Handled;
+        }
+
+        match code {
+            KeyCode::Char('/') => self.input = Some(String::new()),
+            KeyCode::Char('j') => {
+                self.following = false;
+                self.list_state.select_next();
+            }
+            KeyCode::Char('k') => {
+                self.following = false;
+                self.list_state.select_previous();
+            }
+            KeyCode::Char('n') => self.next_match(true),
+            KeyCode::Char('N') => self.next_match(false),
+            KeyCode::Esc if !self.following => self.following = true,
+            KeyCode::Enter => {
+                let start = self
+                    .transcript()
+                    .zip(self.list_state.selected())
+                    .and_then(|(transcript, index)| transcript.cues().get(index))
+                    .map(|cue| cue.start);
+                if let Some(start) = start {
+                    self.following = true;
+                    return PaneKey::Seek(start);
+                }
+            }
+            _ => return PaneKey::Unhandled,
+        }
+        PaneKey::Handled
+    }
+
+    pub fn widget<'a>(&'a mut self, theme: &'a Theme) -> (TranscriptWidget<'a>, &'a mut ListState) {
+        let widget = TranscriptWidget::new(
+            self.episode.is_some(),
+            self.loaded.as_ref(),
+            self.current,
+            &self.matches,
+            self.input.as_deref(),
+            &self.query,
+            theme,
+        );
+        (widget, &mut self.list_state)
+    }
+
+    fn transcript(&self) -> Option<&Transcript> {
+        self.loaded.as_ref()?.as_ref().ok()
+    }
+
+    fn search(&mut self) {
+        self.matches = self
+            .transcript()
+            .map(|transcript| transcript.search(&self.query))
+            .unwrap_or_default();
+    }
+
+    /// Select the next line matching the search after the selected one, or
+    /// the one before it, wrapping around.
+    fn next_match(&mut self, forward: bool) {
+        let selected = self.list_state.selected();
+        let found = if forward {
+            self.matches
+                .iter()
+                .find(|&&index| selected.is_none_or(|selected| index > selected))
+                .or_else(|| self.matches.first())
+        } else {
+            self.matches
+                .iter()
+                .rev()
+                .find(|&&index| selected.is_none_or(|selected| index < selected))
+                .or_else(|| self.matches.last())
+        };
+        if let Some(&index) = found {
+            self.following = false;
+            self.list_state.select(Some(index));
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Drawing artwork with the graphics protocols some terminals have, or with
+//! half blocks everywhere else.
+//!
+//! Images drawn with a protocol are written straight to the terminal after
+//! each frame, ratatui is only told to leave the cells under them alone.
+
+use std::{
+    env,
+    fmt::Write,
+    io::{self, Write as _},
+};
+
+use anyhow::{Result, anyhow};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use image::{RgbaImage, imageops::FilterType};
+use ratatui::{
+    crossterm::{cursor::MoveTo, queue, terminal},
+    layout::Rect,
+};
+
+/// Deletes every image kitty is showing.
+const KITTY_DELETE: &str = "\x1b_Ga=d,d=A,q=2\x1b\\";
+/// Largest piece of an image kitty accepts in one escape sequence.
+const KITTY_CHUNK: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Kitty,
+    Sixel,
+    Iterm2,
+    /// Two pixels per cell, using the colours of `▀`
+    HalfBlocks,
+}
+
+impl Protocol {
+    /// The protocol set by the `artwork` setting, [`None`] if artwork is off.
+    ///
+    /// # Errors
+    /// Fails if the setting is not a known protocol.
+    pub fn from_config(value: Option<&str>) -> Result<Option<Self>> {
+        Ok(Some(match value.unwrap_or("auto") {
+            "auto" => Self::detect(),
+            "kitty" => Self::Kitty,
+            "sixel" => Self::Sixel,
+            "iterm2" => Self::Iterm2,
+            "halfblocks" => Self::HalfBlocks,
+            "off" => return Ok(None),
+            other => {
+                return Err(anyhow!(
+                    "unknown artwork setting \"{other}\", expected auto, kitty, sixel, iterm2, \
+                     halfblocks or off"
+                ));
+            }
+        }))
+    }
+
+    /// Guess what the terminal supports from its environment. Asking the
+    /// terminal would be more reliable, but not every terminal answers.
+    pub fn detect() -> Self {
+        let var = |name| env::var(name).unwrap_or_default();
+        let term = var("TERM");
+        let program = var("TERM_PROGRAM");
+
+        // tmux needs every sequence wrapped to pass it through
+        if env::var_os("TMUX").is_some() {
+            Self::HalfBlocks
+        } else if env::var_os("KITTY_WINDOW_ID").is_some()
+            || term == "xterm-kitty"
+            || program == "ghostty"
+        {
+            Self::Kitty
+        } else if matches!(program.as_str(), "iTerm.app" | "WezTerm")
+            || var("LC_TERMINAL") == "iTerm2"
+        {
+            Self::Iterm2
+        } else if ["foot", "mlterm", "yaft", "contour"]
+            .iter()
+            .any(|name| term.contains(name))
+        {
+            Self::Sixel
+        } else {
+            Self::HalfBlocks
+        }
+    }
+
+    /// If images are written straight to the terminal, rather than drawn in
+    /// cells.
+    pub fn is_escape(self) -> bool {
+        self != Self::HalfBlocks
+    }
+}
+
+/// Size of a cell in pixels, guessing 8 by 16 if the terminal does not say.
+pub fn cell_size() -> (u32, u32) {
+    terminal::window_size()
+        .ok()
+        .filter(|size| size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0)
+        .map_or((8, 16), |size| {
+            (
+                u32::from(size.width / size.columns).max(1),
+                u32::from(size.height / size.rows).max(1),
+            )
+        })
+}
+
+/// The largest area in `area`, from its top left, that shows `image` without
+/// stretching it.
+pub fn fit(image: &RgbaImage, area: Rect, (cell_width, cell_height): (u32, u32)) -> Rect {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 || area.is_empty() {
+        return Rect {
+            width: 0,
+            height: 0,
+            ..area
+        };
+    }
+
+    let columns = u64::from(area.height) * u64::from(cell_height) * u64::from(width)
+        / (u64::from(height) * u64::from(cell_width));
+    let (columns, rows) = if columns <= u64::from(area.width) {
+        (columns, u64::from(area.height))
+    } else {
+        let rows = u64::from(area.width) * u64::from(cell_width) * u64::from(height)
+            / (u64::from(width) * u64::from(cell_height));
+        (u64::from(area.width), rows)
+    };
+    Rect {
+        width: u16::try_from(columns.max(1)).unwrap_or(area.width),
+        height: u16::try_from(rows.max(1)).unwrap_or(area.height),
+        ..area
+    }
+}
+
+/// Draw an image over `area` of the terminal, which should be from [`fit`].
+///
+/// # Errors
+/// Fails if writing to the terminal fails.
+pub fn place(
+    protocol: Protocol,
+    png: &[u8],
+    image: &RgbaImage,
+    area: Rect,
+    cell: (u32, u32),
+) -> io::Result<()> {
+    let sequence = match protocol {
+        Protocol::Kitty => kitty(png, area),
+        Protocol::Iterm2 => iterm2(png, area),
+        Protocol::Sixel => {
+            let scaled = image::imageops::resize(
+                image,
+                u32::from(area.width) * cell.0,
+                u32::from(area.height) * cell.1,
+                FilterType::Triangle,
+            );
+            sixel(&scaled)
+        }
+        Protocol::HalfBlocks => return Ok(()),
+    };
+
+    let mut stdout = io::stdout();
+    queue!(stdout, MoveTo(area.x, area.y))?;
+    stdout.write_all(sequence.as_bytes())?;
+    stdout.flush()
+}
+
+/// Remove anything drawn by [`place`] that text does not cover up.
+///
+/// # Errors
+/// Fails if writing to the terminal fails.
+pub fn clear(protocol: Protocol) -> io::Result<()> {
+    if protocol == Protocol::Kitty {
+        let mut stdout = io::stdout();
+        stdout.write_all(KITTY_DELETE.as_bytes())?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+/// A png sent in chunks, scaled by kitty to fill `area`, without moving the
+/// cursor.
+fn kitty(png: &[u8], area: Rect) -> String {
+    let data = STANDARD.encode(png);
+    let mut sequence = KITTY_DELETE.to_string();
+    let chunks = data.as_bytes().chunks(KITTY_CHUNK).collect::<Vec<_>>();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(index + 1 < chunks.len());
+        let chunk = String::from_utf8_lossy(chunk);
+        if index == 0 {
+            let _ = write!(
+                sequence,
+                "\x1b_Gf=100,a=T,q=2,C=1,c={},r={},m={more};{chunk}\x1b\\",
+                area.width, area.height
+            );
+        } else {
+            let _ = write!(sequence, "\x1b_Gm={more};{chunk}\x1b\\");
+        }
+    }
+    sequence
+}
+
+/// An inline file, scaled by the terminal to fill `area`.
+fn iterm2(png: &[u8], area: Rect) -> String {
+    format!(
+        "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1;\
+         doNotMoveCursor=1:{}\x07",
+        png.len(),
+        area.width,
+        area.height,
+        STANDARD.encode(png)
+    )
+}
+
+/// Encode an image as sixels, with colours rounded to a 6×6×6 cube.
+fn sixel(image: &RgbaImage) -> String {
+    let (width, height) = image.dimensions();
+    let level = |value: u8| (u16::from(value) * 5 + 127) / 255;
+    let colours = image
+        .pixels()
+        .map(|pixel| {
+            let [red, green, blue, _] = pixel.0;
+            level(red) * 36 + level(green) * 6 + level(blue)
+        })
+        .collect::<Vec<_>>();
+
+    let mut sequence = format!("\x1bPq\"1;1;{width};{height}");
+    for colour in 0..216 {
+        let _ = write!(
+            sequence,
+            "#{colour};2;{};{};{}",
+            colour / 36 * 20,
+            colour / 6 % 6 * 20,
+            colour % 6 * 20
+        );
+    }
+
+    for band in (0..height).step_by(6) {
+        let rows = (band..height.min(band + 6)).collect::<Vec<_>>();
+        let mut used = [false; 216];
+        for &y in &rows {
+            for x in 0..width {
+                used[usize::from(colours[(y * width + x) as usize])] = true;
+            }
+        }
+
+        for colour in (0..216).filter(|&colour| used[usize::from(colour)]) {
+            let _ = write!(sequence, "#{colour}");
+            let mut run: Option<(char, u32)> = None;
+            for x in 0..width {
+                let bits = rows
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &y)| colours[(y * width + x) as usize] == colour)
+                    .fold(0, |bits, (row, _)| bits | 1 << row);
+                let sixel = char::from(63 + bits);
+                run = match run {
+                    Some((previous, count)) if previous == sixel => Some((sixel, count + 1)),
+                    Some((previous, count)) => {
+                        push_run(&mut sequence, previous, count);
+                        Some((sixel, 1))
+                    }
+                    None => Some((sixel, 1)),
+                };
+            }
+            if let Some((sixel, count)) = run {
+                push_run(&mut sequence, sixel, count);
+            }
+            // back to the start of the band for the next colour
+            sequence.push('$');
+        }
+        sequence.push('-');
+    }
+
+    sequence.push_str("\x1b\\");
+    sequence
+}
+
+fn push_run(sequence: &mut String, sixel: char, count: u32) {
+    if count > 3 {
+        let _ = write!(sequence, "!{count}{sixel}");
+    } else {
+        for _ in 0..count {
+            sequence.push(sixel);
+        }
+    }
+}
@@ -1,9 +1,28 @@
-use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use undersea_lib::{
+    Config, Shows,
+    artwork::ArtworkCache,
+    control::Server,
+    credentials::CredentialStore,
+    history::History,
+    player::{Backend, Mpv, Player, mpris::Mpris},
+    sync,
+    transcript::TranscriptCache,
+};
 
 mod app;
+mod graphics;
+mod theme;
 mod widgets;
 
-use crate::app::App;
+use crate::{
+    app::{App, Artwork, Playback, Remote, Syncer, TranscriptPane, Unavailable},
+    graphics::Protocol,
+    theme::Theme,
+};
 
 pub const TESTING_URLS: [&str; 5] = [
     // just roll with it
@@ -18,11 +37,138 @@ pub const TESTING_URLS: [&str; 5] = [
     "https://feeds.megaphone.fm/redvalley",
 ];
 
+/// Podcast player for the terminal
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// Config file to use instead of the default one
+    #[arg(long, env = "UNDERSEA_CONFIG")]
+    config: Option<PathBuf>,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
+    let config = Config::load(args.config.as_deref())?;
+    for warning in config.warnings() {
+        eprintln!("warning: {warning}");
+    }
+
+    let theme = Theme::load(&config)?;
+    let artwork = match Protocol::from_config(config.artwork.as_deref())? {
+        Some(protocol) => Some(Artwork::new(
+            ArtworkCache::new(config.artwork_dir(), config.http_client()?),
+            protocol,
+        )),
+        None => None,
+    };
+    let transcripts = TranscriptPane::new(TranscriptCache::new(
+        config.transcript_dir(),
+        config.http_client()?,
+    ));
+    let backends = sync::backends(&config, config.http_client()?)?;
+    // with sync set up the library is fetched from other devices, so the
+    // testing shows are not needed
+    let library_path =
+        Some(config.library_path()).filter(|path| path.exists() || !backends.is_empty());
+    let mut shows = match &library_path {
+        Some(path) => load_shows(&config, path).await?,
+        None => testing_shows(&config).await?,
+    };
+    // play sessions are only kept alongside a library
+    let history = match &library_path {
+        Some(_) => History::load(&config.history_path())?,
+        None => History::default(),
+    };
+    let mut syncer = Syncer::new(backends, library_path.clone());
+    syncer.sync(&mut shows).await;
+    let playback = playback(&config, history).await;
+    let remote = match Server::bind(&config.control_socket()) {
+        Ok(server) => Some(Remote::new(server, &shows)),
+        Err(err) => {
+            eprintln!("warning: undersea ctl will not work, {err}");
+            None
+        }
+    };
+
     let mut terminal = ratatui::init();
-    let mut app = App::new().await;
+    let mut app = App::new(shows, theme, syncer, playback, remote, artwork, transcripts);
     let app_result = app.run(&mut terminal);
     ratatui::restore();
-    app_result
+
+    // saved even if the app failed, so the session is not lost with it,
+    // credentials too as moved feeds take theirs along
+    let saved = match &library_path {
+        Some(path) => save(app.shows_mut(), path),
+        None => Ok(()),
+    };
+    app_result?;
+    saved
+}
+
+/// Write the library and credentials back.
+fn save(shows: &mut Shows, path: &Path) -> Result<()> {
+    shows.save(path)?;
+    shows.credentials_mut().save()?;
+    Ok(())
+}
+
+/// Start mpv and take calls over D-Bus. The tui still runs without either,
+/// playing just fails with the reason mpv could not be started.
+async fn playback(config: &Config, history: History) -> Playback {
+    let backend: Box<dyn Backend> = match Mpv::spawn() {
+        Ok(mpv) => Box::new(mpv),
+        Err(err) => Box::new(Unavailable(format!("could not start mpv: {err}"))),
+    };
+    let playback = Playback::new(
+        Player::new(backend, history)
+            .with_audio(config.audio)
+            .with_smart_resume(config.smart_resume),
+        config,
+    );
+    match Mpris::start(None).await {
+        Ok(mpris) => playback.with_mpris(mpris),
+        Err(err) => {
+            eprintln!("warning: media keys will not work, {err}");
+            playback
+        }
+    }
+}
+
+/// Load the users library, or start a new one, and check stale shows for new
+/// episodes.
+async fn load_shows(config: &Config, path: &Path) -> Result<Shows> {
+    let mut shows = Shows::load_or_default(path)
+        .with_context(|| format!("failed to load library {}", path.display()))?;
+    shows.set_client(config.http_client()?);
+    let credentials_path = config.credentials_path();
+    shows.set_credentials(
+        CredentialStore::load(&credentials_path, config.keyring).with_context(|| {
+            format!("failed to load credentials {}", credentials_path.display())
+        })?,
+    );
+    shows.set_sync_secret_urls(config.sync_secret_urls);
+    if let Some(descriptions) = config.description_cache() {
+        shows.set_description_cache(descriptions);
+    }
+
+    let summary = shows
+        .refresh_stale(config.refresh_interval, config.concurrency)
+        .await;
+    for (url, err) in &summary.failed {
+        eprintln!("warning: failed to refresh {}: {err}", shows.redact(url));
+    }
+    for (from, to) in &summary.moved {
+        eprintln!("{} has moved to {}", shows.redact(from), shows.redact(to));
+    }
+    save(&mut shows, path)?;
+    Ok(shows)
+}
+
+/// Shows to look at before there is a library.
+async fn testing_shows(config: &Config) -> Result<Shows> {
+    let mut shows = Shows::default();
+    shows.set_client(config.http_client()?);
+    shows.add_multiple(TESTING_URLS).await?;
+    Ok(shows)
 }
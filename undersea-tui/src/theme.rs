@@ -0,0 +1,310 @@
+use std::{env, fs, path::PathBuf};
+
+use undersea_lib::Config;
+
+use anyhow::{Context, Result, bail};
+use ratatui::style::{Color, Style, Stylize};
+use serde::Deserialize;
+
+/// Environment variable that can hold either the name of a built-in theme or
+/// a path to a theme file.
+const THEME_ENV: &str = "UNDERSEA_THEME";
+
+/// Colours used by every widget in the tui.
+///
+/// Widgets should never hardcode colours, they should ask the theme for a
+/// [`Style`] instead.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub border: Color,
+    pub border_focused: Color,
+    pub title: Color,
+    pub text: Color,
+    pub text_dim: Color,
+    pub highlight: Color,
+    pub selected: Color,
+    pub played: Color,
+    pub unplayed: Color,
+    pub error: Color,
+    /// Set when `NO_COLOR` is present, only modifiers are used for emphasis.
+    no_color: bool,
+}
+
+/// A theme file, every slot is optional and falls back to the `base` theme.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    base: Option<String>,
+    border: Option<Color>,
+    border_focused: Option<Color>,
+    title: Option<Color>,
+    text: Option<Color>,
+    text_dim: Option<Color>,
+    highlight: Option<Color>,
+    selected: Option<Color>,
+    played: Option<Color>,
+    unplayed: Option<Color>,
+    error: Option<Color>,
+}
+
+impl Theme {
+    /// The default theme, made for terminals with a dark background.
+    pub fn dark() -> Self {
+        Self {
+            border: Color::Blue,
+            border_focused: Color::Blue,
+            title: Color::Blue,
+            text: Color::White,
+            text_dim: Color::Gray,
+            highlight: Color::Yellow,
+            selected: Color::Green,
+            played: Color::DarkGray,
+            unplayed: Color::White,
+            error: Color::Red,
+            no_color: false,
+        }
+    }
+
+    /// A theme for terminals with a light background.
+    pub fn light() -> Self {
+        Self {
+            border: Color::Blue,
+            border_focused: Color::Blue,
+            title: Color::Blue,
+            text: Color::Black,
+            text_dim: Color::DarkGray,
+            highlight: Color::Magenta,
+            selected: Color::Green,
+            played: Color::Gray,
+            unplayed: Color::Black,
+            error: Color::Red,
+            no_color: false,
+        }
+    }
+
+    /// Only uses the brightest colours, for when readability matters most.
+    pub fn high_contrast() -> Self {
+        Self {
+            border: Color::White,
+            border_focused: Color::LightYellow,
+            title: Color::White,
+            text: Color::White,
+            text_dim: Color::White,
+            highlight: Color::LightYellow,
+            selected: Color::LightGreen,
+            played: Color::Gray,
+            unplayed: Color::White,
+            error: Color::LightRed,
+            no_color: false,
+        }
+    }
+
+    /// Uses the terminals own colours everywhere, see <https://no-color.org>.
+    pub fn no_color() -> Self {
+        Self {
+            border: Color::Reset,
+            border_focused: Color::Reset,
+            title: Color::Reset,
+            text: Color::Reset,
+            text_dim: Color::Reset,
+            highlight: Color::Reset,
+            selected: Color::Reset,
+            played: Color::Reset,
+            unplayed: Color::Reset,
+            error: Color::Reset,
+            no_color: true,
+        }
+    }
+
+    /// Get one of the built-in themes by name
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            "no-color" => Some(Self::no_color()),
+            _ => None,
+        }
+    }
+
+    /// Load the theme the user asked for.
+    ///
+    /// `NO_COLOR` always wins, then `UNDERSEA_THEME`, then the `theme` setting
+    /// in the config (both can be a built-in name or a path), then
+    /// `theme.toml` in the config directory, and finally the dark theme.
+    ///
+    /// # Errors
+    /// Fails if a theme file exists but could not be read or parsed.
+    pub fn load(config: &Config) -> Result<Self> {
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return Ok(Self::no_color());
+        }
+
+        if let Some(value) = env::var(THEME_ENV).ok().or_else(|| config.theme.clone()) {
+            return Self::builtin(&value).map_or_else(|| Self::from_file(&value.into()), Ok);
+        }
+
+        let path = config.dirs.config.join("theme.toml");
+        if path.exists() {
+            Self::from_file(&path)
+        } else {
+            Ok(Self::dark())
+        }
+    }
+
+    /// Read a theme from a toml file.
+    ///
+    /// # Errors
+    /// Fails if the file could not be read, is not valid toml, has unknown
+    /// slots or names a base theme that does not exist.
+    pub fn from_file(path: &PathBuf) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read theme file {}", path.display()))?;
+        Self::from_toml(&contents)
+            .with_context(|| format!("failed to parse theme file {}", path.display()))
+    }
+
+    fn from_toml(contents: &str) -> Result<Self> {
+        let file: ThemeFile = toml::from_str(contents)?;
+
+        let mut theme = match file.base.as_deref() {
+            Some(name) => match Self::builtin(name) {
+                Some(theme) => theme,
+                None => bail!("unknown base theme \"{name}\""),
+            },
+            None => Self::dark(),
+        };
+
+        let slots = [
+            (&mut theme.border, file.border),
+            (&mut theme.border_focused, file.border_focused),
+            (&mut theme.title, file.title),
+            (&mut theme.text, file.text),
+            (&mut theme.text_dim, file.text_dim),
+            (&mut theme.highlight, file.highlight),
+            (&mut theme.selected, file.selected),
+            (&mut theme.played, file.played),
+            (&mut theme.unplayed, file.unplayed),
+            (&mut theme.error, file.error),
+        ];
+        for (slot, colour) in slots {
+            if let Some(colour) = colour {
+                *slot = colour;
+            }
+        }
+
+        Ok(theme)
+    }
+
+    /// Style for the border of a block, `focused` blocks are also bold.
+    pub fn border(&self, focused: bool) -> Style {
+        if focused {
+            Style::new().fg(self.border_focused).bold()
+        } else {
+            Style::new().fg(self.border)
+        }
+    }
+
+    /// Style for the title of a block.
+    pub fn title(&self, focused: bool) -> Style {
+        let style = Style::new().fg(self.title);
+        if focused { style.bold() } else { style }
+    }
+
+    /// Style for normal text.
+    pub fn text(&self) -> Style {
+        Style::new().fg(self.text).not_bold()
+    }
+
+    /// Style for less important text, such as dates.
+    pub fn text_dim(&self) -> Style {
+        let style = Style::new().fg(self.text_dim);
+        if self.no_color { style.dim() } else { style }
+    }
+
+    /// Style for the item under the cursor in a list.
+    pub fn highlight(&self) -> Style {
+        let style = Style::new().fg(self.highlight).bold();
+        if self.no_color {
+            style.reversed()
+        } else {
+            style
+        }
+    }
+
+    /// Style for the item that has been picked, like the open episode.
+    pub fn selected(&self) -> Style {
+        let style = Style::new().fg(self.selected).bold();
+        if self.no_color {
+            style.underlined()
+        } else {
+            style
+        }
+    }
+
+    /// Style for an episode, depending on if it has been finished.
+    pub fn episode(&self, finished: bool) -> Style {
+        if finished {
+            let style = Style::new().fg(self.played);
+            if self.no_color { style.dim() } else { style }
+        } else {
+            Style::new().fg(self.unplayed)
+        }
+    }
+
+    /// Style for errors.
+    pub fn error(&self) -> Style {
+        Style::new().fg(self.error).bold()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_override_the_base() {
+        let theme = Theme::from_toml(
+            r##"
+            base = "light"
+            highlight = "cyan"
+            error = "#ff8000"
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(theme.highlight, Color::Cyan);
+        assert_eq!(theme.error, Color::Rgb(0xff, 0x80, 0x00));
+        // the rest is the base theme
+        assert_eq!(theme.text, Theme::light().text);
+        assert_eq!(theme.played, Theme::light().played);
+    }
+
+    #[test]
+    fn the_base_defaults_to_dark() {
+        let theme = Theme::from_toml("text = \"gray\"").unwrap();
+
+        assert_eq!(theme.text, Color::Gray);
+        assert_eq!(theme.border, Theme::dark().border);
+        assert_eq!(Theme::from_toml("").unwrap().text, Theme::dark().text);
+    }
+
+    #[test]
+    fn bad_theme_files_are_errors() {
+        assert!(Theme::from_toml("base = \"sepia\"").is_err());
+        assert!(Theme::from_toml("backgroud = \"black\"").is_err());
+        assert!(Theme::from_toml("text = \"not a colour\"").is_err());
+        assert!(Theme::from_toml("text = ").is_err());
+    }
+
+    #[test]
+    fn focused_borders_are_bold() {
+        let theme = Theme::high_contrast();
+
+        assert_eq!(
+            theme.border(true),
+            Style::new().fg(Color::LightYellow).bold()
+        );
+        assert_eq!(theme.border(false), Style::new().fg(Color::White));
+    }
+}
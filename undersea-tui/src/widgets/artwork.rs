@@ -0,0 +1,61 @@
+use image::{RgbaImage, imageops::FilterType};
+use ratatui::prelude::*;
+
+use crate::graphics::{self, Protocol};
+
+pub struct ArtworkWidget<'a> {
+    image: &'a RgbaImage,
+    protocol: Protocol,
+    /// Size of a cell in pixels
+    cell: (u32, u32),
+}
+
+impl<'a> ArtworkWidget<'a> {
+    pub fn new(image: &'a RgbaImage, protocol: Protocol, cell: (u32, u32)) -> ArtworkWidget<'a> {
+        Self {
+            image,
+            protocol,
+            cell,
+        }
+    }
+}
+
+impl Widget for ArtworkWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let area = graphics::fit(self.image, area, self.cell);
+        if area.is_empty() {
+            return;
+        }
+
+        // the image is written to the terminal after the frame, so leave the
+        // cells under it alone
+        if self.protocol.is_escape() {
+            for position in area.positions() {
+                buf[position].set_skip(true);
+            }
+            return;
+        }
+
+        let scaled = image::imageops::resize(
+            self.image,
+            u32::from(area.width),
+            u32::from(area.height) * 2,
+            FilterType::Triangle,
+        );
+        for position in area.positions() {
+            let x = u32::from(position.x - area.x);
+            let y = u32::from(position.y - area.y) * 2;
+            let colour = |y| {
+                let [red, green, blue, _] = scaled.get_pixel(x, y).0;
+                Color::Rgb(red, green, blue)
+            };
+            buf[position]
+                .set_char('▀')
+                .set_fg(colour(y))
+                .set_bg(colour(y + 1));
+        }
+    }
+}
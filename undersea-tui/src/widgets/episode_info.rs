@@ -1,13 +1,30 @@
 use ratatui::{prelude::*, widgets::Paragraph};
-use undersea_lib::Episode;
+use undersea_lib::{Episode, Shows, player::format_time};
+
+use crate::theme::Theme;
 
 pub struct EpisodeInfoWidget<'a> {
     episode: &'a Episode,
+    /// For the show notes, which may have to be loaded from the cache
+    shows: &'a Shows,
+    /// The bookmark that is jumped to with `g`
+    selected_bookmark: Option<usize>,
+    theme: &'a Theme,
 }
 
 impl<'a> EpisodeInfoWidget<'a> {
-    pub fn new(episode: &'a Episode) -> EpisodeInfoWidget<'a> {
-        Self { episode }
+    pub fn new(
+        episode: &'a Episode,
+        shows: &'a Shows,
+        selected_bookmark: Option<usize>,
+        theme: &'a Theme,
+    ) -> EpisodeInfoWidget<'a> {
+        Self {
+            episode,
+            shows,
+            selected_bookmark,
+            theme,
+        }
     }
 }
 
@@ -16,14 +33,130 @@ impl Widget for EpisodeInfoWidget<'_> {
     where
         Self: Sized,
     {
-        let date = Line::from(format!("uploaded: {}", self.episode.date())).white();
+        let date =
+            Line::from(format!("uploaded: {}", self.episode.date())).style(self.theme.text());
         let newline = Line::from("");
         // TODO: This HTML needs to be parsed
-        let show_notes = Line::from(format!("{}", self.episode.descrpition().unwrap())).white();
+        let description = self.shows.description(self.episode.id());
+        let show_notes = Line::from(description.unwrap_or_default()).style(self.theme.text());
 
-        let lines = vec![date, newline, show_notes];
+        let mut lines = vec![date, newline.clone()];
+        let bookmarks = self.episode.bookmarks();
+        if !bookmarks.is_empty() {
+            lines.push(Line::from("bookmarks:").style(self.theme.text_dim()));
+            for (index, bookmark) in bookmarks.iter().enumerate() {
+                let time = match bookmark.end {
+                    Some(end) => format!("{}-{}", format_time(bookmark.start), format_time(end)),
+                    None => format_time(bookmark.start),
+                };
+                let (marker, style) = if Some(index) == self.selected_bookmark {
+                    ("> ", self.theme.highlight())
+                } else {
+                    ("- ", self.theme.text())
+                };
+                lines.push(Line::from(vec![
+                    Span::from(format!("{marker}{time}  ")).style(self.theme.text_dim()),
+                    Span::from(bookmark.note.clone()).style(style),
+                ]));
+            }
+            lines.push(newline);
+        }
+        lines.push(show_notes);
         Paragraph::new(lines)
             .wrap(ratatui::widgets::Wrap { trim: true })
             .render(area, buf);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use undersea_lib::description::DescriptionCache;
+
+    use super::*;
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+<channel>
+<title>Notes</title>
+<item>
+<title>First</title>
+<guid>first</guid>
+<pubDate>Mon, 01 Jan 2024 00:00:00 GMT</pubDate>
+<enclosure url="http://example.com/first.mp3" length="1" type="audio/mpeg"/>
+<content:encoded>Notes of the first episode</content:encoded>
+</item>
+<item>
+<title>Second</title>
+<guid>second</guid>
+<pubDate>Tue, 02 Jan 2024 00:00:00 GMT</pubDate>
+<enclosure url="http://example.com/second.mp3" length="1" type="audio/mpeg"/>
+<content:encoded>Notes of the second episode</content:encoded>
+</item>
+</channel>
+</rss>"#;
+
+    /// Serve [`FEED`] to every request, returning its url.
+    fn serve_feed() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/feed.xml", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buf[..read]),
+                    }
+                }
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/rss+xml\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{FEED}",
+                    FEED.len()
+                );
+            }
+        });
+        url
+    }
+
+    fn rendered(widget: EpisodeInfoWidget) -> String {
+        let area = Rect::new(0, 0, 60, 10);
+        let mut buf = Buffer::empty(area);
+        widget.render(area, &mut buf);
+        buf.content()
+            .iter()
+            .map(ratatui::buffer::Cell::symbol)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn notes_kept_on_disk_are_shown() {
+        let dir =
+            std::env::temp_dir().join(format!("undersea-test-episode-info-{}", std::process::id()));
+        let mut shows = Shows::default();
+        shows.set_description_cache(DescriptionCache::new(dir.clone(), 1));
+        shows.add(serve_feed()).await.unwrap();
+
+        let show = shows.shows()[0];
+        let first = show
+            .episodes()
+            .into_iter()
+            .find(|episode| episode.id() == "first")
+            .unwrap();
+        // only the newest description stays in memory
+        assert_eq!(first.descrpition(), None);
+
+        let theme = Theme::dark();
+        let widget = EpisodeInfoWidget::new(first, &shows, None, &theme);
+        assert!(rendered(widget).contains("Notes of the first episode"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
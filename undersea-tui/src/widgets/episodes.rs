@@ -1,18 +1,29 @@
 use ratatui::prelude::*;
-use ratatui::style::Stylize;
 use ratatui::widgets::{List, ListState};
-use undersea_lib::Episode;
+use undersea_lib::{Episode, Show};
+
+use crate::theme::Theme;
 
 pub struct EpisodesWidget<'a> {
-    episodes: Vec<&'a Episode>,
+    episodes: Vec<(&'a Show, &'a Episode)>,
     selected_episode: Option<usize>,
+    /// If the name of the show is listed with each episode
+    show_names: bool,
+    theme: &'a Theme,
 }
 
 impl<'a> EpisodesWidget<'a> {
-    pub fn new(episodes: &'a [&Episode], selected_episode: Option<usize>) -> Self {
+    pub fn new(
+        episodes: &'a [(&Show, &Episode)],
+        selected_episode: Option<usize>,
+        show_names: bool,
+        theme: &'a Theme,
+    ) -> Self {
         Self {
             episodes: episodes.to_vec(),
             selected_episode,
+            show_names,
+            theme,
         }
     }
 }
@@ -24,32 +35,40 @@ impl StatefulWidget for EpisodesWidget<'_> {
         Self: Sized,
     {
         let mut items = Vec::new();
-        for (id, episode) in self.episodes.iter().enumerate() {
+        for (id, (show, episode)) in self.episodes.iter().enumerate() {
             let date = episode.date().format("%Y-%m-%d %H:%M");
             let date = date.to_string();
+            let show_name = if self.show_names {
+                format!("{}  ", show.name())
+            } else {
+                String::new()
+            };
 
             // Make the time of upload be allinged to the left
-            // (ep length + date length + 3) - total width
+            // (ep length + show name length + date length + 3) - total width
             // the plus 3 is to account for the ' > ' that is insertde before
             // the highlighted episode
             let distance = area.width.saturating_sub(
-                (3 + episode.title().chars().count() + date.chars().count())
-                    .try_into()
-                    .unwrap_or(0),
+                (3 + episode.title().chars().count()
+                    + show_name.chars().count()
+                    + date.chars().count())
+                .try_into()
+                .unwrap_or(0),
             );
 
             let seperator = " ".repeat(distance as usize);
 
             let title_style = if Some(id) == self.selected_episode {
-                Style::new().green().bold()
+                self.theme.selected()
             } else {
-                Style::new().white().not_bold()
+                self.theme.episode(episode.finished())
             };
 
             let spans = [
                 Span::from(episode.title()).style(title_style),
-                Span::from(seperator).red(),
-                date.gray(),
+                Span::from(seperator),
+                Span::from(show_name).style(self.theme.text()),
+                Span::from(date).style(self.theme.text_dim()),
             ];
 
             let line = Line::default().spans(spans);
@@ -59,7 +78,7 @@ impl StatefulWidget for EpisodesWidget<'_> {
         let list = List::new(items)
             .highlight_symbol("> ")
             .repeat_highlight_symbol(true)
-            .highlight_style(Style::new().yellow().bold())
+            .highlight_style(self.theme.highlight())
             .highlight_spacing(ratatui::widgets::HighlightSpacing::Always);
 
         StatefulWidget::render(list, area, buf, state);
@@ -1,3 +1,7 @@
+pub mod artwork;
 pub mod episode_info;
 pub mod episodes;
+pub mod now_playing;
 pub mod shows;
+pub mod stats;
+pub mod transcript;
@@ -0,0 +1,87 @@
+use ratatui::{prelude::*, widgets::Paragraph};
+use undersea_lib::{
+    Episode, Show,
+    player::{Player, SleepUntil, Status, format_time},
+};
+
+use crate::theme::Theme;
+
+/// A line along the bottom with what is playing and where it is up to, or
+/// what went wrong.
+pub struct NowPlayingWidget<'a> {
+    player: &'a Player,
+    now_playing: Option<(&'a Show, &'a Episode)>,
+    error: Option<&'a str>,
+    theme: &'a Theme,
+}
+
+impl<'a> NowPlayingWidget<'a> {
+    pub fn new(
+        player: &'a Player,
+        now_playing: Option<(&'a Show, &'a Episode)>,
+        error: Option<&'a str>,
+        theme: &'a Theme,
+    ) -> NowPlayingWidget<'a> {
+        Self {
+            player,
+            now_playing,
+            error,
+            theme,
+        }
+    }
+}
+
+impl Widget for NowPlayingWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        if let Some(error) = self.error {
+            Line::from(format!(" {error}"))
+                .style(self.theme.error())
+                .render(area, buf);
+            return;
+        }
+        let Some((show, episode)) = self.now_playing else {
+            return;
+        };
+
+        let icon = match self.player.status() {
+            Status::Playing => "▶",
+            Status::Paused => "⏸",
+            Status::Stopped => "■",
+        };
+        let length = episode
+            .duration()
+            .map(|length| format!(" / {}", format_time(length)))
+            .unwrap_or_default();
+        let sleep = match (self.player.sleep_timer(), self.player.sleep_remaining()) {
+            (None, _) => String::new(),
+            (Some(_), Some(remaining)) => format!("  sleep {}", format_time(remaining)),
+            (Some(timer), None) => match timer.until {
+                SleepUntil::EndOfChapter => "  sleep at chapter end".to_string(),
+                _ => "  sleep at episode end".to_string(),
+            },
+        };
+        let progress = format!(
+            "{}{length}  {}×{sleep} ",
+            format_time(self.player.position()),
+            self.player.speed()
+        );
+
+        let [title, progress_area] = Layout::horizontal([
+            Constraint::Min(10),
+            Constraint::Length(u16::try_from(progress.chars().count()).unwrap_or(u16::MAX)),
+        ])
+        .areas(area);
+        Paragraph::new(Line::from(vec![
+            Span::from(format!(" {icon} ")).style(self.theme.highlight()),
+            Span::from(episode.title().to_string()).style(self.theme.text()),
+            Span::from(format!("  {}", show.name())).style(self.theme.text_dim()),
+        ]))
+        .render(title, buf);
+        Line::from(progress)
+            .style(self.theme.text_dim())
+            .render(progress_area, buf);
+    }
+}
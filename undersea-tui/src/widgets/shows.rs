@@ -1,12 +1,33 @@
 use ratatui::{
-    style::{Style, Stylize},
     text::Line,
     widgets::{List, ListState, StatefulWidget},
 };
-use undersea_lib::Shows;
+use undersea_lib::{Show, playlist::SmartPlaylist};
+
+use crate::theme::Theme;
+
+/// A row in the sidebar.
+pub enum SidebarRow<'a> {
+    Inbox,
+    Playlist(&'a SmartPlaylist),
+    /// A tag, with the shows that have it listed under it unless collapsed
+    Folder {
+        tag: &'a str,
+        collapsed: bool,
+        shows: usize,
+    },
+    Show {
+        show: &'a Show,
+        /// If the show is listed under a folder
+        nested: bool,
+    },
+}
 
 pub struct ShowsWidget<'a> {
-    shows: &'a Shows,
+    rows: &'a [SidebarRow<'a>],
+    /// How many episodes are in the inbox
+    inbox_count: usize,
+    theme: &'a Theme,
 }
 
 impl StatefulWidget for ShowsWidget<'_> {
@@ -18,18 +39,38 @@ impl StatefulWidget for ShowsWidget<'_> {
         buf: &mut ratatui::prelude::Buffer,
         state: &mut Self::State,
     ) {
-        let mut items = Vec::new();
-
-        for show in self.shows.shows() {
-            let line = Line::from(show.name()).white();
-            items.push(line);
-        }
+        let items = self.rows.iter().map(|row| match row {
+            SidebarRow::Inbox => {
+                Line::from(format!("inbox ({})", self.inbox_count)).style(self.theme.title(false))
+            }
+            SidebarRow::Playlist(playlist) => {
+                Line::from(playlist.name.as_str()).style(self.theme.title(false))
+            }
+            SidebarRow::Folder {
+                tag,
+                collapsed,
+                shows,
+            } => {
+                let marker = if *collapsed { '▸' } else { '▾' };
+                Line::from(format!("{marker} {tag} ({shows})")).style(self.theme.title(false))
+            }
+            SidebarRow::Show { show, nested } => {
+                let indent = if *nested { "  " } else { "" };
+                // feeds that have been missing for a while are shown, but
+                // stand out so they can be removed
+                if show.is_dead() {
+                    Line::from(format!("{indent}{} (gone)", show.name())).style(self.theme.error())
+                } else {
+                    Line::from(format!("{indent}{}", show.name())).style(self.theme.text())
+                }
+            }
+        });
 
         let list = List::new(items)
             .highlight_symbol("> ")
             .repeat_highlight_symbol(true)
-            .highlight_style(Style::new().yellow().bold())
-            .style(Style::new().white().not_bold())
+            .highlight_style(self.theme.highlight())
+            .style(self.theme.text())
             .highlight_spacing(ratatui::widgets::HighlightSpacing::Always);
 
         StatefulWidget::render(list, area, buf, state);
@@ -37,7 +78,15 @@ impl StatefulWidget for ShowsWidget<'_> {
 }
 
 impl<'a> ShowsWidget<'a> {
-    pub fn new(shows: &'a Shows) -> ShowsWidget<'a> {
-        Self { shows }
+    pub fn new(
+        rows: &'a [SidebarRow<'a>],
+        inbox_count: usize,
+        theme: &'a Theme,
+    ) -> ShowsWidget<'a> {
+        Self {
+            rows,
+            inbox_count,
+            theme,
+        }
     }
 }
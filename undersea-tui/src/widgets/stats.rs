@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use ratatui::{
+    prelude::*,
+    widgets::{Bar, BarChart, BarGroup, Block, Paragraph, Sparkline},
+};
+use undersea_lib::{Shows, history::Stats};
+
+use crate::theme::Theme;
+
+/// How many days of listening the sparkline shows.
+const DAYS: u64 = 30;
+/// How many weeks of finished episodes are charted.
+const WEEKS: usize = 12;
+/// How many of the most listened shows are charted.
+const TOP_SHOWS: usize = 8;
+
+pub struct StatsWidget<'a> {
+    stats: &'a Stats,
+    shows: &'a Shows,
+    theme: &'a Theme,
+}
+
+impl<'a> StatsWidget<'a> {
+    pub fn new(stats: &'a Stats, shows: &'a Shows, theme: &'a Theme) -> StatsWidget<'a> {
+        Self {
+            stats,
+            shows,
+            theme,
+        }
+    }
+
+    fn block(&self, title: &str) -> Block<'a> {
+        Block::bordered()
+            .border_style(self.theme.border(false))
+            .title(Line::from(format!(" {title} ")).style(self.theme.title(false)))
+    }
+}
+
+impl Widget for StatsWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let [summary, days, charts] = Layout::vertical([
+            Constraint::Length(7),
+            Constraint::Length(5),
+            Constraint::Min(8),
+        ])
+        .areas(area);
+        let [weeks, shows] =
+            Layout::horizontal(Constraint::from_percentages([40, 60])).areas(charts);
+        let stats = self.stats;
+
+        let lines = vec![
+            Line::from(format!(
+                "listened          {}",
+                format_hours(stats.listened)
+            )),
+            Line::from(format!(
+                "time saved        {}",
+                format_hours(stats.time_saved)
+            )),
+            Line::from(format!(
+                "  by silence      {}",
+                format_hours(stats.silence_saved)
+            )),
+            Line::from(format!("episodes finished {}", stats.episodes_finished)),
+            Line::from(format!(
+                "streak            {} days, longest {} days",
+                stats.current_streak, stats.longest_streak
+            )),
+        ];
+        Paragraph::new(lines)
+            .style(self.theme.text())
+            .block(self.block("stats"))
+            .render(summary, buf);
+
+        let minutes = stats
+            .last_days(DAYS)
+            .iter()
+            .map(|listened| listened.as_secs() / 60)
+            .collect::<Vec<_>>();
+        Sparkline::default()
+            .data(&minutes)
+            .style(self.theme.highlight())
+            .block(self.block(&format!("minutes a day, last {DAYS} days")))
+            .render(days, buf);
+
+        let week_bars = stats.finished_by_week
+            [stats.finished_by_week.len().saturating_sub(WEEKS)..]
+            .iter()
+            .map(|(start, finished)| {
+                Bar::default()
+                    .value(*finished as u64)
+                    .label(Line::from(start.format("%d/%m").to_string()))
+            })
+            .collect::<Vec<_>>();
+        BarChart::default()
+            .data(BarGroup::default().bars(&week_bars))
+            .bar_width(5)
+            .bar_gap(1)
+            .bar_style(self.theme.highlight())
+            .value_style(self.theme.selected())
+            .label_style(self.theme.text_dim())
+            .block(self.block("finished a week"))
+            .render(weeks, buf);
+
+        let show_bars = stats
+            .shows
+            .iter()
+            .take(TOP_SHOWS)
+            .map(|totals| {
+                let name = self
+                    .shows
+                    .find_show(&totals.show)
+                    .map_or(totals.show.as_str(), |show| show.name());
+                Bar::default()
+                    .value(totals.listened.as_secs() / 60)
+                    .text_value(format_hours(totals.listened))
+                    .label(Line::from(name.to_string()))
+            })
+            .collect::<Vec<_>>();
+        BarChart::default()
+            .direction(Direction::Horizontal)
+            .data(BarGroup::default().bars(&show_bars))
+            .bar_width(1)
+            .bar_gap(0)
+            .bar_style(self.theme.highlight())
+            .value_style(self.theme.selected())
+            .label_style(self.theme.text())
+            .block(self.block("most listened"))
+            .render(shows, buf);
+    }
+}
+
+/// Format a duration as hours and minutes, like `12h 05m`
+fn format_hours(time: Duration) -> String {
+    let minutes = time.as_secs() / 60;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
@@ -0,0 +1,167 @@
+use ratatui::{
+    prelude::*,
+    widgets::{List, ListItem, ListState, Paragraph, Wrap},
+};
+use undersea_lib::{player::format_time, transcript::Transcript};
+
+use crate::theme::Theme;
+
+/// The lines of a transcript with their times and speakers, and the search
+/// along the bottom.
+pub struct TranscriptWidget<'a> {
+    /// If an episode is playing
+    playing: bool,
+    /// The transcript, or why there is none, [`None`] while it loads
+    transcript: Option<&'a Result<Transcript, String>>,
+    /// The line being said
+    current: Option<usize>,
+    matches: &'a [usize],
+    /// A search being typed
+    input: Option<&'a str>,
+    query: &'a str,
+    theme: &'a Theme,
+}
+
+impl<'a> TranscriptWidget<'a> {
+    pub fn new(
+        playing: bool,
+        transcript: Option<&'a Result<Transcript, String>>,
+        current: Option<usize>,
+        matches: &'a [usize],
+        input: Option<&'a str>,
+        query: &'a str,
+        theme: &'a Theme,
+    ) -> Self {
+        Self {
+            playing,
+            transcript,
+            current,
+            matches,
+            input,
+            query,
+            theme,
+        }
+    }
+}
+
+impl StatefulWidget for TranscriptWidget<'_> {
+    type State = ListState;
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut ListState)
+    where
+        Self: Sized,
+    {
+        let transcript = match self.transcript {
+            None if self.playing => {
+                Paragraph::new("loading…")
+                    .style(self.theme.text_dim())
+                    .render(area, buf);
+                return;
+            }
+            None => {
+                Paragraph::new("nothing is playing")
+                    .style(self.theme.text_dim())
+                    .render(area, buf);
+                return;
+            }
+            Some(Err(err)) => {
+                Paragraph::new(err.as_str())
+                    .style(self.theme.error())
+                    .wrap(Wrap { trim: true })
+                    .render(area, buf);
+                return;
+            }
+            Some(Ok(transcript)) => transcript,
+        };
+
+        let search = match self.input {
+            Some(input) => Some(Line::from(format!("/{input}")).style(self.theme.text())),
+            None if !self.query.is_empty() => Some(
+                Line::from(format!(
+                    "\"{}\": {} matches",
+                    self.query,
+                    self.matches.len()
+                ))
+                .style(self.theme.text_dim()),
+            ),
+            None => None,
+        };
+        let [list_area, search_area] = Layout::vertical([
+            Constraint::Min(1),
+            Constraint::Length(u16::from(search.is_some())),
+        ])
+        .areas(area);
+        if let Some(search) = search {
+            search.render(search_area, buf);
+        }
+
+        // room for the highlight symbol
+        let width = usize::from(list_area.width.saturating_sub(2)).max(10);
+        let items = transcript.cues().iter().enumerate().map(|(index, cue)| {
+            let style = if Some(index) == self.current {
+                self.theme.highlight()
+            } else if self.matches.contains(&index) {
+                self.theme.selected()
+            } else {
+                self.theme.text()
+            };
+            let time = format_time(cue.start);
+            let speaker = cue
+                .speaker
+                .as_ref()
+                .map(|speaker| format!("{speaker}: "))
+                .unwrap_or_default();
+
+            let text = format!("{time}  {speaker}{}", cue.text);
+            let lines = wrap(&text, width)
+                .into_iter()
+                .enumerate()
+                .map(|(line, text)| {
+                    // the time is dimmed so the words stand out
+                    if line == 0 {
+                        let (time, rest) = text.split_at(time.len());
+                        Line::from(vec![
+                            Span::from(time.to_string()).style(self.theme.text_dim()),
+                            Span::from(rest.to_string()).style(style),
+                        ])
+                    } else {
+                        Line::from(text).style(style)
+                    }
+                })
+                .collect::<Vec<_>>();
+            ListItem::new(lines)
+        });
+
+        let list = List::new(items).highlight_symbol("> ");
+        StatefulWidget::render(list, list_area, buf, state);
+    }
+}
+
+/// Split text into lines no wider than `width`, between words where it can.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word = word;
+        let length = word.chars().count();
+        if !line.is_empty() && line.chars().count() + 1 + length > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        // words longer than a line are cut
+        while word.chars().count() > width {
+            let split = word
+                .char_indices()
+                .nth(width)
+                .map_or(word.len(), |(i, _)| i);
+            lines.push(word[..split].to_string());
+            word = &word[split..];
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}